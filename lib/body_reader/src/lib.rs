@@ -4,8 +4,8 @@ use libflate::non_blocking::{deflate, gzip};
 
 use std::{
     cmp,
-    io::{self, Read},
-    iter,
+    io::{self, Read, Write},
+    iter, mem,
 };
 
 // a reader to help us in getting the bytes out of a response body
@@ -123,10 +123,69 @@ impl BodyReader {
     }
 }
 
+enum EncoderInner {
+    Brotli(Box<::brotli::CompressorWriter<Vec<u8>>>),
+    Deflate(Box<libflate::deflate::Encoder<Vec<u8>>>),
+    Gzip(Box<libflate::gzip::Encoder<Vec<u8>>>),
+    None,
+}
+
+pub struct BodyEncoder {
+    inner: EncoderInner,
+}
+
+impl BodyEncoder {
+    pub fn new(c: Compression) -> Result<Self, io::Error> {
+        let inner = match c {
+            Compression::Brotli => EncoderInner::Brotli(
+                ::brotli::CompressorWriter::new(Vec::new(), 8192, 5, 22).into(),
+            ),
+            Compression::Deflate => {
+                EncoderInner::Deflate(libflate::deflate::Encoder::new(Vec::new()).into())
+            }
+            Compression::Gzip => {
+                EncoderInner::Gzip(libflate::gzip::Encoder::new(Vec::new())?.into())
+            }
+            Compression::None => EncoderInner::None,
+        };
+        Ok(BodyEncoder { inner })
+    }
+
+    // used to compress outgoing bytes. Returns whatever compressed bytes are ready, which may be none until more bytes are passed in or the encoder is finished
+    pub fn encode(&mut self, in_bytes: &[u8]) -> Result<Bytes, io::Error> {
+        let out = match &mut self.inner {
+            EncoderInner::Brotli(w) => {
+                w.write_all(in_bytes)?;
+                mem::take(w.get_mut())
+            }
+            EncoderInner::Deflate(w) => {
+                w.write_all(in_bytes)?;
+                mem::take(w.as_inner_mut())
+            }
+            EncoderInner::Gzip(w) => {
+                w.write_all(in_bytes)?;
+                mem::take(w.as_inner_mut())
+            }
+            EncoderInner::None => in_bytes.to_vec(),
+        };
+        Ok(out.into())
+    }
+
+    // used once there are no more bytes to compress. Returns the rest of the compressed bytes
+    pub fn finish(self) -> Result<Bytes, io::Error> {
+        let out = match self.inner {
+            EncoderInner::Brotli(w) => w.into_inner(),
+            EncoderInner::Deflate(w) => w.finish().into_result()?,
+            EncoderInner::Gzip(w) => w.finish().into_result()?,
+            EncoderInner::None => Vec::new(),
+        };
+        Ok(out.into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{cmp, io::Write};
 
     static TRUTH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.";
 
@@ -190,4 +249,25 @@ mod tests {
             assert_eq!(left, TRUTH, "index {}", i);
         }
     }
+
+    #[test]
+    fn body_encoder_works() {
+        for compression in &["br", "gzip", "deflate", ""] {
+            let mut encoder =
+                BodyEncoder::new(Compression::try_from(compression).unwrap()).unwrap();
+            let mut encoded_bytes = BytesMut::new();
+            for chunk in TRUTH.as_bytes().chunks(7) {
+                encoded_bytes.extend(encoder.encode(chunk).unwrap());
+            }
+            encoded_bytes.extend(encoder.finish().unwrap());
+
+            let mut reader = BodyReader::new(Compression::try_from(compression).unwrap());
+            let mut decoded_bytes = BytesMut::new();
+            reader
+                .decode(encoded_bytes.freeze(), &mut decoded_bytes)
+                .unwrap();
+            let left = std::str::from_utf8(&decoded_bytes).unwrap();
+            assert_eq!(left, TRUTH, "compression {:?}", compression);
+        }
+    }
 }
@@ -74,7 +74,7 @@ impl Config {
         self.0
             .loggers
             .values()
-            .map(|l| l.to.as_str().into())
+            .flat_map(|l| l.to.iter().map(|to| to.as_str().into()))
             .collect::<Vec<_>>()
             .into_boxed_slice()
     }
@@ -109,12 +109,10 @@ impl Config {
             .0
             .providers
             .iter()
-            .filter_map(|(_, v)| {
-                if let Provider::File(f) = v {
-                    Some(f.path.as_str().into())
-                } else {
-                    None
-                }
+            .filter_map(|(_, v)| match v.base() {
+                Provider::File(f) => Some(f.path.as_str().into()),
+                Provider::Sqlite(s) => Some(s.path.as_str().into()),
+                _ => None,
             })
             .collect::<Vec<_>>();
         provider_files.append(&mut body_files);
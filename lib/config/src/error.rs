@@ -31,25 +31,48 @@ impl From<ExecutingExpressionError> for Error {
     }
 }
 
-// impl ExpressionError {
-//     fn marker(&self) -> Marker {
-//         match self {
-//             IndexingIntoJson(_, _, marker) => *marker,
-//             InvalidExpression(_, marker) => *marker,
-//             InvalidFunctionArguments(_, marker) => *marker,
-//             UnknownFunction(_, marker) => *marker,
-//             UnknownProvider(_, marker) => *marker,
-//         }
-//     }
-// }
+impl ExecutingExpressionError {
+    fn marker(&self) -> Marker {
+        match self {
+            ExecutingExpressionError::IndexingIntoJson(_, _, marker) => *marker,
+            ExecutingExpressionError::InvalidFunctionArguments(_, marker) => *marker,
+        }
+    }
+}
+
+impl CreatingExpressionError {
+    fn marker(&self) -> Marker {
+        match self {
+            CreatingExpressionError::Executing(e) => e.marker(),
+            CreatingExpressionError::InvalidExpression(_, marker) => *marker,
+            CreatingExpressionError::UnknownFunction(_, marker) => *marker,
+            CreatingExpressionError::UnknownProvider(_, marker) => *marker,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub enum Error {
+    EmptyLoadPattern(Marker),
     ExpressionErr(CreatingExpressionError),
+    // an error in one of several config files merged into one, with the file it's in
+    InConfigFile(String, Box<Error>),
+    InvalidAcceptEncoding(String, Marker),
+    InvalidBodyFile(String, String, Marker),
+    InvalidConfigFile(String, String),
+    InvalidConnectTo(String, Marker),
+    InvalidDependsOn(usize, Marker),
     InvalidDuration(String, Marker),
+    InvalidEnvProviderValue(String, String, Marker),
+    InvalidHeadersFile(String, String, Marker),
+    InvalidListWeights(&'static str, Marker),
     InvalidLoadPattern(Marker),
+    InvalidLoadPatternCurve(f64, Marker),
     InvalidPeakLoad(String, Marker),
     InvalidPercent(String, Marker),
+    InvalidProxyUrl(String, Marker),
+    InvalidStatsPercentile(f64, Marker),
+    InvalidStickyProvider(&'static str, Marker),
     InvalidYaml(ScanError),
     MissingEnvironmentVariable(String, Marker),
     MissingForEach(Marker),
@@ -57,32 +80,62 @@ pub enum Error {
     MissingLoadPattern(Marker),
     MissingYamlField(&'static str, Marker),
     RecursiveForEachReference(Marker),
+    SharedPeakLoadProvider(String, Marker),
+    UnexpectedPeakLoad(Marker),
+    UnknownEndpointGroup(String, Marker),
+    UnknownEnvironment(String, Vec<String>),
     UnknownLogger(String, Marker),
     UnrecognizedKey(String, Option<String>, Marker),
+    // a key in an environment other than those it can override
+    UnsupportedEnvironmentKey(String, Marker),
     YamlDeserialize(Option<String>, Marker),
+    ZeroDurationLoadPattern(Marker),
+    ZeroPeriodLoadPattern(Marker),
 }
 
-// impl Error {
-// fn marker(&self) -> Marker {
-//     match &self {
-//         ExpressionErr(e) => e.marker(),
-//         InvalidDuration(_, marker) => *marker,
-//         InvalidLoadPattern(marker) => *marker,
-//         InvalidPeakLoad(_, marker) => *marker,
-//         InvalidPercent(_, marker) => *marker,
-//         InvalidYaml(e) => *e.marker(),
-//         MissingEnvironmentVariable(_, marker) => *marker,
-//         MissingForEach(marker) => *marker,
-//         MissingPeakLoad(marker) => *marker,
-//         MissingLoadPattern(marker) => *marker,
-//         MissingYamlField(_, marker) => *marker,
-//         RecursiveForEachReference(marker) => *marker,
-//         UnknownLogger(_, marker) => *marker,
-//         UnrecognizedKey(_, _, marker) => *marker,
-//         YamlDeserialize(_, marker) => *marker,
-//     }
-// }
-// }
+impl Error {
+    /// Where in the config the error is, for the errors which have a place in it
+    pub(crate) fn marker(&self) -> Option<Marker> {
+        use Error::*;
+        let marker = match self {
+            EmptyLoadPattern(m)
+            | InvalidLoadPattern(m)
+            | MissingForEach(m)
+            | MissingPeakLoad(m)
+            | MissingLoadPattern(m)
+            | RecursiveForEachReference(m)
+            | UnexpectedPeakLoad(m)
+            | ZeroDurationLoadPattern(m)
+            | ZeroPeriodLoadPattern(m) => *m,
+            InvalidAcceptEncoding(_, m)
+            | InvalidConnectTo(_, m)
+            | InvalidDependsOn(_, m)
+            | InvalidDuration(_, m)
+            | InvalidListWeights(_, m)
+            | InvalidLoadPatternCurve(_, m)
+            | InvalidPeakLoad(_, m)
+            | InvalidPercent(_, m)
+            | InvalidProxyUrl(_, m)
+            | InvalidStatsPercentile(_, m)
+            | InvalidStickyProvider(_, m)
+            | MissingEnvironmentVariable(_, m)
+            | MissingYamlField(_, m)
+            | SharedPeakLoadProvider(_, m)
+            | UnknownEndpointGroup(_, m)
+            | UnknownLogger(_, m)
+            | UnsupportedEnvironmentKey(_, m)
+            | YamlDeserialize(_, m) => *m,
+            InvalidBodyFile(_, _, m)
+            | InvalidEnvProviderValue(_, _, m)
+            | InvalidHeadersFile(_, _, m)
+            | UnrecognizedKey(_, _, m) => *m,
+            ExpressionErr(e) => e.marker(),
+            InvalidYaml(e) => *e.marker(),
+            InConfigFile(..) | InvalidConfigFile(..) | UnknownEnvironment(..) => return None,
+        };
+        Some(marker)
+    }
+}
 
 impl fmt::Display for CreatingExpressionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -136,15 +189,47 @@ impl fmt::Display for ExecutingExpressionError {
     }
 }
 
+/// Something in a config worth pointing out which, unlike an `Error`, doesn't stop the test
+/// from running.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    BodyIgnoredByMethod(String, Marker),
+    DeprecatedLogProviderStats(Marker),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Warning::*;
+        match self {
+            BodyIgnoredByMethod(method, m) => write!(f, "endpoint sends a body with a `{}` request, which servers usually ignore, at line {} column {}", method, m.line(), m.col()),
+            DeprecatedLogProviderStats(m) => write!(f, "a duration for log_provider_stats is deprecated and ignored, use `true` or `false` at line {} column {}", m.line(), m.col()),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use Error::*;
         match self {
+            EmptyLoadPattern(m) => write!(f, "load_pattern must have at least one segment at line {} column {}", m.line(), m.col()),
             ExpressionErr(e) => e.fmt(f),
+            InConfigFile(p, e) => write!(f, "{} in `{}`", e, p),
+            InvalidAcceptEncoding(e, m) => write!(f, "invalid accept_encoding `{}`, expected one of `gzip`, `deflate` or `br` at line {} column {}", e, m.line(), m.col()),
+            InvalidBodyFile(p, e, m) => write!(f, "invalid body file `{}`: {} at line {} column {}", p, e, m.line(), m.col()),
+            InvalidConfigFile(p, e) => write!(f, "invalid config file `{}`: {}", p, e),
+            InvalidConnectTo(a, m) => write!(f, "invalid connect_to `{}`, expected an ip and port such as `10.0.0.1:443` at line {} column {}", a, m.line(), m.col()),
+            InvalidDependsOn(id, m) => write!(f, "invalid depends_on `{}`, an endpoint can only depend on endpoints listed before it at line {} column {}", id, m.line(), m.col()),
             InvalidDuration(d, m) => write!(f, "invalid duration `{}` at line {} column {}", d, m.line(), m.col()),
+            InvalidEnvProviderValue(v, e, m) => write!(f, "invalid value in environment variable `{}` for the env provider at line {} column {}: {}", v, m.line(), m.col(), e),
+            InvalidHeadersFile(p, e, m) => write!(f, "invalid headers file `{}`: {} at line {} column {}", p, e, m.line(), m.col()),
+            InvalidListWeights(e, m) => write!(f, "invalid `weights` for the list provider, {} at line {} column {}", e, m.line(), m.col()),
             InvalidLoadPattern(m) => write!(f, "invalid load_pattern at line {} column {}", m.line(), m.col()),
+            InvalidLoadPatternCurve(c, m) => write!(f, "exponential load_pattern segment must have a `curve` greater than zero, not `{}`, at line {} column {}", c, m.line(), m.col()),
             InvalidPeakLoad(p, m) => write!(f, "invalid peak_load `{}` at line {} column {}", p, m.line(), m.col()),
             InvalidPercent(p, m) => write!(f, "invalid percent `{}` at line {} column {}", p, m.line(), m.col()),
+            InvalidProxyUrl(u, m) => write!(f, "invalid proxy url `{}`, expected an http url such as `http://proxy:3128` at line {} column {}", u, m.line(), m.col()),
+            InvalidStatsPercentile(p, m) => write!(f, "invalid `stats_percentiles` value `{}`, each must be greater than 0 and less than 100, at line {} column {}", p, m.line(), m.col()),
+            InvalidStickyProvider(e, m) => write!(f, "invalid use of a sticky provider, {} at line {} column {}", e, m.line(), m.col()),
             InvalidYaml(e) => write!(f, "yaml syntax error:\n\t{e}"),
             MissingEnvironmentVariable(v, m) => write!(f, "undefined environment variable `{}` at line {} column {}", v, m.line(), m.col()),
             MissingForEach(m) => write!(f, "missing `for_each` at line {} column {}", m.line(), m.col()),
@@ -155,11 +240,19 @@ impl fmt::Display for Error {
             ),
             MissingYamlField(field, m) => write!(f, "missing field `{}` at line {} column {}", field, m.line(), m.col()),
             RecursiveForEachReference(m) => write!(f, "recursive `for_each` reference at line {} column {}", m.line(), m.col()),
+            SharedPeakLoadProvider(p, m) => write!(f, "provider `{}` is read by a `peak_load` and so can't be read anywhere else, unless it's `share: broadcast`, at line {} column {}", p, m.line(), m.col()),
+            UnexpectedPeakLoad(m) => write!(f, "endpoint in an `endpoint_group` cannot have a `peak_load` at line {} column {}", m.line(), m.col()),
+            UnknownEndpointGroup(g, m) => write!(f, "unknown endpoint_group `{}` at line {} column {}", g, m.line(), m.col()),
+            UnknownEnvironment(e, available) if available.is_empty() => write!(f, "unknown environment `{}`, the config has no `environments`", e),
+            UnknownEnvironment(e, available) => write!(f, "unknown environment `{}`, available environments are: {}", e, available.join(", ")),
             UnknownLogger(l, m) => write!(f, "unknown logger `{}` at line {} column {}", l, m.line(), m.col()),
             UnrecognizedKey(k, Some(name), m) => write!(f, "unrecognized key `{}` in `{}` at line {} column {}", k, name, m.line(), m.col()),
             UnrecognizedKey(k, None, m) => write!(f, "unrecognized key `{}` at line {} column {}", k, m.line(), m.col()),
+            UnsupportedEnvironmentKey(k, m) => write!(f, "`{}` can't be set by an environment, which only overrides `vars` and the `headers`, `keepalive`, `request_timeout` and `user_agent` of `client`, at line {} column {}", k, m.line(), m.col()),
             YamlDeserialize(Some(name), m) => write!(f, "unexpected value for `{}` at line {} column {}", name, m.line(), m.col()),
             YamlDeserialize(None, m) => write!(f, "unexpected value for field at line {} column {}", m.line(), m.col()),
+            ZeroDurationLoadPattern(m) => write!(f, "load_pattern segment must have an `over` longer than zero at line {} column {}", m.line(), m.col()),
+            ZeroPeriodLoadPattern(m) => write!(f, "sine load_pattern segment must have a `period` longer than zero at line {} column {}", m.line(), m.col()),
         }
     }
 }
@@ -178,6 +271,7 @@ impl StdError for Error {
         match self {
             Error::ExpressionErr(e) => Some(e),
             Error::InvalidYaml(e) => Some(e),
+            Error::InConfigFile(_, e) => Some(e),
             _ => None,
         }
     }
@@ -9,9 +9,11 @@ use yaml_rust::{
 
 use std::{
     collections::BTreeMap,
+    iter,
     num::{NonZeroU16, NonZeroUsize},
     ops::Range,
     str::FromStr,
+    vec,
 };
 
 pub trait FromYaml: Sized {
@@ -137,6 +139,16 @@ impl FromYaml for NonZeroUsize {
     }
 }
 
+impl FromYaml for f64 {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        event
+            .as_x()
+            .map(|f| (f, marker))
+            .ok_or(Error::YamlDeserialize(None, marker))
+    }
+}
+
 impl FromYaml for i64 {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let (event, marker) = decoder.next()?;
@@ -147,6 +159,26 @@ impl FromYaml for i64 {
     }
 }
 
+impl FromYaml for u8 {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        event
+            .as_x()
+            .map(|i| (i, marker))
+            .ok_or(Error::YamlDeserialize(None, marker))
+    }
+}
+
+impl FromYaml for u64 {
+    fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
+        let (event, marker) = decoder.next()?;
+        event
+            .as_x()
+            .map(|i| (i, marker))
+            .ok_or(Error::YamlDeserialize(None, marker))
+    }
+}
+
 impl FromYaml for usize {
     fn parse<I: Iterator<Item = char>>(decoder: &mut YamlDecoder<I>) -> ParseResult<Self> {
         let (event, marker) = decoder.next()?;
@@ -229,6 +261,9 @@ enum AliasOrEvent {
 pub struct YamlDecoder<I: Iterator<Item = char>> {
     aliased_events: Vec<AliasOrEvent>,
     alias_map: BTreeMap<usize, Range<usize>>,
+    // events which were already decoded, such as those of merged documents, given out before
+    // anything from the parser
+    decoded: vec::IntoIter<(YamlEvent, Marker)>,
     parser: YamlParser<I>,
     peek: Option<(YamlEvent, Marker)>,
     reference_stack: Vec<Option<(usize, usize)>>,
@@ -241,6 +276,7 @@ impl<I: Iterator<Item = char>> YamlDecoder<I> {
         YamlDecoder {
             aliased_events: Vec::new(),
             alias_map: BTreeMap::new(),
+            decoded: Vec::new().into_iter(),
             parser,
             peek: None,
             reference_stack: Vec::new(),
@@ -265,6 +301,9 @@ impl<I: Iterator<Item = char>> YamlDecoder<I> {
                 }
                 continue;
             }
+            if let Some(decoded) = self.decoded.next() {
+                break decoded;
+            }
             let (event, marker) = self.parser.next()?;
             let in_reference = !self.reference_stack.is_empty();
             let (alias_id, event) = match event {
@@ -323,6 +362,168 @@ impl<I: Iterator<Item = char>> YamlDecoder<I> {
     }
 }
 
+impl YamlDecoder<iter::Empty<char>> {
+    /// A decoder for several yaml documents, each given with its name, deep merged into one,
+    /// each over the ones before it. Mappings are merged key by key and anything else, lists
+    /// included, is replaced. Every event keeps its marker from the document it came from, and
+    /// the `MergedSources` tell which document that was.
+    pub fn merged<'a>(
+        documents: impl IntoIterator<Item = (String, &'a str)>,
+    ) -> Result<(Self, MergedSources), Error> {
+        let mut merged: Option<YamlNode> = None;
+        let mut names = Vec::new();
+        for (name, document) in documents {
+            let node = YamlNode::read(&mut YamlDecoder::new(document.chars()), names.len())
+                .map_err(|e| Error::InConfigFile(name.clone(), e.into()))?;
+            names.push(name);
+            match &mut merged {
+                Some(merged) => merged.merge(node),
+                None => merged = Some(node),
+            }
+        }
+        let mut events = Vec::new();
+        let mut markers = Vec::new();
+        if let Some(merged) = merged {
+            merged.into_events(&mut events, &mut markers);
+        }
+        let mut decoder = YamlDecoder::new(iter::empty());
+        decoder.decoded = events.into_iter();
+        Ok((decoder, MergedSources { names, markers }))
+    }
+}
+
+/// Which of several merged documents each part of the merged one came from
+pub struct MergedSources {
+    names: Vec<String>,
+    // the marker of every event in the merged document, with the document it came from
+    markers: Vec<(Marker, usize)>,
+}
+
+impl MergedSources {
+    /// Adds the name of the document an error is in. The line and column of a marker can be in
+    /// more than one of the documents, so an error is left as it is unless its marker is from
+    /// just one of them.
+    pub fn locate(&self, e: Error) -> Error {
+        let marker = match e.marker() {
+            Some(marker) => marker,
+            None => return e,
+        };
+        let mut sources = self
+            .markers
+            .iter()
+            .filter(|(m, _)| *m == marker)
+            .map(|(_, i)| *i);
+        match sources.next() {
+            Some(i) if sources.all(|other| other == i) => {
+                Error::InConfigFile(self.names[i].clone(), e.into())
+            }
+            _ => e,
+        }
+    }
+}
+
+// A yaml document read into a tree of its events, with any aliases already replayed. Each node
+// has the index of the document it came from
+enum YamlNode {
+    Scalar(YamlEvent, Marker, usize),
+    Sequence(Marker, Vec<YamlNode>, Marker, usize),
+    Mapping(Marker, Vec<(YamlNode, YamlNode)>, Marker, usize),
+}
+
+impl YamlNode {
+    fn read<I: Iterator<Item = char>>(
+        decoder: &mut YamlDecoder<I>,
+        source: usize,
+    ) -> Result<Self, Error> {
+        let (event, start) = decoder.next()?;
+        let node = match event {
+            YamlEvent::MappingStart => {
+                let mut entries = Vec::new();
+                loop {
+                    if let (YamlEvent::MappingEnd, _) = decoder.peek()? {
+                        let (_, end) = decoder.next()?;
+                        break YamlNode::Mapping(start, entries, end, source);
+                    }
+                    let key = Self::read(decoder, source)?;
+                    let value = Self::read(decoder, source)?;
+                    entries.push((key, value));
+                }
+            }
+            YamlEvent::SequenceStart => {
+                let mut items = Vec::new();
+                loop {
+                    if let (YamlEvent::SequenceEnd, _) = decoder.peek()? {
+                        let (_, end) = decoder.next()?;
+                        break YamlNode::Sequence(start, items, end, source);
+                    }
+                    items.push(Self::read(decoder, source)?);
+                }
+            }
+            YamlEvent::Scalar(..) => YamlNode::Scalar(event, start, source),
+            YamlEvent::MappingEnd | YamlEvent::SequenceEnd => {
+                return Err(Error::YamlDeserialize(None, start))
+            }
+        };
+        Ok(node)
+    }
+
+    fn key(&self) -> Option<&str> {
+        match self {
+            YamlNode::Scalar(event, ..) => event.as_str(),
+            _ => None,
+        }
+    }
+
+    fn merge(&mut self, overrides: Self) {
+        match (self, overrides) {
+            (YamlNode::Mapping(_, entries, ..), YamlNode::Mapping(_, overrides, ..)) => {
+                for (key, value) in overrides {
+                    let existing = key
+                        .key()
+                        .and_then(|k| entries.iter_mut().find(|(e, _)| e.key() == Some(k)));
+                    match existing {
+                        Some((_, v)) => v.merge(value),
+                        None => entries.push((key, value)),
+                    }
+                }
+            }
+            (base, overrides) => *base = overrides,
+        }
+    }
+
+    fn into_events(
+        self,
+        events: &mut Vec<(YamlEvent, Marker)>,
+        markers: &mut Vec<(Marker, usize)>,
+    ) {
+        match self {
+            YamlNode::Scalar(event, marker, source) => {
+                events.push((event, marker));
+                markers.push((marker, source));
+            }
+            YamlNode::Sequence(start, items, end, source) => {
+                events.push((YamlEvent::SequenceStart, start));
+                markers.push((start, source));
+                for item in items {
+                    item.into_events(events, markers);
+                }
+                events.push((YamlEvent::SequenceEnd, end));
+                markers.push((end, source));
+            }
+            YamlNode::Mapping(start, entries, end, source) => {
+                events.push((YamlEvent::MappingStart, start));
+                markers.push((start, source));
+                for (key, value) in entries {
+                    key.into_events(events, markers);
+                    value.into_events(events, markers);
+                }
+                events.push((YamlEvent::MappingEnd, end));
+                markers.push((end, source));
+            }
+        }
+    }
+}
+
 pub trait Insert: Default {
     type Value;
     fn insert(&mut self, v: Self::Value, start_event: &YamlEvent) -> Result<(), ()>;
@@ -442,7 +643,10 @@ where
                 }
                 YamlEvent::MappingStart | YamlEvent::SequenceStart => {
                     if first_round {
+                        // the collection's opening, check for an end before parsing a value so
+                        // an empty collection can be parsed
                         decoder.next()?;
+                        continue;
                     }
                     let v = FromYaml::parse_into(decoder)?;
                     let event = first_event.as_ref().expect("should have first event");
@@ -1282,6 +1282,21 @@ impl Template {
             .collect()
     }
 
+    /// Like `evaluate`, except a template which is nothing but a single expression keeps the type
+    /// of the value the expression evaluates to, rather than being turned into a string
+    pub fn evaluate_to_value<'a>(
+        &self,
+        d: Cow<'a, json::Value>,
+        for_each: Option<&[Cow<'a, json::Value>]>,
+    ) -> Result<json::Value, ExecutingExpressionError> {
+        match self.pieces.as_slice() {
+            [TemplatePiece::Expression(voe)] => voe
+                .evaluate(d, self.no_recoverable_error, for_each)
+                .map(Cow::into_owned),
+            _ => self.evaluate(d, for_each).map(json::Value::String),
+        }
+    }
+
     pub fn evaluate_with_star(&self) -> String {
         self.pieces
             .iter()
@@ -1814,6 +1829,15 @@ fn parse_path(
     }
     let start = start.expect("expected there to be a start piece while parsing path");
     if let PathStart::Ident(s) = &start {
+        // header names are lowercase in `headers` and `headers_all`, so a name written in the
+        // expression matches whatever its case
+        if let [PathSegment::String(object), PathSegment::String(name), ..] = rest.as_mut_slice() {
+            if (s == "request" || s == "response")
+                && (object == "headers" || object == "headers_all")
+            {
+                name.make_ascii_lowercase();
+            }
+        }
         match rest.first() {
             Some(PathSegment::String(next)) if s == "request" || s == "response" => {
                 providers2.insert(format!("{s}.{next}"), marker);
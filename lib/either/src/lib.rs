@@ -278,6 +278,105 @@ where
     }
 }
 
+/// Allows the mapping of four different (similar) types to determine either A, B, C, or D.
+pub enum Either4<A, B, C, D> {
+    A(A),
+    B(B),
+    C(C),
+    D(D),
+}
+
+impl<A, B, C, D> Stream for Either4<A, B, C, D>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+    C: Stream<Item = A::Item>,
+    D: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<A::Item>> {
+        unsafe {
+            match self.get_unchecked_mut() {
+                Either4::A(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either4::B(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either4::C(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either4::D(x) => Pin::new_unchecked(x).poll_next(cx),
+            }
+        }
+    }
+}
+
+impl<A, B, C, D, T> Iterator for Either4<A, B, C, D>
+where
+    A: Iterator<Item = T>,
+    B: Iterator<Item = T>,
+    C: Iterator<Item = T>,
+    D: Iterator<Item = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Either4::A(a) => a.next(),
+            Either4::B(b) => b.next(),
+            Either4::C(c) => c.next(),
+            Either4::D(d) => d.next(),
+        }
+    }
+}
+
+/// Allows the mapping of five different (similar) types to determine either A, B, C, D, or E.
+pub enum Either5<A, B, C, D, E> {
+    A(A),
+    B(B),
+    C(C),
+    D(D),
+    E(E),
+}
+
+impl<A, B, C, D, E> Stream for Either5<A, B, C, D, E>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+    C: Stream<Item = A::Item>,
+    D: Stream<Item = A::Item>,
+    E: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<A::Item>> {
+        unsafe {
+            match self.get_unchecked_mut() {
+                Either5::A(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either5::B(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either5::C(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either5::D(x) => Pin::new_unchecked(x).poll_next(cx),
+                Either5::E(x) => Pin::new_unchecked(x).poll_next(cx),
+            }
+        }
+    }
+}
+
+impl<A, B, C, D, E, T> Iterator for Either5<A, B, C, D, E>
+where
+    A: Iterator<Item = T>,
+    B: Iterator<Item = T>,
+    C: Iterator<Item = T>,
+    D: Iterator<Item = T>,
+    E: Iterator<Item = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Either5::A(a) => a.next(),
+            Either5::B(b) => b.next(),
+            Either5::C(c) => c.next(),
+            Either5::D(d) => d.next(),
+            Either5::E(e) => e.next(),
+        }
+    }
+}
+
 pub trait FutureExt: Future + Sized {
     fn a<B>(self) -> Either<Self, B>
     where
@@ -211,6 +211,27 @@ impl ModInterval {
         self.segments.push_back(segment);
     }
 
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The number of hits per second scheduled at `time` into the interval, computed without
+    /// running it. Past the end of the interval the rate is `0`.
+    pub fn hits_per_second_at(&self, time: Duration) -> f64 {
+        if time > self.duration {
+            return 0.0;
+        }
+        let mut x_offset = Duration::default();
+        let last_i = self.segments.len().saturating_sub(1);
+        for (i, segment) in self.segments.iter().enumerate() {
+            if segment.duration + x_offset > time || i == last_i {
+                return segment.get_hps_at(time - x_offset);
+            }
+            x_offset += segment.duration;
+        }
+        0.0
+    }
+
     pub fn into_stream(
         self,
         start_at: Option<Duration>,
@@ -617,4 +638,50 @@ mod tests {
 
         assert_eq!(new_mod_interval, expect_mod_interval);
     }
+
+    #[test]
+    fn hits_per_second_at() {
+        let segments = [(0.0, 30, 10.0), (10.0, 30, 30.0)];
+        let mut mod_interval = ModInterval::new();
+        for (start, duration, end) in segments.iter() {
+            mod_interval.append_segment(
+                PerX::second(*start),
+                Duration::from_secs(*duration),
+                PerX::second(*end),
+            );
+        }
+
+        assert_eq!(mod_interval.duration(), Duration::from_secs(60));
+        let rate = |secs| mod_interval.hits_per_second_at(Duration::from_secs(secs));
+        assert!((rate(15) - 5.0).abs() < 1e-9);
+        assert!((rate(30) - 10.0).abs() < 1e-9);
+        assert!((rate(45) - 20.0).abs() < 1e-9);
+        assert!((rate(60) - 30.0).abs() < 1e-9);
+        assert_eq!(rate(61), 0.0);
+    }
+
+    #[test]
+    fn constant_rate_segment() {
+        // a segment which neither ramps up nor down has no slope to divide by
+        let mut mod_interval = ModInterval::new();
+        mod_interval.append_segment(
+            PerX::second(4.0),
+            Duration::from_secs(10),
+            PerX::second(4.0),
+        );
+
+        for secs in 0..=10 {
+            let rate = mod_interval.hits_per_second_at(Duration::from_secs(secs));
+            assert!((rate - 4.0).abs() < 1e-9, "rate was {} at {}s", rate, secs);
+        }
+
+        let stream = Box::pin(mod_interval.into_stream(None));
+        let times: Vec<_> = block_on_stream(stream).collect();
+        assert_eq!(times.len(), 40);
+        for (instant, next) in times {
+            if let Some(next) = next {
+                assert_eq!(next - instant, Duration::from_millis(250));
+            }
+        }
+    }
 }
@@ -1,7 +1,11 @@
 use log::{debug, info};
 use std::{future::Future, io, str::FromStr, sync::Arc, time::Duration};
 
-use futures::{channel::oneshot, future::select, FutureExt};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::select,
+    FutureExt,
+};
 use futures_timer::Delay;
 use http::{header, StatusCode};
 use hyper::{
@@ -20,6 +24,11 @@ async fn echo_route(req: Request<Body>) -> Response<Body> {
         .unwrap_or_else(|| header::HeaderValue::from_static("text/plain"));
     let mut echo = None;
     let mut wait = None;
+    let mut status = None;
+    let mut retry_after = None;
+    let mut location = None;
+    let mut set_cookie = None;
+    let mut require_cookie = None;
     let uri = req.uri();
     let url = uri
         .path_and_query()
@@ -30,9 +39,27 @@ async fn echo_route(req: Request<Body>) -> Response<Body> {
         match &*k {
             "echo" => echo = Some(v.to_string()),
             "wait" => wait = Some(v.to_string()),
+            "status" => status = v.parse::<StatusCode>().ok(),
+            "retry_after" => retry_after = Some(v.to_string()),
+            "location" => location = Some(v.to_string()),
+            "set_cookie" => set_cookie = Some(v.to_string()),
+            "require_cookie" => require_cookie = Some(v.to_string()),
             _ => (),
         }
     }
+    // a request without the cookie it needs is turned away
+    let has_cookie = |cookie: &str| {
+        headers
+            .get(header::COOKIE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split("; ").any(|c| c == cookie))
+    };
+    if require_cookie.is_some_and(|cookie| !has_cookie(&cookie)) {
+        return Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap();
+    }
     if echo.is_some() {
         debug!("Echo Body = {}", echo.clone().unwrap_or_default());
     }
@@ -52,6 +79,22 @@ async fn echo_route(req: Request<Body>) -> Response<Body> {
             .body(Body::empty())
             .unwrap(),
     };
+    if let Some(status) = status {
+        *response.status_mut() = status;
+    }
+    if let Some(retry_after) = retry_after.and_then(|v| header::HeaderValue::from_str(&v).ok()) {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, retry_after);
+    }
+    if let Some(location) = location.and_then(|v| header::HeaderValue::from_str(&v).ok()) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    if let Some(set_cookie) = set_cookie.and_then(|v| header::HeaderValue::from_str(&v).ok()) {
+        response
+            .headers_mut()
+            .insert(header::SET_COOKIE, set_cookie);
+    }
     let ms = wait.and_then(|c| FromStr::from_str(&c).ok()).unwrap_or(0);
     let old_body = std::mem::replace(response.body_mut(), Body::empty());
     if ms > 0 {
@@ -109,6 +152,57 @@ pub fn start_test_server(
     (port, tx, handle)
 }
 
+pub struct CapturedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: header::HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Starts a server which responds to everything with a `200` and sends each request it
+/// receives down the returned channel
+pub fn start_capture_server() -> (
+    u16,
+    mpsc::UnboundedReceiver<CapturedRequest>,
+    oneshot::Sender<()>,
+) {
+    let address = ([127, 0, 0, 1], 0).into();
+    let (captured_tx, captured_rx) = mpsc::unbounded();
+
+    let make_svc = make_service_fn(move |_: &AddrStream| {
+        let captured_tx = captured_tx.clone();
+        async move {
+            let service = service_fn(move |req: Request<Body>| {
+                let captured_tx = captured_tx.clone();
+                async move {
+                    let method = req.method().to_string();
+                    let path = req.uri().path().to_string();
+                    let headers = req.headers().clone();
+                    let body = hyper::body::to_bytes(req.into_body()).await?.to_vec();
+                    let _ = captured_tx.unbounded_send(CapturedRequest {
+                        method,
+                        path,
+                        headers,
+                        body,
+                    });
+                    Ok::<_, Error>(Response::new(Body::empty()))
+                }
+            });
+            Ok::<_, Error>(service)
+        }
+    });
+
+    let (tx, rx) = oneshot::channel();
+
+    let server = Server::bind(&address).serve(make_svc);
+
+    let port = server.local_addr().port();
+
+    tokio::spawn(select(server, rx));
+
+    (port, captured_rx, tx)
+}
+
 #[derive(Clone)]
 pub struct TestWriter(Arc<Mutex<(bool, Vec<u8>)>>);
 
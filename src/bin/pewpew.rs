@@ -1,4 +1,7 @@
-use std::io::{self, IsTerminal};
+use std::{
+    io::{self, IsTerminal},
+    num::NonZeroUsize,
+};
 
 use futures::channel::mpsc as futures_channel;
 use log::{debug, info};
@@ -9,25 +12,46 @@ use yansi::Paint;
 mod args {
     use clap::{Args, Parser, Subcommand};
     use pewpew::{
-        ExecConfig, RunConfig, RunOutputFormat, StatsFileFormat, TryConfig, TryFilter, TryRunFormat,
+        var_from_str, ExecConfig, JsonStyle, RunConfig, RunOutputFormat, S3Location,
+        StatsFileFormat, StatsSocketAddr, TryConfig, TryFilter, TryRunFormat,
     };
     use std::{
         fs::create_dir_all,
+        io::{self, IsTerminal},
+        num::NonZeroUsize,
         path::PathBuf,
         str::FromStr,
         time::{Duration, UNIX_EPOCH},
     };
 
-    pub fn get_cli_config() -> ExecConfig {
+    /// What pewpew was asked to do
+    #[derive(Debug)]
+    pub enum Command {
+        Exec(Box<ExecConfig>),
+        Schema,
+    }
+
+    pub fn get_cli_config() -> Command {
         ArgsData::parse().command.into()
     }
+
+    // Checked for up front so a mistake like `-i x y`, rather than `-i x -i y`, fails with a
+    // usage error instead of `y` being read as another config file
+    fn existing_file(s: &str) -> Result<PathBuf, String> {
+        let path = PathBuf::from(s);
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!("no config file at `{s}`"))
+        }
+    }
     #[cfg(test)]
     use clap::error::{DefaultFormatter, Error};
     #[cfg(test)]
     use std::ffi::OsString;
 
     #[cfg(test)]
-    pub fn try_parse_from<I, T>(itr: I) -> Result<ExecConfig, Error<DefaultFormatter>>
+    pub fn try_parse_command_from<I, T>(itr: I) -> Result<Command, Error<DefaultFormatter>>
     where
         I: IntoIterator<Item = T>,
         T: Into<OsString> + Clone,
@@ -35,6 +59,18 @@ mod args {
         Ok(ArgsData::try_parse_from(itr)?.command.into())
     }
 
+    #[cfg(test)]
+    pub fn try_parse_from<I, T>(itr: I) -> Result<ExecConfig, Error<DefaultFormatter>>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        match try_parse_command_from(itr)? {
+            Command::Exec(exec_config) => Ok(*exec_config),
+            Command::Schema => panic!("subcommand was `schema`"),
+        }
+    }
+
     #[derive(Debug, Parser)]
     #[command(
     version = clap::crate_version!(),
@@ -58,13 +94,16 @@ mod args {
         Run(RunConfigTmp),
         /// Runs the specified endpoint(s) a single time for testing purposes
         Try(TryConfigTmp),
+        /// Prints the JSON Schema of the config file
+        Schema,
     }
 
-    impl From<ExecConfigTmp> for ExecConfig {
+    impl From<ExecConfigTmp> for Command {
         fn from(value: ExecConfigTmp) -> Self {
             match value {
-                ExecConfigTmp::Try(t) => Self::Try(t.into()),
-                ExecConfigTmp::Run(r) => Self::Run(r.into()),
+                ExecConfigTmp::Try(t) => Self::Exec(Box::new(ExecConfig::Try(t.into()))),
+                ExecConfigTmp::Run(r) => Self::Exec(Box::new(ExecConfig::Run(r.into()))),
+                ExecConfigTmp::Schema => Self::Schema,
             }
         }
     }
@@ -74,9 +113,15 @@ mod args {
         /// Load test config file to use
         #[arg(value_name = "CONFIG")]
         config_file: PathBuf,
+        /// More config files deep merged over CONFIG, each over the ones before it
+        #[arg(value_name = "MERGE", value_parser = existing_file)]
+        merge_config_files: Vec<PathBuf>,
         /// Formatting for stats printed to stdout
         #[arg(short = 'f', long, value_name = "FORMAT", default_value_t)]
         output_format: RunOutputFormat,
+        /// How json output is laid out, either a line per event or a single array at the end
+        #[arg(long, value_name = "STYLE", default_value_t)]
+        json_style: JsonStyle,
         /// Directory to store results and logs
         #[arg(short = 'd', long = "results-directory", value_name = "DIRECTORY")]
         results_dir: Option<PathBuf>,
@@ -86,17 +131,93 @@ mod args {
         /// Specify the filename for the stats file
         #[arg(short = 'o', long)]
         stats_file: Option<PathBuf>,
+        /// Write a summary of the run, checked against the config's `gates`, to this file
+        #[arg(long, value_name = "FILE")]
+        result_file: Option<PathBuf>,
         /// Format for the stats file
         #[arg(short, long, value_name = "FORMAT", default_value_t)]
         stats_file_format: StatsFileFormat,
         /// Watch the config file for changes and update the test accordingly
         #[arg(short, long = "watch")]
         watch_config_file: bool,
+        /// Export a span for every request to the OTLP/HTTP collector at this endpoint
+        #[arg(long, value_name = "ENDPOINT")]
+        otlp: Option<String>,
+        /// Write every request's timing to this file as Chrome trace events
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Print the planned request rate over the course of the test without sending any requests
+        #[arg(long)]
+        plan: bool,
+        /// Print each endpoint's planned requests per second at every bucket through its load
+        /// pattern as a json array, without sending any requests
+        #[arg(long)]
+        print_load_curve: bool,
+        /// Print each endpoint's id, method, url and tags without sending any requests
+        #[arg(long)]
+        list_endpoints: bool,
+        /// Check the config and open every provider's file without sending any requests
+        #[arg(long)]
+        config_check_only: bool,
+        /// Seed `random` and `shuffle: once` file providers and `random` list and range providers
+        /// so every run uses the same order
+        #[arg(long, value_name = "SEED")]
+        seed: Option<u64>,
+        /// With json output, print a heartbeat line every bucket even when no requests complete
+        #[arg(long)]
+        heartbeat: bool,
+        /// Fail the run if any endpoint never sent a request
+        #[arg(long)]
+        require_all_endpoints: bool,
+        /// End the test once it has run for this long, however long its load pattern is
+        #[arg(value_parser = |s: &str| config::duration_from_string(s.into()), long, value_name = "DURATION")]
+        max_runtime: Option<Duration>,
+        /// Merge the named entry from the config's `environments` section over the base config
+        #[arg(long = "env", value_name = "NAME")]
+        environment: Option<String>,
+        /// Merge the vars in this yaml file, laid out like the `vars` section, over the config's
+        /// vars
+        #[arg(long, value_name = "FILE", value_parser = existing_file)]
+        vars_file: Option<PathBuf>,
+        /// Set a var, replacing any from the config or --vars-file with the same name. Can be
+        /// given more than once
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = var_from_str)]
+        vars: Vec<(String, String)>,
+        /// Number of worker threads to run the test on, 1 runs everything on a single thread
+        #[arg(long, value_name = "N")]
+        threads: Option<NonZeroUsize>,
+        /// Run the whole test this many times, one after another, with a summary of each run and an
+        /// aggregate summary of them all at the end
+        #[arg(long, value_name = "N", conflicts_with = "watch_config_file")]
+        repeat: Option<NonZeroUsize>,
+        /// Raise the limit on open files as far as the hard limit before the test starts
+        #[arg(long)]
+        raise_fd_limit: bool,
+        /// Write a progress bar to stderr with every bucket, showing how far through the test it
+        /// is and the current requests per second. Only with human output
+        #[arg(long)]
+        progress: bool,
+        /// Serve each bucket's stats as newline delimited json to every client connected to this
+        /// socket, given as host:port for TCP or otherwise as the path of a Unix socket
+        #[arg(long, value_name = "ADDR")]
+        stats_socket: Option<StatsSocketAddr>,
+        /// Serve live metrics at /metrics on this port, in the Prometheus text format, while the
+        /// test runs
+        #[arg(long, value_name = "PORT")]
+        metrics_port: Option<u16>,
+        /// Upload the stats file, logs and a manifest of them to S3 once the test ends, given as
+        /// s3://bucket/prefix. Credentials come from the AWS environment variables
+        #[arg(long, value_name = "S3_URL")]
+        results_s3: Option<S3Location>,
+        /// Start the test without asking, when the config has `general.confirm_before_run`
+        #[arg(short = 'y', long)]
+        yes: bool,
     }
 
     impl From<RunConfigTmp> for RunConfig {
         fn from(value: RunConfigTmp) -> Self {
             let config_file = &value.config_file;
+            let extension = value.stats_file_format.clone();
             let stats_file: PathBuf = value.stats_file.unwrap_or_else(|| {
                 let start_sec = UNIX_EPOCH
                     .elapsed()
@@ -105,8 +226,8 @@ mod args {
                 let test_name = config_file.file_stem().and_then(std::ffi::OsStr::to_str);
                 test_name
                     .map_or_else(
-                        || format!("stats-{start_sec}.json"),
-                        |test_name| format!("stats-{test_name}-{start_sec}.json"),
+                        || format!("stats-{start_sec}.{extension}"),
+                        |test_name| format!("stats-{test_name}-{start_sec}.{extension}"),
                     )
                     .into()
             });
@@ -117,14 +238,48 @@ mod args {
             } else {
                 stats_file
             };
+            let results_dir = &value.results_dir;
+            let result_file = value.result_file.map(|result_file| match results_dir {
+                Some(results_dir) => results_dir.join(result_file),
+                None => result_file,
+            });
+            let trace = value.trace.map(|trace| match results_dir {
+                Some(results_dir) => results_dir.join(trace),
+                None => trace,
+            });
             Self {
                 config_file: value.config_file,
+                merge_config_files: value.merge_config_files,
                 output_format: value.output_format,
+                json_style: value.json_style,
                 results_dir: value.results_dir,
                 start_at: value.start_at,
                 stats_file,
+                result_file,
                 stats_file_format: value.stats_file_format,
                 watch_config_file: value.watch_config_file,
+                otlp: value.otlp,
+                trace,
+                plan: value.plan,
+                print_load_curve: value.print_load_curve,
+                list_endpoints: value.list_endpoints,
+                config_check_only: value.config_check_only,
+                seed: value.seed,
+                heartbeat: value.heartbeat,
+                require_all_endpoints: value.require_all_endpoints,
+                max_runtime: value.max_runtime,
+                environment: value.environment,
+                vars_file: value.vars_file,
+                vars: value.vars,
+                threads: value.threads,
+                repeat: value.repeat,
+                raise_fd_limit: value.raise_fd_limit,
+                progress: value.progress,
+                stats_socket: value.stats_socket,
+                metrics_port: value.metrics_port,
+                results_s3: value.results_s3,
+                yes: value.yes,
+                interactive: io::stdin().is_terminal() && io::stderr().is_terminal(),
             }
         }
     }
@@ -134,6 +289,9 @@ mod args {
         /// Load test config file to use
         #[arg(value_name = "CONFIG")]
         config_file: PathBuf,
+        /// More config files deep merged over CONFIG, each over the ones before it
+        #[arg(value_name = "MERGE", value_parser = existing_file)]
+        merge_config_files: Vec<PathBuf>,
         /// Send results to the specified file instead of stdout
         #[arg(short = 'o', long)]
         file: Option<String>,
@@ -145,6 +303,10 @@ mod args {
         /// a wildcard. Any endpoint matching the filter is included in the test
         #[arg(short = 'i', long = "include", value_parser = TryFilter::from_str, value_name = "INCLUDE")]
         filters: Option<Vec<TryFilter>>,
+        /// Filter which endpoints are left out of the try run, in the same format as --include.
+        /// Exclusions are applied after the include filters
+        #[arg(long = "exclude", value_parser = TryFilter::from_str, value_name = "EXCLUDE")]
+        exclude: Option<Vec<TryFilter>>,
         /// Enable loggers defined in the config file
         #[arg(short = 'l', long = "loggers")]
         loggers_on: bool,
@@ -157,6 +319,24 @@ mod args {
         /// Skips request body from output
         #[arg(short = 'K', long = "skip-request-body")]
         skip_request_body_on: bool,
+        /// Print each endpoint's id, method, url and tags without sending any requests
+        #[arg(long)]
+        list_endpoints: bool,
+        /// Seed `random` and `shuffle: once` file providers and `random` list and range providers
+        /// so every run uses the same order
+        #[arg(long, value_name = "SEED")]
+        seed: Option<u64>,
+        /// Merge the named entry from the config's `environments` section over the base config
+        #[arg(long = "env", value_name = "NAME")]
+        environment: Option<String>,
+        /// Merge the vars in this yaml file, laid out like the `vars` section, over the config's
+        /// vars
+        #[arg(long, value_name = "FILE", value_parser = existing_file)]
+        vars_file: Option<PathBuf>,
+        /// Set a var, replacing any from the config or --vars-file with the same name. Can be
+        /// given more than once
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = var_from_str)]
+        vars: Vec<(String, String)>,
     }
 
     impl From<TryConfigTmp> for TryConfig {
@@ -171,13 +351,20 @@ mod args {
 
             Self {
                 config_file: value.config_file,
+                merge_config_files: value.merge_config_files,
                 loggers_on,
                 results_dir,
                 filters: value.filters,
+                exclude: value.exclude,
                 file: value.file,
                 format: value.format,
                 skip_response_body_on,
                 skip_request_body_on,
+                list_endpoints: value.list_endpoints,
+                seed: value.seed,
+                environment: value.environment,
+                vars_file: value.vars_file,
+                vars: value.vars,
             }
         }
     }
@@ -200,7 +387,13 @@ fn main() {
         let _ = ctrl_c_tx.unbounded_send(());
     });
 
-    let cli_config = args::get_cli_config();
+    let cli_config = match args::get_cli_config() {
+        args::Command::Exec(cli_config) => *cli_config,
+        args::Command::Schema => {
+            print!("{}", config::SCHEMA);
+            return;
+        }
+    };
     // For testing, we can only call the logger inits once. They can't be in get_cli_config so we can call it multiple times
     match cli_config {
         ExecConfig::Run(ref run_config) => {
@@ -227,15 +420,15 @@ fn main() {
         }
     }
 
+    let threads = match &cli_config {
+        ExecConfig::Run(run_config) => run_config.threads,
+        ExecConfig::Try(_) => None,
+    };
+
     // Create Future to run full load test or try test.
     let f = create_run(cli_config, ctrlc_channel, io::stdout(), io::stderr());
 
-    let rt = runtime::Builder::new_multi_thread()
-        .enable_time()
-        .enable_io()
-        .thread_name("pewpew-worker")
-        .build()
-        .unwrap();
+    let rt = create_runtime(threads);
     debug!("rt.block_on start");
     // Run Future to completion
     let result = rt.block_on(f);
@@ -249,11 +442,36 @@ fn main() {
     }
 }
 
+// Without a thread count tokio starts a worker per cpu core. A single thread runs the whole test
+// on the main thread, though blocking work like writing files still gets threads of its own.
+fn create_runtime(threads: Option<NonZeroUsize>) -> runtime::Runtime {
+    let mut builder = match threads.map(NonZeroUsize::get) {
+        Some(1) => runtime::Builder::new_current_thread(),
+        Some(threads) => {
+            let mut builder = runtime::Builder::new_multi_thread();
+            builder.worker_threads(threads);
+            builder
+        }
+        None => runtime::Builder::new_multi_thread(),
+    };
+    builder
+        .enable_time()
+        .enable_io()
+        .thread_name("pewpew-worker")
+        .build()
+        .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
-    use pewpew::{StatsFileFormat, TryFilter};
+    use pewpew::{JsonStyle, StatsFileFormat, TryFilter};
     use regex::Regex;
-    use std::time::Duration;
+    use std::{
+        collections::HashSet,
+        path::PathBuf,
+        sync::{Arc, Barrier},
+        time::Duration,
+    };
 
     use super::*;
 
@@ -270,6 +488,13 @@ mod tests {
         args::ArgsData::command().debug_assert();
     }
 
+    #[test]
+    fn cli_schema() {
+        let command = args::try_parse_command_from(["myprog", "schema"]).unwrap();
+        assert!(matches!(command, args::Command::Schema));
+        assert!(args::try_parse_command_from(["myprog", "schema", YAML_FILE]).is_err());
+    }
+
     #[test]
     fn cli_run_simple() {
         let stats_regex = Regex::new(r"^stats-integration-\d+\.json$").unwrap();
@@ -283,6 +508,7 @@ mod tests {
         assert!(run_config.results_dir.is_none());
         assert!(run_config.start_at.is_none());
         assert!(stats_regex.is_match(run_config.stats_file.to_str().unwrap()));
+        assert!(run_config.result_file.is_none());
         assert!(matches!(
             run_config.stats_file_format,
             StatsFileFormat::Json {}
@@ -340,6 +566,8 @@ mod tests {
             "json",
             "--stats-file",
             STATS_FILE,
+            "--result-file",
+            "result.json",
             "--stats-file-format",
             "json",
             "--start-at",
@@ -361,6 +589,10 @@ mod tests {
             run_config.stats_file.to_str().unwrap(),
             format!("{}{}", TEST_DIR, STATS_FILE)
         );
+        assert_eq!(
+            run_config.result_file.unwrap().to_str().unwrap(),
+            format!("{}result.json", TEST_DIR)
+        );
         assert!(matches!(
             run_config.stats_file_format,
             StatsFileFormat::Json {}
@@ -390,6 +622,485 @@ mod tests {
         assert!(run_config.output_format.is_human());
     }
 
+    #[test]
+    fn cli_run_json_style() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.json_style, JsonStyle::Ndjson);
+
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "-f",
+            "json",
+            "--json-style",
+            "array",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.json_style, JsonStyle::Array);
+
+        let r = args::try_parse_from(["myprog", RUN_COMMAND, "--json-style", "xml", YAML_FILE]);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn cli_run_otlp() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.otlp, None);
+
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "--otlp",
+            "http://localhost:4318",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.otlp.as_deref(), Some("http://localhost:4318"));
+    }
+
+    #[test]
+    fn cli_run_trace() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.trace, None);
+
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "-d",
+            TEST_DIR,
+            "--trace",
+            "trace.json",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(
+            run_config.trace.unwrap().to_str().unwrap(),
+            format!("{}trace.json", TEST_DIR)
+        );
+    }
+
+    #[test]
+    fn cli_run_threads() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.threads, None);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--threads", "2", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.threads, NonZeroUsize::new(2));
+
+        let r = args::try_parse_from(["myprog", RUN_COMMAND, "--threads", "0", YAML_FILE]);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn cli_run_repeat() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.repeat, None);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--repeat", "3", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.repeat, NonZeroUsize::new(3));
+
+        let r = args::try_parse_from(["myprog", RUN_COMMAND, "--repeat", "0", YAML_FILE]);
+        assert!(r.is_err());
+        let r = args::try_parse_from(["myprog", RUN_COMMAND, "--repeat", "2", "-w", YAML_FILE]);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn cli_merge_config_files() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.merge_config_files.is_empty());
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE, YAML_FILE2, "-f", "json"])
+                .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.config_file.to_str().unwrap(), YAML_FILE);
+        assert_eq!(run_config.merge_config_files, [PathBuf::from(YAML_FILE2)]);
+        assert!(matches!(run_config.output_format, RunOutputFormat::Json));
+
+        let cli_config =
+            args::try_parse_from(["myprog", TRY_COMMAND, YAML_FILE, YAML_FILE2, YAML_FILE])
+                .unwrap();
+        let ExecConfig::Try(try_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(try_config.config_file.to_str().unwrap(), YAML_FILE);
+        assert_eq!(
+            try_config.merge_config_files,
+            [PathBuf::from(YAML_FILE2), PathBuf::from(YAML_FILE)]
+        );
+
+        let r = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE, "./tests/missing.yaml"]);
+        assert!(r.is_err());
+    }
+
+    // the distinct threads tasks ran on. The first `threads` tasks each hold a worker until all
+    // of them are running, so there have to be at least that many workers
+    fn threads_used(threads: usize) -> usize {
+        let rt = create_runtime(NonZeroUsize::new(threads));
+        rt.block_on(async {
+            let barrier = Arc::new(Barrier::new(threads));
+            let tasks = (0..threads * 4).map(|i| {
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    if i < threads {
+                        barrier.wait();
+                    } else {
+                        std::thread::sleep(Duration::from_millis(5));
+                    }
+                    std::thread::current().id()
+                })
+            });
+            let ids: HashSet<_> = futures::future::join_all(tasks)
+                .await
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            ids.len()
+        })
+    }
+
+    #[test]
+    fn runtime_uses_configured_threads() {
+        assert_eq!(threads_used(1), 1);
+        assert_eq!(threads_used(3), 3);
+    }
+
+    #[test]
+    fn cli_run_plan() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(!run_config.plan);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--plan", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.plan);
+    }
+
+    #[test]
+    fn cli_run_print_load_curve() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(!run_config.print_load_curve);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--print-load-curve", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.print_load_curve);
+    }
+
+    #[test]
+    fn cli_run_config_check_only() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(!run_config.config_check_only);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--config-check-only", YAML_FILE])
+                .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.config_check_only);
+    }
+
+    #[test]
+    fn cli_list_endpoints() {
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--list-endpoints", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.list_endpoints);
+
+        let cli_config =
+            args::try_parse_from(["myprog", TRY_COMMAND, "--list-endpoints", YAML_FILE]).unwrap();
+        let ExecConfig::Try(try_config) = cli_config else {
+            panic!()
+        };
+        assert!(try_config.list_endpoints);
+    }
+
+    #[test]
+    fn cli_seed() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.seed, None);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--seed", "42", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.seed, Some(42));
+
+        let cli_config =
+            args::try_parse_from(["myprog", TRY_COMMAND, "--seed", "7", YAML_FILE]).unwrap();
+        let ExecConfig::Try(try_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(try_config.seed, Some(7));
+    }
+
+    #[test]
+    fn cli_heartbeat() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(!run_config.heartbeat);
+
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "-f",
+            "json",
+            "--heartbeat",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.heartbeat);
+    }
+
+    #[test]
+    fn cli_require_all_endpoints() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(!run_config.require_all_endpoints);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--require-all-endpoints", YAML_FILE])
+                .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.require_all_endpoints);
+    }
+
+    #[test]
+    fn cli_max_runtime() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.max_runtime, None);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--max-runtime", "5m", YAML_FILE])
+                .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.max_runtime, Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn cli_stats_socket() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.stats_socket.is_none());
+
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "--stats-socket",
+            "127.0.0.1:9000",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(matches!(
+            run_config.stats_socket,
+            Some(pewpew::StatsSocketAddr::Tcp(addr)) if addr.port() == 9000
+        ));
+
+        #[cfg(unix)]
+        {
+            let cli_config = args::try_parse_from([
+                "myprog",
+                RUN_COMMAND,
+                "--stats-socket",
+                "/tmp/pewpew.sock",
+                YAML_FILE,
+            ])
+            .unwrap();
+            let ExecConfig::Run(run_config) = cli_config else {
+                panic!()
+            };
+            assert!(matches!(
+                run_config.stats_socket,
+                Some(pewpew::StatsSocketAddr::Unix(path)) if path.to_str() == Some("/tmp/pewpew.sock")
+            ));
+        }
+    }
+
+    #[test]
+    fn cli_metrics_port() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(run_config.metrics_port.is_none());
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--metrics-port", "9090", YAML_FILE])
+                .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.metrics_port, Some(9090));
+
+        assert!(args::try_parse_from(
+            ["myprog", RUN_COMMAND, "--metrics-port", "http", YAML_FILE,]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn cli_stats_file_format_html() {
+        let stats_regex = Regex::new(r"^\./tests/stats-integration-\d+\.html$").unwrap();
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "-s",
+            "html",
+            "-d",
+            TEST_DIR,
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert!(matches!(
+            run_config.stats_file_format,
+            StatsFileFormat::Html
+        ));
+        assert!(stats_regex.is_match(run_config.stats_file.to_str().unwrap()));
+    }
+
+    #[test]
+    fn cli_env() {
+        let cli_config = args::try_parse_from(["myprog", RUN_COMMAND, YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.environment, None);
+
+        let cli_config =
+            args::try_parse_from(["myprog", RUN_COMMAND, "--env", "prod", YAML_FILE]).unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.environment.as_deref(), Some("prod"));
+
+        let cli_config =
+            args::try_parse_from(["myprog", TRY_COMMAND, "--env", "staging", YAML_FILE]).unwrap();
+        let ExecConfig::Try(try_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(try_config.environment.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn cli_vars() {
+        let cli_config = args::try_parse_from([
+            "myprog",
+            RUN_COMMAND,
+            "--vars-file",
+            YAML_FILE2,
+            "--var",
+            "host=localhost",
+            "--var",
+            "query=a=b",
+            YAML_FILE,
+        ])
+        .unwrap();
+        let ExecConfig::Run(run_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(run_config.vars_file, Some(PathBuf::from(YAML_FILE2)));
+        assert_eq!(
+            run_config.vars,
+            [
+                ("host".to_string(), "localhost".to_string()),
+                ("query".to_string(), "a=b".to_string())
+            ]
+        );
+        // the values of vars could be secrets
+        assert!(!run_config.to_string().contains("localhost"));
+
+        assert!(args::try_parse_from(["myprog", TRY_COMMAND, "--var", "host", YAML_FILE]).is_err());
+        assert!(args::try_parse_from([
+            "myprog",
+            TRY_COMMAND,
+            "--vars-file",
+            "./tests/missing.yaml",
+            YAML_FILE
+        ])
+        .is_err());
+    }
+
     #[test]
     fn cli_run_paths() {
         let cli_config = args::try_parse_from([
@@ -579,6 +1290,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cli_try_exclude() {
+        let cli_config = args::try_parse_from([
+            "myprog",
+            TRY_COMMAND,
+            "-i",
+            "method=POST",
+            "--exclude",
+            "tag=slow",
+            "--exclude",
+            "_id!=1",
+            YAML_FILE2,
+        ])
+        .unwrap();
+        let ExecConfig::Try(try_config) = cli_config else {
+            panic!()
+        };
+        assert_eq!(try_config.filters.unwrap().len(), 1);
+        let exclude = try_config.exclude.unwrap();
+        assert_eq!(exclude.len(), 2);
+        match &exclude[0] {
+            TryFilter::Eq(key, value) => {
+                assert_eq!(key, "tag");
+                assert_eq!(value, "slow");
+            }
+            _ => panic!(),
+        }
+        match &exclude[1] {
+            TryFilter::Ne(key, value) => {
+                assert_eq!(key, "_id");
+                assert_eq!(value, "1");
+            }
+            _ => panic!(),
+        }
+    }
+
     #[test]
     fn cli_try_include2() {
         let cli_config = args::try_parse_from([
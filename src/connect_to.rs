@@ -0,0 +1,71 @@
+use hyper::{service::Service, Uri};
+
+use std::{
+    net::SocketAddr,
+    task::{Context, Poll},
+};
+
+/// Wraps a connector so it dials `addr`, when there is one, rather than the address the
+/// destination's host resolves to. Only the connection is redirected, so requests keep the url's
+/// host for their `Host` header and a TLS connector wrapped around this one still uses it for SNI
+/// and checking the certificate.
+#[derive(Clone)]
+pub(crate) struct ConnectTo<C> {
+    pub(crate) inner: C,
+    pub(crate) addr: Option<SocketAddr>,
+}
+
+impl<C: Service<Uri>> Service<Uri> for ConnectTo<C> {
+    type Response = C::Response;
+    type Error = C::Error;
+    type Future = C::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let uri = match self.addr {
+            // the scheme is kept so the inner connector still knows the default port and whether
+            // it is allowed to connect
+            Some(addr) => Uri::builder()
+                .scheme(uri.scheme_str().unwrap_or("http"))
+                .authority(addr.to_string())
+                .path_and_query("/")
+                .build()
+                .unwrap_or(uri),
+            None => uri,
+        };
+        self.inner.call(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_http_client, ClientSettings};
+    use futures::StreamExt;
+    use hyper::{header::HOST, Body, Request};
+
+    #[tokio::test]
+    async fn connects_to_override_address() {
+        let (port, mut captured, _kill) = test_common::start_capture_server();
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        let client = create_http_client(ClientSettings {
+            connect_to: Some(addr),
+            ..Default::default()
+        })
+        .unwrap();
+        // nothing listens on this address, so the request only succeeds if it went to `addr`
+        let request = Request::get("http://app.example.invalid:1/users")
+            .header(HOST, "app.example.invalid")
+            .body(Body::empty())
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        assert!(response.status().is_success());
+
+        let request = captured.next().await.unwrap();
+        assert_eq!(request.path, "/users");
+        assert_eq!(request.headers[HOST], "app.example.invalid");
+    }
+}
@@ -0,0 +1,389 @@
+use crate::stats::Timing;
+
+use hyper::{
+    client::connect::{dns::Name, Connected, Connection},
+    service::Service,
+    Response, Uri,
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use std::{
+    cell::Cell,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+/// Attached to every connection the client opens, and through hyper to every response received
+/// on it, so a response can tell whether its request was the first one sent on the connection.
+#[derive(Clone)]
+struct Used(Arc<AtomicBool>, Opened);
+
+/// Attached by a `TrackOpen` connector wrapped inside the TLS one, so the time spent on the TCP
+/// connection can be told apart from the TLS handshake. Along with it is the time spent looking up
+/// the host, when it wasn't an ip address.
+#[derive(Clone, Copy)]
+struct TcpOpened(Opened, Option<Opened>);
+
+thread_local! {
+    // set by a `TimedResolver` as its lookup finishes, to be taken by the `TrackOpen` connection
+    // being opened, which is what polls the lookup
+    static RESOLVED: Cell<Option<Opened>> = const { Cell::new(None) };
+}
+
+/// When a connection started being opened and when it was ready for a request to be sent on it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Opened {
+    pub(crate) start: SystemTime,
+    pub(crate) end: SystemTime,
+}
+
+/// Whether the request for `response` was sent on a connection an earlier request already used.
+/// `None` when the response didn't come through a `TrackReuse` connector.
+pub(crate) fn was_reused<B>(response: &Response<B>) -> Option<bool> {
+    response
+        .extensions()
+        .get::<Used>()
+        .map(|used| used.0.swap(true, Ordering::Relaxed))
+}
+
+/// How the connection for `response` was opened, as the TCP connection and then the connection
+/// ready to use (for https that includes the TLS handshake). `None` when the connection was
+/// already used by an earlier request, so this has to be checked before `was_reused`.
+pub(crate) fn opened<B>(response: &Response<B>) -> Option<(Opened, Opened)> {
+    let extensions = response.extensions();
+    let used = extensions.get::<Used>()?;
+    if used.0.load(Ordering::Relaxed) {
+        return None;
+    }
+    let tcp = extensions.get::<TcpOpened>().map_or(used.1, |tcp| tcp.0);
+    Some((tcp, used.1))
+}
+
+/// Where the time went between `start`, when the request was sent, and now, when its response's
+/// headers arrived. Has to be called before `was_reused`. `None` when the response didn't come
+/// through a `TrackReuse` connector.
+pub(crate) fn timing<B>(response: &Response<B>, start: SystemTime) -> Option<Timing> {
+    let now = SystemTime::now();
+    let micros = |from: SystemTime, to: SystemTime| {
+        to.duration_since(from).unwrap_or_default().as_micros() as u64
+    };
+    let extensions = response.extensions();
+    extensions.get::<Used>()?;
+    // a connection opened for an earlier request which then went back in the pool doesn't
+    // count against this one
+    let timing = match opened(response).filter(|(tcp, _)| tcp.start >= start) {
+        Some((tcp, ready)) => {
+            let dns = extensions
+                .get::<TcpOpened>()
+                .and_then(|tcp| tcp.1)
+                .map_or(0, |dns| micros(dns.start, dns.end));
+            Timing {
+                dns,
+                connect: micros(tcp.start, tcp.end).saturating_sub(dns),
+                tls: micros(tcp.end, ready.end),
+                ttfb: micros(ready.end, now),
+            }
+        }
+        None => Timing {
+            ttfb: micros(start, now),
+            ..Default::default()
+        },
+    };
+    Some(timing)
+}
+
+/// Wraps the resolver of the connector inside a `TrackOpen` one, so the time spent looking up the
+/// host can be told apart from the TCP connection
+#[derive(Clone)]
+pub(crate) struct TimedResolver<R>(pub(crate) R);
+
+impl<R> Service<Name> for TimedResolver<R>
+where
+    R: Service<Name>,
+    R::Future: Unpin,
+{
+    type Response = R::Response;
+    type Error = R::Error;
+    type Future = Resolving<R::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        Resolving {
+            future: self.0.call(name),
+            start: SystemTime::now(),
+        }
+    }
+}
+
+/// A host being looked up, which remembers when it started
+pub(crate) struct Resolving<F> {
+    future: F,
+    start: SystemTime,
+}
+
+impl<F: Future + Unpin> Future for Resolving<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let resolved = Pin::new(&mut self.future).poll(cx);
+        if resolved.is_ready() {
+            let opened = Opened {
+                start: self.start,
+                end: SystemTime::now(),
+            };
+            RESOLVED.with(|r| r.set(Some(opened)));
+        }
+        resolved
+    }
+}
+
+/// Wraps a connector so the connections it makes can be told apart in responses
+#[derive(Clone)]
+pub(crate) struct TrackReuse<C>(pub(crate) C);
+
+/// Wraps a connector so responses know how long its connections took to open
+#[derive(Clone)]
+pub(crate) struct TrackOpen<C>(pub(crate) C);
+
+impl<C> Service<Uri> for TrackReuse<C>
+where
+    C: Service<Uri>,
+    C::Future: Unpin,
+{
+    type Response = Tracked<C::Response>;
+    type Error = C::Error;
+    type Future = Connecting<C::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Connecting::new(self.0.call(uri), false)
+    }
+}
+
+impl<C> Service<Uri> for TrackOpen<C>
+where
+    C: Service<Uri>,
+    C::Future: Unpin,
+{
+    type Response = Tracked<C::Response>;
+    type Error = C::Error;
+    type Future = Connecting<C::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Connecting::new(self.0.call(uri), true)
+    }
+}
+
+/// A connection being opened, which remembers when it started
+pub(crate) struct Connecting<F> {
+    future: F,
+    start: SystemTime,
+    tcp: bool,
+    // when the host was looked up, for a TCP connection to a host which isn't an ip address
+    resolved: Option<Opened>,
+}
+
+impl<F> Connecting<F> {
+    fn new(future: F, tcp: bool) -> Self {
+        Connecting {
+            future,
+            start: SystemTime::now(),
+            tcp,
+            resolved: None,
+        }
+    }
+}
+
+impl<F, T, E> Future for Connecting<F>
+where
+    F: Future<Output = Result<T, E>> + Unpin,
+{
+    type Output = Result<Tracked<T>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // only a lookup finishing within this poll is for this connection
+        if self.tcp {
+            RESOLVED.with(|r| r.set(None));
+        }
+        let polled = Pin::new(&mut self.future).poll(cx);
+        if self.tcp {
+            if let Some(resolved) = RESOLVED.with(Cell::take) {
+                self.resolved = Some(resolved);
+            }
+        }
+        let connection = match polled {
+            Poll::Ready(Ok(connection)) => connection,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let opened = Opened {
+            start: self.start,
+            end: SystemTime::now(),
+        };
+        let extra = if self.tcp {
+            Extra::Tcp(TcpOpened(opened, self.resolved))
+        } else {
+            Extra::Used(Used(Default::default(), opened))
+        };
+        Poll::Ready(Ok(Tracked(connection, extra)))
+    }
+}
+
+enum Extra {
+    Used(Used),
+    Tcp(TcpOpened),
+}
+
+pub(crate) struct Tracked<T>(T, Extra);
+
+impl<T: Connection> Connection for Tracked<T> {
+    fn connected(&self) -> Connected {
+        let connected = self.0.connected();
+        match &self.1 {
+            Extra::Used(used) => connected.extra(used.clone()),
+            Extra::Tcp(tcp) => connected.extra(*tcp),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Tracked<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Tracked<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_http_client, ClientSettings};
+    use hyper::Version;
+    use std::time::Duration;
+
+    async fn reused(reuse_connection: bool) -> Vec<bool> {
+        let (port, _captured, _kill) = test_common::start_capture_server();
+        let client = create_http_client(ClientSettings {
+            reuse_connection,
+            ..Default::default()
+        })
+        .unwrap();
+        let uri: Uri = format!("http://127.0.0.1:{}/", port).parse().unwrap();
+        let mut reused = Vec::new();
+        for _ in 0..5 {
+            let response = client.get(uri.clone()).await.unwrap();
+            reused.push(was_reused(&response).unwrap());
+            hyper::body::to_bytes(response.into_body()).await.unwrap();
+            // give the connection a moment to go back in the pool
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        reused
+    }
+
+    #[tokio::test]
+    async fn reports_reused_connections() {
+        let with_keepalive = reused(true).await;
+        assert!(!with_keepalive[0], "{:?}", with_keepalive);
+        let after_warmup = with_keepalive[1..].iter().filter(|r| **r).count();
+        assert!(after_warmup >= 3, "{:?}", with_keepalive);
+
+        let without = reused(false).await;
+        assert!(without.iter().all(|r| !r), "{:?}", without);
+    }
+
+    #[tokio::test]
+    async fn timing_adds_up_to_the_response_time() {
+        let (port, _captured, _kill) = test_common::start_capture_server();
+        let client = create_http_client(ClientSettings::default()).unwrap();
+        // a host name rather than an ip address, so it has to be looked up
+        let uri: Uri = format!("http://localhost:{}/", port).parse().unwrap();
+
+        let start = SystemTime::now();
+        let response = client.get(uri.clone()).await.unwrap();
+        let fresh = timing(&response, start).unwrap();
+        let elapsed = start.elapsed().unwrap().as_micros() as u64;
+        assert_eq!(was_reused(&response), Some(false));
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(fresh.dns > 0, "{:?}", fresh);
+        assert!(fresh.connect > 0, "{:?}", fresh);
+        // no tls over plain http
+        assert!(fresh.tls < 1_000, "{:?}", fresh);
+        assert!(fresh.ttfb > 0, "{:?}", fresh);
+        let sum = fresh.dns + fresh.connect + fresh.tls + fresh.ttfb;
+        assert!(sum <= elapsed, "{:?} {}", fresh, elapsed);
+        assert!(elapsed - sum < 20_000, "{:?} {}", fresh, elapsed);
+
+        // give the connection a moment to go back in the pool
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let start = SystemTime::now();
+        let response = client.get(uri).await.unwrap();
+        let reused = timing(&response, start).unwrap();
+        assert_eq!(was_reused(&response), Some(true));
+        assert_eq!((reused.dns, reused.connect, reused.tls), (0, 0, 0));
+        assert!(reused.ttfb > 0, "{:?}", reused);
+    }
+
+    #[tokio::test]
+    async fn http2_is_used_with_prior_knowledge() {
+        let (port, _captured, _kill) = test_common::start_capture_server();
+        let uri: Uri = format!("http://127.0.0.1:{}/", port).parse().unwrap();
+        for (http2, version) in [(false, Version::HTTP_11), (true, Version::HTTP_2)] {
+            let client = create_http_client(ClientSettings {
+                http2,
+                ..Default::default()
+            })
+            .unwrap();
+            let response = client.get(uri.clone()).await.unwrap();
+            assert_eq!(response.version(), version, "http2: {}", http2);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use futures::{
+    channel::mpsc::{self as futures_channel, Receiver, Sender},
+    Stream, StreamExt,
+};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    Rng,
+};
+
+use std::{future::Future, num::NonZeroUsize, time::Instant};
+
+type Tick = (Instant, Option<Instant>);
+
+/// Fans a single schedule out to the endpoints of an `endpoint_group`. Each tick from the
+/// group's load pattern is handed to exactly one member, chosen at random according to the
+/// member's weight. A member still busy with its last tick misses the ones picked for it in the
+/// meantime, the same as an endpoint with its own load pattern.
+pub(crate) struct EndpointGroup {
+    senders: Vec<Sender<Tick>>,
+    weights: Vec<usize>,
+}
+
+impl EndpointGroup {
+    pub(crate) fn new() -> Self {
+        EndpointGroup {
+            senders: Vec::new(),
+            weights: Vec::new(),
+        }
+    }
+
+    /// Adds a member to the group, returning the stream which drives that member
+    pub(crate) fn add_member(&mut self, weight: NonZeroUsize) -> Receiver<Tick> {
+        // the one sender gets the one slot, so at most a single tick waits for the member
+        let (tx, rx) = futures_channel::channel(0);
+        self.senders.push(tx);
+        self.weights.push(weight.get());
+        rx
+    }
+
+    /// Forwards every tick from `schedule` to one of the members. Completes when the schedule
+    /// ends or when every member has stopped listening.
+    pub(crate) fn dispatch<S, R>(self, mut schedule: S, mut rng: R) -> impl Future<Output = ()>
+    where
+        S: Stream<Item = Tick> + Unpin,
+        R: Rng,
+    {
+        let EndpointGroup {
+            mut senders,
+            weights,
+        } = self;
+        async move {
+            let mut dist = match WeightedIndex::new(&weights) {
+                Ok(d) => d,
+                // a group with no members
+                Err(_) => return,
+            };
+            while let Some(tick) = schedule.next().await {
+                let i = dist.sample(&mut rng);
+                // a full channel means the endpoint hasn't taken its last tick yet, so this one is
+                // skipped. A closed one means the endpoint has finished, so stop picking it
+                if let Err(e) = senders[i].try_send(tick) {
+                    if e.is_disconnected() && dist.update_weights(&[(i, &0)]).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The rng a group picks its members with. With a `--seed` each group gets its own seed, derived
+/// from the group's name, so a seeded test sends the same mix of requests every time.
+pub(crate) fn rng(seed: Option<u64>, group: &str) -> StdRng {
+    config::seeded_rng(crate::util::named_seed(seed, group))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    // ticks which each give the members a chance to take the last one before they're sent. `join!`
+    // takes turns at which future it polls first, so that takes two yields
+    fn schedule(start: Instant, n: u64) -> impl Stream<Item = Tick> + Unpin {
+        Box::pin(stream::iter(0..n).then(move |i| async move {
+            tokio::task::yield_now().await;
+            tokio::task::yield_now().await;
+            (start + Duration::from_millis(i), None)
+        }))
+    }
+
+    #[tokio::test]
+    async fn endpoint_mix_matches_weights() {
+        let mut group = EndpointGroup::new();
+        let reads = group.add_member(NonZeroUsize::new(7).unwrap());
+        let writes = group.add_member(NonZeroUsize::new(3).unwrap());
+
+        let (_, reads, writes) = tokio::join!(
+            group.dispatch(schedule(Instant::now(), 10_000), StdRng::seed_from_u64(42)),
+            reads.count(),
+            writes.count()
+        );
+
+        assert_eq!(reads + writes, 10_000);
+        assert!((6_800..=7_200).contains(&reads), "reads: {}", reads);
+        assert!((2_800..=3_200).contains(&writes), "writes: {}", writes);
+    }
+
+    #[tokio::test]
+    async fn finished_members_are_skipped() {
+        let mut group = EndpointGroup::new();
+        let first = group.add_member(NonZeroUsize::new(1).unwrap());
+        drop(group.add_member(NonZeroUsize::new(1).unwrap()));
+
+        let (_, count) = tokio::join!(
+            group.dispatch(schedule(Instant::now(), 100), StdRng::seed_from_u64(7)),
+            first.count()
+        );
+
+        // at most one tick is lost discovering the dropped member
+        assert!(count >= 99, "count: {}", count);
+    }
+
+    #[tokio::test]
+    async fn busy_members_skip_ticks() {
+        let mut group = EndpointGroup::new();
+        let busy = group.add_member(NonZeroUsize::new(1).unwrap());
+        let idle = group.add_member(NonZeroUsize::new(1).unwrap());
+
+        // `busy` doesn't take any ticks until the schedule has ended
+        let (_, idle) = tokio::join!(
+            group.dispatch(schedule(Instant::now(), 1_000), StdRng::seed_from_u64(3)),
+            idle.count()
+        );
+        let busy = busy.count().await;
+
+        assert_eq!(busy, 1);
+        assert!((400..=600).contains(&idle), "idle: {}", idle);
+    }
+
+    #[tokio::test]
+    async fn seeded_groups_pick_the_same_members() {
+        let picks = |seed, name| async move {
+            let mut group = EndpointGroup::new();
+            let first = group.add_member(NonZeroUsize::new(1).unwrap());
+            let second = group.add_member(NonZeroUsize::new(1).unwrap());
+            let start = Instant::now();
+            let (_, first, _) = tokio::join!(
+                group.dispatch(schedule(start, 200), rng(seed, name)),
+                first.map(|(tick, _)| tick).collect::<Vec<_>>(),
+                second.count()
+            );
+            // the ticks are a millisecond apart, so their offsets give the picks
+            first
+                .iter()
+                .map(|tick| tick.duration_since(start))
+                .collect::<Vec<_>>()
+        };
+
+        let run = picks(Some(42), "group").await;
+        assert_eq!(run, picks(Some(42), "group").await);
+        assert_ne!(run, picks(Some(42), "other").await);
+        assert_ne!(run, picks(Some(43), "group").await);
+    }
+}
@@ -5,6 +5,8 @@ use std::{error::Error as StdError, fmt, path::PathBuf, sync::Arc, time::SystemT
 // An error that can happen in normal execution of an endpoint, but should not halt the test
 #[derive(Clone, Debug)]
 pub enum RecoverableError {
+    // the message of an endpoint's assertion which a response didn't pass
+    AssertionFailed(String),
     ProviderDelay(String),
     BodyErr(Arc<dyn StdError + Send + Sync>),
     ConnectionErr(SystemTime, Arc<dyn StdError + Send + Sync>),
@@ -22,13 +24,47 @@ impl RecoverableError {
             ExecutingExpression(..) => 3,
             Timeout(_) => 4,
             ProviderDelay(_) => 5,
+            AssertionFailed(_) => 6,
         }
     }
+
+    /// The kind of failure, counted separately in the stats: `connect`, `tls`, `timeout`, `body`,
+    /// `assertion` or `other`
+    pub fn category(&self) -> &'static str {
+        match self {
+            AssertionFailed(_) => "assertion",
+            BodyErr(_) => "body",
+            ConnectionErr(_, e) if is_tls_error(e.as_ref()) => "tls",
+            ConnectionErr(..) => "connect",
+            Timeout(_) => "timeout",
+            ExecutingExpression(_) | ProviderDelay(_) => "other",
+        }
+    }
+}
+
+// a failed handshake comes back from the connector wrapped in an io error, which doesn't give the
+// error it wraps as its `source`
+fn is_tls_error(e: &(dyn StdError + 'static)) -> bool {
+    let mut next = Some(e);
+    while let Some(e) = next {
+        if e.is::<native_tls::Error>() {
+            return true;
+        }
+        let wrapped = e.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref());
+        if let Some(wrapped) = wrapped {
+            if is_tls_error(wrapped) {
+                return true;
+            }
+        }
+        next = e.source();
+    }
+    false
 }
 
 impl fmt::Display for RecoverableError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            AssertionFailed(m) => write!(f, "assertion failed: {m}"),
             BodyErr(e) => write!(f, "body error: {e}"),
             ConnectionErr(_, e) => write!(f, "connection error: `{e}`"),
             ExecutingExpression(e) => e.fmt(f),
@@ -41,15 +77,33 @@ impl fmt::Display for RecoverableError {
 // The types of errors that we may encounter during a test
 #[derive(Clone, Debug)]
 pub enum TestError {
+    // the `host:port` of `general.statsd`
+    CannotConnectToStatsd(String, Arc<std::io::Error>),
     CannotCreateLoggerFile(String, Arc<std::io::Error>),
+    // the `--metrics-port` which couldn't be served on
+    CannotCreateMetricsServer(u16, Arc<std::io::Error>),
     CannotCreateStatsFile(String, Arc<std::io::Error>),
+    CannotCreateStatsSocket(String, Arc<std::io::Error>),
+    CannotCreateTraceFile(String, Arc<std::io::Error>),
     CannotOpenFile(PathBuf, Arc<std::io::Error>),
     Config(Box<config::Error>),
     FileReading(String, Arc<std::io::Error>),
+    // the url an http provider couldn't fetch its data from, and why
+    HttpProviderFetch(String, String),
+    // the endpoints which never sent a request, with `--require-all-endpoints`
+    IdleEndpoints(Vec<String>),
     InvalidConfigFilePath(PathBuf),
     InvalidUrl(String),
+    // a failure which doesn't need a variant of its own, described by the message
+    Other(String),
+    ProviderMap(String, Box<config::ExecutingExpressionError>),
     Recoverable(RecoverableError),
     RequestBuilderErr(Arc<HttpError>),
+    // the combined `peak_load` of a run which needed confirming and wasn't confirmed, with whether
+    // it was asked for
+    RunNotConfirmed(f64, bool),
+    // how each endpoint which broke its `sla` broke it
+    SlaBreached(Vec<String>),
     SslError(Arc<native_tls::Error>),
     WritingToFile(String, Arc<std::io::Error>),
 }
@@ -66,16 +120,50 @@ impl fmt::Display for TestError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CannotCreateLoggerFile(s, e) => write!(f, "error creating logger file `{s}`: {e}"),
+            CannotConnectToStatsd(s, e) => write!(f, "error connecting to statsd at `{s}`: {e}"),
+            CannotCreateMetricsServer(p, e) => write!(f, "error serving metrics on port {p}: {e}"),
             CannotCreateStatsFile(s, e) => write!(f, "error creating stats file `{s}`: {e}"),
+            CannotCreateStatsSocket(s, e) => write!(f, "error creating stats socket `{s}`: {e}"),
+            CannotCreateTraceFile(s, e) => write!(f, "error creating trace file `{s}`: {e}"),
             CannotOpenFile(p, e) => write!(f, "error opening file `{}`: {}", p.display(), e),
             Config(e) => e.fmt(f),
             FileReading(s, e) => write!(f, "error reading file `{s}`: {e}"),
+            HttpProviderFetch(u, e) => write!(f, "error fetching provider data from `{u}`: {e}"),
+            IdleEndpoints(endpoints) => write!(
+                f,
+                "endpoints never sent a request: {}",
+                endpoints
+                    .iter()
+                    .map(|e| format!("`{e}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             InvalidConfigFilePath(p) => {
                 write!(f, "could not find config file at path `{}`", p.display())
             }
             InvalidUrl(u) => write!(f, "invalid url `{u}`"),
+            Other(e) => e.fmt(f),
+            ProviderMap(p, e) => write!(f, "error in the `map` of provider `{p}`: {e}"),
             Recoverable(r) => write!(f, "recoverable error: {r}"),
             RequestBuilderErr(e) => write!(f, "error creating request: {e}"),
+            RunNotConfirmed(peak_load, true) => write!(
+                f,
+                "the test was not started, its combined peak_load of {peak_load:.2} hps was not confirmed"
+            ),
+            RunNotConfirmed(peak_load, false) => write!(
+                f,
+                "the test has a combined peak_load of {peak_load:.2} hps and needs confirming before it \
+                 starts, which can't be asked for without a terminal. Pass `--yes` to start it"
+            ),
+            SlaBreached(breaches) => write!(
+                f,
+                "endpoints broke their SLA: {}",
+                breaches
+                    .iter()
+                    .map(|b| format!("`{b}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             SslError(e) => write!(f, "error creating ssl connector: {e}"),
             WritingToFile(l, e) => write!(f, "error writing to file `{l}`: {e}"),
         }
@@ -86,7 +174,11 @@ impl StdError for TestError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match self {
             CannotCreateLoggerFile(_, e) => Some(&**e),
+            CannotConnectToStatsd(_, e) => Some(&**e),
+            CannotCreateMetricsServer(_, e) => Some(&**e),
             CannotCreateStatsFile(_, e) => Some(&**e),
+            CannotCreateStatsSocket(_, e) => Some(&**e),
+            CannotCreateTraceFile(_, e) => Some(&**e),
             CannotOpenFile(_, e) => Some(&**e),
             Config(e) => Some(e),
             FileReading(_, e) => Some(&**e),
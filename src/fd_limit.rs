@@ -0,0 +1,181 @@
+use config::{HitsPer, LoadTest, PeakLoad, Provider};
+
+// stdin, stdout and stderr, the stats file and what the runtime itself keeps open
+const BASE_FDS: u64 = 16;
+
+/// The process's limits on how many file descriptors it can have open.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FdLimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+// `rlim_t` isn't a `u64` on every unix
+#[allow(clippy::unnecessary_cast)]
+#[cfg(unix)]
+pub fn current() -> Option<FdLimit> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `getrlimit` only writes to the `rlimit` it is given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    Some(FdLimit {
+        soft: limit.rlim_cur as u64,
+        hard: limit.rlim_max as u64,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn current() -> Option<FdLimit> {
+    None
+}
+
+/// Raises the soft limit as far as the hard limit, returning the limits afterwards.
+#[cfg(unix)]
+pub fn raise() -> Option<FdLimit> {
+    let limit = current()?;
+    if limit.soft < limit.hard {
+        let raised = libc::rlimit {
+            rlim_cur: limit.hard as libc::rlim_t,
+            rlim_max: limit.hard as libc::rlim_t,
+        };
+        // SAFETY: `setrlimit` only reads the `rlimit` it is given. If it fails (macOS, for one,
+        // won't take an unlimited soft limit) the limit is left as it was
+        unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) };
+    }
+    current()
+}
+
+#[cfg(not(unix))]
+pub fn raise() -> Option<FdLimit> {
+    None
+}
+
+/// A rough count of the file descriptors a test could have open at once: a connection for every
+/// request its endpoints can have in flight, plus its provider and logger files. An endpoint
+/// without `max_parallel_requests` is counted as having a second's worth of its peak load in
+/// flight.
+pub fn needed(config: &LoadTest) -> u64 {
+    let per_second = |hits: &HitsPer| match hits {
+        HitsPer::Second(n) => n.ceil() as u64,
+        HitsPer::Minute(n) => (n / 60.0).ceil() as u64,
+    };
+    let endpoints: u64 = config
+        .endpoints
+        .iter()
+        .map(|e| match (e.max_parallel_requests, &e.peak_load) {
+            (Some(n), _) => n.get() as u64,
+            (None, Some(PeakLoad::Fixed(hits))) => per_second(hits),
+            _ => 1,
+        })
+        .sum();
+    let groups: u64 = config
+        .endpoint_groups
+        .values()
+        .map(|g| per_second(&g.peak_load))
+        .sum();
+    let providers = config.providers.values().filter(|p| opens_file(p)).count() as u64;
+    let loggers = config
+        .loggers
+        .values()
+        .flat_map(|l| &l.to)
+        .filter(|to| *to != "stdout" && *to != "stderr")
+        .count() as u64;
+    BASE_FDS + endpoints + groups + providers + loggers
+}
+
+fn opens_file(provider: &Provider) -> bool {
+    match provider {
+        Provider::File(_) | Provider::Sqlite(_) => true,
+        Provider::Mapped(p, _) | Provider::Paced(p, _) => opens_file(p),
+        Provider::Http(_) | Provider::List(_) | Provider::Range(_) | Provider::Response(_) => false,
+    }
+}
+
+/// The warning for a test which could need about as many file descriptors as it's allowed, or
+/// more. As `needed` is only an estimate it warns once that is within 10% of the soft limit.
+pub fn warning(needed: u64, limit: FdLimit) -> Option<String> {
+    if needed.saturating_mul(10) < limit.soft.saturating_mul(9) {
+        return None;
+    }
+    let raise = if limit.soft < limit.hard {
+        "raise it with `--raise-fd-limit` or `ulimit -n`"
+    } else {
+        "raise it with `ulimit -n`"
+    };
+    Some(format!(
+        "the test could have around {needed} connections and files open at once, close to the \
+        limit of {} open files; {raise} or lower the endpoints' max_parallel_requests",
+        limit.soft
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn needed_for(config: &str) -> u64 {
+        let config =
+            LoadTest::from_config(config.as_bytes(), Path::new("./"), &Default::default()).unwrap();
+        needed(&config)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_the_limit() {
+        let limit = current().unwrap();
+        assert!(limit.soft > 0);
+        assert!(limit.soft <= limit.hard);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn warns_near_the_limit() {
+        let needed = needed_for(
+            r#"
+            providers:
+              ids:
+                file:
+                  path: ids.txt
+            loggers:
+              errors:
+                to: errors.log
+              out:
+                to: stdout
+            endpoints:
+              - url: http://localhost/${ids}
+                max_parallel_requests: 1000
+              - url: http://localhost/
+                peak_load: 30hps
+              - url: http://localhost/
+                peak_load: 90hpm
+            "#,
+        );
+        assert_eq!(needed, BASE_FDS + 1000 + 30 + 2 + 1 + 1);
+
+        let limit = FdLimit {
+            soft: 1024,
+            hard: 4096,
+        };
+        let msg = warning(needed, limit).unwrap();
+        assert!(msg.contains("limit of 1024"), "{}", msg);
+        assert!(msg.contains("--raise-fd-limit"), "{}", msg);
+
+        let at_hard_limit = FdLimit {
+            soft: 1024,
+            hard: 1024,
+        };
+        let msg = warning(needed, at_hard_limit).unwrap();
+        assert!(!msg.contains("--raise-fd-limit"), "{}", msg);
+
+        let limit = FdLimit {
+            soft: 4096,
+            hard: 4096,
+        };
+        assert_eq!(warning(needed, limit), None);
+    }
+}
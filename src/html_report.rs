@@ -0,0 +1,264 @@
+use crate::stats::Tags;
+
+use std::{collections::BTreeMap, fmt::Write};
+
+// the size of each chart, and the room around its plot for the axis labels
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 240.0;
+const MARGIN_LEFT: f64 = 64.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 16.0;
+const MARGIN_BOTTOM: f64 = 32.0;
+
+// the colors of the lines in a chart, in turn
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+h2{margin-top:2em}\
+table{border-collapse:collapse}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+svg{display:block;margin-top:1em}\
+.axis{stroke:#888}.grid{stroke:#eee}\
+.legend{list-style:none;padding:0}.legend li{display:inline-block;margin-right:1.5em}\
+.swatch{display:inline-block;width:1em;height:.6em;margin-right:.4em}";
+
+/// The requests of one endpoint's bucket group over some stretch of time
+pub(crate) struct ReportPoint {
+    pub requests: u64,
+    pub errors: u64,
+    // in milliseconds, one for each of the report's percentiles
+    pub percentiles: Vec<f64>,
+}
+
+/// The `--stats-file-format html` report, built up a bucket at a time and written in place of the
+/// json stats once the test ends. Its charts are inline SVG, so it can be opened without a network.
+pub(crate) struct HtmlReport {
+    test: String,
+    bucket_size: u64,
+    percentiles: Vec<f64>,
+    // the start of every bucket, in seconds since the unix epoch
+    times: Vec<u64>,
+    // by the index of the bucket group, its tags and its stats for each bucket it had any in
+    series: BTreeMap<usize, (Tags, BTreeMap<u64, ReportPoint>)>,
+}
+
+impl HtmlReport {
+    pub(crate) fn new(test: String, bucket_size: u64, percentiles: Vec<f64>) -> Self {
+        HtmlReport {
+            test,
+            bucket_size,
+            percentiles,
+            times: Vec::new(),
+            series: BTreeMap::new(),
+        }
+    }
+
+    /// Add a bucket, starting at `time`, with the stats of each bucket group which had any in it
+    pub(crate) fn add_bucket(&mut self, time: u64, points: Vec<(usize, &Tags, ReportPoint)>) {
+        self.times.push(time);
+        for (index, tags, point) in points {
+            self.series
+                .entry(index)
+                .or_insert_with(|| (tags.clone(), BTreeMap::new()))
+                .1
+                .insert(time, point);
+        }
+    }
+
+    /// The whole report, with the stats of each bucket group over the entire test in `totals`
+    pub(crate) fn render(&self, duration: u64, totals: &BTreeMap<usize, ReportPoint>) -> String {
+        let mut html = String::new();
+        let title = escape(&format!("pewpew results for {}", self.test));
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+        );
+        let _ = writeln!(
+            html,
+            "<p>{} endpoints over {} buckets of {}s</p>",
+            self.series.len(),
+            self.times.len(),
+            self.bucket_size
+        );
+
+        html.push_str("<h2>Summary</h2>\n<table>\n<tr><th>endpoint</th><th>requests</th>");
+        html.push_str("<th>errors</th><th>rps</th>");
+        for p in &self.percentiles {
+            let _ = write!(html, "<th>p{p} (ms)</th>");
+        }
+        html.push_str("</tr>\n");
+        for (index, point) in totals {
+            let label = self
+                .series
+                .get(index)
+                .map(|(tags, _)| label(tags))
+                .unwrap_or_default();
+            let rps = point.requests as f64 / duration.max(1) as f64;
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.2}</td>",
+                escape(&label),
+                point.requests,
+                point.errors,
+                rps
+            );
+            for rtt in &point.percentiles {
+                let _ = write!(html, "<td>{rtt}</td>");
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+
+        let start = self.times.first().copied().unwrap_or_default();
+        let bucket_size = self.bucket_size.max(1) as f64;
+        html.push_str("<h2>Requests per second</h2>\n");
+        let lines: Vec<_> = self
+            .series
+            .values()
+            .map(|(tags, points)| {
+                // a bucket without any stats for the endpoint had no requests
+                let line = self
+                    .times
+                    .iter()
+                    .map(|time| {
+                        let requests = points.get(time).map_or(0, |p| p.requests);
+                        ((time - start) as f64, requests as f64 / bucket_size)
+                    })
+                    .collect();
+                (label(tags), line)
+            })
+            .collect();
+        html.push_str(&line_chart(&lines, "rps"));
+
+        html.push_str("<h2>Response times</h2>\n");
+        for (tags, points) in self.series.values() {
+            let _ = writeln!(html, "<h3>{}</h3>", escape(&label(tags)));
+            let lines: Vec<_> = self
+                .percentiles
+                .iter()
+                .enumerate()
+                .map(|(i, p)| {
+                    let line = points
+                        .iter()
+                        .filter(|(_, point)| point.requests > point.errors)
+                        .filter_map(|(time, point)| {
+                            let rtt = *point.percentiles.get(i)?;
+                            Some(((time - start) as f64, rtt))
+                        })
+                        .collect();
+                    (format!("p{p}"), line)
+                })
+                .collect();
+            html.push_str(&line_chart(&lines, "ms"));
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+// how an endpoint is named in the report, its method and url followed by any other tags
+fn label(tags: &Tags) -> String {
+    let method = tags.get("method").map_or("", String::as_str);
+    let url = tags.get("url").map_or("", String::as_str);
+    let others: Vec<_> = tags
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "_id" | "method" | "url"))
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    if others.is_empty() {
+        format!("{method} {url}")
+    } else {
+        format!("{method} {url} ({})", others.join(", "))
+    }
+}
+
+// a chart of the lines, each a label and its points with the x in seconds since the test started
+fn line_chart(lines: &[(String, Vec<(f64, f64)>)], unit: &str) -> String {
+    let points = || lines.iter().flat_map(|(_, line)| line.iter());
+    let max_x = points().map(|(x, _)| *x).fold(0.0, f64::max).max(1.0);
+    let max_y = nice_max(points().map(|(_, y)| *y).fold(0.0, f64::max));
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    let x_at = |x: f64| MARGIN_LEFT + x / max_x * plot_width;
+    let y_at = |y: f64| MARGIN_TOP + plot_height - y / max_y * plot_height;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+         viewBox=\"0 0 {WIDTH} {HEIGHT}\" font-size=\"11\">"
+    );
+    for i in 0..=4 {
+        let value = max_y * i as f64 / 4.0;
+        let y = y_at(value);
+        // the grid lines are on round numbers, give or take floating point error
+        let value = (value * 100.0).round() / 100.0;
+        let _ = writeln!(
+            svg,
+            "<line class=\"grid\" x1=\"{MARGIN_LEFT}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\"/>\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{value} {unit}</text>",
+            WIDTH - MARGIN_RIGHT,
+            MARGIN_LEFT - 6.0,
+            y + 4.0
+        );
+    }
+    let bottom = MARGIN_TOP + plot_height;
+    let _ = writeln!(
+        svg,
+        "<line class=\"axis\" x1=\"{MARGIN_LEFT}\" y1=\"{bottom}\" x2=\"{}\" y2=\"{bottom}\"/>\
+         <text x=\"{MARGIN_LEFT}\" y=\"{}\">0s</text>\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{max_x}s</text>",
+        WIDTH - MARGIN_RIGHT,
+        bottom + 16.0,
+        WIDTH - MARGIN_RIGHT,
+        bottom + 16.0
+    );
+    for ((label, line), color) in lines.iter().zip(COLORS.iter().cycle()) {
+        let coords: Vec<_> = line
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", x_at(*x), y_at(*y)))
+            .collect();
+        let _ = writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"{color}\" stroke-width=\"2\" points=\"{}\">\
+             <title>{}</title></polyline>",
+            coords.join(" "),
+            escape(label)
+        );
+    }
+    svg.push_str("</svg>\n<ul class=\"legend\">\n");
+    for ((label, _), color) in lines.iter().zip(COLORS.iter().cycle()) {
+        let _ = writeln!(
+            svg,
+            "<li><span class=\"swatch\" style=\"background:{color}\"></span>{}</li>",
+            escape(label)
+        );
+    }
+    svg.push_str("</ul>\n");
+    svg
+}
+
+// the top of a chart's y axis, rounded up to 1, 2 or 5 times a power of 10 so the grid lines
+// land on round numbers
+fn nice_max(max: f64) -> f64 {
+    if max <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(max.log10().floor());
+    [1.0, 2.0, 4.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|top| *top >= max)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
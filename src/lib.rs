@@ -3,22 +3,47 @@
 #![type_length_limit = "19550232"]
 #![allow(clippy::type_complexity)]
 
+mod connect_to;
+mod connection_reuse;
+mod endpoint_group;
 mod error;
+mod fd_limit;
+mod html_report;
 mod line_writer;
+mod listing;
+mod metrics;
+mod otlp;
+mod peak_load;
+mod plan;
 mod providers;
+mod proxy;
 mod request;
+mod s3;
 mod stats;
+mod stats_socket;
+mod statsd;
+mod tls;
+mod trace;
 mod util;
+mod warmup;
 
+use crate::endpoint_group::EndpointGroup;
 use crate::error::TestError;
-use crate::stats::{create_stats_channel, create_try_run_stats_channel, StatsMessage};
+pub use crate::s3::S3Location;
+use crate::stats::{
+    create_stats_channel, create_try_run_stats_channel, RunTotals, StatsDone, StatsMessage,
+};
+pub use crate::stats_socket::StatsSocketAddr;
 
 use clap::{Args, Subcommand, ValueEnum};
 use ether::Either;
 use futures::{
-    channel::mpsc::{
-        Sender as FCSender, UnboundedReceiver as FCUnboundedReceiver,
-        UnboundedSender as FCUnboundedSender,
+    channel::{
+        mpsc::{
+            Sender as FCSender, UnboundedReceiver as FCUnboundedReceiver,
+            UnboundedSender as FCUnboundedSender,
+        },
+        oneshot,
     },
     executor::{block_on, block_on_stream},
     future::{self, try_join_all},
@@ -29,7 +54,9 @@ use futures_timer::Delay;
 use hyper::{client::HttpConnector, Body, Client};
 use hyper_tls::HttpsConnector;
 use itertools::Itertools;
-use line_writer::{blocking_writer, MsgType};
+use line_writer::{
+    blocking_gzip_writer, blocking_json_array_writer, blocking_writer, MsgType, Writers,
+};
 use log::{debug, error, info, warn};
 use mod_interval::{ModInterval, PerX};
 use native_tls::TlsConnector;
@@ -43,13 +70,15 @@ use std::str::FromStr;
 use std::{
     borrow::Cow,
     cell::RefCell,
-    collections::{BTreeMap, BTreeSet},
+    collections::{btree_map, BTreeMap, BTreeSet},
     convert::TryFrom,
     fmt,
     fs::File,
     future::Future,
     io::{Error as IOError, ErrorKind as IOErrorKind, Read, Seek, Write},
-    mem,
+    iter, mem,
+    net::SocketAddr,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
@@ -101,15 +130,22 @@ impl Endpoints {
     where
         F: Fn(&BTreeMap<String, String>) -> bool,
     {
-        let mut endpoints: BTreeMap<_, _> = self
-            .inner
+        let mut inner = self.inner;
+        request::link_dependencies(inner.iter_mut().map(|(_, builder, _)| builder).collect());
+        let mut endpoints: BTreeMap<_, _> = inner
             .into_iter()
             .enumerate()
             .map(|(i, (tags, builder, required_providers))| {
                 let included = filter_fn(&tags);
+                let depends_on = builder.depends_on().to_vec();
                 (
                     i,
-                    (included, builder.build(builder_ctx), required_providers),
+                    (
+                        included,
+                        builder.build(builder_ctx),
+                        required_providers,
+                        depends_on,
+                    ),
                 )
             })
             .collect();
@@ -117,27 +153,41 @@ impl Endpoints {
         let mut providers = self.providers;
         let mut endpoints_needed_for_test = BTreeMap::new();
 
+        // (whether the endpoint's provides are needed, yaml index) of endpoints pulled into the
+        // test by the endpoints already in it
         let required_indices = RefCell::new(std::collections::VecDeque::new());
         let iter = (0..endpoints.len())
-            .map(|i| (false, i))
+            .map(|i| (false, false, i))
             .chain(std::iter::from_fn(|| {
-                required_indices.borrow_mut().pop_front().map(|i| (true, i))
+                required_indices
+                    .borrow_mut()
+                    .pop_front()
+                    .map(|(provides_needed, i)| (true, provides_needed, i))
             }));
-        for (bypass_filter, i) in iter {
+        for (bypass_filter, provides_needed, i) in iter {
             if let Some((included, ..)) = endpoints.get(&i) {
                 if *included || bypass_filter {
-                    if let Some((_, ep, required_providers)) = endpoints.remove(&i) {
+                    if let Some((_, ep, required_providers, depends_on)) = endpoints.remove(&i) {
                         for request_provider in required_providers.intersection(response_providers)
                         {
                             if let Some(indices) = providers.remove(request_provider) {
-                                required_indices.borrow_mut().extend(indices);
+                                required_indices
+                                    .borrow_mut()
+                                    .extend(indices.into_iter().map(|i| (true, i)));
                             }
                         }
-                        endpoints_needed_for_test.insert(i, (ep, bypass_filter));
+                        // an endpoint doesn't start until the endpoints it depends on have made
+                        // a request, so they run once with it
+                        required_indices
+                            .borrow_mut()
+                            .extend(depends_on.into_iter().map(|i| (false, i)));
+                        endpoints_needed_for_test.insert(i, (ep, provides_needed));
                     }
                 }
-            } else if let Some((_, provides_needed)) = endpoints_needed_for_test.get_mut(&i) {
-                *provides_needed = true;
+            } else if provides_needed {
+                if let Some((_, needed)) = endpoints_needed_for_test.get_mut(&i) {
+                    *needed = true;
+                }
             }
         }
         let ret = endpoints_needed_for_test
@@ -192,9 +242,32 @@ impl TryFrom<&str> for RunOutputFormat {
     }
 }
 
+/// How the events of a run are laid out when the output format is `json`.
+#[derive(Copy, Clone, Debug, Serialize, ValueEnum, Default, PartialEq, Eq)]
+pub enum JsonStyle {
+    /// One JSON object per line, written as the events happen
+    #[default]
+    Ndjson,
+    /// A single JSON array of every event, written when the test completes
+    Array,
+}
+
+impl fmt::Display for JsonStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Ndjson => "ndjson",
+                Self::Array => "array",
+            }
+        )
+    }
+}
+
 #[derive(Clone, Debug, Serialize, ValueEnum, Default)]
 pub enum StatsFileFormat {
-    // Html,
+    Html,
     #[default]
     Json,
     // None,
@@ -206,6 +279,7 @@ impl fmt::Display for StatsFileFormat {
             f,
             "{}",
             match self {
+                Self::Html => "html",
                 Self::Json => "json",
             }
         )
@@ -255,9 +329,15 @@ pub struct RunConfig {
     /// Load test config file to use
     #[arg(value_name = "CONFIG")]
     pub config_file: PathBuf,
+    /// More config files deep merged over CONFIG, each over the ones before it
+    #[arg(value_name = "MERGE")]
+    pub merge_config_files: Vec<PathBuf>,
     /// Formatting for stats printed to stdout
     #[arg(short = 'f', long, value_name = "FORMAT", default_value_t)]
     pub output_format: RunOutputFormat,
+    /// How json output is laid out, either a line per event or a single array at the end
+    #[arg(long, value_name = "STYLE", default_value_t)]
+    pub json_style: JsonStyle,
     /// Directory to store results and logs
     #[arg(short = 'd', long = "results-directory", value_name = "DIRECTORY")]
     pub results_dir: Option<PathBuf>,
@@ -267,12 +347,114 @@ pub struct RunConfig {
     /// Specify the filename for the stats file
     #[arg(short = 'o', long)]
     pub stats_file: PathBuf,
+    /// Write a summary of the run, checked against the config's `gates`, to this file
+    #[arg(long, value_name = "FILE")]
+    pub result_file: Option<PathBuf>,
     /// Format for the stats file
     #[arg(short, long, value_name = "FORMAT", default_value_t)]
     pub stats_file_format: StatsFileFormat,
     /// Watch the config file for changes and update the test accordingly
     #[arg(short, long = "watch")]
     pub watch_config_file: bool,
+    /// Export a span for every request to the OTLP/HTTP collector at this endpoint
+    #[arg(long, value_name = "ENDPOINT")]
+    pub otlp: Option<String>,
+    /// Write every request's timing to this file as Chrome trace events
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+    /// Print the planned request rate over the course of the test without sending any requests
+    #[arg(long)]
+    pub plan: bool,
+    /// Print each endpoint's planned requests per second at every bucket through its load pattern
+    /// as a json array, without sending any requests
+    #[arg(long)]
+    pub print_load_curve: bool,
+    /// Print each endpoint's id, method, url and tags without sending any requests
+    #[arg(long)]
+    pub list_endpoints: bool,
+    /// Check the config and open every provider's file without sending any requests
+    #[arg(long)]
+    pub config_check_only: bool,
+    /// Seed `shuffle: once` file providers and `random` range providers so every run uses the
+    /// same order
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// With json output, print a heartbeat line every bucket even when no requests complete
+    #[arg(long)]
+    pub heartbeat: bool,
+    /// Fail the run if any endpoint never sent a request
+    #[arg(long)]
+    pub require_all_endpoints: bool,
+    /// End the test once it has run for this long, however long its load pattern is
+    #[arg(value_parser = |s: &str| config::duration_from_string(s.into()), long, value_name = "DURATION")]
+    pub max_runtime: Option<Duration>,
+    /// Merge the named entry from the config's `environments` section over the base config
+    #[arg(long = "env", value_name = "NAME")]
+    pub environment: Option<String>,
+    /// Merge the vars in this yaml file, laid out like the `vars` section, over the config's vars
+    #[arg(long, value_name = "FILE")]
+    pub vars_file: Option<PathBuf>,
+    /// Set a var, replacing any from the config or --vars-file with the same name. Can be given
+    /// more than once
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = var_from_str)]
+    // left out of the debug log as vars often hold secrets
+    #[serde(skip)]
+    pub vars: Vec<(String, String)>,
+    /// Number of worker threads to run the test on, 1 runs everything on a single thread
+    #[arg(long, value_name = "N")]
+    pub threads: Option<NonZeroUsize>,
+    /// Run the whole test this many times, one after another, with a summary of each run and an
+    /// aggregate summary of them all at the end
+    #[arg(long, value_name = "N", conflicts_with = "watch_config_file")]
+    pub repeat: Option<NonZeroUsize>,
+    /// Raise the limit on open files as far as the hard limit before the test starts
+    #[arg(long)]
+    pub raise_fd_limit: bool,
+    /// Write a progress bar to stderr with every bucket, showing how far through the test it is
+    /// and the current requests per second. Only with human output
+    #[arg(long)]
+    pub progress: bool,
+    /// Serve each bucket's stats as newline delimited json to every client connected to this
+    /// socket, given as host:port for TCP or otherwise as the path of a Unix socket
+    #[arg(long, value_name = "ADDR")]
+    pub stats_socket: Option<StatsSocketAddr>,
+    /// Serve live metrics at /metrics on this port, in the Prometheus text format, while the
+    /// test runs
+    #[arg(long, value_name = "PORT")]
+    pub metrics_port: Option<u16>,
+    /// Upload the stats file, logs and a manifest of them to S3 once the test ends, given as
+    /// s3://bucket/prefix. Credentials come from the AWS environment variables
+    #[arg(long, value_name = "S3_URL")]
+    pub results_s3: Option<S3Location>,
+    /// Start the test without asking, when the config has `general.confirm_before_run`
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+    // whether there's someone at a terminal to ask for confirmation
+    #[arg(skip)]
+    #[serde(skip)]
+    pub interactive: bool,
+}
+
+impl RunConfig {
+    // with `--repeat` each run writes its own stats, result and trace files, named after the
+    // given ones with the number of the run added
+    fn for_run(&self, run: usize) -> Self {
+        let numbered = |path: &Path| {
+            let mut name = path.file_stem().unwrap_or_default().to_os_string();
+            name.push(format!("-{run}"));
+            if let Some(extension) = path.extension() {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        };
+        Self {
+            stats_file: numbered(&self.stats_file),
+            result_file: self.result_file.as_deref().map(numbered),
+            trace: self.trace.as_deref().map(numbered),
+            ..self.clone()
+        }
+    }
 }
 
 impl fmt::Display for RunConfig {
@@ -314,10 +496,21 @@ impl FromStr for TryFilter {
     }
 }
 
+/// Parses a `--var` given as `NAME=VALUE`
+pub fn var_from_str(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.into(), value.into())),
+        _ => Err(format!("`{s}` should be in the format NAME=VALUE")),
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Args)]
 pub struct TryConfig {
     /// Load test config file to use
     pub config_file: PathBuf,
+    /// More config files deep merged over CONFIG, each over the ones before it
+    #[arg(value_name = "MERGE")]
+    pub merge_config_files: Vec<PathBuf>,
     /// Send results to the specified file instead of stdout
     #[arg(short = 'o', long)]
     pub file: Option<String>,
@@ -326,6 +519,10 @@ pub struct TryConfig {
     /// a wildcard. Any endpoint matching the filter is included in the test
     #[arg(short = 'i', long = "include", value_parser = TryFilter::from_str, value_name = "INCLUDE")]
     pub filters: Option<Vec<TryFilter>>,
+    /// Filter which endpoints are left out of the try run, in the same format as --include.
+    /// Exclusions are applied after the include filters
+    #[arg(long = "exclude", value_parser = TryFilter::from_str, value_name = "EXCLUDE")]
+    pub exclude: Option<Vec<TryFilter>>,
     /// Specify the format for the try run output
     #[arg(short, long, default_value_t)]
     pub format: TryRunFormat,
@@ -341,6 +538,25 @@ pub struct TryConfig {
     /// Skips request body from output
     #[arg(short = 'K', long = "skip-request-body")]
     pub skip_request_body_on: bool,
+    /// Print each endpoint's id, method, url and tags without sending any requests
+    #[arg(long)]
+    pub list_endpoints: bool,
+    /// Seed `shuffle: once` file providers and `random` range providers so every run uses the
+    /// same order
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+    /// Merge the named entry from the config's `environments` section over the base config
+    #[arg(long = "env", value_name = "NAME")]
+    pub environment: Option<String>,
+    /// Merge the vars in this yaml file, laid out like the `vars` section, over the config's vars
+    #[arg(long, value_name = "FILE")]
+    pub vars_file: Option<PathBuf>,
+    /// Set a var, replacing any from the config or --vars-file with the same name. Can be given
+    /// more than once
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = var_from_str)]
+    // left out of the debug log as vars often hold secrets
+    #[serde(skip)]
+    pub vars: Vec<(String, String)>,
 }
 
 impl fmt::Display for TryConfig {
@@ -349,7 +565,7 @@ impl fmt::Display for TryConfig {
     }
 }
 
-#[derive(Serialize, Subcommand, Debug)]
+#[derive(Clone, Serialize, Subcommand, Debug)]
 pub enum ExecConfig {
     /// Runs a full load test
     Run(RunConfig),
@@ -371,12 +587,67 @@ impl ExecConfig {
         }
     }
 
+    fn get_merge_config_files(&self) -> &[PathBuf] {
+        match self {
+            Self::Run(r) => &r.merge_config_files,
+            Self::Try(t) => &t.merge_config_files,
+        }
+    }
+
+    fn get_vars_file(&self) -> Option<&PathBuf> {
+        match self {
+            Self::Run(r) => r.vars_file.as_ref(),
+            Self::Try(t) => t.vars_file.as_ref(),
+        }
+    }
+
+    fn get_vars(&self) -> &[(String, String)] {
+        match self {
+            Self::Run(r) => &r.vars,
+            Self::Try(t) => &t.vars,
+        }
+    }
+
+    fn get_environment(&self) -> Option<&str> {
+        match self {
+            Self::Run(r) => r.environment.as_deref(),
+            Self::Try(t) => t.environment.as_deref(),
+        }
+    }
+
+    fn list_endpoints(&self) -> bool {
+        match self {
+            Self::Run(r) => r.list_endpoints,
+            Self::Try(t) => t.list_endpoints,
+        }
+    }
+
     fn get_output_format(&self) -> RunOutputFormat {
         match self {
             Self::Run(r) => r.output_format,
             Self::Try(_) => RunOutputFormat::Human,
         }
     }
+
+    fn get_json_style(&self) -> JsonStyle {
+        match self {
+            Self::Run(r) => r.json_style,
+            Self::Try(_) => JsonStyle::Ndjson,
+        }
+    }
+
+    // how many times the load test runs, which is only ever once for anything that doesn't send
+    // load
+    fn repeat(&self) -> usize {
+        match self {
+            Self::Run(r)
+                if !r.plan && !r.print_load_curve && !r.list_endpoints && !r.config_check_only =>
+            {
+                r.repeat.map_or(1, NonZeroUsize::get)
+            }
+            _ => 1,
+        }
+    }
 }
 
 /// The reason the test ended, whether temporarily or completely.
@@ -388,10 +659,36 @@ pub enum TestEndReason {
     Completed,
     CtrlC,
     KilledByLogger,
-    ProviderEnded,
+    /// An endpoint's assertion with `kill: true` failed, with the assertion's message
+    KilledByAssertion(String),
+    MaxRuntime,
+    /// Every endpoint finished early, with the names of the providers which ran out of values
+    ProviderEnded(Vec<String>),
     ConfigUpdate(Arc<BTreeMap<String, providers::Provider>>),
 }
 
+// the file is returned as well, for `--watch` to check it for changes
+fn read_config_file(config_file: PathBuf) -> Result<(File, Vec<u8>), TestError> {
+    let mut file = File::open(&config_file).map_err(|err| {
+        error!(
+            "File::open({}) error: {}",
+            config_file.to_str().unwrap_or_default(),
+            err
+        );
+        TestError::InvalidConfigFilePath(config_file.clone())
+    })?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|e| {
+        error!(
+            "File::read_to_end({}) error: {}",
+            config_file.to_str().unwrap_or_default(),
+            e
+        );
+        TestError::CannotOpenFile(config_file, e.into())
+    })?;
+    Ok((file, bytes))
+}
+
 /// Inner(1)-level runtime future function.
 ///
 /// Generates runner based on specified values in the [`ExecConfig`], as well as the indicated config
@@ -404,39 +701,34 @@ pub enum TestEndReason {
 /// # Errors
 ///
 /// Returns an `Err` if the test could not be run.
+#[allow(clippy::too_many_arguments)]
 async fn _create_run(
     exec_config: ExecConfig,
-    mut ctrlc_channel: FCUnboundedReceiver<()>,
     stdout: FCSender<MsgType>,
     stderr: FCSender<MsgType>,
     test_ended_tx: broadcast::Sender<Result<TestEndReason, TestError>>,
     mut test_ended_rx: BroadcastStream<Result<TestEndReason, TestError>>,
+    writers: &Writers,
+    stats_done_tx: oneshot::Sender<StatsDone>,
 ) -> Result<TestEndReason, TestError> {
     debug!("{{\"_create_run enter");
     let config_file = exec_config.get_config_file().clone();
     let config_file2 = config_file.clone();
+    let merge_config_files = exec_config.get_merge_config_files().to_vec();
+    let vars_file = exec_config.get_vars_file().cloned();
     debug!("{{\"_create_run spawn_blocking start");
-    let (file, config_bytes) = spawn_blocking(|| {
+    let (file, config_bytes, merge_config_bytes, vars_file_bytes) = spawn_blocking(move || {
         debug!("{{\"_create_run spawn_blocking enter");
-        let mut file = File::open(config_file.clone()).map_err(|err| {
-            error!(
-                "File::open({}) error: {}",
-                config_file.clone().to_str().unwrap_or_default(),
-                err
-            );
-            TestError::InvalidConfigFilePath(config_file.clone())
-        })?;
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes).map_err(|e| {
-            error!(
-                "File::read_to_end({}) error: {}",
-                config_file.to_str().unwrap_or_default(),
-                e
-            );
-            TestError::CannotOpenFile(config_file, e.into())
-        })?;
+        let (file, bytes) = read_config_file(config_file)?;
+        let merge_bytes = merge_config_files
+            .into_iter()
+            .map(|path| read_config_file(path).map(|(_, bytes)| bytes))
+            .collect::<Result<Vec<_>, _>>()?;
+        let vars_file_bytes = vars_file
+            .map(|path| read_config_file(path).map(|(_, bytes)| bytes))
+            .transpose()?;
         debug!("{{\"_create_run spawn_blocking exit");
-        Ok::<_, TestError>((file, bytes))
+        Ok::<_, TestError>((file, bytes, merge_bytes, vars_file_bytes))
     })
     .await
     .map_err(move |e| {
@@ -445,22 +737,6 @@ async fn _create_run(
         TestError::CannotOpenFile(config_file2, e.into())
     })??;
 
-    // watch for ctrl-c and kill the test
-    let test_ended_tx2 = test_ended_tx.clone();
-    let mut test_ended_rx2 = BroadcastStream::new(test_ended_tx.subscribe());
-    debug!("_create_run tokio::spawn future::poll_fn ctrl-c");
-    tokio::spawn(future::poll_fn(move |cx| {
-        match ctrlc_channel.poll_next_unpin(cx) {
-            Poll::Ready(r) => {
-                if r.is_some() {
-                    let _ = test_ended_tx2.send(Ok(TestEndReason::CtrlC));
-                }
-                Poll::Ready(())
-            }
-            Poll::Pending => test_ended_rx2.poll_next_unpin(cx).map(|_| ()),
-        }
-    }));
-
     let env_vars: BTreeMap<String, String> = std::env::vars_os()
         .map(|(k, v)| (k.to_string_lossy().into(), v.to_string_lossy().into()))
         .collect();
@@ -469,14 +745,97 @@ async fn _create_run(
     log::trace!("env_vars={:?}", env_vars.clone());
     let output_format = exec_config.get_output_format();
     let config_file_path = exec_config.get_config_file().clone();
-    let mut config =
-        config::LoadTest::from_config(&config_bytes, exec_config.get_config_file(), &env_vars)?;
+    let configs: Vec<(&Path, &[u8])> = iter::once(exec_config.get_config_file())
+        .chain(exec_config.get_merge_config_files())
+        .map(PathBuf::as_path)
+        .zip(
+            iter::once(&config_bytes)
+                .chain(&merge_config_bytes)
+                .map(Vec::as_slice),
+        )
+        .collect();
+    let overrides = config::VarOverrides {
+        vars_file: exec_config
+            .get_vars_file()
+            .map(PathBuf::as_path)
+            .zip(vars_file_bytes.as_deref()),
+        vars: exec_config.get_vars(),
+    };
+    let mut config = config::LoadTest::from_configs_with_overrides(
+        &configs,
+        &env_vars,
+        exec_config.get_environment(),
+        overrides,
+    )?;
     debug!("config::LoadTest::from_config finished");
+    if let Some(msg) = warnings_message(&config.warnings, output_format) {
+        let mut stderr = stderr.clone();
+        let _ = stderr.send(MsgType::Other(msg)).await;
+    }
     let test_runner = match exec_config {
+        e if e.list_endpoints() => {
+            let listing = listing::EndpointListing::new(&config);
+            let json = match &e {
+                ExecConfig::Run(r) => !r.output_format.is_human(),
+                ExecConfig::Try(t) => matches!(t.format, TryRunFormat::Json),
+            };
+            let msg = if json {
+                listing.to_json()
+            } else {
+                listing.to_human()
+            };
+            let mut stdout = stdout;
+            let _ = stdout.send(MsgType::Other(msg)).await;
+            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+            return Ok(TestEndReason::Completed);
+        }
+        ExecConfig::Run(r) if r.plan => {
+            config.ok_for_loadtest()?;
+            let plan = plan::LoadPlan::new(&config);
+            let msg = match output_format {
+                RunOutputFormat::Human => plan.to_human(),
+                RunOutputFormat::Json => format!("{}\n", plan.to_json()),
+            };
+            let mut stdout = stdout;
+            let _ = stdout.send(MsgType::Other(msg)).await;
+            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+            return Ok(TestEndReason::Completed);
+        }
+        ExecConfig::Run(r) if r.print_load_curve => {
+            config.ok_for_loadtest()?;
+            let plan = plan::LoadPlan::new(&config);
+            let msg = format!("{}\n", plan.load_curve(config.config.general.bucket_size));
+            let mut stdout = stdout;
+            let _ = stdout.send(MsgType::Other(msg)).await;
+            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+            return Ok(TestEndReason::Completed);
+        }
+        ExecConfig::Run(r) if r.config_check_only => {
+            let msg = check_config(&config, &r, &test_ended_tx).await?;
+            let mut stdout = stdout;
+            let _ = stdout.send(MsgType::Other(msg)).await;
+            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+            return Ok(TestEndReason::Completed);
+        }
         ExecConfig::Try(t) => {
-            create_try_run_future(config, t, test_ended_tx.clone(), stdout, stderr).map(Either::A)
+            create_try_run_future(config, t, test_ended_tx.clone(), stdout, stderr, writers)
+                .map(Either::A)
         }
         ExecConfig::Run(r) => {
+            confirm_run(&config, &r, stderr.clone()).await?;
+            let fd_limit = if r.raise_fd_limit {
+                fd_limit::raise()
+            } else {
+                fd_limit::current()
+            };
+            let fd_warning: Vec<_> = fd_limit
+                .and_then(|limit| fd_limit::warning(fd_limit::needed(&config), limit))
+                .into_iter()
+                .collect();
+            if let Some(msg) = warnings_message(&fd_warning, output_format) {
+                let mut stderr = stderr.clone();
+                let _ = stderr.send(MsgType::Other(msg)).await;
+            }
             let config_providers = mem::take(&mut config.providers);
             // build and register the providers
             let (providers, _) = get_providers_from_config(
@@ -484,6 +843,7 @@ async fn _create_run(
                 config.config.general.auto_buffer_start_size,
                 &test_ended_tx,
                 &r.config_file,
+                r.seed,
             )?;
 
             let stats_tx = create_stats_channel(
@@ -491,11 +851,21 @@ async fn _create_run(
                 &config.config.general,
                 &providers,
                 stdout.clone(),
+                stderr.clone(),
                 &r,
+                writers,
+                stats_done_tx,
             )?;
 
             let providers = Arc::new(providers);
 
+            // created once for the whole run, so a config update doesn't start the file over
+            let trace_tx = r
+                .trace
+                .as_ref()
+                .map(|path| trace::writer(path, test_ended_tx.clone(), writers))
+                .transpose()?;
+
             // Allow continuing test with new config file.
             if r.watch_config_file {
                 create_config_watcher(
@@ -508,8 +878,10 @@ async fn _create_run(
                     r.clone(),
                     config_file_path,
                     stats_tx.clone(),
+                    trace_tx.clone(),
                     config_providers,
                     providers.clone(),
+                    writers.clone(),
                 );
             }
 
@@ -519,8 +891,10 @@ async fn _create_run(
                 test_ended_tx,
                 providers,
                 stats_tx,
+                trace_tx,
                 stdout,
                 stderr,
+                writers,
             )
             .map(Either::B)
         }
@@ -550,6 +924,122 @@ async fn _create_run(
     }
 }
 
+/// Goes further than parsing the config by creating its providers, which opens their files and
+/// runs their queries, and taking the first value from each. No requests are sent.
+///
+/// # Errors
+///
+/// Returns the first error found, including one from reading a provider's first value.
+async fn check_config(
+    config: &config::LoadTest,
+    run_config: &RunConfig,
+    test_ended_tx: &broadcast::Sender<Result<TestEndReason, TestError>>,
+) -> Result<String, TestError> {
+    config.ok_for_loadtest()?;
+    let mut test_ended_rx = test_ended_tx.subscribe();
+    let (providers, response_providers) = get_providers_from_config(
+        &config.providers,
+        config.config.general.auto_buffer_start_size,
+        test_ended_tx,
+        &run_config.config_file,
+        run_config.seed,
+    )?;
+    let provider_count = providers.len();
+    // with the provider's own sender dropped its channel closes once the provider stops, so an
+    // empty provider or one which fails doesn't leave this waiting
+    let first_values = providers
+        .into_iter()
+        .filter(|(name, _)| !response_providers.contains(name))
+        .map(|(_, provider)| {
+            let providers::Provider { mut rx, tx, .. } = provider;
+            drop(tx);
+            async move { rx.next().await }
+        });
+    future::join_all(first_values).await;
+    // a provider which can't read or map a value ends the test with the reason
+    if let Ok(Err(e)) = test_ended_rx.try_recv() {
+        return Err(e);
+    }
+    let endpoint_count = config.endpoints.len();
+    let msg = match run_config.output_format {
+        RunOutputFormat::Human => format!(
+            "{} {provider_count} providers and {endpoint_count} endpoints\n",
+            Paint::green("Config check passed:").bold()
+        ),
+        RunOutputFormat::Json => {
+            let json = json::json!({
+                "type": "configCheck",
+                "providers": provider_count,
+                "endpoints": endpoint_count,
+            });
+            format!("{json}\n")
+        }
+    };
+    Ok(msg)
+}
+
+// With `general.confirm_before_run`, a test whose combined `peak_load` is over `confirm_above` only
+// starts once someone at the terminal says it should, or `--yes` was passed. Without a terminal it
+// doesn't start at all
+async fn confirm_run(
+    config: &config::LoadTest,
+    run_config: &RunConfig,
+    mut stderr: FCSender<MsgType>,
+) -> Result<(), TestError> {
+    let general = &config.config.general;
+    if !general.confirm_before_run || run_config.yes {
+        return Ok(());
+    }
+    let peak_load = plan::LoadPlan::new(config).peak_rate();
+    let threshold = general
+        .confirm_above
+        .as_ref()
+        .map_or(0.0, plan::hits_per_second);
+    if peak_load <= threshold {
+        return Ok(());
+    }
+    if !run_config.interactive {
+        return Err(TestError::RunNotConfirmed(peak_load, false));
+    }
+    let msg = format!(
+        "{} the test has a combined peak_load of {:.2} hps. Start it? [y/N] ",
+        Paint::yellow("Confirm:").bold(),
+        peak_load
+    );
+    let _ = stderr.send(MsgType::Other(msg)).await;
+    let answer = spawn_blocking(|| {
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer).map(|_| answer)
+    })
+    .await;
+    match answer {
+        Ok(Ok(answer)) if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") => Ok(()),
+        _ => Err(TestError::RunNotConfirmed(peak_load, true)),
+    }
+}
+
+// The config's warnings, one per line, printed before the test starts (or restarts with an
+// updated config) without stopping it
+fn warnings_message<W: fmt::Display>(
+    warnings: &[W],
+    output_format: RunOutputFormat,
+) -> Option<String> {
+    if warnings.is_empty() {
+        return None;
+    }
+    let msg = warnings
+        .iter()
+        .map(|warning| match output_format {
+            RunOutputFormat::Human => format!("{} {}\n", Paint::yellow("Warning:").bold(), warning),
+            RunOutputFormat::Json => {
+                let json = json::json!({"type": "warning", "msg": format!("{warning}")});
+                format!("{json}\n")
+            }
+        })
+        .collect();
+    Some(msg)
+}
+
 /// Outermost-level runtime future function.
 ///
 /// Creates worker future, and checks the circumstances under which it terminates. Specific
@@ -573,26 +1063,149 @@ where
         "{{\"method\":\"create_run enter\",\"exec_config\":{}}}",
         exec_config
     );
-    let (test_ended_tx, test_ended_rx) = broadcast::channel(1);
-    let test_ended_rx = BroadcastStream::new(test_ended_rx);
+    let (test_ended_tx, _) = broadcast::channel(1);
     let output_format = exec_config.get_output_format();
-    let (stdout, stdout_done) = blocking_writer(stdout, test_ended_tx.clone(), "stdout".into());
-    let (mut stderr, stderr_done) = blocking_writer(stderr, test_ended_tx.clone(), "stderr".into());
-    let test_result = _create_run(
-        exec_config,
-        ctrlc_channel,
-        stdout,
-        stderr.clone(),
-        test_ended_tx.clone(),
-        test_ended_rx,
-    )
-    .await;
+    let json_array = !output_format.is_human() && exec_config.get_json_style() == JsonStyle::Array;
+    let (stderr, stderr_done) = blocking_writer(stderr, test_ended_tx.clone(), "stderr".into());
+    // when the output is a json array, the message for how the test ended goes to the end of
+    // the array on stdout rather than to stderr
+    let (stdout, mut end_msg, stdout_done) = if json_array {
+        blocking_json_array_writer(stdout, test_ended_tx.clone(), "stdout".into())
+    } else {
+        let (stdout, stdout_done) = blocking_writer(stdout, test_ended_tx.clone(), "stdout".into());
+        (stdout, stderr.clone(), stdout_done)
+    };
+
+    // watch for ctrl-c and kill the test, through every run of it when it's repeated
+    let (run_done_tx, mut run_done_rx) = oneshot::channel::<()>();
+    let test_ended_tx2 = test_ended_tx.clone();
+    let mut ctrlc_channel = ctrlc_channel;
+    debug!("create_run tokio::spawn future::poll_fn ctrl-c");
+    tokio::spawn(future::poll_fn(move |cx| {
+        match ctrlc_channel.poll_next_unpin(cx) {
+            Poll::Ready(r) => {
+                if r.is_some() {
+                    let _ = test_ended_tx2.send(Ok(TestEndReason::CtrlC));
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => run_done_rx.poll_unpin(cx).map(|_| ()),
+        }
+    }));
+
+    let repeat = exec_config.repeat();
+    let writers = Writers::default();
+    let mut aggregate: Option<RunTotals> = None;
+    let mut runs = 0;
+    let test_result = loop {
+        runs += 1;
+        let exec_config = match &exec_config {
+            ExecConfig::Run(r) if repeat > 1 => {
+                let mut r = r.for_run(runs);
+                // confirming the first run starts all of them
+                r.yes |= runs > 1;
+                ExecConfig::Run(r)
+            }
+            e => e.clone(),
+        };
+        // each run's summary is its own, rather than the last one overwriting the rest
+        let (run_stdout, run_stdout_done) = if repeat > 1 {
+            let (run_stdout, done) = line_writer::final_as_other(stdout.clone());
+            (run_stdout, Some(done))
+        } else {
+            (stdout.clone(), None)
+        };
+        let results_s3 = match &exec_config {
+            ExecConfig::Run(r) => r.results_s3.clone().map(|location| (location, r.clone())),
+            ExecConfig::Try(_) => None,
+        };
+        let test_ended_rx = BroadcastStream::new(test_ended_tx.subscribe());
+        let (stats_done_tx, mut stats_done_rx) = oneshot::channel();
+        let mut test_result = _create_run(
+            exec_config,
+            run_stdout,
+            stderr.clone(),
+            test_ended_tx.clone(),
+            test_ended_rx,
+            &writers,
+            stats_done_tx,
+        )
+        .await;
+
+        if test_result.is_err() {
+            // send the test end message to ensure the stats channel closes
+            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+        }
+        // flush the loggers and the stats file before anything says the test is over
+        writers.shutdown().await;
+
+        // the stats are done once the stats file is. With `--require-all-endpoints` they say
+        // whether any endpoint sat idle for the whole test, and they say whether any endpoint
+        // broke its `sla`
+        if let Ok(Some(StatsDone {
+            idle_endpoints,
+            sla_breaches,
+            totals,
+        })) = stats_done_rx.try_recv()
+        {
+            if !idle_endpoints.is_empty() && matches!(test_result, Ok(TestEndReason::Completed)) {
+                test_result = Err(TestError::IdleEndpoints(idle_endpoints));
+            } else if !sla_breaches.is_empty()
+                && matches!(
+                    test_result,
+                    Ok(TestEndReason::Completed
+                        | TestEndReason::MaxRuntime
+                        | TestEndReason::ProviderEnded(_))
+                )
+            {
+                test_result = Err(TestError::SlaBreached(sla_breaches));
+            }
+            match &mut aggregate {
+                Some(aggregate) => aggregate.combine(&totals),
+                None => aggregate = Some(totals),
+            }
+        }
+        if let Some(done) = run_stdout_done {
+            let _ = done.await;
+        }
+        let logger_files = writers.take_files();
+        if let Some((location, run_config)) = results_s3 {
+            upload_results(
+                &location,
+                &run_config,
+                logger_files,
+                (repeat > 1).then_some(runs),
+                &test_result,
+                stderr.clone(),
+                output_format,
+            )
+            .await;
+        }
+
+        // a run which was killed or failed stops the rest
+        let next_run = matches!(
+            test_result,
+            Ok(TestEndReason::Completed
+                | TestEndReason::MaxRuntime
+                | TestEndReason::ProviderEnded(_))
+        );
+        if !next_run || runs == repeat {
+            break test_result;
+        }
+    };
+    drop(run_done_tx);
 
+    if let (true, Some(aggregate)) = (repeat > 1, aggregate) {
+        let msg = aggregate.create_aggregate_summary(runs, output_format);
+        let mut stdout = stdout.clone();
+        let _ = stdout.send(MsgType::Other(msg)).await;
+    }
+    drop(stdout);
+
+    let failed = test_result.is_err();
     match test_result {
         Err(e) => {
-            // send the test end message to ensure the stats channel closes
             error!("TestError: {}", e);
-            let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
             let msg = match output_format {
                 RunOutputFormat::Human => format!("\n{} {}\n", Paint::red("Fatal error").bold(), e),
                 RunOutputFormat::Json => {
@@ -600,8 +1213,7 @@ where
                     format!("{json}\n")
                 }
             };
-            let _ = stderr.send(MsgType::Final(msg)).await;
-            return Err(());
+            let _ = end_msg.send(MsgType::Final(msg)).await;
         }
         Ok(TestEndReason::KilledByLogger) => {
             let msg = match output_format {
@@ -613,7 +1225,18 @@ where
                     "{\"type\":\"end\",\"msg\":\"Test killed early by logger\"}\n".to_string()
                 }
             };
-            let _ = stderr.send(MsgType::Final(msg)).await;
+            let _ = end_msg.send(MsgType::Final(msg)).await;
+        }
+        Ok(TestEndReason::KilledByAssertion(message)) => {
+            let msg = format!("Test killed early by a failed assertion: {message}");
+            let msg = match output_format {
+                RunOutputFormat::Human => format!("\n{}\n", Paint::yellow(msg).bold()),
+                RunOutputFormat::Json => {
+                    let json = json::json!({"type": "end", "msg": msg});
+                    format!("{json}\n")
+                }
+            };
+            let _ = end_msg.send(MsgType::Final(msg)).await;
         }
         Ok(TestEndReason::CtrlC) => {
             let msg = match output_format {
@@ -625,31 +1248,124 @@ where
                     "{\"type\":\"end\",\"msg\":\"Test killed early by Ctrl-c\"}\n".to_string()
                 }
             };
-            let _ = stderr.send(MsgType::Final(msg)).await;
+            let _ = end_msg.send(MsgType::Final(msg)).await;
         }
-        Ok(TestEndReason::ProviderEnded) => {
+        Ok(TestEndReason::MaxRuntime) => {
             let msg = match output_format {
                 RunOutputFormat::Human => {
                     format!(
                         "\n{}\n",
-                        Paint::yellow("Test ended early because one or more providers ended")
+                        Paint::yellow("Test ended early because it reached the max runtime")
                     )
                 }
                 RunOutputFormat::Json => {
-                    "{\"type\":\"end\",\"msg\":\"Test ended early because one or more providers ended\"}\n".to_string()
+                    "{\"type\":\"end\",\"msg\":\"Test ended early because it reached the max runtime\"}\n".to_string()
                 }
             };
-            let _ = stderr.send(MsgType::Final(msg)).await;
+            let _ = end_msg.send(MsgType::Final(msg)).await;
+        }
+        Ok(TestEndReason::ProviderEnded(providers)) => {
+            let msg = format!(
+                "Test ended early because one or more providers ended: {}",
+                providers.join(", ")
+            );
+            let msg = match output_format {
+                RunOutputFormat::Human => format!("\n{}\n", Paint::yellow(msg)),
+                RunOutputFormat::Json => {
+                    let json = json::json!({"type": "end", "msg": msg, "providers": providers});
+                    format!("{json}\n")
+                }
+            };
+            let _ = end_msg.send(MsgType::Final(msg)).await;
+        }
+        // Instead of implementing Display for TestEndReason, just log these other two. A normal
+        // completion still gets an end message in a json array so the array has a definite end
+        Ok(TestEndReason::Completed) => {
+            info!("Test Ended with: Completed");
+            if json_array {
+                let msg = "{\"type\":\"end\",\"msg\":\"Test completed\"}\n".to_string();
+                let _ = end_msg.send(MsgType::Final(msg)).await;
+            }
         }
-        // Instead of implementing Display for TestEndReason, just log these other two
-        Ok(TestEndReason::Completed) => info!("Test Ended with: Completed"),
         Ok(TestEndReason::ConfigUpdate(_)) => info!("Test Ended with: ConfigUpdate"),
     };
+    drop(end_msg);
     drop(stderr);
-    // wait for all stderr and stdout output to be written
+    // wait for all stderr and stdout output to be written, which for a json array is when it's
+    // written at all
     let _ = stderr_done.await;
     let _ = stdout_done.await;
-    Ok(())
+    if failed {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// Upload a run's results for `--results-s3`, once they've all been written. Each file is keyed by
+// its path in the results dir, under the run's number when the test is repeated, so files can't
+// replace one another. Any files which can't be uploaded are reported, but don't change how the
+// test ended
+async fn upload_results(
+    location: &S3Location,
+    run_config: &RunConfig,
+    logger_files: Vec<PathBuf>,
+    run: Option<usize>,
+    test_result: &Result<TestEndReason, TestError>,
+    mut stderr: FCSender<MsgType>,
+    output_format: RunOutputFormat,
+) {
+    let mut files = vec![run_config.stats_file.clone()];
+    files.extend(run_config.result_file.clone());
+    files.extend(run_config.trace.clone());
+    files.extend(logger_files);
+    let results_dir = run_config.results_dir.as_deref();
+    let files = files
+        .into_iter()
+        .unique()
+        .map(|file| {
+            let name = s3::object_name(&file, results_dir);
+            let name = match run {
+                Some(run) => format!("run-{run}/{name}"),
+                None => name,
+            };
+            (name, file)
+        })
+        .collect();
+    let manifest = match run {
+        Some(run) => format!("manifest-{run}.json"),
+        None => "manifest.json".into(),
+    };
+    let ended = match test_result {
+        Ok(TestEndReason::Completed) => "completed".into(),
+        Ok(TestEndReason::CtrlC) => "killed by ctrl-c".into(),
+        Ok(TestEndReason::KilledByLogger) => "killed by logger".into(),
+        Ok(TestEndReason::KilledByAssertion(message)) => {
+            format!("killed by a failed assertion: {message}")
+        }
+        Ok(TestEndReason::MaxRuntime) => "reached the max runtime".into(),
+        Ok(TestEndReason::ProviderEnded(providers)) => {
+            format!("providers ended: {}", providers.join(", "))
+        }
+        Ok(TestEndReason::ConfigUpdate(_)) => "config update".into(),
+        Err(e) => e.to_string(),
+    };
+    let test_end = json::json!({
+        "config": run_config.config_file,
+        "passed": test_result.is_ok(),
+        "ended": ended,
+    });
+    let env = std::env::vars().collect();
+    let errors = match create_http_client(ClientSettings::default()) {
+        Ok(client) => s3::upload(location, &env, &client, files, &manifest, test_end).await,
+        Err(e) => vec![format!("could not upload the results to S3, {e}")],
+    };
+    for e in &errors {
+        warn!("results-s3: {}", e);
+    }
+    if let Some(msg) = warnings_message(&errors, output_format) {
+        let _ = stderr.send(MsgType::Other(msg)).await;
+    }
 }
 
 /// Create a watcher to see when the config file has been updated.
@@ -668,8 +1384,10 @@ fn create_config_watcher(
     run_config: RunConfig,
     config_file_path: PathBuf,
     stats_tx: FCUnboundedSender<StatsMessage>,
+    trace_tx: Option<trace::EventsTx>,
     mut previous_config_providers: BTreeMap<String, config::Provider>,
     mut previous_providers: Arc<BTreeMap<String, providers::Provider>>,
+    writers: Writers,
 ) {
     let start_time = Instant::now();
     let mut interval = IntervalStream::new(tokio::time::interval(Duration::from_millis(1000)));
@@ -721,13 +1439,49 @@ fn create_config_watcher(
             if file.read_to_end(&mut config_bytes).is_err() {
                 continue;
             }
+            // only the first config file is watched, but the rest are read again to merge over it
+            let merge_config_bytes = run_config
+                .merge_config_files
+                .iter()
+                .map(std::fs::read)
+                .collect::<Result<Vec<_>, _>>();
+            let merge_config_bytes = match merge_config_bytes {
+                Ok(b) => b,
+                Err(_) => continue,
+            };
+            let configs: Vec<(&Path, &[u8])> = iter::once(&config_file_path)
+                .chain(&run_config.merge_config_files)
+                .map(PathBuf::as_path)
+                .zip(
+                    iter::once(&config_bytes)
+                        .chain(&merge_config_bytes)
+                        .map(Vec::as_slice),
+                )
+                .collect();
+            let vars_file_bytes = match run_config.vars_file.as_ref().map(std::fs::read) {
+                Some(Ok(b)) => Some(b),
+                Some(Err(_)) => continue,
+                None => None,
+            };
+            let overrides = config::VarOverrides {
+                vars_file: run_config
+                    .vars_file
+                    .as_deref()
+                    .zip(vars_file_bytes.as_deref()),
+                vars: &run_config.vars,
+            };
 
             // Config file has updated, re-parse and update.
 
             // A decent amount of this code seems similar to that in `_create_run`; could
             // this be unified into a common function?
 
-            let config = config::LoadTest::from_config(&config_bytes, &config_file_path, &env_vars);
+            let config = config::LoadTest::from_configs_with_overrides(
+                &configs,
+                &env_vars,
+                run_config.environment.as_deref(),
+                overrides,
+            );
             let mut config = match config {
                 Ok(m) => m,
                 Err(e) => {
@@ -746,6 +1500,9 @@ fn create_config_watcher(
                     continue;
                 }
             };
+            if let Some(msg) = warnings_message(&config.warnings, output_format) {
+                let _ = block_on(stderr.send(MsgType::Other(msg)));
+            }
 
             let config_providers = mem::take(&mut config.providers);
 
@@ -755,6 +1512,7 @@ fn create_config_watcher(
                 config.config.general.auto_buffer_start_size,
                 &test_ended_tx,
                 &run_config.config_file,
+                run_config.seed,
             );
             let mut providers = match providers {
                 Ok((p, _)) => p,
@@ -793,7 +1551,13 @@ fn create_config_watcher(
 
             let mut run_config = run_config.clone();
             run_config.start_at = Some(Instant::now() - start_time);
+            // the max runtime is for the whole test, not for each config
+            run_config.max_runtime = run_config
+                .max_runtime
+                .map(|m| m.checked_sub(start_time.elapsed()).unwrap_or_default());
 
+            // report what was collected under the previous config before the new one takes over
+            let _ = stats_tx.unbounded_send(StatsMessage::Flush);
             if test_ended_tx
                 .send(Ok(TestEndReason::ConfigUpdate(providers.clone())))
                 .is_err()
@@ -801,14 +1565,18 @@ fn create_config_watcher(
                 break;
             }
 
+            // the test was already set up under the first config
+            config.setup.clear();
             let f = create_load_test_future(
                 config,
                 run_config,
                 test_ended_tx.clone(),
                 providers,
                 stats_tx.clone(),
+                trace_tx.clone(),
                 stdout.clone(),
                 stderr.clone(),
+                &writers,
             );
             let f = match f {
                 Ok(f) => f,
@@ -847,6 +1615,7 @@ fn create_try_run_future(
     test_ended_tx: broadcast::Sender<Result<TestEndReason, TestError>>,
     stdout: FCSender<MsgType>,
     stderr: FCSender<MsgType>,
+    writers: &Writers,
 ) -> Result<impl Future<Output = ()>, TestError> {
     debug!("create_try_run_future start");
     // create a logger for the try run
@@ -868,7 +1637,7 @@ fn create_try_run_future(
     let select = if matches!(try_config.format, TryRunFormat::Human) {
         format!(
             r#""`\n\
-            Request\n\
+            Request (${{request.url.href}})\n\
             ========================================\n\
             ${{request['start-line']}}\n\
             ${{join(request.headers_all, '\n', ': ')}}\n\
@@ -885,6 +1654,7 @@ fn create_try_run_future(
             r#"{{
                 "request": {{
                     "start-line": "request['start-line']",
+                    "url": "request.url.href",
                     "headers": "request.headers_all",
                     {}
                 }},
@@ -901,7 +1671,10 @@ fn create_try_run_future(
         )
     };
     let to = try_config.file.unwrap_or_else(|| "stdout".into());
-    let logger = config::LoggerPreProcessed::from_str(select.as_str(), &to).unwrap();
+    // several endpoints can be in a try run, so each value says which one it's from
+    let logger = config::LoggerPreProcessed::from_str(select.as_str(), &to)
+        .unwrap()
+        .with_endpoint_tags();
     if !try_config.loggers_on {
         debug!("loggers_on: {}. Clearing Loggers", try_config.loggers_on);
         config.clear_loggers();
@@ -910,6 +1683,15 @@ fn create_try_run_future(
     config.add_logger("try_run".into(), logger)?;
 
     let config_config = config.config;
+    let config_file = &try_config.config_file;
+    let tls = config_config
+        .client
+        .tls
+        .as_ref()
+        .map(|tls| tls::ClientTls::load(tls, config_file))
+        .transpose()?;
+    let connect_to_clients =
+        create_connect_to_clients(&config_config.client, &config.endpoints, tls.as_ref())?;
 
     // build and register the providers
     let (providers, response_providers) = get_providers_from_config(
@@ -917,35 +1699,43 @@ fn create_try_run_future(
         config_config.general.auto_buffer_start_size,
         &test_ended_tx,
         &try_config.config_file,
+        try_config.seed,
     )?;
 
     // setup "filters" which decide which endpoints are included in this try run
-    let filters: Vec<_> = try_config
-        .filters
-        .unwrap_or_default()
-        .into_iter()
-        .map(|try_filter| {
-            let (is_eq, key, right) = match try_filter {
-                TryFilter::Eq(key, right) => (true, key, right),
-                TryFilter::Ne(key, right) => (false, key, right),
-            };
-            let right = right.split('*').map(regex::escape).join(".*?");
-            let right = format!("^{right}$");
-            (
-                is_eq,
-                key,
-                // Should never panic, as regex::escape ensures that the result is a valid literal,
-                // and the only expressions added after are ".*?"
-                regex::Regex::new(&right).expect("filter should be a valid regex"),
-            )
-        })
-        .collect();
-    let filter_fn = move |tags: &BTreeMap<String, String>| -> bool {
-        filters.is_empty()
-            || filters.iter().any(|(is_eq, key, regex)| {
-                // "should it match" compared to "does it match"
-                *is_eq == tags.get(key).map_or(false, |left| regex.is_match(left))
+    let to_regexes = |filters: Option<Vec<TryFilter>>| -> Vec<_> {
+        filters
+            .unwrap_or_default()
+            .into_iter()
+            .map(|try_filter| {
+                let (is_eq, key, right) = match try_filter {
+                    TryFilter::Eq(key, right) => (true, key, right),
+                    TryFilter::Ne(key, right) => (false, key, right),
+                };
+                let right = right.split('*').map(regex::escape).join(".*?");
+                let right = format!("^{right}$");
+                (
+                    is_eq,
+                    key,
+                    // Should never panic, as regex::escape ensures that the result is a valid literal,
+                    // and the only expressions added after are ".*?"
+                    regex::Regex::new(&right).expect("filter should be a valid regex"),
+                )
             })
+            .collect()
+    };
+    let filters = to_regexes(try_config.filters);
+    let exclude = to_regexes(try_config.exclude);
+    let matches = |tags: &BTreeMap<String, String>,
+                   (is_eq, key, regex): &(bool, String, regex::Regex)| {
+        // "should it match" compared to "does it match"
+        *is_eq == tags.get(key).map_or(false, |left| regex.is_match(left))
+    };
+    // an endpoint is included when it matches any include filter (or there are none), unless it
+    // then matches any exclude filter
+    let filter_fn = move |tags: &BTreeMap<String, String>| -> bool {
+        (filters.is_empty() || filters.iter().any(|f| matches(tags, f)))
+            && !exclude.iter().any(|f| matches(tags, f))
     };
 
     // create the loggers
@@ -955,6 +1745,7 @@ fn create_try_run_future(
         &test_ended_tx,
         &stdout,
         &stderr,
+        writers,
     )?;
 
     let mut endpoints = Endpoints::new();
@@ -994,19 +1785,39 @@ fn create_try_run_future(
         endpoints.append(static_tags, builder, provides_set, required_providers);
     }
 
-    let client = create_http_client(config_config.client.keepalive)?;
+    let client_settings = ClientSettings::from_config(&config_config.client, tls.as_ref());
+    let proxy = client_settings.proxy.clone();
+    let client = create_http_client(client_settings)?;
 
     // create the stats channel
     let test_complete = BroadcastStream::new(test_ended_tx.subscribe());
     let stats_tx = create_try_run_stats_channel(test_complete, stderr);
 
+    let rate_limit = config_config
+        .general
+        .max_total_rate
+        .as_ref()
+        .map(request::RateLimit::new);
+    let cookie_jar = config_config
+        .client
+        .cookies
+        .then(request::CookieJar::default);
     let mut builder_ctx = request::BuilderContext {
         config: config_config,
         config_path: try_config.config_file,
         client: Arc::new(client),
+        connect_to_clients,
+        proxy,
+        cookie_jar,
         loggers,
         providers: providers.into(),
         stats_tx,
+        spans_tx: None,
+        trace_tx: None,
+        seed: try_config.seed,
+        ended_providers: Default::default(),
+        rate_limit,
+        test_killer: test_ended_tx.clone(),
     };
 
     let endpoint_calls = endpoints.build(filter_fn, &mut builder_ctx, &response_providers)?;
@@ -1032,17 +1843,30 @@ fn create_try_run_future(
 /// # Errors
 ///
 /// Returns an `Err` if the config file is missing data that a full test requires.
+#[allow(clippy::too_many_arguments)]
 fn create_load_test_future(
     config: config::LoadTest,
     run_config: RunConfig,
     test_ended_tx: broadcast::Sender<Result<TestEndReason, TestError>>,
     providers: Arc<BTreeMap<String, providers::Provider>>,
     stats_tx: FCUnboundedSender<StatsMessage>,
+    trace_tx: Option<trace::EventsTx>,
     stdout: FCSender<MsgType>,
     stderr: FCSender<MsgType>,
+    writers: &Writers,
 ) -> Result<impl Future<Output = ()>, TestError> {
     debug!("create_load_test_future start");
     config.ok_for_loadtest()?;
+    let plan = plan::LoadPlan::new(&config);
+    let listing = run_config
+        .require_all_endpoints
+        .then(|| listing::EndpointListing::new(&config));
+    let slas: BTreeMap<_, _> = config
+        .endpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(id, e)| Some((id, e.sla.clone()?)))
+        .collect();
 
     let mut duration = config.get_duration();
     if let Some(t) = run_config.start_at {
@@ -1050,6 +1874,29 @@ fn create_load_test_future(
     }
 
     let config_config = config.config;
+    let config_file = &run_config.config_file;
+    let tls = config_config
+        .client
+        .tls
+        .as_ref()
+        .map(|tls| tls::ClientTls::load(tls, config_file))
+        .transpose()?;
+    let connect_to_clients =
+        create_connect_to_clients(&config_config.client, &config.endpoints, tls.as_ref())?;
+    let client_settings = ClientSettings::from_config(&config_config.client, tls.as_ref());
+    let proxy = client_settings.proxy.clone();
+    let client = Arc::new(create_http_client(client_settings)?);
+    // connections which aren't reused would be closed as soon as they were warmed up
+    let warmup = match config_config.client.connection_warmup {
+        0 => None,
+        _ if !config_config.client.reuse_connection => None,
+        count => {
+            let targets = warmup::targets(&config.endpoints, &client, &connect_to_clients);
+            Some(warmup::warm_up(targets, count))
+        }
+    };
+    // done once the providers which fetch their values at the start have them
+    let providers_ready = future::join_all(providers.values().map(providers::Provider::ready));
 
     // create the loggers
     let loggers = get_loggers_from_config(
@@ -1058,97 +1905,422 @@ fn create_load_test_future(
         &test_ended_tx,
         &stdout,
         &stderr,
+        writers,
     )?;
 
+    // the response times of each endpoint go to the paced providers it takes values from
+    let pacers: BTreeMap<_, Vec<_>> = config
+        .endpoints
+        .iter()
+        .enumerate()
+        .filter_map(|(id, endpoint)| {
+            let pacers: Vec<_> = endpoint
+                .required_providers
+                .iter()
+                .filter_map(|(name, _)| providers.get(name)?.pacer.clone())
+                .collect();
+            (!pacers.is_empty()).then(|| (id.to_string(), pacers))
+        })
+        .collect();
+
     // create the endpoints
+    let mut endpoint_groups: BTreeMap<_, _> = BTreeMap::new();
+    // the requests each endpoint has open, by endpoint id, for `--metrics-port`
+    let mut in_flight = BTreeMap::new();
     #[allow(clippy::needless_collect)]
-    let builders: Vec<_> = config
+    let mut builders: Vec<_> = config
         .endpoints
         .into_iter()
-        .map(|mut endpoint| {
+        .enumerate()
+        .map(|(id, mut endpoint)| {
             let mut mod_interval: Option<
                 Pin<Box<dyn Stream<Item = (Instant, Option<Instant>)> + Send>>,
             > = None;
 
-            if let (Some(peak_load), Some(load_pattern)) =
+            if let Some(group) = endpoint.group.clone() {
+                let rx = endpoint_groups
+                    .entry(group)
+                    .or_insert_with(EndpointGroup::new)
+                    .add_member(endpoint.weight);
+                mod_interval = Some(Box::pin(rx));
+            } else if let (Some(peak_load), Some(load_pattern)) =
                 (endpoint.peak_load.as_ref(), endpoint.load_pattern.take())
             {
-                let mut mod_interval2 = ModInterval::new();
-                let pieces = match load_pattern {
-                    config::LoadPattern::Linear(l) => l.pieces,
+                mod_interval = match peak_load {
+                    config::PeakLoad::Fixed(peak_load) => Some(Box::pin(
+                        create_mod_interval(peak_load, load_pattern)
+                            .into_stream(run_config.start_at),
+                    )),
+                    config::PeakLoad::Provider(peak_load) => {
+                        // the providers were verified when the config was parsed
+                        let receivers = peak_load
+                            .providers()
+                            .filter_map(|name| {
+                                Some((name.clone(), providers.get(name)?.subscribe()))
+                            })
+                            .collect();
+                        Some(Box::pin(peak_load::provider_load_stream(
+                            peak_load.clone(),
+                            load_pattern,
+                            receivers,
+                            run_config.start_at,
+                        )))
+                    }
                 };
-                for piece in pieces {
-                    let (start, end) = match peak_load {
-                        config::HitsPer::Minute(m) => (
-                            PerX::minute(piece.start_percent * *m as f64),
-                            PerX::minute(piece.end_percent * *m as f64),
-                        ),
-                        config::HitsPer::Second(s) => (
-                            PerX::second(piece.start_percent * *s as f64),
-                            PerX::second(piece.end_percent * *s as f64),
-                        ),
-                    };
-                    mod_interval2.append_segment(start, piece.duration, end);
-                }
-                mod_interval = Some(Box::pin(mod_interval2.into_stream(run_config.start_at)));
             }
 
-            request::EndpointBuilder::new(endpoint, mod_interval)
+            let count = metrics::InFlight::default();
+            in_flight.insert(id.to_string(), count.clone());
+            request::EndpointBuilder::new(endpoint, mod_interval).with_in_flight(count)
         })
         .collect();
+    request::link_dependencies(builders.iter_mut().collect());
 
-    let client = create_http_client(config_config.client.keepalive)?;
+    // each group is driven by its own load pattern and hands every hit to one of its endpoints
+    let mut config_groups = config.endpoint_groups;
+    let group_calls: Vec<_> = endpoint_groups
+        .into_iter()
+        .filter_map(|(name, group)| {
+            let config_group = config_groups.remove(&name)?;
+            let load_pattern = config_group.load_pattern?;
+            let schedule = Box::pin(
+                create_mod_interval(&config_group.peak_load, load_pattern)
+                    .into_stream(run_config.start_at),
+            );
+            let f = group.dispatch(schedule, endpoint_group::rng(run_config.seed, &name));
+            let f: Box<dyn Future<Output = Result<(), TestError>> + Send + Unpin> =
+                Box::new(Box::pin(f.map(Ok)));
+            Some(f)
+        })
+        .collect();
+
+    let (spans_tx, exporter) = match &run_config.otlp {
+        Some(endpoint) => {
+            let (tx, exporter) = otlp::exporter(endpoint, client.clone());
+            (Some(tx), Some(exporter))
+        }
+        None => (None, None),
+    };
 
+    let ended_providers = request::EndedProviders::default();
+    let rate_limit = config_config
+        .general
+        .max_total_rate
+        .as_ref()
+        .map(request::RateLimit::new);
+    let cookie_jar = config_config
+        .client
+        .cookies
+        .then(request::CookieJar::default);
     let mut builder_ctx = request::BuilderContext {
         config: config_config,
         config_path: run_config.config_file,
-        client: Arc::new(client),
+        client,
+        connect_to_clients,
+        proxy,
+        cookie_jar,
         loggers,
         providers,
         stats_tx: stats_tx.clone(),
+        spans_tx,
+        trace_tx,
+        seed: run_config.seed,
+        ended_providers: ended_providers.clone(),
+        rate_limit,
+        test_killer: test_ended_tx.clone(),
+    };
+
+    // setup and teardown endpoints make a single request each, which isn't part of the stats
+    let (phase_stats_tx, _) = futures::channel::mpsc::unbounded();
+    let endpoints_stats_tx = mem::replace(&mut builder_ctx.stats_tx, phase_stats_tx);
+    let mut phase_calls = |endpoints: Vec<config::Endpoint>| -> Vec<_> {
+        endpoints
+            .into_iter()
+            .map(|endpoint| {
+                let once: Pin<Box<dyn Stream<Item = (Instant, Option<Instant>)> + Send>> =
+                    Box::pin(stream::once(future::ready((Instant::now(), None))));
+                request::EndpointBuilder::new(endpoint, Some(once))
+                    .build(&mut builder_ctx)
+                    .into_future()
+            })
+            .collect()
+    };
+    let setup_calls = phase_calls(config.setup);
+    let teardown_calls = phase_calls(config.teardown);
+    builder_ctx.stats_tx = endpoints_stats_tx;
+    // anything the teardown logs is written before the loggers are closed
+    let teardown_done = if teardown_calls.is_empty() {
+        None
+    } else {
+        let (tx, rx) = oneshot::channel();
+        writers.close_after(rx);
+        Some(tx)
     };
 
     let endpoint_calls = builders
         .into_iter()
-        .map(move |builder| builder.build(&mut builder_ctx).into_future());
-
-    let _ = stats_tx.unbounded_send(StatsMessage::Start(duration));
-    let mut f = try_join_all(endpoint_calls);
-    let mut test_timeout = Delay::new(duration);
+        .map(move |builder| builder.build(&mut builder_ctx).into_future())
+        .chain(group_calls);
+
+    let start_at = run_config.start_at.unwrap_or_default();
+    let _ = stats_tx.unbounded_send(StatsMessage::Plan(plan, start_at));
+    let _ = stats_tx.unbounded_send(StatsMessage::Pacers(pacers));
+    let _ = stats_tx.unbounded_send(StatsMessage::InFlight(in_flight));
+    let _ = stats_tx.unbounded_send(StatsMessage::Slas(slas));
+    if let Some(listing) = listing {
+        let _ = stats_tx.unbounded_send(StatsMessage::Endpoints(listing));
+    }
     let mut test_ended_rx = BroadcastStream::new(test_ended_tx.subscribe());
-    let f = future::poll_fn(move |cx| match f.poll_unpin(cx) {
-        Poll::Ready(r) => {
-            let _ = test_ended_tx.send(r.map(|_| TestEndReason::Completed));
-            Poll::Ready(())
-        }
-        Poll::Pending => match test_ended_rx.poll_next_unpin(cx).map(|_| ()) {
-            Poll::Ready(_) => Poll::Ready(()),
-            Poll::Pending => match test_timeout.poll_unpin(cx) {
-                Poll::Ready(_) => {
-                    let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
-                    Poll::Ready(())
+    let setup_ended_rx = BroadcastStream::new(test_ended_tx.subscribe());
+    let setup_ended_tx = test_ended_tx.clone();
+    let max_runtime = run_config.max_runtime;
+    // the test, and with it its duration, only starts once any connections are warmed up and the
+    // providers which fetch their values have them
+    let warmup = match warmup {
+        Some(warmup) => Either::A(warmup),
+        None => Either::B(future::ready(())),
+    };
+    let f = future::join(warmup, providers_ready).then(move |_| {
+        let _ = stats_tx.unbounded_send(StatsMessage::Start(duration));
+        let mut f = try_join_all(endpoint_calls);
+        let mut test_timeout = Delay::new(duration);
+        let mut max_runtime = max_runtime.map(Delay::new);
+        future::poll_fn(move |cx| match f.poll_unpin(cx) {
+            Poll::Ready(r) => {
+                // every endpoint finishing before the test's duration is up means providers they
+                // needed ran out
+                let ended = ended_providers.names();
+                let reason = if !ended.is_empty() && test_timeout.poll_unpin(cx).is_pending() {
+                    TestEndReason::ProviderEnded(ended)
+                } else {
+                    TestEndReason::Completed
+                };
+                let _ = test_ended_tx.send(r.map(|_| reason));
+                Poll::Ready(false)
+            }
+            Poll::Pending => match test_ended_rx.poll_next_unpin(cx) {
+                // an updated config carries on with the test, rather than ending it
+                Poll::Ready(r) => {
+                    Poll::Ready(matches!(r, Some(Ok(Ok(TestEndReason::ConfigUpdate(_))))))
                 }
-                Poll::Pending => Poll::Pending,
+                Poll::Pending => match test_timeout.poll_unpin(cx) {
+                    Poll::Ready(_) => {
+                        let _ = test_ended_tx.send(Ok(TestEndReason::Completed));
+                        Poll::Ready(false)
+                    }
+                    Poll::Pending => match max_runtime.as_mut().map(|d| d.poll_unpin(cx)) {
+                        Some(Poll::Ready(_)) => {
+                            let _ = test_ended_tx.send(Ok(TestEndReason::MaxRuntime));
+                            Poll::Ready(false)
+                        }
+                        _ => Poll::Pending,
+                    },
+                },
             },
-        },
+        })
     });
+    // the teardown runs however the test ends, unless it carries on with an updated config
+    let f = async move {
+        let config_updated =
+            run_phase(setup_calls, setup_ended_rx, &setup_ended_tx).await && f.await;
+        if !config_updated {
+            let test_ended_rx = BroadcastStream::new(setup_ended_tx.subscribe());
+            run_phase(teardown_calls, test_ended_rx, &setup_ended_tx).await;
+        }
+        drop(teardown_done);
+    };
+
+    // the endpoints (and with them every span sender) are dropped once the test ends, letting
+    // the exporter drain what is left
+    let f = match exporter {
+        Some(exporter) => Either::A(async move {
+            futures::pin_mut!(f, exporter);
+            if let future::Either::Left((_, exporter)) = future::select(f, exporter).await {
+                let _ = future::select(exporter, Delay::new(Duration::from_secs(5))).await;
+            }
+        }),
+        None => Either::B(f),
+    };
 
     debug!("create_load_test_future finish");
     Ok(f)
 }
 
-pub(crate) fn create_http_client(
-    keepalive: Duration,
-) -> Result<
-    Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>,
-    TestError,
-> {
-    let mut http = HttpConnector::new();
+/// Runs the endpoints of a `setup` or `teardown`, one after another. A fatal error ends the test,
+/// as does the test being ended from elsewhere, such as by ctrl-c.
+///
+/// Returns whether every endpoint finished.
+async fn run_phase(
+    calls: Vec<Box<dyn Future<Output = Result<(), TestError>> + Send + Unpin>>,
+    mut test_ended_rx: BroadcastStream<Result<TestEndReason, TestError>>,
+    test_ended_tx: &broadcast::Sender<Result<TestEndReason, TestError>>,
+) -> bool {
+    for call in calls {
+        match future::select(call, test_ended_rx.next()).await {
+            future::Either::Left((Ok(()), _)) => (),
+            future::Either::Left((Err(e), _)) => {
+                let _ = test_ended_tx.send(Err(e));
+                return false;
+            }
+            future::Either::Right(_) => return false,
+        }
+    }
+    true
+}
+
+fn create_mod_interval(
+    peak_load: &config::HitsPer,
+    load_pattern: config::LoadPattern,
+) -> ModInterval {
+    let mut mod_interval = ModInterval::new();
+    let pieces = match load_pattern {
+        config::LoadPattern::Linear(l) => l.pieces,
+    };
+    for piece in pieces {
+        let (start, end) = match peak_load {
+            config::HitsPer::Minute(m) => (
+                PerX::minute(piece.start_percent * *m as f64),
+                PerX::minute(piece.end_percent * *m as f64),
+            ),
+            config::HitsPer::Second(s) => (
+                PerX::second(piece.start_percent * *s as f64),
+                PerX::second(piece.end_percent * *s as f64),
+            ),
+        };
+        mod_interval.append_segment(start, piece.duration, end);
+    }
+    mod_interval
+}
+
+pub(crate) type HttpClient = Client<
+    connection_reuse::TrackReuse<
+        HttpsConnector<
+            connection_reuse::TrackOpen<
+                proxy::Proxy<
+                    connect_to::ConnectTo<
+                        HttpConnector<
+                            connection_reuse::TimedResolver<
+                                hyper::client::connect::dns::GaiResolver,
+                            >,
+                        >,
+                    >,
+                >,
+            >,
+        >,
+    >,
+>;
+
+/// How an http client connects. Clients for a test are made from its `client` section, while
+/// the default is what's used for requests pewpew makes on its own, such as to S3.
+pub(crate) struct ClientSettings<'a> {
+    pub(crate) keepalive: Duration,
+    pub(crate) reuse_connection: bool,
+    // the address every request is sent to, whatever its url, from an endpoint's `connect_to`
+    pub(crate) connect_to: Option<SocketAddr>,
+    pub(crate) http2: bool,
+    pub(crate) proxy: Option<Arc<proxy::ProxySettings>>,
+    pub(crate) tls: Option<&'a tls::ClientTls>,
+}
+
+impl<'a> ClientSettings<'a> {
+    fn from_config(client_config: &config::ClientConfig, tls: Option<&'a tls::ClientTls>) -> Self {
+        ClientSettings {
+            keepalive: client_config.keepalive,
+            reuse_connection: client_config.reuse_connection,
+            connect_to: None,
+            http2: client_config.http2,
+            proxy: proxy_settings(client_config),
+            tls,
+        }
+    }
+}
+
+impl Default for ClientSettings<'_> {
+    fn default() -> Self {
+        ClientSettings {
+            keepalive: Duration::from_secs(60),
+            reuse_connection: true,
+            connect_to: None,
+            http2: false,
+            proxy: None,
+            tls: None,
+        }
+    }
+}
+
+pub(crate) fn create_http_client(settings: ClientSettings<'_>) -> Result<HttpClient, TestError> {
+    let ClientSettings {
+        keepalive,
+        reuse_connection,
+        connect_to,
+        http2,
+        proxy,
+        tls,
+    } = settings;
+    let resolver = connection_reuse::TimedResolver(hyper::client::connect::dns::GaiResolver::new());
+    let mut http = HttpConnector::new_with_resolver(resolver);
     http.set_keepalive(Some(keepalive));
     http.set_reuse_address(true);
     http.enforce_http(false);
-    let https = HttpsConnector::from((http, TlsConnector::new()?.into()));
-    Ok(Client::builder().set_host(false).build::<_, Body>(https))
+    let http = connect_to::ConnectTo {
+        inner: http,
+        addr: connect_to,
+    };
+    let http = proxy::Proxy {
+        inner: http,
+        settings: proxy,
+    };
+    let mut tls_builder = TlsConnector::builder();
+    // with only h2 offered over tls, a server which can't speak it fails the handshake rather
+    // than answering in HTTP/1.1
+    if http2 {
+        tls_builder.request_alpns(&["h2"]);
+    }
+    if let Some(tls) = tls {
+        tls.configure(&mut tls_builder);
+    }
+    let https = HttpsConnector::from((
+        connection_reuse::TrackOpen(http),
+        tls_builder.build()?.into(),
+    ));
+    let mut builder = Client::builder();
+    builder.set_host(false);
+    if !reuse_connection {
+        builder.pool_max_idle_per_host(0);
+    }
+    // over plain http this is h2 with prior knowledge, there being no tls to negotiate it with
+    builder.http2_only(http2);
+    Ok(builder.build::<_, Body>(connection_reuse::TrackReuse(https)))
+}
+
+// the `client.proxy` settings, when there is a proxy
+fn proxy_settings(client_config: &config::ClientConfig) -> Option<Arc<proxy::ProxySettings>> {
+    client_config
+        .proxy
+        .as_ref()
+        .map(|p| Arc::new(proxy::ProxySettings::new(p)))
+}
+
+/// Creates a client for each address endpoints set as their `connect_to`. Endpoints dialing the
+/// same address share a client, and so its pool of connections.
+fn create_connect_to_clients(
+    client_config: &config::ClientConfig,
+    endpoints: &[config::Endpoint],
+    tls: Option<&tls::ClientTls>,
+) -> Result<BTreeMap<SocketAddr, Arc<HttpClient>>, TestError> {
+    let mut clients = BTreeMap::new();
+    for addr in endpoints.iter().filter_map(|e| e.connect_to) {
+        if let btree_map::Entry::Vacant(entry) = clients.entry(addr) {
+            let client = create_http_client(ClientSettings {
+                connect_to: Some(addr),
+                // the address to dial is set explicitly, so these aren't proxied
+                proxy: None,
+                ..ClientSettings::from_config(client_config, tls)
+            })?;
+            entry.insert(Arc::new(client));
+        }
+    }
+    Ok(clients)
 }
 
 type ProvidersResult = Result<(BTreeMap<String, providers::Provider>, BTreeSet<String>), TestError>;
@@ -1158,12 +2330,30 @@ fn get_providers_from_config(
     auto_size: usize,
     test_ended_tx: &broadcast::Sender<Result<TestEndReason, TestError>>,
     config_path: &Path,
+    seed: Option<u64>,
 ) -> ProvidersResult {
     let mut providers = BTreeMap::new();
     let mut response_providers = BTreeSet::new();
     let default_buffer_size = config::default_auto_buffer_start_size();
+    // shared by the http providers, only created when there are any
+    let mut http_client = None;
     for (name, template) in config_providers {
-        let provider = match template.clone() {
+        // a paced provider is created as the provider it wraps, with its values slowed down
+        let (template, pacer) = match template.clone() {
+            config::Provider::Paced(template, target) => {
+                (*template, Some(providers::Pacer::new(target)))
+            }
+            template => (template, None),
+        };
+        // a mapped provider is created as the provider it wraps, with its values run through the map
+        let (template, mapper) = match template {
+            config::Provider::Mapped(template, map) => (
+                *template,
+                Some(providers::Mapper::new(map, name, test_ended_tx.clone())),
+            ),
+            template => (template, None),
+        };
+        let provider = match template {
             config::Provider::File(mut template) => {
                 // the auto_buffer_start_size is not the default
                 if auto_size != default_buffer_size {
@@ -1172,9 +2362,9 @@ fn get_providers_from_config(
                     }
                 }
                 util::tweak_path(&mut template.path, config_path);
-                providers::file(template, test_ended_tx.clone(), name)?
+                providers::file(template, test_ended_tx.clone(), name, seed, mapper, pacer)?
             }
-            config::Provider::Range(range) => providers::range(range, name),
+            config::Provider::Range(range) => providers::range(range, name, seed, mapper, pacer),
             config::Provider::Response(mut template) => {
                 // the auto_buffer_start_size is not the default
                 if auto_size != default_buffer_size {
@@ -1185,7 +2375,37 @@ fn get_providers_from_config(
                 response_providers.insert(name.clone());
                 providers::response(template, name)
             }
-            config::Provider::List(values) => providers::list(values.clone(), name),
+            config::Provider::List(values) => providers::list(values, name, seed, mapper, pacer),
+            config::Provider::Sqlite(mut template) => {
+                // the auto_buffer_start_size is not the default
+                if auto_size != default_buffer_size {
+                    if let config::Limit::Dynamic(_) = &template.buffer {
+                        template.buffer = config::Limit::Dynamic(auto_size);
+                    }
+                }
+                util::tweak_path(&mut template.path, config_path);
+                providers::sqlite(template, test_ended_tx.clone(), name, mapper, pacer)?
+            }
+            config::Provider::Http(mut template) => {
+                // the auto_buffer_start_size is not the default
+                if auto_size != default_buffer_size {
+                    if let config::Limit::Dynamic(_) = &template.buffer {
+                        template.buffer = config::Limit::Dynamic(auto_size);
+                    }
+                }
+                let client = match &http_client {
+                    Some(client) => Arc::clone(client),
+                    None => {
+                        let client = Arc::new(create_http_client(ClientSettings::default())?);
+                        http_client.insert(client).clone()
+                    }
+                };
+                let test_killer = test_ended_tx.clone();
+                providers::http_seed(template, client, test_killer, name, seed, mapper, pacer)
+            }
+            config::Provider::Mapped(..) | config::Provider::Paced(..) => {
+                unreachable!("a provider has a single map and pace")
+            }
         };
         providers.insert(name.clone(), provider);
     }
@@ -1198,29 +2418,46 @@ fn get_loggers_from_config(
     test_ended_tx: &broadcast::Sender<Result<TestEndReason, TestError>>,
     stdout: &FCSender<MsgType>,
     stderr: &FCSender<MsgType>,
+    writers: &Writers,
 ) -> Result<BTreeMap<String, providers::Logger>, TestError> {
     config_loggers
         .into_iter()
         .map(|(name, mut template)| {
             let to = mem::take(&mut template.to);
-            let name2 = name.clone();
-            let writer = match to.as_str() {
-                "stdout" => stdout.clone(),
-                "stderr" => stderr.clone(),
-                _ => {
-                    let mut file_path = results_dir.map_or_else(PathBuf::new, Clone::clone);
-                    file_path.push(to);
-                    let f = File::create(&file_path)
-                        .map_err(|e| TestError::CannotCreateLoggerFile(name2, e.into()))?;
-                    blocking_writer(
-                        f,
-                        test_ended_tx.clone(),
-                        file_path.to_string_lossy().to_string(),
-                    )
-                    .0
-                }
-            };
-            let sender = providers::logger(template, test_ended_tx, writer);
+            let logger_writers = to
+                .into_iter()
+                .map(|to| {
+                    let writer = match to.as_str() {
+                        "stdout" => stdout.clone(),
+                        "stderr" => stderr.clone(),
+                        _ => {
+                            let mut file_path = results_dir.map_or_else(PathBuf::new, Clone::clone);
+                            file_path.push(to);
+                            let f = File::create(&file_path).map_err(|e| {
+                                TestError::CannotCreateLoggerFile(name.clone(), e.into())
+                            })?;
+                            let file_name = file_path.to_string_lossy().to_string();
+                            let (writer, done) = match template.compress {
+                                Some(config::LoggerCompression::Gzip) => {
+                                    blocking_gzip_writer(f, test_ended_tx.clone(), file_name)
+                                        .map_err(|e| {
+                                            TestError::CannotCreateLoggerFile(
+                                                name.clone(),
+                                                e.into(),
+                                            )
+                                        })?
+                                }
+                                None => blocking_writer(f, test_ended_tx.clone(), file_name),
+                            };
+                            writers.close_on_shutdown(&writer, done);
+                            writers.add_file(file_path);
+                            writer
+                        }
+                    };
+                    Ok(writer)
+                })
+                .collect::<Result<_, TestError>>()?;
+            let sender = providers::logger(template, test_ended_tx, logger_writers);
             Ok((name, sender))
         })
         .collect()
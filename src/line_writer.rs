@@ -1,17 +1,27 @@
 use futures::{
     channel::{mpsc, oneshot},
     executor::block_on_stream,
+    future::join_all,
+    SinkExt, StreamExt,
 };
+use libflate::gzip;
+use serde_json as json;
 use tokio::{sync::broadcast, task::spawn_blocking};
 
+use crate::util::str_to_json;
 use crate::{TestEndReason, TestError};
 
-use std::io::Write;
+use std::{
+    io::Write,
+    mem,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
 // The `Sender` returned from `blocking_writer` accepts two types of messages `Final` and `Other`
 // `Other` messages are written out to the writer as soon as they are received
 // `Final` are written after the internal `futures::mpsc::Receiver` closes
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum MsgType {
     Final(String),
     Other(String),
@@ -22,10 +32,40 @@ pub enum MsgType {
 // The `Sender` is used to send messages into the writer.
 // The `Receiver` is used to signal when this writer has finished
 pub fn blocking_writer<W: Write + Send + 'static>(
-    mut writer: W,
+    writer: W,
     test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
     file_name: String,
 ) -> (mpsc::Sender<MsgType>, oneshot::Receiver<()>) {
+    blocking_writer_with_finish(writer, |_| Ok(()), test_killer, file_name)
+}
+
+// The same as `blocking_writer` except everything is gzipped on its way to `writer`. The gzip
+// stream is finished once everything sent has been written, before the `Receiver` signals
+pub fn blocking_gzip_writer<W: Write + Send + 'static>(
+    writer: W,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    file_name: String,
+) -> Result<(mpsc::Sender<MsgType>, oneshot::Receiver<()>), std::io::Error> {
+    let encoder = gzip::Encoder::new(writer)?;
+    let finish = |encoder: gzip::Encoder<W>| encoder.finish().into_result().map(drop);
+    Ok(blocking_writer_with_finish(
+        encoder,
+        finish,
+        test_killer,
+        file_name,
+    ))
+}
+
+fn blocking_writer_with_finish<W, F>(
+    mut writer: W,
+    finish: F,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    file_name: String,
+) -> (mpsc::Sender<MsgType>, oneshot::Receiver<()>)
+where
+    W: Write + Send + 'static,
+    F: FnOnce(W) -> Result<(), std::io::Error> + Send + 'static,
+{
     // create the needed channels
     let (tx, rx) = mpsc::channel(5);
     let (done_tx, done_rx) = oneshot::channel();
@@ -50,14 +90,219 @@ pub fn blocking_writer<W: Write + Send + 'static>(
                 }
             }
         }
-        if let Some(s) = final_msg {
-            // if there's a final message write that to the `Writer`
-            if let Err(e) = writer.write_all(s.as_bytes()) {
-                let _ = test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
-            }
+        // if there's a final message write that to the `Writer`
+        let written = final_msg.map_or(Ok(()), |s| writer.write_all(s.as_bytes()));
+        if let Err(e) = written.and_then(|_| finish(writer)) {
+            let _ = test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
         }
         let _ = done_tx.send(());
         log::trace!("{{\"blocking_writer spawn_blocking exit");
     });
     (tx, done_rx)
 }
+
+// Passes messages through to `tx`, except that a `Final` message is held back until every `Sender`
+// for the returned channel has been dropped, and then passed along as an `Other`. This is what
+// lets one writer take several runs of a test, each of which ends with its own `Final` summary.
+// The returned `Receiver` signals once everything has been passed along
+pub fn final_as_other(
+    mut tx: mpsc::Sender<MsgType>,
+) -> (mpsc::Sender<MsgType>, oneshot::Receiver<()>) {
+    let (forward_tx, mut rx) = mpsc::channel(5);
+    let (done_tx, done_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let mut final_msg = None;
+        while let Some(msg) = rx.next().await {
+            match msg {
+                MsgType::Final(s) => final_msg = Some(s),
+                MsgType::Other(s) => {
+                    let _ = tx.send(MsgType::Other(s)).await;
+                }
+            }
+        }
+        if let Some(s) = final_msg {
+            let _ = tx.send(MsgType::Other(s)).await;
+        }
+        let _ = done_tx.send(());
+    });
+    (forward_tx, done_rx)
+}
+
+/// The writers (for file loggers and the stats file) which have to finish before a run exits.
+/// Writers which are only dropped when the test ends are left to a `shutdown` rather than to
+/// whenever the last `Sender` happens to be dropped.
+#[derive(Clone, Default)]
+pub struct Writers {
+    writers: Arc<Mutex<Vec<(Option<mpsc::Sender<MsgType>>, oneshot::Receiver<()>)>>>,
+    // work which can still write, such as a `teardown`, so it's waited on before any channel closes
+    before_close: Arc<Mutex<Vec<oneshot::Receiver<()>>>>,
+    // the files written by loggers, for `--results-s3` to upload
+    files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl Writers {
+    // the writer's channel is closed at shutdown, after which anything already sent is written
+    pub fn close_on_shutdown(&self, tx: &mpsc::Sender<MsgType>, done: oneshot::Receiver<()>) {
+        self.push(Some(tx.clone()), done);
+    }
+
+    // the writer finishes on its own once its last `Sender` is dropped
+    pub fn wait_on_shutdown(&self, done: oneshot::Receiver<()>) {
+        self.push(None, done);
+    }
+
+    // the writers' channels aren't closed until `done`, so what is written before then isn't lost
+    pub fn close_after(&self, done: oneshot::Receiver<()>) {
+        self.before_close
+            .lock()
+            .expect("writers lock should not be poisoned")
+            .push(done);
+    }
+
+    // a file written by one of the writers
+    pub fn add_file(&self, file: PathBuf) {
+        self.files
+            .lock()
+            .expect("writers lock should not be poisoned")
+            .push(file);
+    }
+
+    // the files added since the last time they were taken
+    pub fn take_files(&self) -> Vec<PathBuf> {
+        mem::take(
+            &mut *self
+                .files
+                .lock()
+                .expect("writers lock should not be poisoned"),
+        )
+    }
+
+    fn push(&self, tx: Option<mpsc::Sender<MsgType>>, done: oneshot::Receiver<()>) {
+        self.writers
+            .lock()
+            .expect("writers lock should not be poisoned")
+            .push((tx, done));
+    }
+
+    /// Closes the writers' channels and waits for everything sent to them to be written
+    pub async fn shutdown(&self) {
+        let before_close = mem::take(
+            &mut *self
+                .before_close
+                .lock()
+                .expect("writers lock should not be poisoned"),
+        );
+        join_all(before_close).await;
+        let writers = mem::take(
+            &mut *self
+                .writers
+                .lock()
+                .expect("writers lock should not be poisoned"),
+        );
+        let done = writers.into_iter().map(|(tx, done)| {
+            if let Some(mut tx) = tx {
+                tx.close_channel();
+            }
+            done
+        });
+        join_all(done).await;
+    }
+}
+
+// Similar to `blocking_writer`, but instead of writing messages as they come in, each line of every
+// message is parsed as json and collected. Once the channels close, everything collected is written
+// to the writer as a single json array.
+// Two `Sender`s are returned. Messages sent into the second `Sender` are always placed after the
+// ones sent into the first (including its `Final` messages), which is used for the message saying
+// why the test ended.
+pub fn blocking_json_array_writer<W: Write + Send + 'static>(
+    mut writer: W,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    file_name: String,
+) -> (
+    mpsc::Sender<MsgType>,
+    mpsc::Sender<MsgType>,
+    oneshot::Receiver<()>,
+) {
+    let (tx, rx) = mpsc::channel(5);
+    let (trailer_tx, trailer_rx) = mpsc::channel(5);
+    let (done_tx, done_rx) = oneshot::channel();
+
+    log::trace!("{{\"blocking_json_array_writer spawn_blocking start");
+    spawn_blocking(move || {
+        log::trace!("{{\"blocking_json_array_writer spawn_blocking enter");
+        let mut events = Vec::new();
+        let mut final_events = Vec::new();
+        let push_lines = |events: &mut Vec<json::Value>, s: String| {
+            events.extend(
+                s.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(str_to_json),
+            );
+        };
+
+        for msg in block_on_stream(rx) {
+            match msg {
+                MsgType::Final(s) => push_lines(&mut final_events, s),
+                MsgType::Other(s) => push_lines(&mut events, s),
+            }
+        }
+        events.append(&mut final_events);
+        for msg in block_on_stream(trailer_rx) {
+            let (MsgType::Final(s) | MsgType::Other(s)) = msg;
+            push_lines(&mut events, s);
+        }
+
+        let mut output = json::Value::Array(events).to_string();
+        output.push('\n');
+        if let Err(e) = writer.write_all(output.as_bytes()) {
+            let _ = test_killer.send(Err(TestError::WritingToFile(file_name, e.into())));
+        }
+        let _ = done_tx.send(());
+        log::trace!("{{\"blocking_json_array_writer spawn_blocking exit");
+    });
+    (tx, trailer_tx, done_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::SinkExt;
+    use test_common::TestWriter;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn json_array_writer_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (test_killer, _) = broadcast::channel(1);
+            let writer = TestWriter::new();
+            let (mut tx, mut trailer_tx, done) =
+                blocking_json_array_writer(writer.clone(), test_killer, "".into());
+
+            let _ = trailer_tx
+                .send(MsgType::Final("{\"type\":\"end\"}\n".into()))
+                .await;
+            let _ = tx
+                .send(MsgType::Other("{\"type\":\"start\"}\n".into()))
+                .await;
+            let _ = tx
+                .send(MsgType::Final("{\"a\":1}\n\n{\"b\":2}\n".into()))
+                .await;
+            let _ = tx.send(MsgType::Other("foo\n".into())).await;
+            drop(tx);
+            drop(trailer_tx);
+            let _ = done.await;
+
+            let left: json::Value = json::from_str(&writer.get_string()).unwrap();
+            let right = json::json!([
+                {"type": "start"},
+                "foo",
+                {"a": 1},
+                {"b": 2},
+                {"type": "end"}
+            ]);
+            assert_eq!(left, right);
+        });
+    }
+}
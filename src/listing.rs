@@ -0,0 +1,128 @@
+use serde_json as json;
+
+use std::{collections::BTreeMap, fmt::Write};
+
+/// An endpoint's id, method, url and tags as they are known before the test starts. Any part of
+/// the url or a tag which comes from a provider is shown as `*`, the same wildcard `--include`
+/// filters use.
+#[derive(Debug)]
+struct ListedEndpoint {
+    id: usize,
+    method: String,
+    url: String,
+    tags: BTreeMap<String, String>,
+}
+
+/// The endpoints in a config, for building `--include` filters without running the test
+#[derive(Debug)]
+pub(crate) struct EndpointListing(Vec<ListedEndpoint>);
+
+impl EndpointListing {
+    pub(crate) fn new(config: &config::LoadTest) -> Self {
+        let endpoints = config
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(id, e)| ListedEndpoint {
+                id,
+                method: e.method.to_string(),
+                url: config::url_without_userinfo(&e.url.evaluate_with_star()).into_owned(),
+                tags: e
+                    .tags
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.evaluate_with_star()))
+                    .collect(),
+            })
+            .collect();
+        EndpointListing(endpoints)
+    }
+
+    /// `id: METHOD url` for each endpoint whose id `fired` says didn't send any requests
+    pub(crate) fn idle(&self, fired: impl Fn(usize) -> bool) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|e| !fired(e.id))
+            .map(|e| format!("{}: {} {}", e.id, e.method, e.url))
+            .collect()
+    }
+
+    pub(crate) fn to_human(&self) -> String {
+        let mut out = String::new();
+        for e in &self.0 {
+            let _ = writeln!(out, "endpoint {}: {} {}", e.id, e.method, e.url);
+            for (k, v) in &e.tags {
+                let _ = writeln!(out, "  {k}: {v}");
+            }
+        }
+        out
+    }
+
+    /// One line of json per endpoint
+    pub(crate) fn to_json(&self) -> String {
+        let mut out = String::new();
+        for e in &self.0 {
+            let line = json::json!({
+                "type": "endpoint",
+                "id": e.id,
+                "method": e.method,
+                "url": e.url,
+                "tags": e.tags,
+            });
+            let _ = writeln!(out, "{line}");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn includes_generated_tags() {
+        let config = config::LoadTest::from_config(
+            br#"
+            providers:
+              id:
+                range: {}
+            endpoints:
+              - url: http://localhost/foo
+                tags:
+                  name: foo
+              - method: POST
+                url: http://localhost/bar/${id}
+                tags:
+                  name: bar ${id}
+            "#,
+            Path::new("./"),
+            &Default::default(),
+        )
+        .unwrap();
+        let listing = EndpointListing::new(&config);
+
+        let tags: Vec<_> = listing.0.iter().map(|e| &e.tags).collect();
+        assert_eq!(
+            tags[0].iter().collect::<Vec<_>>(),
+            [
+                (&"_id".to_string(), &"0".to_string()),
+                (&"method".to_string(), &"GET".to_string()),
+                (&"name".to_string(), &"foo".to_string()),
+                (&"url".to_string(), &"http://localhost/foo".to_string()),
+            ]
+        );
+        assert_eq!(tags[1]["_id"], "1");
+        assert_eq!(tags[1]["method"], "POST");
+        assert_eq!(tags[1]["url"], "http://localhost/bar/*");
+        assert_eq!(tags[1]["name"], "bar *");
+
+        let lines: Vec<json::Value> = listing
+            .to_json()
+            .lines()
+            .map(|l| json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["url"], "http://localhost/bar/*");
+        assert_eq!(lines[1]["tags"]["method"], "POST");
+    }
+}
@@ -0,0 +1,387 @@
+use crate::stats::{ResponseStat, StatKind, Tags};
+
+use futures::{channel::oneshot, future, FutureExt};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::debug;
+use tokio::task::JoinHandle;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    fmt::Write,
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// how far back the request rate is averaged over
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// The server for `--metrics-port`, which serves the stats at `/metrics` in the Prometheus text
+/// format while the test runs, fed the same stats as the summaries
+pub(crate) struct MetricsServer {
+    local_addr: SocketAddr,
+    metrics: Arc<Mutex<Metrics>>,
+    stop: oneshot::Sender<()>,
+    server: JoinHandle<()>,
+}
+
+struct Metrics {
+    started: Instant,
+    endpoints: BTreeMap<Tags, EndpointMetrics>,
+    // by endpoint id, the requests each endpoint has open
+    in_flight: BTreeMap<String, InFlight>,
+}
+
+/// The requests an endpoint has sent which haven't ended yet, counted by the endpoint as it sends
+/// them and read by the metrics server
+#[derive(Clone, Debug, Default)]
+pub(crate) struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    /// Count a request as in flight until the returned guard is dropped, once the request has
+    /// ended with a response or an error
+    pub(crate) fn start(&self) -> InFlightGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(self.0.clone())
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+struct EndpointMetrics {
+    requests: u64,
+    // by the category of the error
+    errors: BTreeMap<&'static str, u64>,
+    // when each request in the last `RATE_WINDOW` ended
+    recent: VecDeque<Instant>,
+}
+
+impl EndpointMetrics {
+    fn forget_old(&mut self, now: Instant) {
+        while let Some(ended) = self.recent.front() {
+            if now.duration_since(*ended) <= RATE_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
+
+impl MetricsServer {
+    /// Start serving on the port, on every interface. Must be called from within the runtime.
+    pub(crate) fn bind(port: u16) -> Result<Self, io::Error> {
+        let listener = std::net::TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let metrics = Arc::new(Mutex::new(Metrics {
+            started: Instant::now(),
+            endpoints: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+        }));
+        let metrics2 = metrics.clone();
+        let make_svc = make_service_fn(move |_| {
+            let metrics = metrics2.clone();
+            let service =
+                service_fn(move |req| future::ok::<_, Infallible>(respond(req, &metrics)));
+            future::ok::<_, Infallible>(service)
+        });
+        let (stop, stop_rx) = oneshot::channel();
+        let server = Server::from_tcp(listener)
+            .map_err(io::Error::other)?
+            .serve(make_svc)
+            // dropping the server without `shutdown`, as when the test fails, stops it too
+            .with_graceful_shutdown(stop_rx.map(|_| ()));
+        let server = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                debug!("metrics server error: {e}");
+            }
+        });
+        Ok(MetricsServer {
+            local_addr,
+            metrics,
+            stop,
+            server,
+        })
+    }
+
+    #[cfg(test)]
+    fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Count a response or error toward its endpoint's metrics
+    pub(crate) fn record(&self, stat: &ResponseStat) {
+        // an attempt which was retried is part of the request it was retried for
+        if stat.retry {
+            return;
+        }
+        let now = Instant::now();
+        let mut metrics = self
+            .metrics
+            .lock()
+            .expect("metrics lock should not be poisoned");
+        if !metrics.endpoints.contains_key(&*stat.tags) {
+            metrics
+                .endpoints
+                .insert((*stat.tags).clone(), Default::default());
+        }
+        let endpoint = metrics
+            .endpoints
+            .get_mut(&*stat.tags)
+            .expect("endpoint should have just been added");
+        endpoint.requests += 1;
+        if let StatKind::RecoverableError(e) = &stat.kind {
+            *endpoint.errors.entry(e.category()).or_default() += 1;
+        }
+        endpoint.recent.push_back(now);
+        endpoint.forget_old(now);
+    }
+
+    /// The counts of the requests each endpoint has open, by endpoint id
+    pub(crate) fn set_in_flight(&self, in_flight: BTreeMap<String, InFlight>) {
+        self.metrics
+            .lock()
+            .expect("metrics lock should not be poisoned")
+            .in_flight = in_flight;
+    }
+
+    /// Stop serving, waiting until the port is free again so another run can use it
+    pub(crate) async fn shutdown(self) {
+        debug!("shutting down the metrics server on {}", self.local_addr);
+        let _ = self.stop.send(());
+        let _ = self.server.await;
+    }
+}
+
+fn respond(req: Request<Body>, metrics: &Mutex<Metrics>) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+    let mut metrics = metrics.lock().expect("metrics lock should not be poisoned");
+    let mut response = Response::new(render(&mut metrics).into());
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        "text/plain; version=0.0.4"
+            .parse()
+            .expect("valid header value"),
+    );
+    response
+}
+
+// the metrics in the Prometheus text format
+fn render(metrics: &mut Metrics) -> String {
+    let now = Instant::now();
+    // early on the rate is over however long the test has been running
+    let window = now
+        .duration_since(metrics.started)
+        .min(RATE_WINDOW)
+        .as_secs_f64()
+        .max(1.0);
+    for endpoint in metrics.endpoints.values_mut() {
+        endpoint.forget_old(now);
+    }
+    let endpoints = &metrics.endpoints;
+    let mut out = String::new();
+
+    write_header(
+        &mut out,
+        "pewpew_requests_total",
+        "counter",
+        "Requests which got a response or ended in an error.",
+    );
+    for (tags, endpoint) in endpoints {
+        let labels = labels(tags, None);
+        let _ = writeln!(
+            out,
+            "pewpew_requests_total{{{labels}}} {}",
+            endpoint.requests
+        );
+    }
+
+    write_header(
+        &mut out,
+        "pewpew_errors_total",
+        "counter",
+        "Requests which ended in an error, by the kind of error.",
+    );
+    for (tags, endpoint) in endpoints {
+        for (kind, count) in &endpoint.errors {
+            let labels = labels(tags, Some(("kind", kind)));
+            let _ = writeln!(out, "pewpew_errors_total{{{labels}}} {count}");
+        }
+    }
+
+    write_header(
+        &mut out,
+        "pewpew_requests_per_second",
+        "gauge",
+        "Requests ended per second over the last 10 seconds.",
+    );
+    for (tags, endpoint) in endpoints {
+        let labels = labels(tags, None);
+        let rps = endpoint.recent.len() as f64 / window;
+        let _ = writeln!(out, "pewpew_requests_per_second{{{labels}}} {rps}");
+    }
+
+    // an endpoint's tags can come from its responses, which a request in flight doesn't have yet,
+    // so these are only by endpoint
+    write_header(
+        &mut out,
+        "pewpew_in_flight_requests",
+        "gauge",
+        "Requests sent which haven't yet got a response or ended in an error.",
+    );
+    for (id, in_flight) in &metrics.in_flight {
+        let labels = format!("endpoint=\"{}\"", escape_label_value(id));
+        let count = in_flight.count();
+        let _ = writeln!(out, "pewpew_in_flight_requests{{{labels}}} {count}");
+    }
+    out
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+// an endpoint's tags as labels, with its `_id` as `endpoint`
+fn labels(tags: &Tags, extra: Option<(&str, &str)>) -> String {
+    tags.iter()
+        .map(|(name, value)| match name.as_str() {
+            "_id" => ("endpoint".into(), value.as_str()),
+            _ => (label_name(name), value.as_str()),
+        })
+        .chain(extra.map(|(name, value)| (name.to_string(), value)))
+        .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// label names can only have letters, digits and underscores, can't start with a digit and names
+// starting with `__` are reserved
+fn label_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) || name.starts_with("__") {
+        format!("tag{name}")
+    } else {
+        name
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RecoverableError;
+    use std::time::SystemTime;
+
+    fn stat(tags: &Arc<Tags>, kind: StatKind, retry: bool) -> ResponseStat {
+        ResponseStat {
+            connection_reused: None,
+            kind,
+            rtt: Some(250_000),
+            retry,
+            success: None,
+            time: SystemTime::now(),
+            tags: tags.clone(),
+            timing: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_metrics_in_the_prometheus_format() {
+        let server = MetricsServer::bind(0).unwrap();
+        let tags: Arc<Tags> = Arc::new(BTreeMap::from([
+            ("_id".into(), "0".into()),
+            ("method".into(), "GET".into()),
+            ("url".into(), "http://localhost/\"quoted\"".into()),
+            ("content-type".into(), "json".into()),
+        ]));
+        server.record(&stat(&tags, StatKind::Response(200), false));
+        server.record(&stat(&tags, StatKind::Response(200), false));
+        let timeout = RecoverableError::Timeout(SystemTime::now());
+        server.record(&stat(&tags, StatKind::RecoverableError(timeout), false));
+        // not counted, the request it was retried for is
+        server.record(&stat(&tags, StatKind::Response(503), true));
+        let in_flight = InFlight::default();
+        server.set_in_flight(BTreeMap::from([("0".into(), in_flight.clone())]));
+        let _first = in_flight.start();
+        let _second = in_flight.start();
+        // one which has ended isn't in flight
+        drop(in_flight.start());
+
+        let client = hyper::Client::new();
+        let uri = format!("http://127.0.0.1:{}/metrics", server.local_addr().port());
+        let response = client.get(uri.parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "text/plain; version=0.0.4"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+
+        // every line is a comment or a metric with its labels and a number
+        let mut samples = BTreeMap::new();
+        for line in body.lines() {
+            if line.starts_with("# HELP ") || line.starts_with("# TYPE ") {
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            let value: f64 = value.parse().unwrap();
+            let (name, labels) = series.split_once('{').unwrap();
+            assert!(labels.ends_with('}'), "{}", line);
+            samples.insert((name.to_string(), labels.to_string()), value);
+        }
+        let labels =
+            r#"endpoint="0",content_type="json",method="GET",url="http://localhost/\"quoted\""}"#;
+        let sample = |name: &str, labels: &str| samples[&(name.to_string(), labels.to_string())];
+        assert_eq!(sample("pewpew_requests_total", labels), 3.0);
+        let timeouts = labels.replace('}', r#",kind="timeout"}"#);
+        assert_eq!(sample("pewpew_errors_total", &timeouts), 1.0);
+        assert!(sample("pewpew_requests_per_second", labels) > 0.0);
+        assert_eq!(sample("pewpew_in_flight_requests", r#"endpoint="0"}"#), 2.0);
+        assert!(body.contains("# TYPE pewpew_requests_total counter\n"));
+        assert!(body.contains("# TYPE pewpew_in_flight_requests gauge\n"));
+
+        let uri = format!("http://127.0.0.1:{}/other", server.local_addr().port());
+        let response = client.get(uri.parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), 404);
+
+        // the port is free again once it's shut down
+        let addr = server.local_addr();
+        server.shutdown().await;
+        std::net::TcpListener::bind(("0.0.0.0", addr.port())).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+use crate::HttpClient;
+
+use futures::{
+    channel::mpsc::{self as futures_channel, UnboundedSender},
+    Future, StreamExt,
+};
+use hyper::{header::CONTENT_TYPE, Body, Method, Request};
+use log::warn;
+use rand::Rng;
+use serde_json as json;
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// the most spans sent to the collector in a single export request
+const MAX_BATCH_SIZE: usize = 512;
+
+pub(crate) type SpansTx = UnboundedSender<Span>;
+
+pub(crate) struct Span {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: BTreeMap<String, json::Value>,
+    error: Option<String>,
+}
+
+/// A span for a request which is still in flight. Finishing it queues the span for export.
+pub(crate) struct PendingSpan {
+    tx: SpansTx,
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    name: String,
+    start: SystemTime,
+    attributes: BTreeMap<String, json::Value>,
+}
+
+impl PendingSpan {
+    pub(crate) fn new(tx: SpansTx, method: &str, tags: &BTreeMap<String, String>) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut attributes: BTreeMap<_, json::Value> = tags
+            .iter()
+            .map(|(k, v)| (format!("pewpew.tag.{k}"), v.as_str().into()))
+            .collect();
+        if let Some(id) = tags.get("_id") {
+            attributes.insert("pewpew.endpoint.id".into(), id.as_str().into());
+        }
+        attributes.insert("http.method".into(), method.into());
+        PendingSpan {
+            tx,
+            trace_id: rng.gen(),
+            span_id: rng.gen(),
+            name: format!("{method} {}", tags.get("url").map_or("", String::as_str)),
+            start: SystemTime::now(),
+            attributes,
+        }
+    }
+
+    /// The w3c `traceparent` header value which links the server's spans to this one
+    pub(crate) fn traceparent(&self) -> String {
+        format!("00-{}-{}-01", hex(&self.trace_id), hex(&self.span_id))
+    }
+
+    pub(crate) fn finish(self, status: Result<u16, String>) {
+        let mut attributes = self.attributes;
+        let error = match status {
+            Ok(status) => {
+                attributes.insert("http.status_code".into(), status.into());
+                None
+            }
+            Err(e) => Some(e),
+        };
+        let span = Span {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            name: self.name,
+            start: self.start,
+            end: SystemTime::now(),
+            attributes,
+            error,
+        };
+        let _ = self.tx.unbounded_send(span);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn attribute_value(value: &json::Value) -> json::Value {
+    match value {
+        json::Value::Number(n) if n.is_i64() || n.is_u64() => {
+            json::json!({ "intValue": n.to_string() })
+        }
+        json::Value::String(s) => json::json!({ "stringValue": s }),
+        _ => json::json!({ "stringValue": value.to_string() }),
+    }
+}
+
+// builds the OTLP/HTTP json encoding of an ExportTraceServiceRequest
+fn export_request(spans: &[Span]) -> json::Value {
+    let spans: Vec<_> = spans
+        .iter()
+        .map(|span| {
+            let attributes: Vec<_> = span
+                .attributes
+                .iter()
+                .map(|(k, v)| json::json!({ "key": k, "value": attribute_value(v) }))
+                .collect();
+            // 1 is STATUS_CODE_OK, 2 is STATUS_CODE_ERROR
+            let status = match &span.error {
+                Some(message) => json::json!({ "code": 2, "message": message }),
+                None => json::json!({ "code": 1 }),
+            };
+            json::json!({
+                "traceId": hex(&span.trace_id),
+                "spanId": hex(&span.span_id),
+                "name": span.name,
+                // SPAN_KIND_CLIENT
+                "kind": 3,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": attributes,
+                "status": status,
+            })
+        })
+        .collect();
+    json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": "pewpew" } }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "pewpew", "version": clap::crate_version!() },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.into()
+    } else {
+        format!("{endpoint}/v1/traces")
+    }
+}
+
+/// Creates the channel spans are sent on and the future which exports them to the collector at
+/// `endpoint`. Spans which queue up while an export is in progress are sent together in the
+/// next export. The future completes once every sender is dropped and the queue is drained.
+pub(crate) fn exporter(
+    endpoint: &str,
+    client: Arc<HttpClient>,
+) -> (SpansTx, impl Future<Output = ()> + Send) {
+    let url = traces_url(endpoint);
+    let (tx, rx) = futures_channel::unbounded();
+    let f = rx.ready_chunks(MAX_BATCH_SIZE).for_each(move |spans| {
+        let body = export_request(&spans).to_string();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body));
+        let response = request.map(|r| client.request(r));
+        let url = url.clone();
+        async move {
+            match response {
+                Ok(response) => match response.await {
+                    Ok(r) if !r.status().is_success() => {
+                        warn!("otlp export to `{}` failed with status {}", url, r.status())
+                    }
+                    Err(e) => warn!("otlp export to `{}` failed: {}", url, e),
+                    _ => (),
+                },
+                Err(e) => warn!("invalid otlp endpoint `{}`: {}", url, e),
+            }
+        }
+    });
+    (tx, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traceparent_format() {
+        let (tx, _rx) = futures_channel::unbounded();
+        let span = PendingSpan::new(tx, "GET", &BTreeMap::new());
+        let traceparent = span.traceparent();
+        let pieces: Vec<_> = traceparent.split('-').collect();
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces[0], "00");
+        assert_eq!(pieces[1].len(), 32);
+        assert_eq!(pieces[2].len(), 16);
+        assert_eq!(pieces[3], "01");
+    }
+
+    #[test]
+    fn traces_url_works() {
+        assert_eq!(
+            traces_url("http://localhost:4318"),
+            "http://localhost:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://localhost:4318/v1/traces/"),
+            "http://localhost:4318/v1/traces"
+        );
+    }
+}
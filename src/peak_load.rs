@@ -0,0 +1,154 @@
+use crate::create_mod_interval;
+use crate::providers::ProviderReceiver;
+
+use futures::{
+    stream::{self, Stream},
+    StreamExt,
+};
+use futures_timer::Delay;
+use log::warn;
+use serde_json as json;
+
+use std::{
+    pin::Pin,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+// how often a provider backed `peak_load` is re-read
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+type Schedule = Pin<Box<dyn Stream<Item = (Instant, Option<Instant>)> + Send>>;
+
+/// Creates the start stream for an endpoint whose `peak_load` is read from providers. Each time
+/// a new rate is read the schedule is rebuilt from the load pattern, continuing from the current
+/// point in the load pattern. No requests are made until the first rate is read, and the last
+/// rate read is kept if the providers end. Reading a rate takes the value from the provider, which
+/// is why the config only lets a `peak_load` read a provider nothing else reads, unless it's
+/// broadcast.
+pub(crate) fn provider_load_stream(
+    peak_load: config::ProviderPeakLoad,
+    load_pattern: config::LoadPattern,
+    receivers: Vec<(String, ProviderReceiver)>,
+    start_at: Option<Duration>,
+) -> impl Stream<Item = (Instant, Option<Instant>)> + Send {
+    let rates = stream::unfold((receivers, true), |(mut receivers, first)| async move {
+        if !first {
+            Delay::new(POLL_INTERVAL).await;
+        }
+        let mut values = json::Map::new();
+        for (name, rx) in &mut receivers {
+            values.insert(name.clone(), rx.next().await?);
+        }
+        Some((json::Value::Object(values), (receivers, false)))
+    })
+    .filter_map(move |values| {
+        let rate = match peak_load.evaluate(values) {
+            Ok(rate) => Some(rate),
+            Err(e) => {
+                warn!("ignoring peak_load update: {}", e);
+                None
+            }
+        };
+        async move { rate }
+    });
+    let mut rates = Some(Box::pin(rates));
+    let mut schedule: Option<Schedule> = None;
+    let mut started = None;
+    stream::poll_fn(move |cx| {
+        let started = *started.get_or_insert_with(Instant::now);
+        while let Some(r) = &mut rates {
+            match r.poll_next_unpin(cx) {
+                Poll::Ready(Some(rate)) => {
+                    let elapsed = start_at.unwrap_or_default() + started.elapsed();
+                    let s =
+                        create_mod_interval(&rate, load_pattern.clone()).into_stream(Some(elapsed));
+                    schedule = Some(Box::pin(s));
+                }
+                Poll::Ready(None) => rates = None,
+                Poll::Pending => break,
+            }
+        }
+        match &mut schedule {
+            Some(s) => s.poll_next_unpin(cx),
+            None if rates.is_none() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ether::Either;
+    use futures::SinkExt;
+    use std::path::Path;
+
+    // the interval the schedule was built with, from the times of the tick and the one after it
+    fn interval(tick: (Instant, Option<Instant>)) -> Duration {
+        let (next, following) = tick;
+        following.expect("schedule should go on") - next
+    }
+
+    fn assert_near(interval: Duration, expected: Duration) {
+        let diff = interval.max(expected) - interval.min(expected);
+        assert!(diff < Duration::from_millis(1), "{:?}", interval);
+    }
+
+    #[tokio::test]
+    async fn new_rate_changes_the_schedule() {
+        let config = config::LoadTest::from_config(
+            br#"
+            providers:
+              rate:
+                response: {}
+            load_pattern:
+              - linear:
+                  from: 100%
+                  to: 100%
+                  over: 1m
+            endpoints:
+              - url: http://localhost/foo
+                peak_load: ${rate}hps
+            "#,
+            Path::new("./"),
+            &Default::default(),
+        )
+        .unwrap();
+        let endpoint = config.endpoints.into_iter().next().unwrap();
+        let peak_load = match endpoint.peak_load {
+            Some(config::PeakLoad::Provider(p)) => p,
+            _ => panic!("expected a provider peak_load"),
+        };
+        assert_eq!(peak_load.providers().collect::<Vec<_>>(), ["rate"]);
+
+        let (mut tx, rx) = channel::channel(channel::Limit::Static(1), false, "rate");
+        let mut stream = Box::pin(provider_load_stream(
+            peak_load,
+            endpoint.load_pattern.unwrap(),
+            vec![("rate".into(), Either::A(rx))],
+            None,
+        ));
+
+        tx.send(10.into()).await.unwrap();
+        assert_near(
+            interval(stream.next().await.unwrap()),
+            Duration::from_millis(100),
+        );
+
+        // the schedule is rebuilt once the new rate is read, which happens within a poll interval
+        tx.send(40.into()).await.unwrap();
+        let rebuilt = async {
+            loop {
+                let interval = interval(stream.next().await.unwrap());
+                if interval < Duration::from_millis(100) {
+                    return interval;
+                }
+            }
+        };
+        let interval = tokio::time::timeout(POLL_INTERVAL * 10, rebuilt)
+            .await
+            .expect("new rate should be read");
+        assert_near(interval, Duration::from_millis(25));
+    }
+}
@@ -0,0 +1,479 @@
+use crate::create_mod_interval;
+
+use mod_interval::ModInterval;
+use serde_json as json;
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    time::Duration,
+};
+
+// how many intervals the test duration is divided into when printing the plan
+const PLAN_INTERVALS: u32 = 10;
+
+struct PlannedEndpoint {
+    id: usize,
+    method: String,
+    url: String,
+    schedule: ModInterval,
+    // the hits per second of the `peak_load` the schedule is built from
+    peak_load: f64,
+    // the portion of the schedule's hits which go to this endpoint, less than 1 when the
+    // schedule is shared by an endpoint group
+    share: f64,
+}
+
+/// The arrival rate a load test is expected to generate, computed from the load patterns and peak
+/// loads without sending any requests.
+pub(crate) struct LoadPlan {
+    duration: Duration,
+    endpoints: Vec<PlannedEndpoint>,
+}
+
+impl LoadPlan {
+    pub(crate) fn new(config: &config::LoadTest) -> Self {
+        let mut group_weights: BTreeMap<_, usize> = BTreeMap::new();
+        for e in &config.endpoints {
+            if let Some(group) = &e.group {
+                *group_weights.entry(group.as_str()).or_default() += e.weight.get();
+            }
+        }
+        let endpoints = config
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(id, e)| {
+                let (peak, load_pattern, share) = match &e.group {
+                    Some(group) => {
+                        let g = config.endpoint_groups.get(group)?;
+                        let share = e.weight.get() as f64 / group_weights[group.as_str()] as f64;
+                        (&g.peak_load, g.load_pattern.as_ref()?, share)
+                    }
+                    // a peak_load read from providers isn't known until the test runs
+                    None => match e.peak_load.as_ref()? {
+                        config::PeakLoad::Fixed(peak_load) => {
+                            (peak_load, e.load_pattern.as_ref()?, 1.0)
+                        }
+                        config::PeakLoad::Provider(_) => return None,
+                    },
+                };
+                Some(PlannedEndpoint {
+                    id,
+                    method: e.method.to_string(),
+                    url: e.url.evaluate_with_star(),
+                    schedule: create_mod_interval(peak, load_pattern.clone()),
+                    peak_load: hits_per_second(peak),
+                    share,
+                })
+            })
+            .collect();
+        LoadPlan {
+            duration: config.get_duration(),
+            endpoints,
+        }
+    }
+
+    /// The hits per second for each planned endpoint at `time` into the test
+    pub(crate) fn endpoint_rates_at(&self, time: Duration) -> Vec<f64> {
+        self.endpoints
+            .iter()
+            .map(|e| e.schedule.hits_per_second_at(time) * e.share)
+            .collect()
+    }
+
+    /// The index, method and url of each endpoint with a planned rate
+    pub(crate) fn endpoints(&self) -> impl Iterator<Item = (usize, &str, &str)> {
+        self.endpoints
+            .iter()
+            .map(|e| (e.id, e.method.as_str(), e.url.as_str()))
+    }
+
+    /// The hits per second for the endpoint with the given index at `time` into the test, if it
+    /// has a planned rate
+    pub(crate) fn endpoint_rate_at(&self, id: usize, time: Duration) -> Option<f64> {
+        self.endpoints
+            .iter()
+            .find(|e| e.id == id)
+            .map(|e| e.schedule.hits_per_second_at(time) * e.share)
+    }
+
+    /// The combined hits per second for all endpoints at `time` into the test
+    pub(crate) fn aggregate_rate_at(&self, time: Duration) -> f64 {
+        self.endpoint_rates_at(time).iter().sum()
+    }
+
+    /// The combined hits per second of every planned endpoint's `peak_load`, the most the test
+    /// could send at once
+    pub(crate) fn peak_rate(&self) -> f64 {
+        self.endpoints.iter().map(|e| e.peak_load * e.share).sum()
+    }
+
+    fn sample_times(&self) -> impl Iterator<Item = Duration> {
+        let step = self.duration / PLAN_INTERVALS;
+        (0..=PLAN_INTERVALS).map(move |i| step * i)
+    }
+
+    pub(crate) fn to_human(&self) -> String {
+        let mut out = format!(
+            "Load plan for {}\n\n",
+            short_duration(self.duration.as_secs())
+        );
+        for e in &self.endpoints {
+            let _ = writeln!(out, "endpoint {}: {} {}", e.id, e.method, e.url);
+        }
+        let _ = write!(out, "\n{:>10} {:>12}", "time", "total hps");
+        for e in &self.endpoints {
+            let _ = write!(out, " {:>12}", format!("endpoint {}", e.id));
+        }
+        out.push('\n');
+        for time in self.sample_times() {
+            let rates = self.endpoint_rates_at(time);
+            let total = self.aggregate_rate_at(time);
+            let _ = write!(
+                out,
+                "{:>10} {:>12.2}",
+                short_duration(time.as_secs()),
+                total
+            );
+            for rate in rates {
+                let _ = write!(out, " {:>12.2}", rate);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub(crate) fn to_json(&self) -> json::Value {
+        let endpoints: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|e| json::json!({ "id": e.id, "method": e.method, "url": e.url }))
+            .collect();
+        let timeline: Vec<_> = self
+            .sample_times()
+            .map(|time| {
+                let rates = self.endpoint_rates_at(time);
+                let total = self.aggregate_rate_at(time);
+                json::json!({
+                    "time": time.as_secs_f64(),
+                    "total": total,
+                    "endpoints": rates,
+                })
+            })
+            .collect();
+        json::json!({
+            "type": "plan",
+            "duration": self.duration.as_secs_f64(),
+            "endpoints": endpoints,
+            "timeline": timeline,
+        })
+    }
+
+    /// Each planned endpoint's hits per second every `step` through its load pattern along with
+    /// at its end, as a json array, for `--print-load-curve`. With a `step` of zero only the start
+    /// and end are sampled.
+    pub(crate) fn load_curve(&self, step: Duration) -> json::Value {
+        self.endpoints
+            .iter()
+            .map(|e| {
+                let duration = e.schedule.duration();
+                let mut times = Vec::new();
+                let mut time = Duration::from_secs(0);
+                while time < duration {
+                    times.push(time);
+                    if step.is_zero() {
+                        break;
+                    }
+                    time += step;
+                }
+                times.push(duration);
+                let curve: Vec<_> = times
+                    .into_iter()
+                    .map(|time| {
+                        let rate = e.schedule.hits_per_second_at(time) * e.share;
+                        json::json!([time.as_secs_f64(), rate])
+                    })
+                    .collect();
+                json::json!({
+                    "id": e.id,
+                    "method": e.method,
+                    "url": e.url,
+                    "curve": curve,
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for LoadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadPlan")
+            .field("duration", &self.duration)
+            .field("endpoints", &self.endpoints().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+pub(crate) fn hits_per_second(hits_per: &config::HitsPer) -> f64 {
+    match *hits_per {
+        config::HitsPer::Second(n) => n.into(),
+        config::HitsPer::Minute(n) => f64::from(n) / 60.0,
+    }
+}
+
+fn short_duration(mut secs: u64) -> String {
+    let mut out = String::new();
+    for (unit, name) in [(3600, "h"), (60, "m"), (1, "s")] {
+        let count = secs / unit;
+        if count > 0 {
+            secs -= count * unit;
+            let _ = write!(out, "{count}{name}");
+        }
+    }
+    if out.is_empty() {
+        out.push_str("0s");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn plan(yaml: &str) -> LoadPlan {
+        let config =
+            config::LoadTest::from_config(yaml.as_bytes(), Path::new("./"), &Default::default())
+                .unwrap();
+        LoadPlan::new(&config)
+    }
+
+    #[test]
+    fn aggregate_rate_at_ramp_midpoint() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - linear:
+                  to: 100%
+                  over: 10m
+            endpoints:
+              - url: http://localhost/foo
+                peak_load: 10hps
+              - url: http://localhost/bar
+                peak_load: 1200hpm
+            "#,
+        );
+
+        let midpoint = Duration::from_secs(300);
+        let rates = plan.endpoint_rates_at(midpoint);
+        assert!((rates[0] - 5.0).abs() < 1e-9, "rates: {:?}", rates);
+        assert!((rates[1] - 10.0).abs() < 1e-9, "rates: {:?}", rates);
+        assert!((plan.aggregate_rate_at(midpoint) - 15.0).abs() < 1e-9);
+        assert_eq!(plan.aggregate_rate_at(Duration::from_secs(601)), 0.0);
+        assert!((plan.peak_rate() - 30.0).abs() < 1e-9);
+
+        let json = plan.to_json();
+        assert_eq!(json["timeline"].as_array().unwrap().len(), 11);
+        assert_eq!(json["timeline"][5]["time"], 300.0);
+    }
+
+    #[test]
+    fn endpoint_groups_split_their_rate() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - linear:
+                  from: 100%
+                  to: 100%
+                  over: 1m
+            endpoint_groups:
+              mixed:
+                peak_load: 100hps
+            endpoints:
+              - url: http://localhost/read
+                group: mixed
+                weight: 7
+              - url: http://localhost/write
+                group: mixed
+                weight: 3
+            "#,
+        );
+
+        let rates = plan.endpoint_rates_at(Duration::from_secs(30));
+        assert!((rates[0] - 70.0).abs() < 1e-9, "rates: {:?}", rates);
+        assert!((rates[1] - 30.0).abs() < 1e-9, "rates: {:?}", rates);
+    }
+
+    #[test]
+    fn flat_endpoint_holds_its_rate_for_the_whole_test() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - linear:
+                  to: 100%
+                  over: 5m
+              - linear:
+                  to: 50%
+                  over: 5m
+            endpoints:
+              - url: http://localhost/ramped
+                peak_load: 10hps
+              - url: http://localhost/flat
+                peak_load: 10hps
+                load_pattern: flat
+            "#,
+        );
+
+        assert_eq!(plan.duration, Duration::from_secs(600));
+        for secs in [0, 150, 300, 450, 599] {
+            let rates = plan.endpoint_rates_at(Duration::from_secs(secs));
+            assert!((rates[1] - 10.0).abs() < 1e-9, "{}s: {:?}", secs, rates);
+        }
+        let rates = plan.endpoint_rates_at(Duration::from_secs(150));
+        assert!((rates[0] - 5.0).abs() < 1e-9, "rates: {:?}", rates);
+    }
+
+    #[test]
+    fn step_holds_each_plateau() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - step:
+                  - at: 20%
+                    over: 5m
+                  - at: 100%
+                    over: 1m
+                  - at: 20%
+                    over: 5m
+            endpoints:
+              - url: http://localhost/
+                peak_load: 10hps
+            "#,
+        );
+
+        assert_eq!(plan.duration, Duration::from_secs(660));
+        let rate_at = |millis| plan.aggregate_rate_at(Duration::from_millis(millis));
+        for (millis, expected) in [
+            (0, 2.0),
+            (150_000, 2.0),
+            (299_999, 2.0),
+            (300_000, 10.0),
+            (330_000, 10.0),
+            (359_999, 10.0),
+            (360_000, 2.0),
+            (659_999, 2.0),
+        ] {
+            let rate = rate_at(millis);
+            assert!(
+                (rate - expected).abs() < 1e-9,
+                "{}ms: {} expected {}",
+                millis,
+                rate,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn load_curve_follows_each_endpoints_load_pattern() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - linear:
+                  to: 100%
+                  over: 10m
+            endpoint_groups:
+              mixed:
+                peak_load: 100hps
+                load_pattern:
+                  - linear:
+                      from: 100%
+                      to: 100%
+                      over: 5m
+            endpoints:
+              - url: http://localhost/ramped
+                peak_load: 10hps
+              - url: http://localhost/read
+                group: mixed
+                weight: 3
+              - url: http://localhost/write
+                group: mixed
+                weight: 1
+            "#,
+        );
+
+        // a ramp from zero starts at the schedule's floor rather than zero, so it has a first hit
+        let start = plan.endpoint_rate_at(0, Duration::from_secs(0)).unwrap();
+        assert!(start > 0.0 && start < 0.1, "{}", start);
+        let curve = plan.load_curve(Duration::from_secs(300));
+        assert_eq!(
+            curve,
+            json::json!([
+                {
+                    "id": 0,
+                    "method": "GET",
+                    "url": "http://localhost/ramped",
+                    "curve": [[0.0, start], [300.0, 5.0], [600.0, 10.0]],
+                },
+                {
+                    "id": 1,
+                    "method": "GET",
+                    "url": "http://localhost/read",
+                    "curve": [[0.0, 75.0], [300.0, 75.0]],
+                },
+                {
+                    "id": 2,
+                    "method": "GET",
+                    "url": "http://localhost/write",
+                    "curve": [[0.0, 25.0], [300.0, 25.0]],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn load_curve_samples_the_end_of_the_load_pattern() {
+        let plan = plan(
+            r#"
+            load_pattern:
+              - linear:
+                  to: 100%
+                  over: 10m
+              - linear:
+                  to: 50%
+                  over: 5m
+            endpoints:
+              - url: http://localhost/
+                peak_load: 1200hpm
+            "#,
+        );
+        let curve = |secs| {
+            let curve = plan.load_curve(Duration::from_secs(secs));
+            json::from_value::<Vec<(f64, f64)>>(curve[0]["curve"].clone()).unwrap()
+        };
+
+        let start = plan.endpoint_rate_at(0, Duration::from_secs(0)).unwrap();
+        let expected = [(0.0, start), (300.0, 10.0), (600.0, 20.0), (900.0, 10.0)];
+        let sampled = curve(300);
+        assert_eq!(sampled.len(), expected.len(), "{:?}", sampled);
+        for ((time, rate), (expected_time, expected_rate)) in sampled.iter().zip(expected) {
+            assert_eq!(*time, expected_time);
+            assert!((rate - expected_rate).abs() < 1e-9, "{:?}", sampled);
+        }
+
+        // the end is always sampled, even when it isn't a whole number of steps
+        let times: Vec<_> = curve(400).iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [0.0, 400.0, 800.0, 900.0]);
+
+        let times: Vec<_> = curve(0).iter().map(|(time, _)| *time).collect();
+        assert_eq!(times, [0.0, 900.0]);
+    }
+
+    #[test]
+    fn short_duration_works() {
+        assert_eq!(short_duration(0), "0s");
+        assert_eq!(short_duration(90), "1m30s");
+        assert_eq!(short_duration(3600), "1h");
+    }
+}
@@ -1,42 +1,70 @@
 mod csv_reader;
+mod delimited_reader;
+mod file_source;
+mod json_lines_reader;
 mod json_reader;
 mod line_reader;
-
-use self::{csv_reader::CsvReader, json_reader::JsonReader, line_reader::LineReader};
+mod random_range;
+mod shuffled;
+mod sqlite_reader;
+
+use self::{
+    csv_reader::CsvReader, delimited_reader::DelimitedReader, file_source::FileSource,
+    json_lines_reader::JsonLinesReader, json_reader::JsonReader, line_reader::LineReader,
+    random_range::RandomRange, shuffled::ShuffledOnce, sqlite_reader::SqliteReader,
+};
 
 use crate::error::TestError;
 use crate::line_writer::MsgType;
-use crate::util::{config_limit_to_channel_limit, json_value_to_string};
-use crate::TestEndReason;
+use crate::util::{config_limit_to_channel_limit, json_value_to_string, named_seed};
+use crate::{HttpClient, TestEndReason};
 
-use ether::Either3;
+use chrono::{SecondsFormat, Utc};
+use ether::{Either, Either5};
 use futures::{
-    channel::mpsc::{self, channel, Sender as FCSender},
+    channel::{
+        mpsc::{self, channel, Sender as FCSender},
+        oneshot,
+    },
     executor::block_on,
+    future::{self, join_all, Shared},
+    ready,
     sink::{Sink, SinkExt},
-    stream, Stream, StreamExt, TryStreamExt,
+    stream, Future, FutureExt, Stream, StreamExt, TryStreamExt,
 };
+use futures_timer::Delay;
+use hyper::{header::HOST, Body, Request, Uri};
 use log::debug;
 use serde_json as json;
 use tokio::{sync::broadcast, task::spawn_blocking};
 
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     io,
     pin::Pin,
     sync::{
-        atomic::{AtomicIsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicIsize, AtomicU64, Ordering},
+        Arc, Mutex,
     },
     task::{Context, Poll},
+    time::Duration,
 };
 
+// how long fetching an http provider's data, its body included, can take before the test ends
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Clone)]
 pub struct Provider {
     pub auto_return: Option<config::EndpointProvidesSendOptions>,
     pub rx: channel::Receiver<json::Value>,
     pub tx: channel::Sender<json::Value>,
     pub on_demand: channel::OnDemandReceiver<json::Value>,
+    // with `pace_by_latency`, where the response times of the endpoints using the provider go
+    pub pacer: Option<Pacer>,
+    broadcast: Option<Arc<Broadcast>>,
+    // for a provider whose values aren't there when it's created, done once they are
+    ready: Option<Shared<oneshot::Receiver<()>>>,
 }
 
 impl Provider {
@@ -44,14 +72,221 @@ impl Provider {
         auto_return: Option<config::EndpointProvidesSendOptions>,
         rx: channel::Receiver<json::Value>,
         tx: channel::Sender<json::Value>,
+        share: config::ProviderShare,
+        pacer: Option<Pacer>,
     ) -> Self {
+        let broadcast = if share.is_broadcast() {
+            Some(Arc::new(Broadcast::new(rx.clone(), tx.name())))
+        } else {
+            None
+        };
         Self {
             auto_return,
             on_demand: channel::OnDemandReceiver::new(&rx),
             rx,
             tx,
+            pacer,
+            broadcast,
+            ready: None,
+        }
+    }
+
+    // done once the provider has its values, or has failed to get them. The load waits on this
+    // so the endpoints don't start out stalled on the provider
+    pub fn ready(&self) -> impl Future<Output = ()> {
+        let ready = self.ready.clone();
+        async move {
+            if let Some(ready) = ready {
+                let _ = ready.await;
+            }
+        }
+    }
+
+    // get a stream of values for one consumer of the provider. Normally every consumer reads from
+    // the same receiver, but with `share: broadcast` each consumer gets its own copy of every value
+    pub fn subscribe(&self) -> ProviderReceiver {
+        match &self.broadcast {
+            Some(broadcast) => Either::B(broadcast.subscribe()),
+            None => Either::A(self.rx.clone()),
+        }
+    }
+}
+
+pub type ProviderReceiver = Either<channel::Receiver<json::Value>, BroadcastReceiver>;
+
+// fans the values of a provider out to every subscriber. Each subscriber has its own small buffer,
+// but as every subscriber gets every value a subscriber which falls behind by more than its
+// buffer holds back the provider, and so the others, until it catches up
+struct Broadcast {
+    name: String,
+    rx: channel::Receiver<json::Value>,
+    started: AtomicBool,
+    subscribers: Mutex<Vec<channel::Sender<json::Value>>>,
+}
+
+impl Broadcast {
+    fn new(rx: channel::Receiver<json::Value>, name: String) -> Self {
+        Self {
+            name,
+            rx,
+            started: AtomicBool::new(false),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn subscribe(self: &Arc<Self>) -> BroadcastReceiver {
+        let name = format!("{}-subscriber", self.name);
+        let (tx, rx) = channel::channel(channel::Limit::dynamic(5), false, &name);
+        self.subscribers
+            .lock()
+            .expect("broadcast subscribers lock should not be poisoned")
+            .push(tx);
+        BroadcastReceiver {
+            broadcast: self.clone(),
+            rx,
         }
     }
+
+    // values only start flowing once a subscriber is first polled. By then all of the endpoints
+    // have been built, so no subscriber misses the start of the provider
+    fn start(self: &Arc<Self>) {
+        if self.started.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let this = self.clone();
+        let mut rx = self.rx.clone();
+        let forward_task = async move {
+            while let Some(value) = rx.next().await {
+                let subscribers = this
+                    .subscribers
+                    .lock()
+                    .expect("broadcast subscribers lock should not be poisoned")
+                    .clone();
+                let sends = subscribers.into_iter().map(|mut tx| {
+                    let value = value.clone();
+                    async move { tx.send(value).await }
+                });
+                // the next value waits until this one is in every subscriber's buffer
+                join_all(sends).await;
+                let mut subscribers = this
+                    .subscribers
+                    .lock()
+                    .expect("broadcast subscribers lock should not be poisoned");
+                subscribers.retain(|tx| !tx.no_receivers());
+                if subscribers.is_empty() {
+                    break;
+                }
+            }
+            // dropping the senders ends the streams of the subscribers
+            this.subscribers
+                .lock()
+                .expect("broadcast subscribers lock should not be poisoned")
+                .clear();
+        };
+        debug!("Provider::broadcast tokio::spawn forward_task");
+        tokio::spawn(forward_task);
+    }
+}
+
+pub struct BroadcastReceiver {
+    broadcast: Arc<Broadcast>,
+    rx: channel::Receiver<json::Value>,
+}
+
+impl Stream for BroadcastReceiver {
+    type Item = json::Value;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.broadcast.start();
+        self.rx.poll_next_unpin(cx)
+    }
+}
+
+// runs each value of a provider through the provider's `map` before it goes into the channel. A
+// value which can't be mapped kills the test
+pub struct Mapper {
+    map: config::ProviderMap,
+    name: String,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+}
+
+impl Mapper {
+    pub fn new(
+        map: config::ProviderMap,
+        name: &str,
+        test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    ) -> Self {
+        Mapper {
+            map,
+            name: name.into(),
+            test_killer,
+        }
+    }
+
+    fn apply(&self, value: json::Value) -> Result<json::Value, channel::ChannelClosed> {
+        self.map.apply(value).map_err(|e| {
+            let e = TestError::ProviderMap(self.name.clone(), e.into());
+            let _ = self.test_killer.send(Err(e));
+            channel::ChannelClosed::new()
+        })
+    }
+}
+
+// how much the latest response time counts towards the smoothed one
+const PACER_WEIGHT: f64 = 0.2;
+
+/// Slows down a provider with `pace_by_latency`. The stats record the response times of the
+/// endpoints using the provider, and once their smoothed response time is over the target each
+/// value waits for as long as it is over before going into the provider.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    target: Duration,
+    // microseconds, 0 until the first response time is recorded
+    latency: Arc<AtomicU64>,
+}
+
+impl Pacer {
+    pub fn new(target: Duration) -> Self {
+        Pacer {
+            target,
+            latency: Default::default(),
+        }
+    }
+
+    pub fn record(&self, rtt_micros: u64) {
+        let previous = self.latency.load(Ordering::Relaxed);
+        let latency = if previous == 0 {
+            rtt_micros
+        } else {
+            (previous as f64 * (1.0 - PACER_WEIGHT) + rtt_micros as f64 * PACER_WEIGHT) as u64
+        };
+        self.latency.store(latency.max(1), Ordering::Relaxed);
+    }
+
+    fn delay(&self) -> Duration {
+        Duration::from_micros(self.latency.load(Ordering::Relaxed)).saturating_sub(self.target)
+    }
+}
+
+// the sink the values of a provider are pushed into
+fn mapped_sink(
+    tx: channel::Sender<json::Value>,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
+) -> impl Sink<json::Value, Error = channel::ChannelClosed> {
+    tx.with(move |value| {
+        let value = match &mapper {
+            Some(mapper) => mapper.apply(value),
+            None => Ok(value),
+        };
+        let delay = pacer.as_ref().map(Pacer::delay).unwrap_or_default();
+        async move {
+            if !delay.is_zero() {
+                Delay::new(delay).await;
+            }
+            value
+        }
+    })
 }
 
 // create a file provider. It takes a "test_killer" because a file provider has the means of killing a test
@@ -60,30 +295,60 @@ pub fn file(
     mut fp: config::FileProvider,
     test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
     name: &str,
+    seed: Option<u64>,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
 ) -> Result<Provider, TestError> {
     let file = std::mem::take(&mut fp.path);
     debug!("providers::file={}", file);
     let file2 = file.clone();
+    // the provider's own `random_seed` takes precedence over the test's `--seed`, which each
+    // provider derives its own seed from. It's written back so a reader with `random` picks its
+    // values with it too
+    fp.random_seed = fp.random_seed.or(named_seed(seed, name));
+    let seed = fp.random_seed;
+    let shuffle = shuffle_once(&mut fp);
     // create a stream from the file that yields values
     let stream = match fp.format {
-        config::FileFormat::Csv => Either3::A(into_stream(
+        config::FileFormat::Csv => Either5::A(into_stream(maybe_shuffle(
             CsvReader::new(&fp, &file)
                 .map_err(|e| TestError::CannotOpenFile(file.into(), e.into()))?,
-        )),
-        config::FileFormat::Json => Either3::B(into_stream(
+            shuffle,
+            seed,
+        ))),
+        config::FileFormat::Delimited {
+            delimiter,
+            skip_header,
+        } => Either5::B(into_stream(maybe_shuffle(
+            DelimitedReader::new(&fp, &file, delimiter, skip_header)
+                .map_err(|e| TestError::CannotOpenFile(file.into(), e.into()))?,
+            shuffle,
+            seed,
+        ))),
+        config::FileFormat::Json => Either5::C(into_stream(maybe_shuffle(
             JsonReader::new(&fp, &file)
                 .map_err(|e| TestError::CannotOpenFile(file.into(), e.into()))?,
-        )),
-        config::FileFormat::Line => Either3::C(into_stream(
+            shuffle,
+            seed,
+        ))),
+        config::FileFormat::JsonLines => Either5::D(into_stream(maybe_shuffle(
+            JsonLinesReader::new(&fp, &file)
+                .map_err(|e| TestError::CannotOpenFile(file.into(), e.into()))?,
+            shuffle,
+            seed,
+        ))),
+        config::FileFormat::Line => Either5::E(into_stream(maybe_shuffle(
             LineReader::new(&fp, &file)
                 .map_err(|e| TestError::CannotOpenFile(file.into(), e.into()))?,
-        )),
+            shuffle,
+            seed,
+        ))),
     };
 
     // create the channel for the provider
     let limit = config_limit_to_channel_limit(fp.buffer);
     let (tx, rx) = channel::channel(limit, fp.unique, name);
-    let tx2 = tx.clone();
+    let tx2 = mapped_sink(tx.clone(), mapper, pacer.clone());
 
     // create a new task that pushes data from the file into the channel
     let primer_task = async move {
@@ -103,7 +368,202 @@ pub fn file(
     debug!("Provider::file tokio::spawn primer_task");
     tokio::spawn(primer_task);
 
-    Ok(Provider::new(fp.auto_return, rx, tx))
+    Ok(Provider::new(fp.auto_return, rx, tx, fp.share, pacer))
+}
+
+// with `shuffle: once` the reader goes through the file a single time, in order, and the shuffled
+// values are repeated instead
+fn shuffle_once(fp: &mut config::FileProvider) -> Option<bool> {
+    match fp.shuffle {
+        config::FileShuffle::Once => {
+            let repeat = fp.repeat;
+            fp.random = false;
+            fp.repeat = false;
+            Some(repeat)
+        }
+        config::FileShuffle::Never => None,
+    }
+}
+
+// create an http provider. Its data is fetched once, when the test starts, and is then read the
+// same as a file provider's file. Like a file provider it can kill the test, if fetching or reading
+// the data fails
+pub fn http_seed(
+    hp: config::HttpProvider,
+    client: Arc<HttpClient>,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    name: &str,
+    seed: Option<u64>,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
+) -> Provider {
+    let config::HttpProvider {
+        csv,
+        auto_return,
+        buffer,
+        format,
+        headers,
+        random,
+        random_seed,
+        repeat,
+        shuffle,
+        unique,
+        share,
+        url,
+    } = hp;
+    debug!("providers::http_seed={}", url);
+    let mut fp = config::FileProvider {
+        csv,
+        auto_return,
+        buffer,
+        compression: None,
+        format,
+        path: String::new(),
+        random,
+        random_seed,
+        repeat,
+        shuffle,
+        unique,
+        share,
+    };
+    fp.random_seed = fp.random_seed.or(named_seed(seed, name));
+    let seed = fp.random_seed;
+    let shuffle = shuffle_once(&mut fp);
+
+    // create the channel for the provider
+    let limit = config_limit_to_channel_limit(fp.buffer);
+    let (tx, rx) = channel::channel(limit, fp.unique, name);
+    let tx2 = mapped_sink(tx.clone(), mapper, pacer.clone());
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let auto_return = fp.auto_return;
+    let share = fp.share;
+
+    // create a new task that fetches the data then pushes it into the channel
+    let primer_task = async move {
+        let data = match fetch(&client, &url, headers, FETCH_TIMEOUT).await {
+            Ok(data) => FileSource::Memory(io::Cursor::new(data)),
+            Err(e) => {
+                let _ = test_killer.send(Err(TestError::HttpProviderFetch(url, e)));
+                return;
+            }
+        };
+        let stream = match fp.format {
+            config::FileFormat::Csv => CsvReader::from_source(&fp, data)
+                .map(|r| Either5::A(into_stream(maybe_shuffle(r, shuffle, seed)))),
+            config::FileFormat::Delimited {
+                delimiter,
+                skip_header,
+            } => DelimitedReader::from_source(&fp, data, delimiter, skip_header)
+                .map(|r| Either5::B(into_stream(maybe_shuffle(r, shuffle, seed)))),
+            config::FileFormat::Json => JsonReader::from_source(&fp, data)
+                .map(|r| Either5::C(into_stream(maybe_shuffle(r, shuffle, seed)))),
+            config::FileFormat::JsonLines => JsonLinesReader::from_source(&fp, data)
+                .map(|r| Either5::D(into_stream(maybe_shuffle(r, shuffle, seed)))),
+            config::FileFormat::Line => LineReader::from_source(&fp, data)
+                .map(|r| Either5::E(into_stream(maybe_shuffle(r, shuffle, seed)))),
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                let _ = test_killer.send(Err(TestError::FileReading(url, e.into())));
+                return;
+            }
+        };
+        let _ = ready_tx.send(());
+        let r = stream
+            .map_err(move |e| {
+                let e = TestError::FileReading(url.clone(), e.into());
+                channel::ChannelClosed::wrapped(e)
+            })
+            .forward(tx2)
+            .await;
+        if let Err(e) = r {
+            if let Some(e) = e.inner_cast() {
+                let _ = test_killer.send(Err(*e));
+            }
+        }
+    };
+    debug!("Provider::http_seed tokio::spawn primer_task");
+    tokio::spawn(primer_task);
+
+    let mut provider = Provider::new(auto_return, rx, tx, share, pacer);
+    provider.ready = Some(ready_rx.shared());
+    provider
+}
+
+// get the body of a successful response to a GET of the url, giving up after `timeout`
+async fn fetch(
+    client: &HttpClient,
+    url: &str,
+    headers: Vec<(String, String)>,
+    timeout: Duration,
+) -> Result<bytes::Bytes, String> {
+    let uri: Uri = url.parse().map_err(|e| format!("invalid url: {e}"))?;
+    // the client doesn't set the `Host` header itself
+    let mut request = Request::get(uri.clone());
+    if !headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("host")) {
+        if let Some(authority) = uri.authority() {
+            request = request.header(HOST, authority.as_str());
+        }
+    }
+    for (k, v) in headers {
+        request = request.header(k, v);
+    }
+    let request = request.body(Body::empty()).map_err(|e| e.to_string())?;
+    let body = async {
+        let response = client.request(request).await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("the server responded with {status}"));
+        }
+        hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| e.to_string())
+    };
+    match future::select(Box::pin(body), Delay::new(timeout)).await {
+        future::Either::Left((body, _)) => body,
+        future::Either::Right(_) => Err(format!("timed out after {timeout:?}")),
+    }
+}
+
+// create a sqlite provider. Like a file provider it can kill the test if the query fails
+pub fn sqlite(
+    mut sp: config::SqliteProvider,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    name: &str,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
+) -> Result<Provider, TestError> {
+    let file = std::mem::take(&mut sp.path);
+    debug!("providers::sqlite={} query={}", file, sp.query);
+    let stream = SqliteReader::new(&sp, &file)
+        .map_err(|e| TestError::CannotOpenFile(file.as_str().into(), e.into()))?
+        .into_stream();
+
+    // create the channel for the provider
+    let limit = config_limit_to_channel_limit(sp.buffer);
+    let (tx, rx) = channel::channel(limit, sp.unique, name);
+    let tx2 = mapped_sink(tx.clone(), mapper, pacer.clone());
+
+    // create a new task that pushes the rows into the channel
+    let primer_task = async move {
+        let r = stream
+            .map_err(move |e| {
+                let e = TestError::FileReading(file.clone(), e.into());
+                channel::ChannelClosed::wrapped(e)
+            })
+            .forward(tx2)
+            .await;
+        if let Err(e) = r {
+            if let Some(e) = e.inner_cast() {
+                let _ = test_killer.send(Err(*e));
+            }
+        }
+    };
+    debug!("Provider::sqlite tokio::spawn primer_task");
+    tokio::spawn(primer_task);
+
+    Ok(Provider::new(sp.auto_return, rx, tx, sp.share, pacer))
 }
 
 // create a response provider
@@ -112,53 +572,153 @@ pub fn response(rp: config::ResponseProvider, name: &str) -> Provider {
     // create the channel for the provider
     let limit = config_limit_to_channel_limit(rp.buffer);
     let (tx, rx) = channel::channel(limit, rp.unique, name);
+    // seeded values are forced in, so an `initial` longer than the buffer is kept whole
+    for value in rp.initial {
+        tx.force_send(value);
+    }
 
-    Provider::new(rp.auto_return, rx, tx)
+    Provider::new(rp.auto_return, rx, tx, config::ProviderShare::Split, None)
 }
 
 // create a list provider
-pub fn list(lp: config::ListProvider, name: &str) -> Provider {
+pub fn list(
+    mut lp: config::ListProvider,
+    name: &str,
+    seed: Option<u64>,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
+) -> Provider {
     debug!("providers::list={:?}", lp);
+    // the provider's own `random_seed` takes precedence over the test's `--seed`
+    if let config::ListProvider::WithOptions(l) = &mut lp {
+        l.random_seed = l.random_seed.or(named_seed(seed, name));
+    }
     // create the channel for the provider
     let unique = lp.unique();
+    let share = lp.share();
     let rs = stream::iter(lp.into_iter().map(Ok));
     let limit = channel::Limit::dynamic(5);
     let (tx, rx) = channel::channel(limit, unique, name);
 
     // create a new task that pushes data from the list into the channel
-    let tx2 = tx.clone();
+    let tx2 = mapped_sink(tx.clone(), mapper, pacer.clone());
     let primer_task = rs.forward(tx2);
     debug!("Provider::list tokio::spawn primer_task");
     tokio::spawn(primer_task);
 
-    Provider::new(None, rx, tx)
+    Provider::new(None, rx, tx, share, pacer)
 }
 
 // create a range provider
-pub fn range(rp: config::RangeProvider, name: &str) -> Provider {
+pub fn range(
+    rp: config::RangeProvider,
+    name: &str,
+    seed: Option<u64>,
+    mapper: Option<Mapper>,
+    pacer: Option<Pacer>,
+) -> Provider {
     debug!("providers::range={}", rp);
     // create the channel for the provider
     let limit = channel::Limit::dynamic(5);
     let (tx, rx) = channel::channel(limit, rp.unique(), name);
-
+    let share = rp.share();
+
+    let values = if rp.random() {
+        let (start, end, step) = rp.bounds();
+        Either::A(RandomRange::new(
+            start,
+            end,
+            step,
+            rp.repeat(),
+            rp.unique(),
+            named_seed(seed, name),
+        ))
+    } else {
+        Either::B(rp.0)
+    };
     // create a new task that pushes data from the range into the channel
-    let prime_tx = stream::iter(rp.0.map(|v| Ok(v.into()))).forward(tx.clone());
+    let prime_tx = stream::iter(values.map(|v| Ok(v.into()))).forward(mapped_sink(
+        tx.clone(),
+        mapper,
+        pacer.clone(),
+    ));
     debug!("Provider::range tokio::spawn prime_tx");
     tokio::spawn(prime_tx);
 
-    Provider::new(None, rx, tx)
+    Provider::new(None, rx, tx, share, pacer)
 }
 
 #[derive(Clone, Debug)]
 pub struct Logger {
+    endpoint_tags: bool,
+    envelope: bool,
     limit: Option<Arc<AtomicIsize>>,
     pretty: bool,
+    // the tags of the endpoint whose values are being logged, used for the envelope
+    tags: Option<Arc<BTreeMap<String, String>>>,
     test_killer: Option<broadcast::Sender<Result<TestEndReason, TestError>>>,
-    writer: FCSender<MsgType>,
+    // every value is written to each of these
+    writers: Vec<FCSender<MsgType>>,
 }
 
 impl Logger {
+    /// A copy of this logger for values logged on behalf of the endpoint with these `tags`
+    pub fn with_tags(&self, tags: &Arc<BTreeMap<String, String>>) -> Self {
+        let mut logger = self.clone();
+        if logger.envelope || logger.endpoint_tags {
+            logger.tags = Some(tags.clone());
+        }
+        logger
+    }
+
+    // wraps a logged value with when and where it was logged
+    fn envelope(&self, value: json::Value) -> json::Value {
+        let tags = self.tags.as_deref();
+        let endpoint = tags
+            .and_then(|t| t.get("_id"))
+            .and_then(|id| id.parse::<u64>().ok());
+        json::json!({
+            "ts": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "endpoint": endpoint,
+            "tags": tags.cloned().unwrap_or_default(),
+            "value": value,
+        })
+    }
+
+    // says which endpoint a value was logged for. An object gets the endpoint's id and tags as
+    // keys and a string gets a line naming them before it
+    fn add_endpoint_tags(&self, value: json::Value) -> json::Value {
+        let tags = self.tags.as_deref().cloned().unwrap_or_default();
+        let id = tags.get("_id").and_then(|id| id.parse::<u64>().ok());
+        match value {
+            json::Value::Object(mut o) => {
+                o.insert("endpoint".into(), id.into());
+                o.insert("tags".into(), json::json!(tags));
+                o.into()
+            }
+            json::Value::String(s) => {
+                let rest: Vec<_> = tags
+                    .iter()
+                    .filter(|(k, _)| *k != "_id")
+                    .map(|(k, v)| format!("{k}: {v}"))
+                    .collect();
+                // keep any blank lines separating the values at the start
+                let body = s.trim_start_matches('\n');
+                let lead = &s[..s.len() - body.len()];
+                let id = id.map(|id| id.to_string()).unwrap_or_default();
+                format!("{lead}Endpoint {id} ({})\n{body}", rest.join(", ")).into()
+            }
+            value => value,
+        }
+    }
+
     fn json_to_msg_type(&self, j: json::Value) -> MsgType {
+        let j = if self.endpoint_tags {
+            self.add_endpoint_tags(j)
+        } else {
+            j
+        };
+        let j = if self.envelope { self.envelope(j) } else { j };
         let s = if self.pretty && !j.is_string() {
             format!("{j:#}\n")
         } else {
@@ -176,7 +736,23 @@ impl Sink<json::Value> for Logger {
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         let this = Pin::into_inner(self);
-        Pin::new(&mut this.writer).poll_ready(cx)
+        let mut ret = Poll::Ready(Ok(()));
+        let mut i = 0;
+        while i < this.writers.len() {
+            match Pin::new(&mut this.writers[i]).poll_ready(cx) {
+                Poll::Ready(Ok(())) => i += 1,
+                Poll::Pending => {
+                    ret = Poll::Pending;
+                    i += 1;
+                }
+                Poll::Ready(Err(e)) if this.writers.len() == 1 => return Poll::Ready(Err(e)),
+                // a destination which has gone away is dropped so the others are still written to
+                Poll::Ready(Err(_)) => {
+                    this.writers.remove(i);
+                }
+            }
+        }
+        ret
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: json::Value) -> Result<(), Self::Error> {
@@ -192,20 +768,40 @@ impl Sink<json::Value> for Logger {
                 if let Some(killer) = &self.test_killer {
                     let _ = killer.send(Ok(TestEndReason::KilledByLogger));
                 }
-                self.writer.disconnect();
+                for writer in &mut self.writers {
+                    writer.disconnect();
+                }
             }
         }
-        self.writer.start_send(msg)
+        let (last, rest) = self
+            .writers
+            .split_last_mut()
+            .expect("logger should have a writer");
+        // as with `poll_ready`, a destination which fails doesn't keep the others from the value
+        let mut ok = false;
+        for writer in rest {
+            ok |= writer.start_send(msg.clone()).is_ok();
+        }
+        match last.start_send(msg) {
+            Err(_) if ok => Ok(()),
+            r => r,
+        }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         let this = Pin::into_inner(self);
-        Pin::new(&mut this.writer).poll_flush(cx)
+        for writer in &mut this.writers {
+            ready!(Pin::new(writer).poll_flush(cx))?;
+        }
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         let this = Pin::into_inner(self);
-        Pin::new(&mut this.writer).poll_close(cx)
+        for writer in &mut this.writers {
+            ready!(Pin::new(writer).poll_close(cx))?;
+        }
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -213,9 +809,11 @@ impl Sink<json::Value> for Logger {
 pub fn logger(
     logger: config::Logger,
     test_killer: &broadcast::Sender<Result<TestEndReason, TestError>>,
-    writer: FCSender<MsgType>,
+    writers: Vec<FCSender<MsgType>>,
 ) -> Logger {
     debug!("providers::logger={}", logger);
+    let endpoint_tags = logger.endpoint_tags;
+    let envelope = logger.envelope;
     let pretty = logger.pretty;
     let kill = logger.kill;
 
@@ -233,15 +831,30 @@ pub fn logger(
     .map(|limit| Arc::new(AtomicIsize::new(limit as isize)));
 
     Logger {
+        endpoint_tags,
+        envelope,
         limit,
         pretty,
+        tags: None,
         test_killer,
-        writer,
+        writers,
     }
 }
 
 // a helper function used by the different types of file readers to turn blocking iterators
 // into a stream
+// `shuffle` is whether to repeat, when the reader's values should be shuffled
+fn maybe_shuffle<I>(
+    reader: I,
+    shuffle: Option<bool>,
+    seed: Option<u64>,
+) -> Either<ShuffledOnce<I>, I> {
+    match shuffle {
+        Some(repeat) => Either::A(ShuffledOnce::new(reader, repeat, seed)),
+        None => Either::B(reader),
+    }
+}
+
 fn into_stream<I: Iterator<Item = Result<json::Value, io::Error>> + Send + 'static>(
     iter: I,
 ) -> impl Stream<Item = Result<json::Value, io::Error>> {
@@ -264,11 +877,10 @@ fn into_stream<I: Iterator<Item = Result<json::Value, io::Error>> + Send + 'stat
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::line_writer::blocking_writer;
+    use crate::line_writer::{blocking_gzip_writer, blocking_writer};
 
     use config::FromYaml;
     use futures::executor::{block_on, block_on_stream};
-    use futures_timer::Delay;
     use json::json;
     use test_common::TestWriter;
     use tokio::{runtime::Runtime, time};
@@ -285,7 +897,13 @@ mod tests {
             "#;
             let range_params =
                 config::RangeProviderPreProcessed::from_yaml_str(range_params).unwrap();
-            let p = range(range_params.into(), &"range_provider_works1".to_string());
+            let p = range(
+                range_params.into(),
+                &"range_provider_works1".to_string(),
+                None,
+                None,
+                None,
+            );
             let expect: Vec<_> = (0..=20).collect();
 
             let Provider { rx, tx, .. } = p;
@@ -302,7 +920,13 @@ mod tests {
             "#;
             let range_params =
                 config::RangeProviderPreProcessed::from_yaml_str(range_params).unwrap();
-            let p = range(range_params.into(), &"range_provider_works2".to_string());
+            let p = range(
+                range_params.into(),
+                &"range_provider_works2".to_string(),
+                None,
+                None,
+                None,
+            );
 
             let expect: Vec<_> = (0..=20).step_by(2).collect();
 
@@ -320,7 +944,13 @@ mod tests {
                 "#;
             let range_params =
                 config::RangeProviderPreProcessed::from_yaml_str(range_params).unwrap();
-            let p = range(range_params.into(), &"range_provider_works3".to_string());
+            let p = range(
+                range_params.into(),
+                &"range_provider_works3".to_string(),
+                None,
+                None,
+                None,
+            );
 
             let expect: Vec<_> = (0..=20).cycle().take(100).collect();
 
@@ -333,6 +963,59 @@ mod tests {
         });
     }
 
+    #[test]
+    fn pacer_smooths_latency() {
+        let pacer = Pacer::new(Duration::from_millis(10));
+        assert_eq!(pacer.delay(), Duration::ZERO);
+        pacer.record(5_000);
+        assert_eq!(pacer.delay(), Duration::ZERO);
+        pacer.record(55_000);
+        // 5ms * 0.8 + 55ms * 0.2
+        assert_eq!(pacer.delay(), Duration::from_millis(5));
+    }
+
+    #[test]
+    fn paced_provider_slows_with_latency() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let lwo = config::ListWithOptions {
+                values: vec![json!(1)],
+                repeat: true,
+                random: false,
+                random_seed: None,
+                weights: None,
+                unique: false,
+                share: config::ProviderShare::Split,
+            };
+            let pacer = Pacer::new(Duration::from_millis(10));
+            let Provider { rx, tx, .. } = list(
+                lwo.into(),
+                "paced_provider_slows_with_latency",
+                None,
+                None,
+                Some(pacer.clone()),
+            );
+            drop(tx);
+            let mut rx = rx;
+
+            // under the target the values come as fast as they're taken
+            pacer.record(5_000);
+            let start = time::Instant::now();
+            let values: Vec<_> = rx.by_ref().take(40).collect().await;
+            assert_eq!(values.len(), 40);
+            assert!(start.elapsed() < Duration::from_millis(200));
+
+            // 50ms over the target, so once the buffered values are gone each takes 50ms
+            for _ in 0..20 {
+                pacer.record(60_000);
+            }
+            let start = time::Instant::now();
+            let values: Vec<_> = rx.take(20).collect().await;
+            assert_eq!(values.len(), 20);
+            assert!(start.elapsed() >= Duration::from_millis(400));
+        });
+    }
+
     #[test]
     fn literals_provider_works() {
         let rt = Runtime::new().unwrap();
@@ -342,10 +1025,19 @@ mod tests {
                 values: jsons.clone(),
                 repeat: false,
                 random: false,
+                random_seed: None,
+                weights: None,
                 unique: false,
+                share: config::ProviderShare::Split,
             };
 
-            let p = list(lwo.into(), &"literals_provider_works1".to_string());
+            let p = list(
+                lwo.into(),
+                &"literals_provider_works1".to_string(),
+                None,
+                None,
+                None,
+            );
             let expect = jsons.clone();
 
             let Provider { rx, tx, .. } = p;
@@ -359,10 +1051,19 @@ mod tests {
                 values: jsons.clone(),
                 repeat: false,
                 random: true,
+                random_seed: None,
+                weights: None,
                 unique: false,
+                share: config::ProviderShare::Split,
             };
 
-            let p = list(lwo.into(), &"literals_provider_works2".to_string());
+            let p = list(
+                lwo.into(),
+                &"literals_provider_works2".to_string(),
+                None,
+                None,
+                None,
+            );
             let mut expect: Vec<_> = jsons.iter().map(|j| j.as_u64().unwrap()).collect();
 
             let Provider { rx, tx, .. } = p;
@@ -379,10 +1080,19 @@ mod tests {
                 values: jsons.clone(),
                 repeat: true,
                 random: false,
+                random_seed: None,
+                weights: None,
                 unique: false,
+                share: config::ProviderShare::Split,
             };
 
-            let p = list(lwo.into(), &"literals_provider_works3".to_string());
+            let p = list(
+                lwo.into(),
+                &"literals_provider_works3".to_string(),
+                None,
+                None,
+                None,
+            );
             let expect: Vec<_> = jsons.clone().into_iter().cycle().take(100).collect();
 
             let values: Vec<_> = p.rx.take(100).collect().await;
@@ -393,10 +1103,19 @@ mod tests {
                 values: jsons.clone(),
                 repeat: true,
                 random: true,
+                random_seed: None,
+                weights: None,
                 unique: false,
+                share: config::ProviderShare::Split,
             };
 
-            let p = list(lwo.into(), &"literals_provider_works4".to_string());
+            let p = list(
+                lwo.into(),
+                &"literals_provider_works4".to_string(),
+                None,
+                None,
+                None,
+            );
             let mut expect: Vec<_> = jsons
                 .iter()
                 .cycle()
@@ -421,10 +1140,19 @@ mod tests {
                 values: vec![json!(1), json!(2), json!(1), json!(2), json!(1)],
                 repeat: false,
                 random: false,
+                random_seed: None,
+                weights: None,
                 unique: true,
+                share: config::ProviderShare::Split,
             };
 
-            let p = list(lwo.into(), &"literals_provider_works5".to_string());
+            let p = list(
+                lwo.into(),
+                &"literals_provider_works5".to_string(),
+                None,
+                None,
+                None,
+            );
             let Provider { rx, tx, .. } = p;
             drop(tx);
 
@@ -441,6 +1169,567 @@ mod tests {
         });
     }
 
+    #[test]
+    fn seeded_list_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let jsons: Vec<_> = (0..20).map(|i| json!(i)).collect();
+            for repeat in [true, false] {
+                let take = |name, random_seed, seed| {
+                    let lwo = config::ListWithOptions {
+                        values: jsons.clone(),
+                        repeat,
+                        random: true,
+                        random_seed,
+                        weights: None,
+                        unique: false,
+                        share: config::ProviderShare::Split,
+                    };
+                    let p = list(lwo.into(), name, seed, None, None);
+                    p.rx.take(20).collect::<Vec<_>>()
+                };
+
+                let first = take("ids", Some(1), None).await;
+                let second = take("ids", Some(1), None).await;
+                let third = take("ids", Some(2), None).await;
+
+                assert_eq!(first, second, "same seed, repeat: {repeat}");
+                assert_ne!(first, third, "different seed, repeat: {repeat}");
+
+                // the provider's own `random_seed` is used as is, whatever the test's `--seed`
+                let overridden = take("ids", Some(1), Some(2)).await;
+                assert_eq!(first, overridden, "own seed first, repeat: {repeat}");
+
+                // otherwise each provider's seed is derived from the test's `--seed` and its name
+                let from_test = take("ids", None, Some(1)).await;
+                assert_eq!(
+                    from_test,
+                    take("ids", None, Some(1)).await,
+                    "repeat: {repeat}"
+                );
+                let other = take("other_ids", None, Some(1)).await;
+                assert_ne!(from_test, other, "other provider, repeat: {repeat}");
+            }
+        });
+    }
+
+    #[test]
+    fn seeded_range_providers_differ() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let take = |name| {
+                let range_params = r#"
+                    start: 0
+                    end: 1000
+                    random: true
+                "#;
+                let range_params =
+                    config::RangeProviderPreProcessed::from_yaml_str(range_params).unwrap();
+                let p = range(range_params.into(), name, Some(7), None, None);
+                p.rx.take(20).collect::<Vec<_>>()
+            };
+
+            // two random ranges with the same bounds under one `--seed`
+            let user_ids = take("user_id").await;
+            let order_ids = take("order_id").await;
+            assert_eq!(user_ids, take("user_id").await);
+            assert_ne!(user_ids, order_ids);
+        });
+    }
+
+    #[test]
+    fn weighted_list_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let weights = vec![1.0, 2.0, 0.0, 5.0];
+            let lwo = config::ListWithOptions {
+                values: (0..4).map(|i| json!(i)).collect(),
+                repeat: true,
+                random: true,
+                random_seed: Some(1),
+                weights: Some(weights.clone()),
+                unique: false,
+                share: config::ProviderShare::Split,
+            };
+
+            let p = list(lwo.into(), "weighted_list_provider_works", None, None, None);
+            let samples = 20_000;
+            let values: Vec<_> = p.rx.take(samples).collect().await;
+
+            let mut counts = [0usize; 4];
+            for v in values {
+                counts[v.as_u64().unwrap() as usize] += 1;
+            }
+            let total: f64 = weights.iter().sum();
+            for (i, (count, weight)) in counts.iter().zip(&weights).enumerate() {
+                let frequency = *count as f64 / samples as f64;
+                let expect = weight / total;
+                assert!(
+                    (frequency - expect).abs() < 0.02,
+                    "value {} came up {}, expected {}",
+                    i,
+                    frequency,
+                    expect
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn truncated_gzip_file_provider_errors() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+            std::io::Write::write_all(&mut encoder, b"1\n2\n3\n").unwrap();
+            let gzipped = encoder.finish().into_result().unwrap();
+            let mut tmp = tempfile::Builder::new().suffix(".gz").tempfile().unwrap();
+            std::io::Write::write_all(&mut tmp, &gzipped[..gzipped.len() - 4]).unwrap();
+
+            let fp = config::FileProvider {
+                path: tmp.path().to_str().unwrap().into(),
+                ..Default::default()
+            };
+            let (test_killer, mut test_ended) = broadcast::channel(1);
+            let p = file(fp, test_killer, "truncated_gzip", None, None, None).unwrap();
+            drop(p.tx);
+            let _: Vec<_> = p.rx.collect().await;
+
+            let r = test_ended.recv().await.unwrap();
+            assert!(matches!(r, Err(TestError::FileReading(..))));
+        });
+    }
+
+    #[test]
+    fn random_file_provider_uses_the_test_seed() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut tmp = tempfile::NamedTempFile::new().unwrap();
+            let lines: String = (0..20).map(|i| format!("{}\n", i)).collect();
+            std::io::Write::write_all(&mut tmp, lines.as_bytes()).unwrap();
+
+            let take = |seed| {
+                let fp = config::FileProvider {
+                    path: tmp.path().to_str().unwrap().into(),
+                    format: config::FileFormat::Line,
+                    random: true,
+                    repeat: true,
+                    ..Default::default()
+                };
+                let (test_killer, _) = broadcast::channel(1);
+                let p = file(fp, test_killer, "random_file", seed, None, None).unwrap();
+                p.rx.take(20).collect::<Vec<_>>()
+            };
+
+            let first = take(Some(1)).await;
+            let second = take(Some(1)).await;
+            let third = take(Some(2)).await;
+            assert_eq!(first, second, "same seed");
+            assert_ne!(first, third, "different seed");
+        });
+    }
+
+    #[test]
+    fn json_lines_file_provider_errors_with_the_line() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut tmp = tempfile::NamedTempFile::new().unwrap();
+            std::io::Write::write_all(&mut tmp, b"{\"id\": 1}\n{\"id\": 2\n{\"id\": 3}\n").unwrap();
+
+            let fp = config::FileProvider {
+                path: tmp.path().to_str().unwrap().into(),
+                format: config::FileFormat::JsonLines,
+                ..Default::default()
+            };
+            let (test_killer, mut test_ended) = broadcast::channel(1);
+            let p = file(fp, test_killer, "json_lines", None, None, None).unwrap();
+            drop(p.tx);
+            // the value before the bad line can be dropped along with the error, but none after it
+            // are read
+            let values: Vec<_> = p.rx.collect().await;
+            assert!(values.len() <= 1, "unexpected values: {:?}", values);
+            assert!(values.iter().all(|v| v == &json!({"id": 1})));
+
+            match test_ended.recv().await.unwrap() {
+                Err(e @ TestError::FileReading(..)) => {
+                    let e = e.to_string();
+                    assert!(
+                        e.contains("line 2: invalid json"),
+                        "unexpected error: {}",
+                        e
+                    );
+                }
+                _ => panic!("expected a file reading error"),
+            }
+        });
+    }
+
+    #[test]
+    fn http_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, _kill, _) = test_common::start_test_server(None);
+            let client = Arc::new(
+                crate::create_http_client(crate::ClientSettings {
+                    keepalive: Duration::from_secs(1),
+                    ..Default::default()
+                })
+                .unwrap(),
+            );
+
+            let hp = config::HttpProvider {
+                url: format!("http://127.0.0.1:{port}/?echo=1%0A2%0A3%0A"),
+                headers: vec![("x-token".into(), "abc".into())],
+                csv: Default::default(),
+                auto_return: None,
+                buffer: Default::default(),
+                format: config::FileFormat::Line,
+                random: false,
+                random_seed: None,
+                repeat: false,
+                shuffle: config::FileShuffle::Never,
+                unique: false,
+                share: Default::default(),
+            };
+            let (test_killer, mut test_ended) = broadcast::channel(1);
+            let p = http_seed(
+                hp.clone(),
+                client.clone(),
+                test_killer,
+                "http",
+                None,
+                None,
+                None,
+            );
+            p.ready().await;
+            drop(p.tx);
+            let values: Vec<_> = p.rx.collect().await;
+            assert_eq!(values, vec![json!(1), json!(2), json!(3)]);
+            assert!(test_ended.try_recv().is_err());
+
+            // the server only knows the `/` path
+            let hp = config::HttpProvider {
+                url: format!("http://127.0.0.1:{port}/missing"),
+                ..hp
+            };
+            let (test_killer, mut test_ended) = broadcast::channel(1);
+            let p = http_seed(hp, client, test_killer, "http_missing", None, None, None);
+            p.ready().await;
+            drop(p.tx);
+            let values: Vec<_> = p.rx.collect().await;
+            assert!(values.is_empty());
+
+            match test_ended.recv().await.unwrap() {
+                Err(TestError::HttpProviderFetch(url, e)) => {
+                    assert!(url.ends_with("/missing"));
+                    assert!(e.contains("404"), "unexpected error: {}", e);
+                }
+                _ => panic!("expected a fetch error"),
+            }
+        });
+    }
+
+    #[test]
+    fn http_provider_fetch_times_out() {
+        use std::io::Read;
+
+        // reads the request but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let mut streams = Vec::new();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let _ = stream.read(&mut [0; 1024]);
+                streams.push(stream);
+            }
+        });
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let client = crate::create_http_client(Default::default()).unwrap();
+            let url = format!("http://127.0.0.1:{port}/");
+            let e = fetch(&client, &url, Vec::new(), Duration::from_millis(200))
+                .await
+                .unwrap_err();
+            assert_eq!(e, "timed out after 200ms");
+        });
+    }
+
+    #[test]
+    fn broadcast_list_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let jsons: Vec<_> = (0..20).map(|i| json!(i)).collect();
+            let lwo = config::ListWithOptions {
+                values: jsons.clone(),
+                repeat: false,
+                random: false,
+                random_seed: None,
+                weights: None,
+                unique: false,
+                share: config::ProviderShare::Broadcast,
+            };
+
+            let p = list(
+                lwo.into(),
+                "broadcast_list_provider_works",
+                None,
+                None,
+                None,
+            );
+            let first = p.subscribe();
+            let second = p.subscribe();
+            drop(p);
+
+            let (first, second): (Vec<_>, Vec<_>) =
+                futures::future::join(first.collect(), second.collect()).await;
+
+            assert_eq!(first, jsons, "first subscriber");
+            assert_eq!(second, jsons, "second subscriber");
+
+            // without broadcast the values are split between the two
+            let lwo = config::ListWithOptions {
+                values: jsons.clone(),
+                repeat: false,
+                random: false,
+                random_seed: None,
+                weights: None,
+                unique: false,
+                share: config::ProviderShare::Split,
+            };
+
+            let p = list(lwo.into(), "split_list_provider_works", None, None, None);
+            let first = p.subscribe();
+            let second = p.subscribe();
+            drop(p);
+
+            let (first, second): (Vec<_>, Vec<_>) =
+                futures::future::join(first.collect(), second.collect()).await;
+
+            assert_eq!(first.len() + second.len(), jsons.len(), "split subscribers");
+        });
+    }
+
+    #[test]
+    fn broadcast_subscribers_are_held_back_by_the_slowest() {
+        use std::sync::atomic::AtomicUsize;
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let jsons: Vec<_> = (0..40).map(|i| json!(i)).collect();
+            let lwo = config::ListWithOptions {
+                values: jsons.clone(),
+                repeat: false,
+                random: false,
+                random_seed: None,
+                weights: None,
+                unique: false,
+                share: config::ProviderShare::Broadcast,
+            };
+
+            let p = list(
+                lwo.into(),
+                "broadcast_subscribers_are_held_back_by_the_slowest",
+                None,
+                None,
+                None,
+            );
+            let fast = p.subscribe();
+            let slow = p.subscribe();
+            drop(p);
+
+            let slow_read = Arc::new(AtomicUsize::new(0));
+            let slow_read2 = slow_read.clone();
+            let slow = async move {
+                let mut slow = Box::pin(slow);
+                let mut values = Vec::new();
+                while let Some(value) = slow.next().await {
+                    values.push(value);
+                    slow_read2.fetch_add(1, Ordering::SeqCst);
+                    time::sleep(Duration::from_millis(5)).await;
+                }
+                values
+            };
+            // how far ahead of the slow subscriber the fast one was for each value it read
+            let fast = fast
+                .map(|value| (value, slow_read.load(Ordering::SeqCst)))
+                .collect::<Vec<_>>();
+
+            let (fast, slow) = futures::future::join(fast, slow).await;
+
+            assert_eq!(slow, jsons, "slow subscriber");
+            let (values, slow_reads): (Vec<_>, Vec<_>) = fast.into_iter().unzip();
+            assert_eq!(values, jsons, "fast subscriber");
+            // only the buffers between the provider and the slow subscriber let the fast one get
+            // ahead, not the whole list
+            let ahead = slow_reads
+                .iter()
+                .enumerate()
+                .map(|(i, slow_read)| (i + 1).saturating_sub(*slow_read))
+                .max()
+                .unwrap();
+            assert!(ahead < 20, "fast subscriber got {} values ahead", ahead);
+        });
+    }
+
+    #[test]
+    fn mapped_list_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let config = r#"
+                providers:
+                  n:
+                    list:
+                      - 1
+                      - 2
+                      - 3
+                    map: n * 10
+                  user:
+                    list:
+                      - id: 7
+                        name: ann
+                      - 8
+                    map: user.name
+                endpoints:
+                  - url: http://localhost/${n}/${user}
+            "#;
+            let mut config = config::LoadTest::from_config(
+                config.as_bytes(),
+                std::path::Path::new("./"),
+                &Default::default(),
+            )
+            .unwrap();
+            let (test_killer, mut test_ended) = broadcast::channel(1);
+            let mapper = |name: &str, config: &mut config::LoadTest| match config
+                .providers
+                .remove(name)
+                .unwrap()
+            {
+                config::Provider::Mapped(p, map) => match *p {
+                    config::Provider::List(lp) => (lp, Mapper::new(map, name, test_killer.clone())),
+                    _ => panic!("expected a list provider"),
+                },
+                _ => panic!("expected a mapped provider"),
+            };
+
+            let (lp, n_mapper) = mapper("n", &mut config);
+            let Provider { rx, tx, .. } = list(lp, "n", None, Some(n_mapper), None);
+            drop(tx);
+            let values: Vec<_> = rx.take(4).collect().await;
+            assert_eq!(values, vec![json!(10), json!(20), json!(30), json!(10)]);
+
+            // the second value can't be indexed into, which ends the test
+            let (lp, user_mapper) = mapper("user", &mut config);
+            let Provider { rx, tx, .. } = list(lp, "user", None, Some(user_mapper), None);
+            drop(tx);
+            let values: Vec<_> = rx.collect().await;
+            assert_eq!(values, vec![json!("ann")]);
+            match test_ended.recv().await.unwrap() {
+                Err(TestError::ProviderMap(name, _)) => assert_eq!(name, "user"),
+                _ => panic!("expected a provider map error"),
+            }
+        });
+    }
+
+    #[test]
+    fn sqlite_provider_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("users.db");
+            let db = rusqlite::Connection::open(&path).unwrap();
+            db.execute_batch(
+                "CREATE TABLE users (id INTEGER, name TEXT, score REAL, active BOOLEAN, note TEXT);
+                INSERT INTO users VALUES (1, 'ann', 1.5, 1, NULL);
+                INSERT INTO users VALUES (2, 'bob', 2.25, 0, 'x');
+                INSERT INTO users VALUES (3, 'cy', -3.0, 1, '');",
+            )
+            .unwrap();
+            drop(db);
+
+            let (test_killer, _) = broadcast::channel(1);
+            let sp = config::SqliteProvider {
+                auto_return: None,
+                buffer: config::Limit::dynamic(),
+                path: path.to_string_lossy().into_owned(),
+                query: "SELECT * FROM users ORDER BY id".into(),
+                repeat: false,
+                unique: false,
+                share: config::ProviderShare::default(),
+            };
+            let p = sqlite(
+                sp.clone(),
+                test_killer.clone(),
+                "sqlite_provider_works",
+                None,
+                None,
+            )
+            .unwrap();
+            let Provider { rx, tx, .. } = p;
+            drop(tx);
+            let values: Vec<_> = rx.collect().await;
+            assert_eq!(
+                values,
+                [
+                    json!({"id": 1, "name": "ann", "score": 1.5, "active": true, "note": null}),
+                    json!({"id": 2, "name": "bob", "score": 2.25, "active": false, "note": "x"}),
+                    json!({"id": 3, "name": "cy", "score": -3.0, "active": true, "note": ""}),
+                ]
+            );
+
+            // with repeat the rows come around again
+            let p = sqlite(
+                config::SqliteProvider {
+                    repeat: true,
+                    ..sp.clone()
+                },
+                test_killer.clone(),
+                "sqlite_provider_repeats",
+                None,
+                None,
+            )
+            .unwrap();
+            let ids: Vec<_> =
+                p.rx.take(7)
+                    .map(|v| v["id"].as_i64().unwrap())
+                    .collect()
+                    .await;
+            assert_eq!(ids, [1, 2, 3, 1, 2, 3, 1]);
+
+            // rows are sent as they're read, so a query with no end still gives values
+            let p = sqlite(
+                config::SqliteProvider {
+                    query: "WITH RECURSIVE n(id) AS (SELECT 1 UNION ALL SELECT id + 1 FROM n) \
+                        SELECT id FROM n"
+                        .into(),
+                    buffer: config::Limit::Static(5),
+                    ..sp.clone()
+                },
+                test_killer.clone(),
+                "sqlite_provider_streams",
+                None,
+                None,
+            )
+            .unwrap();
+            let ids: Vec<_> = time::timeout(
+                Duration::from_secs(5),
+                p.rx.take(3).map(|v| v["id"].as_i64().unwrap()).collect(),
+            )
+            .await
+            .expect("rows should be sent before the query ends");
+            assert_eq!(ids, [1, 2, 3]);
+
+            let missing = config::SqliteProvider {
+                path: dir.path().join("missing.db").to_string_lossy().into_owned(),
+                ..sp
+            };
+            assert!(matches!(
+                sqlite(missing, test_killer, "sqlite_provider_missing", None, None),
+                Err(TestError::CannotOpenFile(..))
+            ));
+        });
+    }
+
     #[test]
     fn response_provider_works() {
         let jsons = vec![json!(1), json!(2), json!(3)];
@@ -448,6 +1737,8 @@ mod tests {
             auto_return: None,
             buffer: config::Limit::dynamic(),
             unique: false,
+            sticky: false,
+            initial: Vec::new(),
         };
         let mut p = response(rp, &"response_provider_works".to_string());
         for value in &jsons {
@@ -464,6 +1755,32 @@ mod tests {
         assert_eq!(values, expects);
     }
 
+    #[test]
+    fn response_provider_starts_with_initial_values() {
+        let rp = config::ResponseProvider {
+            auto_return: None,
+            buffer: config::Limit::Static(1),
+            unique: false,
+            sticky: false,
+            initial: vec![json!("seed1"), json!("seed2")],
+        };
+        let p = response(
+            rp,
+            &"response_provider_starts_with_initial_values".to_string(),
+        );
+        // the seeds already fill the buffer
+        p.tx.force_send(json!(3));
+
+        let expects = vec![json!("seed1"), json!("seed2"), json!(3)];
+
+        let Provider { rx, tx, .. } = p;
+        drop(tx);
+
+        let values: Vec<_> = block_on_stream(rx).collect();
+
+        assert_eq!(values, expects);
+    }
+
     #[test]
     fn unique_response_provider_works() {
         let jsons = vec![
@@ -481,6 +1798,8 @@ mod tests {
             auto_return: None,
             buffer: config::Limit::Static(jsons.len()),
             unique: true,
+            sticky: false,
+            initial: Vec::new(),
         };
         let mut p = response(rp, &"unique_response_provider_works".to_string());
         for value in &jsons {
@@ -514,17 +1833,18 @@ mod tests {
             .unwrap();
             let (test_killer, mut test_killed_rx) = broadcast::channel(1);
             let writer = TestWriter::new();
-            let (writer_channel, _) =
+            let (writer_channel, done) =
                 blocking_writer(writer.clone(), test_killer.clone(), "".into());
 
-            let mut tx = logger(logger_params, &test_killer, writer_channel);
+            let mut tx = logger(logger_params, &test_killer, vec![writer_channel]);
 
             for value in vec![json!(1), json!(2)] {
                 let _ = tx.send(value).await;
             }
 
-            // add slight delay because writing to the channel does not mean it's yet written to the file
-            Delay::new(Duration::from_millis(100)).await;
+            // the writer is done once the logger is dropped and everything sent to it is written
+            drop(tx);
+            let _ = done.await;
 
             let left = writer.get_string();
             let right = "1\n";
@@ -555,16 +1875,17 @@ mod tests {
             .unwrap();
             let (test_killer, mut test_killed_rx) = broadcast::channel(1);
             let writer = TestWriter::new();
-            let (writer_channel, _) = blocking_writer(writer.clone(), test_killer.clone(), "".into());
+            let (writer_channel, done) = blocking_writer(writer.clone(), test_killer.clone(), "".into());
 
-            let mut tx = logger(logger_params, &test_killer, writer_channel);
+            let mut tx = logger(logger_params, &test_killer, vec![writer_channel]);
 
             let right: String = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat. Duis aute irure dolor in reprehenderit in voluptate velit esse cillum dolore eu fugiat nulla pariatur. Excepteur sint occaecat cupidatat non proident, sunt in culpa qui officia deserunt mollit anim id est laborum.".repeat(1000);
 
             let _ = tx.send(right.clone().into()).await;
 
-            // add slight delay because writing to the channel does not mean it's yet written to the file
-            Delay::new(Duration::from_millis(100)).await;
+            // the writer is done once the logger is dropped and everything sent to it is written
+            drop(tx);
+            let _ = done.await;
 
             let left = writer.get_string();
             assert_eq!(left, format!("{}\n", right), "value in writer should match");
@@ -595,17 +1916,18 @@ mod tests {
             let (test_killer, mut test_killed_rx) = broadcast::channel(1);
             let writer = TestWriter::new();
             writer.do_would_block_on_next_write();
-            let (writer_channel, _) =
+            let (writer_channel, done) =
                 blocking_writer(writer.clone(), test_killer.clone(), "".into());
 
-            let mut tx = logger(logger_params, &test_killer, writer_channel);
+            let mut tx = logger(logger_params, &test_killer, vec![writer_channel]);
 
             for value in vec![json!(1), json!(2)] {
                 let _ = tx.send(value).await;
             }
 
-            // add slight delay because writing to the channel does not mean it's yet written to the file
-            Delay::new(Duration::from_millis(100)).await;
+            // the writer is done once the logger is dropped and everything sent to it is written
+            drop(tx);
+            let _ = done.await;
 
             let left = writer.get_string();
             let right = "1\n2\n";
@@ -637,17 +1959,18 @@ mod tests {
             .unwrap();
             let (test_killer, mut test_killed_rx) = broadcast::channel(1);
             let writer = TestWriter::new();
-            let (writer_channel, _) =
+            let (writer_channel, done) =
                 blocking_writer(writer.clone(), test_killer.clone(), "".into());
 
-            let mut tx = logger(logger_params, &test_killer, writer_channel);
+            let mut tx = logger(logger_params, &test_killer, vec![writer_channel]);
 
             for value in vec![json!(1), json!(2)] {
                 let _ = tx.send(value).await;
             }
 
-            // add slight delay because writing to the channel does not mean it's yet written to the file
-            Delay::new(Duration::from_millis(100)).await;
+            // the writer is done once the logger is dropped and everything sent to it is written
+            drop(tx);
+            let _ = done.await;
 
             let left = writer.get_string();
             let right = "1\n";
@@ -658,6 +1981,104 @@ mod tests {
         });
     }
 
+    #[test]
+    fn logger_multiple_destinations_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("logged.out");
+            let logger_params = r#"
+                to:
+                  - logged.out
+                  - stderr
+            "#;
+            let logger_params = config::FromYaml::from_yaml_str(logger_params).unwrap();
+            let (logger_params, _) = config::Logger::from_pre_processed(
+                logger_params,
+                &Default::default(),
+                &mut Default::default(),
+            )
+            .unwrap();
+            assert_eq!(logger_params.to, vec!["logged.out", "stderr"]);
+
+            let (test_killer, mut test_killed_rx) = broadcast::channel(1);
+            let file = std::fs::File::create(&path).unwrap();
+            let (file_channel, file_done) =
+                blocking_writer(file, test_killer.clone(), "logged.out".into());
+            let stderr = TestWriter::new();
+            let (stderr_channel, stderr_done) =
+                blocking_writer(stderr.clone(), test_killer.clone(), "stderr".into());
+            // a destination which has gone away doesn't keep the value from the others
+            let (closed_channel, _) = channel(5);
+
+            let mut tx = logger(
+                logger_params,
+                &test_killer,
+                vec![closed_channel, file_channel, stderr_channel],
+            );
+            tx.send(json!({ "a": 1 })).await.unwrap();
+
+            // the writers are done once the logger is dropped and everything sent to them is written
+            drop(tx);
+            let _ = join_all(vec![file_done, stderr_done]).await;
+
+            let right = "{\"a\":1}\n";
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), right);
+            assert_eq!(stderr.get_string(), right);
+            assert!(
+                test_killed_rx.try_recv().is_err(),
+                "test should not be killed"
+            );
+        });
+    }
+
+    #[test]
+    fn logger_gzip_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("logged.out.gz");
+            let logger_params = r#"
+                to: logged.out.gz
+                compress: gzip
+            "#;
+            let logger_params = config::FromYaml::from_yaml_str(logger_params).unwrap();
+            let (logger_params, _) = config::Logger::from_pre_processed(
+                logger_params,
+                &Default::default(),
+                &mut Default::default(),
+            )
+            .unwrap();
+            assert_eq!(
+                logger_params.compress,
+                Some(config::LoggerCompression::Gzip)
+            );
+
+            let (test_killer, mut test_killed_rx) = broadcast::channel(1);
+            let file = std::fs::File::create(&path).unwrap();
+            let (file_channel, done) =
+                blocking_gzip_writer(file, test_killer.clone(), "logged.out.gz".into()).unwrap();
+
+            let mut tx = logger(logger_params, &test_killer, vec![file_channel]);
+            for value in [json!({"a": 1}), json!("b"), json!(3)] {
+                tx.send(value).await.unwrap();
+            }
+            // the gzip stream is only finished once the logger is dropped
+            drop(tx);
+            let _ = done.await;
+
+            let file = std::fs::File::open(&path).unwrap();
+            let mut decoder = libflate::gzip::Decoder::new(file).unwrap();
+            let mut left = String::new();
+            std::io::Read::read_to_string(&mut decoder, &mut left).unwrap();
+            assert_eq!(left, "{\"a\":1}\nb\n3\n");
+            assert!(
+                test_killed_rx.try_recv().is_err(),
+                "test should not be killed"
+            );
+        });
+    }
+
     #[test]
     fn logger_pretty_works() {
         let rt = Runtime::new().unwrap();
@@ -675,16 +2096,17 @@ mod tests {
             .unwrap();
             let (test_killer, mut test_killed_rx) = broadcast::channel(1);
             let writer = TestWriter::new();
-            let (writer_channel, _) =
+            let (writer_channel, done) =
                 blocking_writer(writer.clone(), test_killer.clone(), "".into());
 
-            let mut tx = logger(logger_params, &test_killer, writer_channel);
+            let mut tx = logger(logger_params, &test_killer, vec![writer_channel]);
 
             for value in vec![json!({"foo": [1, 2, 3]}), json!(2)] {
                 let _ = tx.send(value).await;
             }
-            // add slight delay because writing to the channel does not mean it's yet written to the file
-            Delay::new(Duration::from_millis(100)).await;
+            // the writer is done once the logger is dropped and everything sent to it is written
+            drop(tx);
+            let _ = done.await;
 
             let left = writer.get_string();
             let right = "{\n  \"foo\": [\n    1,\n    2,\n    3\n  ]\n}\n2\n";
@@ -694,4 +2116,54 @@ mod tests {
             assert!(check, "test should not be killed");
         });
     }
+
+    #[test]
+    fn logger_envelope_works() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut tags = BTreeMap::new();
+            tags.insert("_id".to_string(), "3".to_string());
+            tags.insert("name".to_string(), "login".to_string());
+            let tags = Arc::new(tags);
+
+            for envelope in [true, false] {
+                let logger_params = format!("to: \"\"\nenvelope: {}", envelope);
+                let logger_params = config::FromYaml::from_yaml_str(&logger_params).unwrap();
+                let (logger_params, _) = config::Logger::from_pre_processed(
+                    logger_params,
+                    &Default::default(),
+                    &mut Default::default(),
+                )
+                .unwrap();
+                let (test_killer, _) = broadcast::channel(1);
+                let writer = TestWriter::new();
+                let (writer_channel, done) =
+                    blocking_writer(writer.clone(), test_killer.clone(), "".into());
+
+                let mut tx =
+                    logger(logger_params, &test_killer, vec![writer_channel]).with_tags(&tags);
+                let _ = tx.send(json!({"foo": [1, 2]})).await;
+                // the writer is done once the logger is dropped and everything sent to it is written
+                drop(tx);
+                let _ = done.await;
+
+                let output = writer.get_string();
+                if !envelope {
+                    assert_eq!(output, "{\"foo\":[1,2]}\n");
+                    continue;
+                }
+                assert_eq!(output.lines().count(), 1);
+                let record: json::Value = json::from_str(&output).unwrap();
+                assert_eq!(record["endpoint"], json!(3));
+                assert_eq!(record["tags"], json!({"_id": "3", "name": "login"}));
+                assert_eq!(record["value"], json!({"foo": [1, 2]}));
+                let ts = record["ts"].as_str().unwrap();
+                assert!(
+                    chrono::DateTime::parse_from_rfc3339(ts).is_ok(),
+                    "ts: {}",
+                    ts
+                );
+            }
+        });
+    }
 }
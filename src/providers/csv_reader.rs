@@ -1,8 +1,12 @@
+use super::file_source::FileSource;
 use crate::util::str_to_json;
-use rand::distributions::{Distribution, Uniform};
+use rand::{
+    distributions::{Distribution, Uniform},
+    rngs::StdRng,
+};
 use serde_json as json;
 
-use std::{fs::File, io, iter::Iterator};
+use std::{io, iter::Iterator};
 
 // A type of file reader which reads a csv file.
 // Each row in the csv is converted into a json value.
@@ -16,13 +20,17 @@ pub struct CsvReader {
     positions: Vec<csv::Position>,
     headers: Option<csv::StringRecord>,
     random: Option<Uniform<usize>>,
-    reader: csv::Reader<File>,
+    rng: StdRng,
+    reader: csv::Reader<FileSource>,
     repeat: bool,
 }
 
 impl CsvReader {
     pub fn new(config: &config::FileProvider, file: &str) -> Result<Self, io::Error> {
-        let file = File::open(file)?;
+        CsvReader::from_source(config, FileSource::open(file, config.compression)?)
+    }
+
+    pub fn from_source(config: &config::FileProvider, file: FileSource) -> Result<Self, io::Error> {
         let csv = &config.csv;
         let mut builder = csv::ReaderBuilder::new();
         builder.comment(csv.comment).escape(csv.escape);
@@ -64,6 +72,7 @@ impl CsvReader {
             positions: Vec::new(),
             headers,
             random: None,
+            rng: config::seeded_rng(config.random_seed),
             reader,
             repeat: config.repeat,
         };
@@ -88,7 +97,7 @@ impl CsvReader {
             }
             let pos_index = if config.random && !cr.positions.is_empty() {
                 let random = Uniform::new(0, cr.positions.len());
-                let pos_index = random.sample(&mut rand::thread_rng());
+                let pos_index = random.sample(&mut cr.rng);
                 cr.random = Some(random);
                 pos_index
             } else {
@@ -113,7 +122,7 @@ impl Iterator for CsvReader {
             if self.positions.is_empty() {
                 return None;
             }
-            let i = random.sample(&mut rand::thread_rng()) % self.positions.len();
+            let i = random.sample(&mut self.rng) % self.positions.len();
             let pos = if self.repeat {
                 self.positions
                     .get(i)
@@ -190,4 +199,50 @@ mod tests {
             assert_eq!(values, expect);
         }
     }
+
+    #[test]
+    fn csv_reader_gzip_works() {
+        let text = CSV_LINES.join("\n");
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(text.as_bytes()).unwrap();
+        let gzipped = encoder.finish().into_result().unwrap();
+
+        let mut plain = NamedTempFile::new().unwrap();
+        plain.write_all(text.as_bytes()).unwrap();
+        let mut compressed = tempfile::Builder::new()
+            .suffix(".csv.gz")
+            .tempfile()
+            .unwrap();
+        compressed.write_all(&gzipped).unwrap();
+
+        for random in [false, true] {
+            let fp = config::FileProvider {
+                format: config::FileFormat::Csv,
+                random,
+                random_seed: Some(1),
+                repeat: random,
+                ..Default::default()
+            };
+            let read = |path: &std::path::Path| -> Vec<_> {
+                CsvReader::new(&fp, path.to_str().unwrap())
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .take(10)
+                    .collect()
+            };
+
+            assert_eq!(read(compressed.path()), read(plain.path()));
+        }
+
+        // a truncated file errors instead of ending early
+        let mut truncated = tempfile::Builder::new().suffix(".gz").tempfile().unwrap();
+        truncated.write_all(&gzipped[..gzipped.len() - 10]).unwrap();
+        let fp = config::FileProvider {
+            format: config::FileFormat::Csv,
+            ..Default::default()
+        };
+        let path = truncated.path().to_str().unwrap();
+        let values: Vec<_> = CsvReader::new(&fp, path).unwrap().collect();
+        assert!(values.iter().any(Result::is_err));
+    }
 }
@@ -0,0 +1,120 @@
+use super::{file_source::FileSource, line_reader::LineReader};
+use serde_json as json;
+
+use std::{io, iter::Iterator};
+
+// A type of file reader which splits each line on a delimiter.
+// Each line becomes a json array of the strings between the delimiters. Unlike the csv reader
+// there is no quoting or escaping, every character other than the delimiter is kept as is.
+pub struct DelimitedReader {
+    delimiter: char,
+    lines: LineReader,
+}
+
+impl DelimitedReader {
+    pub fn new(
+        config: &config::FileProvider,
+        file: &str,
+        delimiter: u8,
+        skip_header: bool,
+    ) -> Result<Self, io::Error> {
+        let file = FileSource::open(file, config.compression)?;
+        DelimitedReader::from_source(config, file, delimiter, skip_header)
+    }
+
+    pub fn from_source(
+        config: &config::FileProvider,
+        file: FileSource,
+        delimiter: u8,
+        skip_header: bool,
+    ) -> Result<Self, io::Error> {
+        let lines = LineReader::with_parser(config, file, |s| Ok(s.into()), skip_header)?;
+        Ok(Self {
+            delimiter: delimiter.into(),
+            lines,
+        })
+    }
+}
+
+impl Iterator for DelimitedReader {
+    type Item = Result<json::Value, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let delimiter = self.delimiter;
+        self.lines.next().map(|r| {
+            r.map(|v| match v {
+                json::Value::String(s) => s.split(delimiter).map(json::Value::from).collect(),
+                _ => unreachable!("lines are parsed as strings"),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    use std::io::Write;
+
+    const TSV: &str = "id\tname\tnote\n\
+                       1\t\"quoted\"\tit's\n\
+                       2\tbob\t\n";
+
+    #[test]
+    fn delimited_reader_round_trips() {
+        let fp = config::FileProvider::default();
+
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(tmp, "{}", TSV).unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+
+        let values: Vec<_> = DelimitedReader::new(&fp, &path, b'\t', true)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let expect = vec![
+            json::json!(["1", "\"quoted\"", "it's"]),
+            json::json!(["2", "bob", ""]),
+        ];
+        assert_eq!(values, expect);
+
+        // putting the fields back together gives the original rows
+        let rows: Vec<_> = values
+            .iter()
+            .map(|v| {
+                let fields: Vec<_> = v
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|f| f.as_str().unwrap())
+                    .collect();
+                fields.join("\t")
+            })
+            .collect();
+        let expect: Vec<_> = TSV.lines().skip(1).collect();
+        assert_eq!(rows, expect);
+    }
+
+    #[test]
+    fn delimited_reader_repeat_skips_header() {
+        let fp = config::FileProvider {
+            repeat: true,
+            ..Default::default()
+        };
+
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(tmp, "{}", TSV).unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+
+        let values: Vec<_> = DelimitedReader::new(&fp, &path, b'\t', true)
+            .unwrap()
+            .map(Result::unwrap)
+            .take(4)
+            .collect();
+
+        let ids: Vec<_> = values.iter().map(|v| v[0].clone()).collect();
+        assert_eq!(ids, vec!["1", "2", "1", "2"]);
+    }
+}
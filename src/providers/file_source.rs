@@ -0,0 +1,157 @@
+use bytes::Bytes;
+use libflate::gzip;
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+// The file a file provider's reader reads from, decompressing it on the fly when it is gzipped.
+// A gzipped file is never held in memory as a whole, so seeking within it means decompressing
+// up to that point, from the start of the file when seeking backwards. The data of an http
+// provider is already in memory, having been fetched as a whole.
+pub enum FileSource {
+    Plain(File),
+    Gzip(Box<GzipSource>),
+    Memory(io::Cursor<Bytes>),
+}
+
+impl FileSource {
+    pub fn open(
+        file: &str,
+        compression: Option<config::FileCompression>,
+    ) -> Result<Self, io::Error> {
+        let gzip = match compression {
+            Some(config::FileCompression::Gzip) => true,
+            Some(config::FileCompression::None) => false,
+            None => file.ends_with(".gz"),
+        };
+        if gzip {
+            GzipSource::open(file.into()).map(|g| FileSource::Gzip(g.into()))
+        } else {
+            File::open(file).map(FileSource::Plain)
+        }
+    }
+}
+
+impl Read for FileSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FileSource::Plain(f) => f.read(buf),
+            FileSource::Gzip(g) => g.read(buf),
+            FileSource::Memory(m) => m.read(buf),
+        }
+    }
+}
+
+impl Seek for FileSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            FileSource::Plain(f) => f.seek(pos),
+            FileSource::Gzip(g) => g.seek(pos),
+            FileSource::Memory(m) => m.seek(pos),
+        }
+    }
+}
+
+pub struct GzipSource {
+    decoder: gzip::MultiDecoder<BufReader<File>>,
+    path: PathBuf,
+    // how far into the decompressed data the decoder is
+    position: u64,
+}
+
+impl GzipSource {
+    fn open(path: PathBuf) -> Result<Self, io::Error> {
+        // the gzip header is read here, so a file which isn't gzipped errors right away
+        let decoder = gzip::MultiDecoder::new(BufReader::new(File::open(&path)?))?;
+        Ok(GzipSource {
+            decoder,
+            path,
+            position: 0,
+        })
+    }
+}
+
+impl Read for GzipSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.decoder.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for GzipSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+            // the decompressed length isn't known without reading the whole file
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "cannot seek from the end of a gzipped file",
+                ))
+            }
+        };
+        let target = target.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        if target < self.position {
+            *self = GzipSource::open(self.path.clone())?;
+        }
+        let skip = target - self.position;
+        io::copy(&mut self.by_ref().take(skip), &mut io::sink())?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    use std::io::Write;
+
+    const TEXT: &str = "one\ntwo\nthree\n";
+
+    fn gzipped(text: &str) -> Vec<u8> {
+        let mut encoder = gzip::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    #[test]
+    fn gzip_source_reads_and_seeks() {
+        let mut tmp = NamedTempFile::new().unwrap();
+        tmp.write_all(&gzipped(TEXT)).unwrap();
+        let path = tmp.path().to_str().unwrap();
+
+        let mut source = FileSource::open(path, Some(config::FileCompression::Gzip)).unwrap();
+        let mut s = String::new();
+        source.read_to_string(&mut s).unwrap();
+        assert_eq!(s, TEXT);
+
+        // backwards, then forwards
+        for (pos, expect) in [(4, "two\nthree\n"), (8, "three\n"), (0, TEXT)] {
+            assert_eq!(source.seek(SeekFrom::Start(pos)).unwrap(), pos);
+            let mut s = String::new();
+            source.read_to_string(&mut s).unwrap();
+            assert_eq!(s, expect);
+        }
+    }
+
+    #[test]
+    fn gzip_source_errors_on_a_plain_file() {
+        let mut tmp = NamedTempFile::new().unwrap();
+        tmp.write_all(TEXT.as_bytes()).unwrap();
+        let path = tmp.path().to_str().unwrap();
+
+        assert!(FileSource::open(path, Some(config::FileCompression::Gzip)).is_err());
+        assert!(FileSource::open(path, None).is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+use super::{file_source::FileSource, line_reader::LineReader};
+use serde_json as json;
+
+use std::{io, iter::Iterator};
+
+// A type of file reader which reads exactly one json value from each line.
+// Unlike the json reader a line which isn't valid json is an error, giving the number of the line,
+// instead of the parser carrying on into the lines after it.
+pub struct JsonLinesReader {
+    lines: LineReader,
+}
+
+impl JsonLinesReader {
+    pub fn new(config: &config::FileProvider, file: &str) -> Result<Self, io::Error> {
+        JsonLinesReader::from_source(config, FileSource::open(file, config.compression)?)
+    }
+
+    pub fn from_source(
+        config: &config::FileProvider,
+        reader: FileSource,
+    ) -> Result<Self, io::Error> {
+        let lines = LineReader::with_parser(config, reader, parse_line, false)?;
+        Ok(Self { lines })
+    }
+}
+
+fn parse_line(s: &str) -> Result<json::Value, String> {
+    json::from_str(s).map_err(|e| {
+        // the parser only ever sees the one line, so its own line number is always 1
+        let msg = e.to_string();
+        let at = format!(" at line {} column {}", e.line(), e.column());
+        let msg = msg.strip_suffix(&at).unwrap_or(&msg);
+        format!("invalid json at column {}, {msg}", e.column())
+    })
+}
+
+impl Iterator for JsonLinesReader {
+    type Item = Result<json::Value, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    use std::io::Write;
+
+    #[test]
+    fn json_lines_reader_works() {
+        let fp = config::FileProvider::default();
+
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(tmp, "{{\"a\": 1}}\r\n[1, 2]\n\"text\"\n").unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+
+        let values: Vec<_> = JsonLinesReader::new(&fp, &path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let expect = vec![
+            json::json!({"a": 1}),
+            json::json!([1, 2]),
+            json::json!("text"),
+        ];
+        assert_eq!(values, expect);
+    }
+
+    #[test]
+    fn json_lines_reader_reports_the_bad_line() {
+        let fp = config::FileProvider::default();
+
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(
+            tmp,
+            "{{\"id\": 1}}\n{{\"id\": 2}}\n{{\"id\": }}\n{{\"id\": 4}}\n"
+        )
+        .unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+
+        let mut reader = JsonLinesReader::new(&fp, &path).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), json::json!({"id": 1}));
+        assert_eq!(reader.next().unwrap().unwrap(), json::json!({"id": 2}));
+        let e = reader.next().unwrap().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            e.to_string(),
+            "line 3: invalid json at column 8, expected value"
+        );
+        // the bad line doesn't take the lines after it with it
+        assert_eq!(reader.next().unwrap().unwrap(), json::json!({"id": 4}));
+
+        // with `random` every line is read up front, so the reader can't be created
+        let fp = config::FileProvider {
+            random: true,
+            ..Default::default()
+        };
+        let e = JsonLinesReader::new(&fp, &path).err().unwrap();
+        assert!(
+            e.to_string().starts_with("line 3: "),
+            "unexpected error: {}",
+            e
+        );
+    }
+}
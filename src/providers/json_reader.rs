@@ -1,8 +1,11 @@
-use rand::distributions::{Distribution, Uniform};
+use super::file_source::FileSource;
+use rand::{
+    distributions::{Distribution, Uniform},
+    rngs::StdRng,
+};
 use serde_json as json;
 
 use std::{
-    fs::File,
     io::{self, Read, Seek},
     iter::{self, Iterator},
 };
@@ -14,19 +17,28 @@ pub struct JsonReader {
     position: u64,
     positions: Vec<(io::SeekFrom, usize)>,
     random: Option<Uniform<usize>>,
-    reader: File,
+    rng: StdRng,
+    reader: FileSource,
     repeat: bool,
 }
 
 impl JsonReader {
     pub fn new(config: &config::FileProvider, file: &str) -> Result<Self, io::Error> {
+        JsonReader::from_source(config, FileSource::open(file, config.compression)?)
+    }
+
+    pub fn from_source(
+        config: &config::FileProvider,
+        reader: FileSource,
+    ) -> Result<Self, io::Error> {
         let mut jr = Self {
             staging_buffer: vec![0; 8 * (1 << 10)],
             buffer: Vec::new(),
             position: 0,
             positions: Vec::new(),
             random: None,
-            reader: File::open(file)?,
+            rng: config::seeded_rng(config.random_seed),
+            reader,
             repeat: config.repeat,
         };
         if config.random {
@@ -41,7 +53,7 @@ impl JsonReader {
             }
             if !jr.positions.is_empty() {
                 let random = Uniform::new(0, jr.positions.len());
-                let rand_pos = jr.positions.get(random.sample(&mut rand::thread_rng()));
+                let rand_pos = jr.positions.get(random.sample(&mut jr.rng));
                 if let Some((pos, _)) = rand_pos {
                     let pos = *pos;
                     jr.seek(pos)?;
@@ -118,7 +130,7 @@ impl Iterator for JsonReader {
             if self.positions.is_empty() {
                 return None;
             }
-            let i = random.sample(&mut rand::thread_rng()) % self.positions.len();
+            let i = random.sample(&mut self.rng) % self.positions.len();
             let (pos, size) = if self.repeat {
                 self.positions[i]
             } else {
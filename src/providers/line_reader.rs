@@ -1,11 +1,14 @@
+use super::file_source::FileSource;
 use crate::util::str_to_json;
-use rand::distributions::{Distribution, Uniform};
+use rand::{
+    distributions::{Distribution, Uniform},
+    rngs::StdRng,
+};
 use serde_json as json;
 
 static KB8: usize = 8 * (1 << 10);
 
 use std::{
-    fs::File,
     io::{self, Read, Seek},
     iter::{self, Iterator},
 };
@@ -16,28 +19,61 @@ pub struct LineReader {
     byte_buffer: Vec<u8>,
     buf_data_len: usize,
     position: u64,
-    positions: Vec<(io::SeekFrom, usize)>,
+    // the number of the line at `position`, counting from 1
+    line: u64,
+    positions: Vec<(io::SeekFrom, usize, u64)>,
     random: Option<Uniform<usize>>,
-    reader: File,
+    rng: StdRng,
+    reader: FileSource,
     repeat: bool,
+    // turns a line into its value, or says why it can't be
+    parse: fn(&str) -> Result<json::Value, String>,
 }
 
 impl LineReader {
     pub fn new(config: &config::FileProvider, file: &str) -> Result<Self, io::Error> {
+        LineReader::from_source(config, FileSource::open(file, config.compression)?)
+    }
+
+    pub fn from_source(
+        config: &config::FileProvider,
+        reader: FileSource,
+    ) -> Result<Self, io::Error> {
+        LineReader::with_parser(config, reader, |s| Ok(str_to_json(s)), false)
+    }
+
+    // a reader which turns each line into a value with `parse`, where a line which can't be parsed
+    // is an error giving its line number. With `skip_first_line` the first line is never yielded,
+    // not even when going back through the file with `repeat`
+    pub fn with_parser(
+        config: &config::FileProvider,
+        reader: FileSource,
+        parse: fn(&str) -> Result<json::Value, String>,
+        skip_first_line: bool,
+    ) -> Result<Self, io::Error> {
         let mut jr = Self {
             byte_buffer: vec![0; KB8],
             buf_data_len: 0,
             position: 0,
+            line: 1,
             positions: Vec::new(),
             random: None,
-            reader: File::open(file)?,
+            rng: config::seeded_rng(config.random_seed),
+            reader,
             repeat: config.repeat,
+            parse,
         };
+        if skip_first_line {
+            if let Some(Err(e)) = jr.get_value(None) {
+                return Err(e);
+            }
+        }
         if config.random {
             loop {
+                let line = jr.line;
                 match jr.get_value(None) {
                     Some(Ok((_, pos, length))) => {
-                        jr.positions.push((io::SeekFrom::Start(pos), length))
+                        jr.positions.push((io::SeekFrom::Start(pos), length, line))
                     }
                     Some(Err(e)) => return Err(e),
                     None => break,
@@ -45,15 +81,16 @@ impl LineReader {
             }
             if !jr.positions.is_empty() {
                 let random = Uniform::new(0, jr.positions.len());
-                let rand_pos = jr.positions.get(random.sample(&mut rand::thread_rng()));
-                if let Some((pos, _)) = rand_pos {
+                let rand_pos = jr.positions.get(random.sample(&mut jr.rng));
+                if let Some((pos, ..)) = rand_pos {
                     let pos = *pos;
                     jr.seek(pos)?;
                 }
                 jr.random = Some(random);
             }
         } else if config.repeat {
-            jr.positions.push((io::SeekFrom::Start(0), 0));
+            jr.positions
+                .push((io::SeekFrom::Start(jr.position), 0, jr.line));
         }
         Ok(jr)
     }
@@ -94,10 +131,16 @@ impl LineReader {
                     raw_value = &self.byte_buffer[..i2];
                 }
                 let value = String::from_utf8_lossy(raw_value);
-                let value = str_to_json(&value);
+                let value = (self.parse)(&value).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {e}", self.line),
+                    )
+                });
                 self.byte_buffer.drain(..i + 1);
                 self.buf_data_len -= self.buf_data_len.min(i + 1);
-                return Some(Ok((value, position, i)));
+                self.line += 1;
+                return Some(value.map(|value| (value, position, i)));
             } else {
                 let start_length = self.buf_data_len;
                 let new_length = KB8 + start_length;
@@ -134,8 +177,8 @@ impl Iterator for LineReader {
             if self.positions.is_empty() {
                 return None;
             }
-            let i = random.sample(&mut rand::thread_rng()) % self.positions.len();
-            let (pos, size) = if self.repeat {
+            let i = random.sample(&mut self.rng) % self.positions.len();
+            let (pos, size, line) = if self.repeat {
                 self.positions[i]
             } else {
                 self.positions.remove(i)
@@ -143,16 +186,18 @@ impl Iterator for LineReader {
             if let Err(e) = self.seek(pos) {
                 return Some(Err(e));
             }
+            self.line = line;
             Some(size)
         } else {
             None
         };
         let mut result = self.get_value(size_hint);
         if result.is_none() && self.repeat {
-            if let Some((pos, size)) = self.positions.first().cloned() {
+            if let Some((pos, size, line)) = self.positions.first().cloned() {
                 if let Err(e) = self.seek(pos) {
                     return Some(Err(e));
                 } else {
+                    self.line = line;
                     result = self.get_value(Some(size));
                 }
             } else {
@@ -264,4 +309,33 @@ mod tests {
             assert_eq!(values.len(), 5);
         }
     }
+
+    #[test]
+    fn line_reader_random_seed_works() {
+        let mut fp = config::FileProvider {
+            random: true,
+            repeat: true,
+            ..Default::default()
+        };
+
+        let mut tmp = NamedTempFile::new().unwrap();
+        write!(tmp, "{}", LINES.join("\n")).unwrap();
+        let path = tmp.path().to_str().unwrap().to_string();
+
+        let mut take = |random_seed| {
+            fp.random_seed = Some(random_seed);
+            LineReader::new(&fp, &path)
+                .unwrap()
+                .map(Result::unwrap)
+                .take(50)
+                .collect::<Vec<_>>()
+        };
+
+        let first = take(1);
+        let second = take(1);
+        let third = take(2);
+
+        assert_eq!(first, second, "same seed");
+        assert_ne!(first, third, "different seed");
+    }
 }
@@ -0,0 +1,172 @@
+use rand::{distributions::Uniform, rngs::StdRng, Rng};
+
+// the number of times a value is mixed when picking its place in the shuffled order
+const ROUNDS: usize = 4;
+
+// Yields the values of a `range` provider in a random order. Without `unique` each value is
+// picked independently, so a value can come up more than once; with it every value in the range
+// comes up exactly once before any repeats. Either way one pass yields as many values as the range
+// has and `repeat` keeps going.
+//
+// A range can span all of `i64`, so for `unique` the shuffled order isn't held in memory. Instead
+// a value's index is found by running a counter through a random bijection over the smallest
+// power of two which fits the range, skipping anything past the end of the range.
+pub struct RandomRange {
+    start: i64,
+    step: i128,
+    len: u128,
+    repeat: bool,
+    rng: StdRng,
+    // (multiplier, xor key) of each round of the bijection
+    rounds: [(u128, u128); ROUNDS],
+    mask: u128,
+    shift: u32,
+    // how far through the bijection's domain (with `unique`) or the pass (without) it is
+    position: u128,
+    unique: bool,
+}
+
+impl RandomRange {
+    // with a `seed` the values are the same every run
+    pub fn new(
+        start: i64,
+        end: i64,
+        step: u16,
+        repeat: bool,
+        unique: bool,
+        seed: Option<u64>,
+    ) -> Self {
+        let mut rng = config::seeded_rng(seed);
+        let step = i128::from(step);
+        let len = if end < start {
+            0
+        } else {
+            ((i128::from(end) - i128::from(start)) / step + 1) as u128
+        };
+        // `len` is at most 2^64, so this can't overflow
+        let domain = len.next_power_of_two();
+        let mask = domain - 1;
+        let bits = domain.trailing_zeros();
+        let mut rounds = [(0, 0); ROUNDS];
+        for round in &mut rounds {
+            // an odd multiplier is a bijection modulo a power of two
+            *round = ((rng.gen::<u128>() | 1) & mask, rng.gen::<u128>() & mask);
+        }
+        RandomRange {
+            start,
+            step,
+            len,
+            repeat,
+            rng,
+            rounds,
+            mask,
+            shift: bits.div_ceil(2).max(1),
+            position: 0,
+            unique,
+        }
+    }
+
+    fn permute(&self, mut x: u128) -> u128 {
+        for (multiplier, key) in &self.rounds {
+            x ^= key;
+            x = x.wrapping_mul(*multiplier) & self.mask;
+            x ^= x >> self.shift;
+        }
+        x
+    }
+
+    fn value_at(&self, index: u128) -> i64 {
+        (i128::from(self.start) + index as i128 * self.step) as i64
+    }
+
+    fn next_unique(&mut self) -> Option<i64> {
+        loop {
+            if self.position > self.mask {
+                if !self.repeat {
+                    return None;
+                }
+                self.position = 0;
+            }
+            let index = self.permute(self.position);
+            self.position += 1;
+            if index < self.len {
+                return Some(self.value_at(index));
+            }
+        }
+    }
+}
+
+impl Iterator for RandomRange {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        if self.unique {
+            return self.next_unique();
+        }
+        if self.position == self.len {
+            if !self.repeat {
+                return None;
+            }
+            self.position = 0;
+        }
+        self.position += 1;
+        let index = self.rng.sample(Uniform::new(0, self.len));
+        Some(self.value_at(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_values_stay_in_the_range() {
+        let values: Vec<_> = RandomRange::new(-10, 30, 4, false, false, Some(7)).collect();
+        // -10, -6, ... 30
+        assert_eq!(values.len(), 11);
+        for v in &values {
+            assert!((-10..=30).contains(v), "{}", v);
+            assert_eq!((v + 10) % 4, 0, "{}", v);
+        }
+        let again: Vec<_> = RandomRange::new(-10, 30, 4, false, false, Some(7)).collect();
+        assert_eq!(values, again);
+
+        let repeated = RandomRange::new(-10, 30, 4, true, false, None).take(1_000);
+        assert_eq!(repeated.count(), 1_000);
+
+        let huge: Vec<_> = RandomRange::new(i64::MIN, i64::MAX, 1, false, false, None)
+            .take(100)
+            .collect();
+        assert_eq!(huge.len(), 100);
+    }
+
+    #[test]
+    fn unique_random_covers_the_range_once() {
+        for (start, end, step) in [(0, 99, 1), (5, 1_000, 3), (7, 7, 1), (0, 1, 1)] {
+            let values: Vec<_> =
+                RandomRange::new(start, end, step, false, true, Some(42)).collect();
+            let expected: Vec<_> = (start..=end).step_by(step.into()).collect();
+            assert_eq!(values.len(), expected.len());
+            if expected.len() > 10 {
+                assert_ne!(values, expected);
+            }
+            let mut sorted = values;
+            sorted.sort_unstable();
+            assert_eq!(sorted, expected);
+        }
+
+        // with repeat the same order comes around again
+        let values: Vec<_> = RandomRange::new(0, 99, 1, true, true, None)
+            .take(200)
+            .collect();
+        assert_eq!(values[..100], values[100..]);
+
+        let huge: Vec<_> = RandomRange::new(i64::MIN, i64::MAX, 1, false, true, None)
+            .take(100)
+            .collect();
+        assert_eq!(huge.len(), 100);
+    }
+}
@@ -0,0 +1,96 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde_json as json;
+
+use std::io;
+
+// Reads all of the values from a file provider's reader, shuffles them once, then yields them in
+// that order. With `repeat` the same order is yielded every time through. The whole file is held
+// in memory for the life of the provider.
+pub struct ShuffledOnce<I> {
+    reader: Option<I>,
+    repeat: bool,
+    rng: StdRng,
+    values: Vec<json::Value>,
+    next: usize,
+}
+
+impl<I> ShuffledOnce<I> {
+    // with a `seed` the order is the same every run
+    pub fn new(reader: I, repeat: bool, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        ShuffledOnce {
+            reader: Some(reader),
+            repeat,
+            rng,
+            values: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<I> Iterator for ShuffledOnce<I>
+where
+    I: Iterator<Item = Result<json::Value, io::Error>>,
+{
+    type Item = Result<json::Value, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the file is only read once the first value is asked for, so it happens off of the
+        // async runtime along with the rest of the reading
+        if let Some(reader) = self.reader.take() {
+            match reader.collect::<Result<Vec<_>, _>>() {
+                Ok(values) => self.values = values,
+                Err(e) => return Some(Err(e)),
+            }
+            self.values.shuffle(&mut self.rng);
+        }
+        if self.next == self.values.len() {
+            if !self.repeat || self.values.is_empty() {
+                return None;
+            }
+            self.next = 0;
+        }
+        let value = self.values[self.next].clone();
+        self.next += 1;
+        Some(Ok(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> impl Iterator<Item = Result<json::Value, io::Error>> {
+        (0..20).map(|i| Ok(i.into()))
+    }
+
+    #[test]
+    fn same_seed_same_order() {
+        let first: Vec<_> = ShuffledOnce::new(values(), false, Some(42))
+            .map(Result::unwrap)
+            .collect();
+        let second: Vec<_> = ShuffledOnce::new(values(), false, Some(42))
+            .map(Result::unwrap)
+            .collect();
+        let in_order: Vec<_> = values().map(Result::unwrap).collect();
+        assert_eq!(first, second);
+        assert_ne!(first, in_order);
+
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|v| v.as_u64());
+        assert_eq!(sorted, in_order);
+    }
+
+    #[test]
+    fn repeats_the_same_order() {
+        let values: Vec<_> = ShuffledOnce::new(values(), true, None)
+            .take(60)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(values[..20], values[20..40]);
+        assert_eq!(values[..20], values[40..]);
+    }
+}
@@ -0,0 +1,111 @@
+use futures::{
+    channel::mpsc::{channel, Sender},
+    executor::block_on,
+    SinkExt, Stream,
+};
+use rusqlite::{types::ValueRef, Connection, OpenFlags, Row};
+use serde_json as json;
+use tokio::task::spawn_blocking;
+
+use std::io;
+
+type RowSender = Sender<Result<json::Value, io::Error>>;
+
+// Reads the rows of a `sqlite` provider's query. The database is opened up front, so a bad path
+// is reported when the test starts, but the query runs off of the async runtime, with each row
+// sent on as it's read so no more rows are read than the provider has room for. With `repeat`
+// every row is held in memory so the query only runs once.
+pub struct SqliteReader {
+    connection: Connection,
+    query: String,
+    repeat: bool,
+}
+
+impl SqliteReader {
+    pub fn new(config: &config::SqliteProvider, file: &str) -> Result<Self, io::Error> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connection = Connection::open_with_flags(file, flags).map_err(to_io_error)?;
+        Ok(SqliteReader {
+            connection,
+            query: config.query.clone(),
+            repeat: config.repeat,
+        })
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<json::Value, io::Error>> {
+        let (mut tx, rx) = channel(5);
+        spawn_blocking(move || {
+            let mut rows = Vec::new();
+            match self.run_query(&mut tx, &mut rows) {
+                Ok(true) => (),
+                // the receiver was dropped
+                Ok(false) => return,
+                Err(e) => {
+                    let _ = block_on(tx.send(Err(to_io_error(e))));
+                    return;
+                }
+            }
+            if !self.repeat || rows.is_empty() {
+                return;
+            }
+            for value in rows.iter().cycle() {
+                if block_on(tx.send(Ok(value.clone()))).is_err() {
+                    break;
+                }
+            }
+        });
+        rx
+    }
+
+    // sends each row of the query as it's read, keeping a copy in `kept` with `repeat`. Returns
+    // whether every row was sent
+    fn run_query(
+        &self,
+        tx: &mut RowSender,
+        kept: &mut Vec<json::Value>,
+    ) -> Result<bool, rusqlite::Error> {
+        let mut statement = self.connection.prepare(&self.query)?;
+        let columns: Vec<_> = statement
+            .columns()
+            .iter()
+            .map(|c| {
+                // sqlite stores booleans as integers, so the declared type is all that sets them
+                // apart
+                let is_bool = c.decl_type().is_some_and(|t| {
+                    t.eq_ignore_ascii_case("boolean") || t.eq_ignore_ascii_case("bool")
+                });
+                (c.name().to_string(), is_bool)
+            })
+            .collect();
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let value = row_to_json(row, &columns)?;
+            if self.repeat {
+                kept.push(value.clone());
+            }
+            if block_on(tx.send(Ok(value))).is_err() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn row_to_json(row: &Row<'_>, columns: &[(String, bool)]) -> Result<json::Value, rusqlite::Error> {
+    let mut object = json::Map::new();
+    for (i, (name, is_bool)) in columns.iter().enumerate() {
+        let value = match row.get_ref(i)? {
+            ValueRef::Null => json::Value::Null,
+            ValueRef::Integer(n) if *is_bool => (n != 0).into(),
+            ValueRef::Integer(n) => n.into(),
+            ValueRef::Real(n) => n.into(),
+            ValueRef::Text(s) | ValueRef::Blob(s) => String::from_utf8_lossy(s).into_owned().into(),
+        };
+        object.insert(name.clone(), value);
+    }
+    Ok(object.into())
+}
+
+fn to_io_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
@@ -1,10 +1,20 @@
 #![allow(clippy::type_complexity)]
 mod body_handler;
+mod cookie_jar;
+mod depends_on;
+mod random_body;
+mod rate_limit;
 mod request_maker;
 mod response_handler;
+mod retry_after;
 
-use self::body_handler::BodyHandler;
+use self::body_handler::{Assertions, BodyHandler};
+pub use self::cookie_jar::CookieJar;
+use self::depends_on::ReadySignal;
+use self::random_body::RandomBody;
+pub use self::rate_limit::RateLimit;
 use self::request_maker::RequestMaker;
+use self::retry_after::RetryAfterGate;
 
 use log::debug;
 use request_maker::ProviderDelays;
@@ -19,38 +29,45 @@ use futures::{
     stream, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt,
 };
 use hyper::{
-    client::HttpConnector,
-    header::{Entry as HeaderEntry, HeaderName, HeaderValue, CONTENT_DISPOSITION},
-    Body as HyperBody, Client, Method, Response,
+    header::{Entry as HeaderEntry, HeaderMap, HeaderName, HeaderValue, CONTENT_DISPOSITION},
+    Body as HyperBody, Method, Response,
 };
-use hyper_tls::HttpsConnector;
 use rand::distributions::{Alphanumeric, Distribution};
 use select_any::select_any;
 use serde_json as json;
 use tokio::{
     fs::File as TokioFile,
     io::{AsyncRead, ReadBuf},
+    sync::{broadcast, watch},
 };
 use zip_all::zip_all;
 
 use crate::error::{RecoverableError, TestError};
+use crate::metrics::InFlight;
+use crate::otlp;
 use crate::providers;
+use crate::proxy::ProxySettings;
 use crate::stats;
+use crate::trace;
 use crate::util::tweak_path;
+use crate::{HttpClient, TestEndReason};
+use body_reader::{BodyEncoder, Compression};
 use config::{
-    BodyTemplate, EndpointProvidesSendOptions, MultipartBody, ProviderStream, Select, Template,
+    BodyEncoding, BodyTemplate, EndpointProvidesSendOptions, MultipartBody, ProviderStream, Select,
+    Template,
 };
 
 use std::{
     borrow::Cow,
     collections::{BTreeMap, BTreeSet},
     future::Future,
+    net::SocketAddr,
     num::NonZeroUsize,
     ops::{Deref, DerefMut},
     path::PathBuf,
     pin::Pin,
     str,
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
     time::{Duration, Instant},
 };
@@ -102,6 +119,37 @@ impl AutoReturn {
     }
 }
 
+/// The names of the providers which ended an endpoint by running out of values
+#[derive(Clone, Default)]
+pub struct EndedProviders(Arc<Mutex<BTreeSet<String>>>);
+
+impl EndedProviders {
+    // passes on the values from `stream`, noting `name` when it ends
+    fn track<S>(&self, name: String, stream: S) -> impl Stream<Item = S::Item> + Unpin
+    where
+        S: Stream + Unpin,
+    {
+        let ended = self.clone();
+        stream.chain(stream::poll_fn(move |_| {
+            ended
+                .0
+                .lock()
+                .expect("ended providers lock should not be poisoned")
+                .insert(name.clone());
+            Poll::Ready(None)
+        }))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .expect("ended providers lock should not be poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TemplateValues(json::Value);
 
@@ -155,6 +203,14 @@ impl ProviderOrLogger {
         }
     }
 
+    // loggers need the endpoint's tags when they wrap values in an envelope
+    fn with_tags(&self, tags: &Arc<BTreeMap<String, String>>) -> Self {
+        match &self {
+            Self::Provider(tx) => Self::Provider(tx.clone()),
+            Self::Logger(logger) => Self::Logger(logger.with_tags(tags)),
+        }
+    }
+
     fn name(&self) -> String {
         match &self {
             Self::Provider(provider) => format!("Provider: {}", provider.name()),
@@ -187,7 +243,7 @@ type ProviderStreamStream<Ar> = Box<
 impl ProviderStream<AutoReturn> for providers::Provider {
     fn into_stream(&self) -> ProviderStreamStream<AutoReturn> {
         let auto_return = self.auto_return.map(|ar| (ar, self.tx.clone()));
-        let future = self.rx.clone().map(move |v| {
+        let future = self.subscribe().map(move |v| {
             let mut outgoing = Vec::new();
             if let Some((ar, tx)) = &auto_return {
                 outgoing.push(AutoReturn::new(*ar, tx.clone(), vec![v.clone()]));
@@ -202,19 +258,80 @@ pub struct BuilderContext {
     pub config: config::Config,
     pub config_path: PathBuf,
     // the http client
-    pub client:
-        Arc<Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>>,
+    pub client: Arc<HttpClient>,
+    // the clients for endpoints which set `connect_to`, by the address they dial
+    pub connect_to_clients: BTreeMap<SocketAddr, Arc<HttpClient>>,
+    // `client.proxy`, which the client's requests go through
+    pub proxy: Option<Arc<ProxySettings>>,
+    // `client.cookies`, the cookies shared by every endpoint's requests
+    pub cookie_jar: Option<CookieJar>,
     // a mapping of names to their prospective providers
     pub providers: Arc<BTreeMap<String, providers::Provider>>,
     // a mapping of names to their prospective loggers
     pub loggers: BTreeMap<String, providers::Logger>,
     // channel that receives and aggregates stats for the test
     pub stats_tx: StatsTx,
+    // channel that receives spans to export over otlp, if enabled
+    pub spans_tx: Option<otlp::SpansTx>,
+    // channel that receives each request's events for the `--trace` file, if enabled
+    pub trace_tx: Option<trace::EventsTx>,
+    // seeds the lengths and bytes of `random_bytes` bodies
+    pub seed: Option<u64>,
+    // collects the providers which run out while endpoints are using them
+    pub ended_providers: EndedProviders,
+    // shared by every endpoint when `general.max_total_rate` is set
+    pub rate_limit: Option<RateLimit>,
+    // ends the test when an assertion with `kill` fails
+    pub test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
 }
 
 pub struct EndpointBuilder {
     endpoint: config::Endpoint,
     start_stream: Option<Pin<Box<dyn Stream<Item = (Instant, Option<Instant>)> + Send>>>,
+    ready: Option<ReadySignal>,
+    dependencies: Vec<watch::Receiver<bool>>,
+    in_flight: InFlight,
+}
+
+/// Makes each endpoint wait on the endpoints listed in its `depends_on`. The builders must be in
+/// the same order as the endpoints in the config, as `depends_on` references them by index.
+pub fn link_dependencies(mut builders: Vec<&mut EndpointBuilder>) {
+    for i in 0..builders.len() {
+        for id in builders[i].endpoint.depends_on.clone() {
+            if let Some(dependency) = builders.get_mut(id) {
+                let rx = dependency
+                    .ready
+                    .get_or_insert_with(ReadySignal::new)
+                    .subscribe();
+                builders[i].dependencies.push(rx);
+            }
+        }
+    }
+}
+
+// the `User-Agent` sent on requests which don't set their own
+fn default_user_agent(user_agent: &config::UserAgent) -> Option<HeaderValue> {
+    match user_agent {
+        config::UserAgent::Custom(ua) => match HeaderValue::from_str(ua) {
+            Ok(ua) => Some(ua),
+            Err(_) => {
+                log::warn!("ignoring invalid user_agent `{}`", ua);
+                None
+            }
+        },
+        config::UserAgent::Default => Some(HeaderValue::from_static(concat!(
+            "pewpew/",
+            clap::crate_version!()
+        ))),
+        config::UserAgent::None => None,
+    }
+}
+
+fn method_expects_body(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::DELETE | Method::OPTIONS | Method::TRACE
+    )
 }
 
 fn convert_to_debug<T>(value: &[(String, T)]) -> Vec<String> {
@@ -229,17 +346,35 @@ impl EndpointBuilder {
         Self {
             endpoint,
             start_stream,
+            ready: None,
+            dependencies: Vec::new(),
+            in_flight: InFlight::default(),
         }
     }
 
+    /// Counts the endpoint's requests in `in_flight` while they're open
+    pub(crate) fn with_in_flight(mut self, in_flight: InFlight) -> Self {
+        self.in_flight = in_flight;
+        self
+    }
+
+    pub fn depends_on(&self) -> &[usize] {
+        &self.endpoint.depends_on
+    }
+
     pub fn build(self, ctx: &mut BuilderContext) -> Endpoint {
         let mut outgoing = Vec::new();
         let mut on_demand_streams: OnDemandStreams = Vec::new();
 
         let config::Endpoint {
+            assertions,
             method,
             headers,
             body,
+            body_encoding,
+            connect_to,
+            content_type,
+            follow_redirects,
             no_auto_returns,
             providers_to_stream,
             url,
@@ -249,14 +384,39 @@ impl EndpointBuilder {
             on_demand,
             tags,
             request_timeout,
+            honor_retry_after,
+            is_success,
+            replay,
+            response_format,
+            retries,
+            where_clause,
             ..
         } = self.endpoint;
+        let no_auto_returns = no_auto_returns.unwrap_or(ctx.config.general.no_auto_returns);
         debug!("EndpointBuilder.build method=\"{}\" url=\"{}\" body=\"{}\" headers=\"{:?}\" no_auto_returns=\"{}\" \
             max_parallel_requests=\"{:?}\" provides=\"{:?}\" logs=\"{:?}\" on_demand=\"{}\" request_timeout=\"{:?}\"",
             method.as_str(), url.evaluate_with_star(), body, convert_to_debug(&headers), no_auto_returns,
             max_parallel_requests, convert_to_debug(&provides), convert_to_debug(&logs), on_demand, request_timeout);
 
-        let timeout = request_timeout.unwrap_or(ctx.config.client.request_timeout);
+        // a body is always sent when one is configured, even on methods which conventionally
+        // don't have one, as some servers (e.g. search APIs) expect a body on a GET
+        if !matches!(body, BodyTemplate::None) && !method_expects_body(&method) {
+            log::warn!(
+                "endpoint `{} {}` has a body which will be sent even though {} requests usually don't have one",
+                method,
+                url.evaluate_with_star(),
+                method
+            );
+        }
+
+        let timeout = request_timeout
+            .unwrap_or(ctx.config.client.request_timeout)
+            .duration();
+
+        let random_body = match &body {
+            BodyTemplate::RandomBytes(r) => Some(RandomBody::new(r.min, r.max, ctx.seed)),
+            _ => None,
+        };
 
         let mut provides_set = if self.start_stream.is_none() && !provides.is_empty() {
             Some(BTreeSet::new())
@@ -285,8 +445,15 @@ impl EndpointBuilder {
             })
             .collect();
 
+        let retry_after = honor_retry_after.then(RetryAfterGate::default);
         let mut streams: StreamCollection = Vec::new();
-        if let Some(start_stream) = self.start_stream {
+        if let Some(mut start_stream) = self.start_stream {
+            if let Some(gate) = &retry_after {
+                start_stream = Box::pin(gate.clone().gate(start_stream));
+            }
+            if let Some(rate_limit) = &ctx.rate_limit {
+                start_stream = Box::pin(rate_limit.clone().gate(start_stream));
+            }
             streams.push((
                 true,
                 Box::new(start_stream.map(|(_, d)| Ok(StreamItem::Instant(d)))),
@@ -300,7 +467,13 @@ impl EndpointBuilder {
                     Poll::Ready(Some(Ok(StreamItem::None)))
                 }
             });
-            streams.push((true, Box::new(stream)));
+            match &ctx.rate_limit {
+                Some(rate_limit) => {
+                    let stream = rate_limit.clone().throttle(stream);
+                    streams.push((true, Box::new(Box::pin(stream))))
+                }
+                None => streams.push((true, Box::new(stream))),
+            }
         }
         // Add any loggers to the outgoing providers/loggers
         for (k, v) in logs {
@@ -325,11 +498,13 @@ impl EndpointBuilder {
                 None => continue,
             };
             debug!("EndpointBuilder.build unique_providers name=\"{}\"", name);
-            let receiver = provider.rx.clone();
+            let receiver = ctx
+                .ended_providers
+                .track(name.clone(), provider.subscribe());
             let ar = provider
                 .auto_return
                 .map(|send_option| (send_option, provider.tx.clone()));
-            let provider_stream = Box::new(receiver.map(move |v| {
+            let provider_stream = receiver.map(move |v| {
                 let ar = if no_auto_returns {
                     None
                 } else {
@@ -342,8 +517,8 @@ impl EndpointBuilder {
                     ar,
                     Instant::now(),
                 ))
-            }));
-            streams.push((false, provider_stream));
+            });
+            streams.push((false, Box::new(provider_stream)));
         }
 
         for (name, vce) in self.endpoint.declare {
@@ -360,11 +535,44 @@ impl EndpointBuilder {
             streams.push((false, Box::new(stream)));
         }
         let stats_tx = ctx.stats_tx.clone();
-        let client = ctx.client.clone();
+        let spans_tx = ctx.spans_tx.clone();
+        let trace_tx = ctx.trace_tx.clone();
+        let (client, proxy) = match connect_to {
+            Some(addr) => {
+                let client = ctx
+                    .connect_to_clients
+                    .get(&addr)
+                    .expect("connect_to should have a client")
+                    .clone();
+                (client, None)
+            }
+            None => (ctx.client.clone(), ctx.proxy.clone()),
+        };
+        let user_agent = default_user_agent(&ctx.config.client.user_agent);
+        let mut headers = headers;
+        let accept_encoding = &ctx.config.client.accept_encoding;
+        if !accept_encoding.is_empty()
+            && !headers
+                .iter()
+                .any(|(k, _)| k.eq_ignore_ascii_case("accept-encoding"))
+        {
+            let value = Template::simple(&accept_encoding.join(", "));
+            headers.push(("accept-encoding".into(), value));
+        }
         Endpoint {
             body,
+            body_encoding,
+            content_type,
+            random_body,
             client,
             headers,
+            is_success: is_success.map(Arc::new),
+            assertions: (!assertions.is_empty()).then(|| {
+                Arc::new(Assertions {
+                    assertions,
+                    test_killer: ctx.test_killer.clone(),
+                })
+            }),
             max_parallel_requests,
             method,
             no_auto_returns,
@@ -375,9 +583,24 @@ impl EndpointBuilder {
             rr_providers,
             tags: Arc::new(tags),
             stats_tx,
+            spans_tx,
+            trace_tx,
             stream_collection: streams,
             url,
             timeout,
+            user_agent,
+            follow_redirects,
+            proxy,
+            cookie_jar: ctx.cookie_jar.clone(),
+            retries: retries.map(Arc::new),
+            retry_after,
+            replay,
+            response_format,
+            decompress: ctx.config.client.decompress,
+            where_clause: where_clause.map(Arc::new),
+            ready: self.ready,
+            dependencies: self.dependencies,
+            in_flight: self.in_flight,
         }
     }
 }
@@ -572,15 +795,67 @@ async fn create_file_hyper_body(filename: String) -> Result<(u64, HyperBody), Te
     Ok((bytes, body))
 }
 
+// compresses a request body for the endpoint's `body_encoding`. A body which is already in memory
+// is compressed all at once so its `Content-Length` can be sent, but a file or multipart body is
+// compressed as it streams, so it's sent chunked
+async fn encode_body(
+    encoding: BodyEncoding,
+    body: HyperBody,
+) -> Result<(u64, HyperBody), TestError> {
+    let compression = match encoding {
+        BodyEncoding::Brotli => Compression::Brotli,
+        BodyEncoding::Gzip => Compression::Gzip,
+    };
+    let encode_err = |e: std::io::Error| TestError::Other(format!("error compressing body: {e}"));
+    let mut encoder = BodyEncoder::new(compression).map_err(encode_err)?;
+    if hyper::body::HttpBody::size_hint(&body).exact().is_some() {
+        let bytes = hyper::body::to_bytes(body)
+            .await
+            .map_err(|e| TestError::Other(format!("error compressing body: {e}")))?;
+        let mut encoded = encoder.encode(&bytes).map_err(encode_err)?.to_vec();
+        encoded.extend_from_slice(&encoder.finish().map_err(encode_err)?);
+        return Ok((encoded.len() as u64, encoded.into()));
+    }
+    // `None` marks the end of the body, when the rest of the compressed bytes are flushed
+    let mut encoder = Some(encoder);
+    let stream = body
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .filter_map(move |chunk| {
+            let encoded = match (chunk, encoder.take()) {
+                (Some(Ok(bytes)), Some(mut e)) => {
+                    let encoded = e.encode(&bytes);
+                    encoder = Some(e);
+                    encoded
+                }
+                (Some(Err(err)), _) => Err(std::io::Error::other(err)),
+                (None, Some(e)) => e.finish(),
+                (_, None) => return future::ready(None),
+            };
+            // the encoder may not have anything to send yet
+            let encoded = match encoded {
+                Ok(b) if b.is_empty() => None,
+                r => Some(r),
+            };
+            future::ready(encoded)
+        });
+    Ok((0, HyperBody::wrap_stream(stream)))
+}
+
 fn body_template_as_hyper_body(
     body_template: &BodyTemplate,
+    random_body: Option<&RandomBody>,
     template_values: &TemplateValues,
     copy_body_value: bool,
     body_value: &mut Option<String>,
     content_type_entry: HeaderEntry<'_, HeaderValue>,
 ) -> impl Future<Output = Result<(u64, HyperBody), TestError>> {
-    let template = match body_template {
-        BodyTemplate::File(_, t) => t,
+    let body = match body_template {
+        BodyTemplate::File(_, t) | BodyTemplate::String(t) => {
+            t.evaluate(Cow::Borrowed(template_values.as_json()), None)
+        }
+        BodyTemplate::Form(fields) => form_body(fields, template_values.as_json()),
+        BodyTemplate::Json(j) => j.evaluate(template_values.as_json()).map(|v| v.to_string()),
         BodyTemplate::Multipart(m) => {
             let r = multipart_body_as_hyper_body(
                 m,
@@ -592,9 +867,17 @@ fn body_template_as_hyper_body(
             return Either3::A(future::ready(r).and_then(|x| x));
         }
         BodyTemplate::None => return Either3::B(future::ok((0, HyperBody::empty()))),
-        BodyTemplate::String(t) => t,
+        BodyTemplate::RandomBytes(_) => {
+            let bytes = random_body
+                .expect("random_bytes body should have a RandomBody")
+                .next();
+            if copy_body_value {
+                *body_value = Some(format!("<<{} random bytes>>", bytes.len()));
+            }
+            return Either3::B(future::ok((bytes.len() as u64, bytes.into())));
+        }
     };
-    let mut body = match template.evaluate(Cow::Borrowed(template_values.as_json()), None) {
+    let mut body = match body {
         Ok(b) => b,
         Err(e) => return Either3::B(future::err(TestError::from(e))),
     };
@@ -612,6 +895,56 @@ fn body_template_as_hyper_body(
     }
 }
 
+// url encode each of a form body's fields
+fn form_body(
+    fields: &[(String, Template)],
+    template_values: &json::Value,
+) -> Result<String, config::ExecutingExpressionError> {
+    let mut form = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in fields {
+        form.append_pair(name, &value.evaluate(Cow::Borrowed(template_values), None)?);
+    }
+    Ok(form.finish())
+}
+
+// `request.headers` or `response.headers`, keyed by the lowercase header name. The values of a
+// header which came more than once are joined with commas, as they could have been in one header
+fn headers_to_json(headers: &HeaderMap) -> json::Value {
+    let mut headers_json = json::Map::new();
+    for (k, v) in headers {
+        let v = String::from_utf8_lossy(v.as_bytes());
+        match headers_json.entry(k.as_str()) {
+            json::map::Entry::Vacant(entry) => {
+                entry.insert(v.into_owned().into());
+            }
+            json::map::Entry::Occupied(mut entry) => {
+                if let json::Value::String(s) = entry.get_mut() {
+                    s.push_str(", ");
+                    s.push_str(&v);
+                }
+            }
+        }
+    }
+    json::Value::Object(headers_json)
+}
+
+// `request.headers_all` or `response.headers_all`, which keeps each value of a header, as headers
+// like `Set-Cookie` can't be joined into one
+fn headers_all_to_json(headers: &HeaderMap) -> json::Value {
+    let mut headers_json = json::Map::new();
+    for (k, v) in headers {
+        headers_json
+            .entry(k.as_str())
+            .or_insert_with(|| json::Value::Array(Vec::new()))
+            .as_array_mut()
+            .expect("should be a json array")
+            .push(json::Value::String(
+                String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            ))
+    }
+    json::Value::Object(headers_json)
+}
+
 type StreamCollection = Vec<(
     bool,
     Box<dyn Stream<Item = Result<StreamItem, TestError>> + Send + Unpin + 'static>,
@@ -621,8 +954,13 @@ pub type StatsTx = futures_channel::UnboundedSender<stats::StatsMessage>;
 
 pub struct Endpoint {
     body: BodyTemplate,
-    client: Arc<Client<HttpsConnector<HttpConnector<hyper::client::connect::dns::GaiResolver>>>>,
+    body_encoding: Option<BodyEncoding>,
+    content_type: Option<Template>,
+    random_body: Option<RandomBody>,
+    client: Arc<HttpClient>,
     headers: Vec<(String, Template)>,
+    is_success: Option<Arc<Select>>,
+    assertions: Option<Arc<Assertions>>,
     max_parallel_requests: Option<NonZeroUsize>,
     method: Method,
     no_auto_returns: bool,
@@ -633,9 +971,25 @@ pub struct Endpoint {
     rr_providers: u16,
     tags: Arc<BTreeMap<String, Template>>,
     stats_tx: StatsTx,
+    spans_tx: Option<otlp::SpansTx>,
+    trace_tx: Option<trace::EventsTx>,
     stream_collection: StreamCollection,
-    timeout: Duration,
+    // `None` when the endpoint's requests never time out
+    timeout: Option<Duration>,
     url: Template,
+    user_agent: Option<HeaderValue>,
+    follow_redirects: Option<u8>,
+    proxy: Option<Arc<ProxySettings>>,
+    cookie_jar: Option<CookieJar>,
+    retries: Option<Arc<config::Retries>>,
+    retry_after: Option<RetryAfterGate>,
+    replay: Option<String>,
+    response_format: Option<config::ResponseFormat>,
+    decompress: bool,
+    where_clause: Option<Arc<Select>>,
+    ready: Option<ReadySignal>,
+    dependencies: Vec<watch::Receiver<bool>>,
+    in_flight: InFlight,
 }
 
 impl Endpoint {
@@ -663,6 +1017,8 @@ impl Endpoint {
         let method = self.method;
         let headers = self.headers;
         let body = self.body;
+        let content_type = self.content_type;
+        let random_body = self.random_body;
         let rr_providers = self.rr_providers;
         let client = self.client;
         let stats_tx = self.stats_tx;
@@ -691,6 +1047,11 @@ impl Endpoint {
         } else {
             zipped_streams.b()
         };
+        let stream = if self.dependencies.is_empty() {
+            stream.a()
+        } else {
+            depends_on::wait_for(self.dependencies, stream).b()
+        };
         let mut outgoing = self.outgoing;
         outgoing.extend(self.provides);
         let outgoing = Arc::new(outgoing);
@@ -714,6 +1075,9 @@ impl Endpoint {
             method,
             headers,
             body,
+            body_encoding: self.body_encoding,
+            content_type,
+            random_body,
             rr_providers,
             client,
             stats_tx,
@@ -722,6 +1086,21 @@ impl Endpoint {
             precheck_rr_providers,
             tags,
             timeout,
+            spans_tx: self.spans_tx,
+            trace_tx: self.trace_tx,
+            user_agent: self.user_agent,
+            follow_redirects: self.follow_redirects,
+            proxy: self.proxy,
+            cookie_jar: self.cookie_jar,
+            retries: self.retries,
+            retry_after: self.retry_after,
+            replay: self.replay,
+            response_format: self.response_format,
+            decompress: self.decompress,
+            where_clause: self.where_clause,
+            is_success: self.is_success,
+            assertions: self.assertions,
+            in_flight: self.in_flight,
         };
         let limit_fn: Option<Box<dyn FnMut(usize) -> usize + Send + Unpin>> =
             match (blocking_outgoing.is_empty(), max_parallel_requests) {
@@ -812,7 +1191,15 @@ impl Endpoint {
                 (true, Some(n)) => Some(Box::new(move |_| n.get())),
                 (true, None) => None,
             };
-        let f = ForEachParallel::new(limit_fn, stream, move |values| rm.send_request(values));
+        let ready = self.ready;
+        let f = ForEachParallel::new(limit_fn, stream, move |values| {
+            let ready = ready.clone();
+            rm.send_request(values).inspect(move |_| {
+                if let Some(ready) = ready {
+                    ready.ready();
+                }
+            })
+        });
         Box::new(f)
     }
 }
@@ -928,4 +1315,75 @@ mod tests {
         let file_bytes = include_bytes!("../tests/test.jpg").to_vec();
         assert_eq!(file_bytes, streamed_bytes);
     }
+
+    #[test]
+    fn endpoint_request_timeout_overrides_client() {
+        let config = r#"
+            config:
+              client:
+                request_timeout: 20s
+            endpoints:
+              - url: http://localhost/default
+              - url: http://localhost/override
+                request_timeout: 5s
+              - url: http://localhost/never
+                request_timeout: none
+        "#;
+        let load_test = config::LoadTest::from_config(
+            config.as_bytes(),
+            std::path::Path::new("./"),
+            &Default::default(),
+        )
+        .unwrap();
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (stats_tx, _stats_rx) = futures_channel::unbounded();
+            let client = crate::create_http_client(crate::ClientSettings {
+                keepalive: Duration::from_secs(1),
+                ..Default::default()
+            })
+            .unwrap();
+            let mut ctx = BuilderContext {
+                config: load_test.config,
+                config_path: Default::default(),
+                client: Arc::new(client),
+                connect_to_clients: Default::default(),
+                proxy: None,
+                cookie_jar: None,
+                providers: Default::default(),
+                loggers: Default::default(),
+                stats_tx,
+                spans_tx: None,
+                trace_tx: None,
+                seed: None,
+                ended_providers: Default::default(),
+                rate_limit: None,
+                test_killer: broadcast::channel(1).0,
+            };
+            let timeouts: Vec<_> = load_test
+                .endpoints
+                .into_iter()
+                .map(|e| EndpointBuilder::new(e, None).build(&mut ctx).timeout)
+                .collect();
+            assert_eq!(
+                timeouts,
+                vec![
+                    Some(Duration::from_secs(20)),
+                    Some(Duration::from_secs(5)),
+                    None
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn default_user_agent_works() {
+        let ua = default_user_agent(&config::UserAgent::Default).unwrap();
+        assert_eq!(ua, concat!("pewpew/", clap::crate_version!()));
+
+        let ua = default_user_agent(&config::UserAgent::Custom("custom/1.0".into())).unwrap();
+        assert_eq!(ua, "custom/1.0");
+
+        assert!(default_user_agent(&config::UserAgent::None).is_none());
+    }
 }
@@ -1,7 +1,8 @@
-use crate::error::RecoverableError;
+use crate::error::{RecoverableError, TestError};
 use crate::stats;
+use crate::TestEndReason;
 
-use config::{EndpointProvidesSendOptions, Template};
+use config::{EndpointProvidesSendOptions, Select, Template};
 use ether::EitherExt;
 use futures::{
     future::{select_all, try_join_all},
@@ -9,6 +10,7 @@ use futures::{
 };
 use log::debug;
 use serde_json as json;
+use tokio::sync::broadcast;
 
 use std::{
     borrow::Cow,
@@ -20,8 +22,22 @@ use std::{
 
 use super::{BlockSender, Outgoing, ProviderDelays, ProviderOrLogger, StatsTx, TemplateValues};
 
+/// An endpoint's `assertions`, along with what ends the test when one with `kill` fails
+pub(super) struct Assertions {
+    pub(super) assertions: Vec<config::Assertion>,
+    pub(super) test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+}
+
 pub(super) struct BodyHandler {
+    pub(super) assertions: Option<Arc<Assertions>>,
+    // the size of the response body as it came over the wire, before any decompression
+    pub(super) bytes_in: u64,
+    // the size of the response body after any decompression
+    pub(super) body_size: u64,
+    // whether the request was sent on a connection an earlier request already used
+    pub(super) connection_reused: Option<bool>,
     pub(super) included_outgoing_indexes: BTreeSet<usize>,
+    pub(super) is_success: Option<Arc<Select>>,
     pub(super) now: Instant,
     pub(super) outgoing: Arc<Vec<Outgoing>>,
     pub(super) provider_delays: ProviderDelays,
@@ -29,6 +45,8 @@ pub(super) struct BodyHandler {
     pub(super) status: u16,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
     pub(super) template_values: TemplateValues,
+    // where the time up to the response's headers went
+    pub(super) timing: Option<stats::Timing>,
 }
 
 impl BodyHandler {
@@ -47,7 +65,15 @@ impl BodyHandler {
         let has_logger = outgoing.iter().any(|o| o.tx.is_logger());
         let rtt = self.now.elapsed().as_micros() as u64;
         let mut template_values = self.template_values;
-        template_values.insert("stats".into(), json::json!({ "rtt": rtt as f64 / 1000.0 }));
+        let mut stats = json::json!({
+            "rtt": rtt as f64 / 1000.0,
+            "bytes_in": self.bytes_in,
+            "body_size": self.body_size,
+        });
+        if let Some(timing) = self.timing {
+            stats["timing"] = timing.to_json_ms(1);
+        }
+        template_values.insert("stats".into(), stats);
         let error_result = match result {
             Ok(Some(body)) => {
                 template_values
@@ -61,6 +87,8 @@ impl BodyHandler {
             Err(e) => Some(e),
             _ => None,
         };
+        // every provides and log reads from this one copy, which matters when the response body
+        // is large, and only what a select picks out of it is copied
         let template_values = Arc::new(template_values.0);
         let template_values2 = template_values.clone();
         let tags: BTreeMap<String, String> = self
@@ -74,8 +102,9 @@ impl BodyHandler {
             .collect();
         let tags = Arc::new(tags);
         self.provider_delays.log(&tags, &stats_tx);
+        let logger_tags = tags.clone();
 
-        let send_response_stat = move |kind, rtt| {
+        let send_response_stat = move |kind, rtt, connection_reused, timing, success| {
             let mut futures = Vec::new();
             if let stats::StatKind::RecoverableError(e) = &kind {
                 if has_logger {
@@ -94,10 +123,8 @@ impl BodyHandler {
                         if let ProviderOrLogger::Logger(tx) = &o.tx {
                             if let Ok(iter) = select.iter(tv) {
                                 let iter = iter.map(|v| v.map_err(Into::into));
-                                futures.push(
-                                    BlockSender::new(iter, ProviderOrLogger::Logger(tx.clone()))
-                                        .into_future(),
-                                );
+                                let tx = ProviderOrLogger::Logger(tx.with_tags(&tags));
+                                futures.push(BlockSender::new(iter, tx).into_future());
                             }
                         }
                     }
@@ -105,10 +132,14 @@ impl BodyHandler {
             }
             let _ = stats_tx.unbounded_send(
                 stats::ResponseStat {
+                    connection_reused,
                     kind,
                     rtt,
+                    retry: false,
+                    success,
                     time: SystemTime::now(),
                     tags: tags.clone(),
+                    timing,
                 }
                 .into(),
             );
@@ -118,9 +149,10 @@ impl BodyHandler {
         if let Some(f) = auto_returns {
             futures.push(f.map(|_| Ok(())).a().b3());
         }
+        let error_result_seen = error_result.is_some();
         if let Some(e) = error_result {
             let kind = stats::StatKind::RecoverableError(e);
-            futures.push(send_response_stat(kind, None).a3());
+            futures.push(send_response_stat(kind, None, None, None, None).a3());
         } else {
             let mut blocked = Vec::new();
             for (i, o) in self.outgoing.iter().enumerate() {
@@ -134,7 +166,7 @@ impl BodyHandler {
                     Err(e) => {
                         let r = RecoverableError::ExecutingExpression(e);
                         let kind = stats::StatKind::RecoverableError(r);
-                        futures.push(send_response_stat(kind, None).a3());
+                        futures.push(send_response_stat(kind, None, None, None, None).a3());
                         continue;
                     }
                 };
@@ -145,7 +177,7 @@ impl BodyHandler {
                             "BodyHandler:handle EndpointProvidesSendOptions::Block {}",
                             o.tx.name()
                         );
-                        let tx = o.tx.clone();
+                        let tx = o.tx.with_tags(&logger_tags);
                         let f = BlockSender::new(iter, tx).into_future().map(|_| Ok(()));
                         if o.tx.is_logger() {
                             futures.push(f.c3());
@@ -163,7 +195,9 @@ impl BodyHandler {
                                 Ok(v) => v,
                                 Err(r) => {
                                     let kind = stats::StatKind::RecoverableError(r);
-                                    futures.push(send_response_stat(kind, None).a3());
+                                    futures.push(
+                                        send_response_stat(kind, None, None, None, None).a3(),
+                                    );
                                     break;
                                 }
                             };
@@ -182,7 +216,9 @@ impl BodyHandler {
                                 Ok(v) => v,
                                 Err(r) => {
                                     let kind = stats::StatKind::RecoverableError(r);
-                                    futures.push(send_response_stat(kind, None).a3());
+                                    futures.push(
+                                        send_response_stat(kind, None, None, None, None).a3(),
+                                    );
                                     break;
                                 }
                             };
@@ -206,7 +242,51 @@ impl BodyHandler {
                 futures.push(f.b().b3());
             }
         }
-        futures.push(send_response_stat(stats::StatKind::Response(self.status), Some(rtt)).a3());
+        // a response whose `is_success` can't be evaluated is counted as an error of its own, along
+        // with the response as its status would count it
+        let success = match (&self.is_success, error_result_seen) {
+            (Some(is_success), false) => match is_success.execute_where(&template_values) {
+                Ok(success) => Some(success),
+                Err(e) => {
+                    let r = RecoverableError::ExecutingExpression(e.into());
+                    let kind = stats::StatKind::RecoverableError(r);
+                    futures.push(send_response_stat(kind, None, None, None, None).a3());
+                    None
+                }
+            },
+            _ => None,
+        };
+        // a failed assertion is counted as an error, while one which can't be evaluated is
+        // counted as the error evaluating it
+        if let (Some(assertions), false) = (&self.assertions, error_result_seen) {
+            for assertion in &assertions.assertions {
+                let r = match assertion.expr.execute_where(&template_values) {
+                    Ok(true) => continue,
+                    Ok(false) => {
+                        if assertion.kill {
+                            let reason =
+                                TestEndReason::KilledByAssertion(assertion.message.clone());
+                            let _ = assertions.test_killer.send(Ok(reason));
+                        }
+                        RecoverableError::AssertionFailed(assertion.message.clone())
+                    }
+                    Err(e) => RecoverableError::ExecutingExpression(e.into()),
+                };
+                let kind = stats::StatKind::RecoverableError(r);
+                futures.push(send_response_stat(kind, None, None, None, None).a3());
+            }
+        }
+        let kind = stats::StatKind::Response(self.status);
+        futures.push(
+            send_response_stat(
+                kind,
+                Some(rtt),
+                self.connection_reused,
+                self.timing,
+                success,
+            )
+            .a3(),
+        );
         try_join_all(futures).map_ok(|_| ())
     }
 }
@@ -258,10 +338,16 @@ mod tests {
         let tags = Arc::new(btreemap! {"_id".into() => Template::simple("0") });
 
         let bh = BodyHandler {
+            assertions: None,
+            bytes_in: 0,
+            body_size: 0,
+            connection_reused: None,
             now,
             provider_delays: ProviderDelays::new(),
             template_values,
+            timing: None,
             included_outgoing_indexes,
+            is_success: None,
             outgoing,
             stats_tx,
             status,
@@ -377,10 +463,16 @@ mod tests {
         let tags = Arc::new(BTreeMap::new());
 
         let bh = BodyHandler {
+            assertions: None,
+            bytes_in: 0,
+            body_size: 0,
+            connection_reused: None,
             now,
             provider_delays: ProviderDelays::new(),
             template_values,
+            timing: None,
             included_outgoing_indexes,
+            is_success: None,
             outgoing,
             stats_tx,
             status,
@@ -433,4 +525,270 @@ mod tests {
         };
         assert!(b, "receiver 3 is closed, {:?}", r);
     }
+
+    // handles a response with `body`, giving what each select sent
+    fn handle_body(body: json::Value, selects: Vec<Select>) -> Vec<Vec<json::Value>> {
+        let (outgoing, mut receivers): (Vec<_>, Vec<_>) =
+            selects.into_iter().map(create_outgoing).unzip();
+        let (stats_tx, _) = futures_channel::unbounded();
+
+        let bh = BodyHandler {
+            assertions: None,
+            bytes_in: 0,
+            body_size: 0,
+            connection_reused: None,
+            now: Instant::now(),
+            provider_delays: ProviderDelays::new(),
+            template_values: json::json!({"response": {}}).into(),
+            timing: None,
+            included_outgoing_indexes: (0..receivers.len()).collect(),
+            is_success: None,
+            outgoing: Arc::new(outgoing),
+            stats_tx,
+            status: 200,
+            tags: Arc::new(BTreeMap::new()),
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+        let r = block_on(bh.handle(Ok(Some(body)), auto_returns));
+        assert!(r.is_ok());
+
+        receivers
+            .iter_mut()
+            .map(|rx| std::iter::from_fn(|| rx.next().now_or_never().flatten()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn handles_large_body_with_several_provides() {
+        let items: Vec<_> = (0..10_000)
+            .map(|i| json::json!({"id": i, "name": format!("item {}", i)}))
+            .collect();
+        let body = json::json!({"count": items.len(), "items": items});
+
+        let selects = vec![
+            Select::simple("response.body.count", Force, None, None, None),
+            Select::simple("response.body.items[9999].name", Block, None, None, None),
+            Select::simple("response.body.items[0].id", IfNotFull, None, None, None),
+        ];
+        let received = handle_body(body.clone(), selects);
+        assert_eq!(
+            received,
+            vec![
+                vec![json::json!(10_000)],
+                vec![json::json!("item 9999")],
+                vec![json::json!(0)],
+            ]
+        );
+        // a `for_each` works on its own copy of what it iterates
+        let for_each = Select::simple(
+            "for_each[0].id",
+            Force,
+            Some(vec!["response.body.items"]),
+            Some("for_each[0].id % 2500 == 0"),
+            None,
+        );
+        let received = handle_body(body, vec![for_each]);
+        assert_eq!(
+            received,
+            vec![vec![
+                json::json!(0),
+                json::json!(2500),
+                json::json!(5000),
+                json::json!(7500)
+            ]]
+        );
+    }
+
+    #[test]
+    fn is_success_overrides_the_status() {
+        let responses = vec![
+            (200, json::json!({"ok": false}), Some(false)),
+            (200, json::json!({"ok": true}), Some(true)),
+            (503, json::json!({"ok": true}), Some(true)),
+        ];
+        for (status, body, expect) in responses {
+            let is_success = Select::simple(
+                json::Value::Null,
+                Force,
+                None,
+                Some("response.body.ok"),
+                None,
+            );
+            let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+
+            let bh = BodyHandler {
+                assertions: None,
+                bytes_in: 0,
+                body_size: 0,
+                connection_reused: None,
+                now: Instant::now(),
+                provider_delays: ProviderDelays::new(),
+                template_values: json::json!({"response": {}}).into(),
+                timing: None,
+                included_outgoing_indexes: BTreeSet::new(),
+                is_success: Some(Arc::new(is_success)),
+                outgoing: Arc::new(Vec::new()),
+                stats_tx,
+                status,
+                tags: Arc::new(BTreeMap::new()),
+            };
+
+            type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+            let auto_returns: AutoReturns = None;
+            let r = block_on(bh.handle(Ok(Some(body.clone())), auto_returns));
+            assert!(r.is_ok());
+
+            match stats_rx.next().now_or_never() {
+                Some(Some(stats::StatsMessage::ResponseStat(rs))) => {
+                    assert_eq!(rs.success, expect, "status {} with body {}", status, body);
+                }
+                r => panic!("stats_rx should have received response stat. {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn failed_assertions_are_counted_as_errors() {
+        let assertion = |expr: &'static str, kill| config::Assertion {
+            expr: Select::simple(json::Value::Null, Force, None, Some(expr), None),
+            message: format!("expected {expr}"),
+            kill,
+        };
+        let (test_killer, mut test_killed_rx) = broadcast::channel(1);
+        let assertions = Assertions {
+            assertions: vec![
+                assertion("response.status == 200", true),
+                assertion("stats.rtt >= 0", false),
+                assertion("response.body.missing.field > 0", false),
+            ],
+            test_killer,
+        };
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+
+        let bh = BodyHandler {
+            assertions: Some(Arc::new(assertions)),
+            bytes_in: 0,
+            body_size: 0,
+            connection_reused: None,
+            now: Instant::now(),
+            provider_delays: ProviderDelays::new(),
+            template_values: json::json!({"response": {"status": 500}}).into(),
+            timing: None,
+            included_outgoing_indexes: BTreeSet::new(),
+            is_success: None,
+            outgoing: Arc::new(Vec::new()),
+            stats_tx,
+            status: 500,
+            tags: Arc::new(BTreeMap::new()),
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+        let r = block_on(bh.handle(Ok(Some(json::json!({}))), auto_returns));
+        assert!(r.is_ok());
+
+        let mut kinds = Vec::new();
+        while let Some(Some(stats::StatsMessage::ResponseStat(rs))) = stats_rx.next().now_or_never()
+        {
+            kinds.push(rs.kind);
+        }
+        assert_eq!(kinds.len(), 3, "{:?}", kinds);
+        match &kinds[0] {
+            stats::StatKind::RecoverableError(RecoverableError::AssertionFailed(m)) => {
+                assert_eq!(m, "expected response.status == 200")
+            }
+            k => panic!("expected a failed assertion, got {:?}", k),
+        }
+        // an assertion which can't be evaluated isn't a failed one
+        assert!(
+            matches!(
+                kinds[1],
+                stats::StatKind::RecoverableError(RecoverableError::ExecutingExpression(_))
+            ),
+            "{:?}",
+            kinds[1]
+        );
+        // the response itself is still counted by its status
+        assert!(
+            matches!(kinds[2], stats::StatKind::Response(500)),
+            "{:?}",
+            kinds[2]
+        );
+
+        match test_killed_rx.try_recv() {
+            Ok(Ok(TestEndReason::KilledByAssertion(m))) => {
+                assert_eq!(m, "expected response.status == 200")
+            }
+            _ => panic!("the test should be killed by the assertion"),
+        }
+    }
+
+    #[test]
+    fn timing_is_in_the_stats() {
+        let assertion = |expr: &'static str| config::Assertion {
+            expr: Select::simple(json::Value::Null, Force, None, Some(expr), None),
+            message: format!("expected {expr}"),
+            kill: false,
+        };
+        let (test_killer, _) = broadcast::channel(1);
+        let assertions = Assertions {
+            assertions: vec![
+                assertion("stats.timing.dns == 1.5"),
+                assertion("stats.timing.connect == 2.0"),
+                assertion("stats.timing.tls == 0.0"),
+                assertion("stats.timing.ttfb == 4.0"),
+            ],
+            test_killer,
+        };
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let timing = stats::Timing {
+            dns: 1_500,
+            connect: 2_000,
+            tls: 0,
+            ttfb: 4_000,
+        };
+
+        let bh = BodyHandler {
+            assertions: Some(Arc::new(assertions)),
+            bytes_in: 0,
+            body_size: 0,
+            connection_reused: Some(false),
+            now: Instant::now(),
+            provider_delays: ProviderDelays::new(),
+            template_values: json::json!({"response": {"status": 200}}).into(),
+            timing: Some(timing),
+            included_outgoing_indexes: BTreeSet::new(),
+            is_success: None,
+            outgoing: Arc::new(Vec::new()),
+            stats_tx,
+            status: 200,
+            tags: Arc::new(BTreeMap::new()),
+        };
+
+        type AutoReturns = Option<Box<dyn Future<Output = ()> + Send + Unpin>>;
+        let auto_returns: AutoReturns = None;
+        let r = block_on(bh.handle(Ok(Some(json::json!({}))), auto_returns));
+        assert!(r.is_ok());
+
+        // every assertion passed, so the response is the only stat
+        match stats_rx.next().now_or_never() {
+            Some(Some(stats::StatsMessage::ResponseStat(rs))) => {
+                assert!(
+                    matches!(rs.kind, stats::StatKind::Response(200)),
+                    "{:?}",
+                    rs
+                );
+                assert_eq!(rs.timing, Some(timing));
+            }
+            r => panic!("stats_rx should have received response stat. {:?}", r),
+        }
+        let r = stats_rx.next().now_or_never();
+        assert!(
+            matches!(r, Some(None)),
+            "stats_rx should be closed. {:?}",
+            r
+        );
+    }
 }
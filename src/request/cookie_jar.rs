@@ -0,0 +1,296 @@
+use hyper::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// The cookies responses have set, from `client.cookies`, which are sent back with later requests
+/// to the hosts and paths they were set for. Endpoints send requests concurrently, so every
+/// endpoint shares the one jar.
+#[derive(Clone, Default)]
+pub struct CookieJar {
+    cookies: Arc<Mutex<Vec<Cookie>>>,
+}
+
+struct Cookie {
+    name: String,
+    value: String,
+    // lowercased and without a leading dot
+    domain: String,
+    // whether the cookie didn't set a `Domain`, so it only goes back to the host which set it
+    host_only: bool,
+    path: String,
+    // `None` for a session cookie, which lasts as long as the test
+    expires: Option<SystemTime>,
+    secure: bool,
+}
+
+impl Cookie {
+    // parses a `Set-Cookie` value, returning `None` for one which isn't valid for `url`
+    fn parse(set_cookie: &str, url: &url::Url, now: SystemTime) -> Option<Self> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut attributes = set_cookie.split(';');
+        let (name, value) = attributes.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let mut cookie = Cookie {
+            name: name.into(),
+            value: value.trim().into(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url.path()).into(),
+            expires: None,
+            secure: false,
+        };
+        let mut max_age = None;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // a host can only set cookies for itself and the domains above it
+                    if !domain.is_empty() {
+                        if !domain_matches(&host, &domain) {
+                            return None;
+                        }
+                        cookie.domain = domain;
+                        cookie.host_only = false;
+                    }
+                }
+                "expires" => {
+                    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+                        cookie.expires = Some(date.into());
+                    }
+                }
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(seconds);
+                    }
+                }
+                "path" if value.starts_with('/') => cookie.path = value.into(),
+                "secure" => cookie.secure = true,
+                _ => (),
+            }
+        }
+        // `Max-Age` wins over `Expires`, and a zero or negative one expires the cookie right away
+        if let Some(seconds) = max_age {
+            let seconds = seconds.max(0) as u64;
+            cookie.expires = Some(now + Duration::from_secs(seconds));
+        }
+        Some(cookie)
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn matches(&self, url: &url::Url, now: SystemTime) -> bool {
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        let domain_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        domain_matches
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired(now)
+    }
+}
+
+impl CookieJar {
+    /// Stores the cookies from a response's `Set-Cookie` headers, replacing any with the same
+    /// name, domain and path
+    pub(super) fn store(&self, url: &url::Url, headers: &HeaderMap) {
+        let now = SystemTime::now();
+        let mut cookies = self
+            .cookies
+            .lock()
+            .expect("cookie jar lock should not be poisoned");
+        let set_cookies = headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| Cookie::parse(v, url, now));
+        for cookie in set_cookies {
+            let existing = cookies.iter().position(|c| {
+                (&c.name, &c.domain, &c.path) == (&cookie.name, &cookie.domain, &cookie.path)
+            });
+            match existing {
+                Some(i) => cookies[i] = cookie,
+                None => cookies.push(cookie),
+            }
+        }
+        // an expired cookie is how a server deletes one
+        cookies.retain(|c| !c.is_expired(now));
+    }
+
+    /// Adds the cookies stored for `url` to the `Cookie` header, after any cookies the endpoint
+    /// sends itself
+    pub(super) fn add_cookies(&self, url: &url::Url, headers: &mut HeaderMap) {
+        let now = SystemTime::now();
+        let cookies = self
+            .cookies
+            .lock()
+            .expect("cookie jar lock should not be poisoned");
+        let mut matching: Vec<_> = cookies.iter().filter(|c| c.matches(url, now)).collect();
+        if matching.is_empty() {
+            return;
+        }
+        // cookies with longer paths are listed first
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let stored = matching
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let cookie = match headers.get(COOKIE).and_then(|v| v.to_str().ok()) {
+            Some(own) if !own.is_empty() => format!("{own}; {stored}"),
+            _ => stored,
+        };
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            headers.insert(COOKIE, cookie);
+        }
+    }
+}
+
+// the directory of the request's path, for a cookie which doesn't set its own `Path`
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.len() > domain.len()
+            && host.ends_with(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.')
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path.as_bytes()[cookie_path.len()] == b'/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> url::Url {
+        url::Url::parse(url).unwrap()
+    }
+
+    fn set_cookies(jar: &CookieJar, from: &str, set_cookies: &[&str]) {
+        let mut headers = HeaderMap::new();
+        for set_cookie in set_cookies {
+            headers.append(SET_COOKIE, HeaderValue::from_str(set_cookie).unwrap());
+        }
+        jar.store(&url(from), &headers);
+    }
+
+    fn cookie_header(jar: &CookieJar, to: &str) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        jar.add_cookies(&url(to), &mut headers);
+        headers.get(COOKIE).map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn sends_cookies_back_to_the_host_which_set_them() {
+        let jar = CookieJar::default();
+        set_cookies(
+            &jar,
+            "http://example.com/login",
+            &["session=abc; HttpOnly", "theme=dark; Path=/"],
+        );
+        assert_eq!(
+            cookie_header(&jar, "http://example.com/account"),
+            Some("session=abc; theme=dark".into())
+        );
+        assert_eq!(cookie_header(&jar, "http://other.example/"), None);
+        // without a `Domain` a cookie doesn't go to subdomains either
+        assert_eq!(cookie_header(&jar, "http://api.example.com/"), None);
+
+        // a later cookie with the same name replaces the first
+        set_cookies(&jar, "http://example.com/", &["session=def"]);
+        assert_eq!(
+            cookie_header(&jar, "http://example.com/"),
+            Some("session=def; theme=dark".into())
+        );
+
+        // and they're added to a `Cookie` header the endpoint sets
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, HeaderValue::from_static("own=1"));
+        jar.add_cookies(&url("http://example.com/"), &mut headers);
+        assert_eq!(headers[COOKIE], "own=1; session=def; theme=dark");
+    }
+
+    #[test]
+    fn scopes_cookies_to_domains_and_paths() {
+        let jar = CookieJar::default();
+        set_cookies(
+            &jar,
+            "https://www.example.com/app/login",
+            &[
+                "shared=1; Domain=.Example.com; Path=/",
+                "app=2",
+                "admin=3; Path=/admin",
+                "secret=4; Secure; Path=/",
+                // a host can't set cookies for hosts outside its own domain
+                "leak=5; Domain=other.example",
+                "sibling=6; Domain=api.example.com",
+            ],
+        );
+        assert_eq!(
+            cookie_header(&jar, "https://api.example.com/app"),
+            Some("shared=1".into())
+        );
+        assert_eq!(
+            cookie_header(&jar, "https://www.example.com/app/page"),
+            Some("app=2; shared=1; secret=4".into())
+        );
+        assert_eq!(
+            cookie_header(&jar, "https://www.example.com/admin/users"),
+            Some("admin=3; shared=1; secret=4".into())
+        );
+        // `/app` isn't `/application`'s directory, and `Secure` cookies need https
+        assert_eq!(
+            cookie_header(&jar, "http://www.example.com/application"),
+            Some("shared=1".into())
+        );
+        assert_eq!(cookie_header(&jar, "https://other.example/"), None);
+    }
+
+    #[test]
+    fn honors_expires_and_max_age() {
+        let jar = CookieJar::default();
+        set_cookies(
+            &jar,
+            "http://example.com/",
+            &[
+                "past=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+                "future=2; Expires=Fri, 01 Jan 2100 00:00:00 GMT",
+                "gone=3; Max-Age=0",
+                // `Max-Age` wins over `Expires`
+                "kept=4; Max-Age=3600; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            ],
+        );
+        assert_eq!(
+            cookie_header(&jar, "http://example.com/"),
+            Some("future=2; kept=4".into())
+        );
+
+        // an expired cookie deletes the one it replaces
+        set_cookies(&jar, "http://example.com/", &["future=2; Max-Age=-1"]);
+        assert_eq!(
+            cookie_header(&jar, "http://example.com/"),
+            Some("kept=4".into())
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use futures::{future, FutureExt, Stream, StreamExt};
+use tokio::sync::watch;
+
+use std::sync::Arc;
+
+/// Tells the endpoints which list this endpoint in their `depends_on` that it has finished a
+/// request. The signal is dropped when the endpoint ends, which also releases anything still
+/// waiting on it.
+#[derive(Clone)]
+pub(super) struct ReadySignal(Arc<watch::Sender<bool>>);
+
+impl ReadySignal {
+    pub(super) fn new() -> Self {
+        ReadySignal(Arc::new(watch::channel(false).0))
+    }
+
+    pub(super) fn subscribe(&self) -> watch::Receiver<bool> {
+        self.0.subscribe()
+    }
+
+    pub(super) fn ready(&self) {
+        self.0
+            .send_if_modified(|ready| !std::mem::replace(ready, true));
+    }
+}
+
+/// Holds back an endpoint's stream until each of the endpoints it depends on has either finished
+/// a request or ended. Nothing is pulled from the stream, including provider values, until then.
+pub(super) fn wait_for<S>(
+    dependencies: Vec<watch::Receiver<bool>>,
+    stream: S,
+) -> impl Stream<Item = S::Item> + Send + Unpin
+where
+    S: Stream + Send + Unpin,
+    S::Item: Send,
+{
+    let wait = async move {
+        for mut rx in dependencies {
+            // an error means the endpoint ended
+            let _ = rx.wait_for(|ready| *ready).await;
+        }
+    };
+    Box::pin(wait.into_stream().filter_map(|_| future::ready(None))).chain(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn waits_until_ready_or_dropped() {
+        let a = ReadySignal::new();
+        let b = ReadySignal::new();
+        let mut s = wait_for(vec![a.subscribe(), b.subscribe()], stream::iter(vec![1, 2]));
+        assert!(s.next().now_or_never().is_none());
+
+        a.ready();
+        assert!(s.next().now_or_never().is_none());
+
+        drop(b);
+        assert_eq!(s.next().await, Some(1));
+        assert_eq!(s.next().await, Some(2));
+    }
+}
@@ -0,0 +1,68 @@
+use bytes::Bytes;
+use rand::{distributions::Uniform, rngs::StdRng, Rng, RngCore, SeedableRng};
+
+use std::sync::{Arc, Mutex};
+
+// The bodies for a `random_bytes` body. One buffer of `max` random bytes is filled when the
+// endpoint is built and each body is a slice of it of a random length, so sending a request
+// doesn't allocate or generate anything beyond picking the length.
+#[derive(Clone)]
+pub(super) struct RandomBody {
+    buffer: Bytes,
+    lengths: Uniform<usize>,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl RandomBody {
+    // with a `seed` the bytes and the sequence of lengths are the same every run
+    pub(super) fn new(min: usize, max: usize, seed: Option<u64>) -> Self {
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut buffer = vec![0; max];
+        rng.fill_bytes(&mut buffer);
+        RandomBody {
+            buffer: buffer.into(),
+            lengths: Uniform::new_inclusive(min, max),
+            rng: Arc::new(Mutex::new(rng)),
+        }
+    }
+
+    pub(super) fn next(&self) -> Bytes {
+        let len = self
+            .rng
+            .lock()
+            .expect("random body lock should not be poisoned")
+            .sample(self.lengths);
+        self.buffer.slice(..len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_stay_in_the_range() {
+        let body = RandomBody::new(10, 20, Some(3));
+        let lengths: Vec<_> = (0..200).map(|_| body.next().len()).collect();
+        assert!(
+            lengths.iter().all(|l| (10..=20).contains(l)),
+            "{:?}",
+            lengths
+        );
+        assert!(
+            lengths.contains(&10) && lengths.contains(&20),
+            "{:?}",
+            lengths
+        );
+
+        let again = RandomBody::new(10, 20, Some(3));
+        let lengths2: Vec<_> = (0..200).map(|_| again.next().len()).collect();
+        assert_eq!(lengths, lengths2);
+
+        let empty = RandomBody::new(0, 0, None);
+        assert!(empty.next().is_empty());
+    }
+}
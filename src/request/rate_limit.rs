@@ -0,0 +1,210 @@
+use config::HitsPer;
+use futures::{future, FutureExt, Stream, StreamExt};
+use futures_timer::Delay;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
+// how long the bucket takes to fill, so how big a burst it lets through after a quiet spell
+const BURST: Duration = Duration::from_secs(1);
+
+/// Caps the combined rate of requests from every endpoint, from `general.max_total_rate`, with a
+/// token bucket shared by every endpoint. The bucket refills at the rate and holds a second's
+/// worth of requests, at least two, so endpoints whose requests are due at the same moment all
+/// get through as long as together they stay under the cap. Once the cap is reached, a request
+/// which finds the bucket empty goes without, whichever endpoint it's from. The cap is applied to
+/// an endpoint's start stream, before any provider values are drawn for a request.
+#[derive(Clone)]
+pub struct RateLimit {
+    interval: Duration,
+    capacity: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    // can go below zero when requests without a load pattern have reserved capacity in advance
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    pub fn new(rate: &HitsPer) -> Self {
+        let per_second = match *rate {
+            HitsPer::Second(n) => n as f64,
+            HitsPer::Minute(n) => n as f64 / 60.0,
+        };
+        // a rate of 0 lets a request through once a day, which is as good as none
+        let interval = Duration::try_from_secs_f64(1.0 / per_second)
+            .unwrap_or(ONE_DAY)
+            .min(ONE_DAY);
+        let capacity = (BURST.as_secs_f64() / interval.as_secs_f64()).max(2.0);
+        RateLimit {
+            interval,
+            capacity,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    // runs `f` on the bucket after it's refilled for the time since it was last updated. The
+    // refill is counted from the last update rather than from when a request was let through,
+    // so a request which wakes up late doesn't push the ones after it back
+    fn with_bucket<T>(&self, f: impl FnOnce(&mut Bucket) -> T) -> T {
+        let now = Instant::now();
+        let mut bucket = self
+            .bucket
+            .lock()
+            .expect("rate limit lock should not be poisoned");
+        let refill = now.saturating_duration_since(bucket.updated).as_secs_f64()
+            / self.interval.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+        bucket.updated = now;
+        f(&mut bucket)
+    }
+
+    /// Claims a token for a request, returning how long the request has to wait for it
+    fn reserve(&self) -> Duration {
+        let interval = self.interval;
+        self.with_bucket(|bucket| {
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                Duration::ZERO
+            } else {
+                interval.mul_f64(-bucket.tokens)
+            }
+        })
+    }
+
+    /// Claims a token only if there is one now
+    fn try_take(&self) -> bool {
+        self.with_bucket(|bucket| {
+            let take = bucket.tokens >= 1.0;
+            if take {
+                bucket.tokens -= 1.0;
+            }
+            take
+        })
+    }
+
+    /// Wraps an endpoint's start stream so only the ticks which find a free slot are let
+    /// through. Ticks over the rate are dropped rather than queued, the same as the ticks an
+    /// endpoint misses while it's paused by a `Retry-After`.
+    pub(super) fn gate<S>(self, stream: S) -> impl Stream<Item = (Instant, Option<Instant>)>
+    where
+        S: Stream<Item = (Instant, Option<Instant>)>,
+    {
+        stream.filter(move |_| future::ready(self.try_take()))
+    }
+
+    /// Wraps the start stream of an endpoint without a load pattern, which makes requests as
+    /// fast as its providers allow, so each item waits for the next slot before it's let through
+    pub(super) fn throttle<S>(self, stream: S) -> impl Stream<Item = S::Item>
+    where
+        S: Stream,
+    {
+        stream.then(move |item| Delay::new(self.reserve()).map(move |_| item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, stream};
+
+    // an endpoint's start stream, with a tick every `interval` for `count` ticks from `start`
+    fn ticks(
+        start: Instant,
+        interval: Duration,
+        count: u32,
+    ) -> impl Stream<Item = (Instant, Option<Instant>)> {
+        stream::iter(1..=count).then(move |i| {
+            let at = start + interval * i;
+            Delay::new(at.saturating_duration_since(Instant::now())).map(move |_| (at, None))
+        })
+    }
+
+    #[test]
+    fn spaces_out_reservations() {
+        let limit = RateLimit::new(&HitsPer::Second(10.0));
+        // the bucket starts with a second's worth
+        for _ in 0..10 {
+            assert_eq!(limit.reserve(), Duration::ZERO);
+        }
+        let waits: Vec<_> = (0..3).map(|_| limit.reserve()).collect();
+        for (i, wait) in waits.into_iter().enumerate() {
+            let expected = Duration::from_millis(100 * (i as u64 + 1));
+            assert!(
+                wait <= expected && wait > expected - Duration::from_millis(20),
+                "{:?}",
+                wait
+            );
+        }
+
+        // the limit is shared between clones
+        let other = limit.clone();
+        assert!(other.reserve() > Duration::from_millis(350));
+
+        // slow rates still let two through at once
+        let limit = RateLimit::new(&HitsPer::Minute(60.0));
+        limit.reserve();
+        assert_eq!(limit.reserve(), Duration::ZERO);
+        assert!(limit.reserve() > Duration::from_millis(950));
+    }
+
+    #[test]
+    fn gate_shares_the_rate_under_the_cap() {
+        let limit = RateLimit::new(&HitsPer::Second(20.0));
+        // use up the initial burst so only the refill is left to share
+        while limit.try_take() {}
+
+        // two endpoints at 10hps each, with ticks at the same instants, together make 20hps
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        let a = limit.clone().gate(ticks(start, interval, 10)).map(|_| 'a');
+        let b = limit.gate(ticks(start, interval, 10)).map(|_| 'b');
+        let let_through = block_on(stream::select(a, b).collect::<Vec<_>>());
+        let a = let_through.iter().filter(|e| **e == 'a').count();
+        let b = let_through.iter().filter(|e| **e == 'b').count();
+        assert!(a >= 9 && b >= 9, "{:?}", let_through);
+    }
+
+    #[test]
+    fn gate_keeps_up_with_an_endpoint_at_the_cap() {
+        let limit = RateLimit::new(&HitsPer::Second(20.0));
+        while limit.try_take() {}
+
+        let ticks = ticks(Instant::now(), Duration::from_millis(50), 20);
+        let count = block_on(limit.gate(ticks).count());
+        assert!(count >= 19, "{}", count);
+    }
+
+    #[test]
+    fn gate_caps_the_combined_rate() {
+        let limit = RateLimit::new(&HitsPer::Second(20.0));
+        while limit.try_take() {}
+
+        // two endpoints at 50hps each for a second, far over the shared 20hps
+        let start = Instant::now();
+        let interval = Duration::from_millis(20);
+        let a = limit.clone().gate(ticks(start, interval, 50));
+        let b = limit.gate(ticks(start, interval, 50));
+        let count = block_on(stream::select(a, b).count());
+        assert!((18..=22).contains(&count), "{}", count);
+    }
+
+    #[test]
+    fn throttle_waits_for_each_slot() {
+        let limit = RateLimit::new(&HitsPer::Second(50.0));
+        while limit.try_take() {}
+        let start = Instant::now();
+        let items = block_on(limit.throttle(stream::iter(0..5)).collect::<Vec<_>>());
+        assert_eq!(items, vec![0, 1, 2, 3, 4]);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(79), "{:?}", elapsed);
+    }
+}
@@ -1,8 +1,15 @@
 use super::*;
+use crate::connection_reuse;
+use crate::util::str_to_json;
 
-use config::{RESPONSE_BODY, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE, STATS};
+use config::{
+    ResponseFormat, RESPONSE_BODY, RESPONSE_HEADERS, RESPONSE_HEADERS_ALL, RESPONSE_STARTLINE,
+    STATS,
+};
 use futures::TryStreamExt;
 
+use std::time::SystemTime;
+
 pub(super) struct ResponseHandler {
     pub(super) provider_delays: ProviderDelays,
     pub(super) template_values: TemplateValues,
@@ -12,6 +19,15 @@ pub(super) struct ResponseHandler {
     pub(super) now: Instant,
     pub(super) stats_tx: StatsTx,
     pub(super) tags: Arc<BTreeMap<String, Template>>,
+    pub(super) retry_after: Option<RetryAfterGate>,
+    pub(super) response_format: Option<ResponseFormat>,
+    pub(super) is_success: Option<Arc<Select>>,
+    pub(super) assertions: Option<Arc<Assertions>>,
+    // `client.decompress`, whether the body is decoded according to its `Content-Encoding`
+    pub(super) decompress: bool,
+    // `client.cookies`, the jar which stores the response's cookies along with the url it came
+    // from
+    pub(super) cookies: Option<(CookieJar, url::Url)>,
 }
 
 impl ResponseHandler {
@@ -27,6 +43,17 @@ impl ResponseHandler {
     {
         let status_code = response.status();
         let status = status_code.as_u16();
+        let sent = SystemTime::now()
+            .checked_sub(self.now.elapsed())
+            .unwrap_or_else(SystemTime::now);
+        let timing = connection_reuse::timing(&response, sent);
+        let connection_reused = connection_reuse::was_reused(&response);
+        if let Some(gate) = &self.retry_after {
+            gate.check_response(status_code, response.headers());
+        }
+        if let Some((jar, url)) = &self.cookies {
+            jar.store(url, response.headers());
+        }
         let response_provider = json::json!({ "status": status });
         let mut template_values = self.template_values;
         template_values.insert("response".into(), response_provider);
@@ -75,56 +102,92 @@ impl ResponseHandler {
             Ok(i) => i,
             Err(e) => return future::err(e).a(),
         };
-        let ce_header = response.headers().get("content-encoding").map(|h| {
-            h.to_str()
-                .expect("content-encoding header should cast to str")
-        });
-        let ce_header = ce_header.unwrap_or("");
-        let body_future = match (
-            response_fields_added & RESPONSE_BODY != 0,
-            body_reader::Compression::try_from(ce_header),
-        ) {
-            (true, Some(ce)) => {
+        // `response.body` is only read into memory (and decompressed) when a provides, log or
+        // where clause references it, and `stats` only need it decompressed for `body_size`.
+        // Otherwise the body is drained a chunk at a time and discarded, which still lets the
+        // connection be reused
+        let read_body = response_fields_added & RESPONSE_BODY != 0;
+        let read_stats = (rr_providers | where_clause_special_providers) & STATS != 0;
+        let compression = if read_body || read_stats {
+            let ce_header = if self.decompress {
+                response
+                    .headers()
+                    .get("content-encoding")
+                    .and_then(|h| h.to_str().ok())
+                    .unwrap_or("")
+            } else {
+                ""
+            };
+            // an encoding which can't be decoded is passed through as it is
+            let ce = body_reader::Compression::try_from(ce_header.trim())
+                .unwrap_or(body_reader::Compression::None);
+            Some(ce)
+        } else {
+            None
+        };
+        let response_format = self.response_format;
+        let body_future = match compression {
+            Some(ce) => {
                 let body = response
                     .into_body()
                     .map_err(|e| RecoverableError::BodyErr(Arc::new(e)));
                 let br = body_reader::BodyReader::new(ce);
                 let body_buffer = bytes::BytesMut::new();
                 body.try_fold(
-                    (br, body_buffer),
-                    |(mut br, mut body_buffer), chunks| match br.decode(chunks, &mut body_buffer) {
-                        Ok(_) => future::ready(Ok((br, body_buffer))),
-                        Err(e) => future::ready(Err(RecoverableError::BodyErr(Arc::new(e)))),
+                    (br, body_buffer, 0, 0),
+                    move |(mut br, mut body_buffer, bytes_in, mut body_size), chunks| {
+                        let bytes_in = bytes_in + chunks.len() as u64;
+                        if let Err(e) = br.decode(chunks, &mut body_buffer) {
+                            return future::ready(Err(RecoverableError::BodyErr(Arc::new(e))));
+                        }
+                        // when only its size is needed the decompressed body isn't kept
+                        if !read_body {
+                            body_size += body_buffer.len() as u64;
+                            body_buffer.clear();
+                        }
+                        future::ready(Ok((br, body_buffer, bytes_in, body_size)))
                     },
                 )
-                .map_ok(|(_, body_buffer)| {
-                    let body_string = str::from_utf8(&body_buffer).unwrap_or("<<binary data>>");
-                    let value = json::from_str(body_string)
-                        .ok()
-                        .unwrap_or_else(|| json::Value::String(body_string.into()));
-                    Some(value)
+                .and_then(move |(_, body_buffer, bytes_in, body_size)| {
+                    let value = if read_body {
+                        let sizes = (bytes_in, body_buffer.len() as u64);
+                        parse_body(&body_buffer, response_format).map(|v| (Some(v), sizes))
+                    } else {
+                        Ok((None, (bytes_in, body_size)))
+                    };
+                    future::ready(value)
                 })
                 .a()
             }
-            _ => {
-                // when we don't need the body, skip parsing it, but make sure we get it all
-                response
-                    .into_body()
-                    .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))
-                    .try_fold((), |_, _| future::ok(()))
-                    .map_ok(|_| None)
-                    .b()
-            }
+            None => response
+                .into_body()
+                .map_err(|e| RecoverableError::BodyErr(Arc::new(e)))
+                .try_fold(0, |bytes_in, chunk| {
+                    future::ok(bytes_in + chunk.len() as u64)
+                })
+                .map_ok(|bytes_in| (None, (bytes_in, 0)))
+                .b(),
         };
         let provider_delays = self.provider_delays;
         let now = self.now;
         let outgoing = self.outgoing;
         let stats_tx = self.stats_tx;
         let tags = self.tags;
+        let is_success = self.is_success;
+        let assertions = self.assertions;
         body_future
             .then(move |body_value| {
+                let (body_value, (bytes_in, body_size)) = match body_value {
+                    Ok((body_value, sizes)) => (Ok(body_value), sizes),
+                    Err(e) => (Err(e), (0, 0)),
+                };
                 let bh = BodyHandler {
+                    assertions,
+                    bytes_in,
+                    body_size,
+                    connection_reused,
                     included_outgoing_indexes,
+                    is_success,
                     now,
                     outgoing,
                     provider_delays,
@@ -132,6 +195,7 @@ impl ResponseHandler {
                     status,
                     tags,
                     template_values,
+                    timing,
                 };
                 bh.handle(body_value, auto_returns)
             })
@@ -139,6 +203,83 @@ impl ResponseHandler {
     }
 }
 
+// Parse a response body into `response.body` as the endpoint's `response_format`. Without one the
+// body is json when it parses as json and text when it doesn't
+fn parse_body(
+    body: &[u8],
+    format: Option<ResponseFormat>,
+) -> Result<json::Value, RecoverableError> {
+    fn body_err<E: std::error::Error + Send + Sync + 'static>(e: E) -> RecoverableError {
+        RecoverableError::BodyErr(Arc::new(e))
+    }
+    let text = || str::from_utf8(body).unwrap_or("<<binary data>>");
+    let value = match format {
+        None => str_to_json(text()),
+        Some(ResponseFormat::Text) => json::Value::String(text().into()),
+        Some(ResponseFormat::Json) => json::from_slice(body).map_err(body_err)?,
+        Some(ResponseFormat::Xml) => {
+            let text = str::from_utf8(body).map_err(body_err)?;
+            let document = roxmltree::Document::parse(text).map_err(body_err)?;
+            let root = document.root_element();
+            json::json!({ root.tag_name().name(): xml_to_json(root) })
+        }
+        Some(ResponseFormat::Csv) => {
+            let mut reader = csv::Reader::from_reader(body);
+            let headers = reader.headers().map_err(body_err)?.clone();
+            let rows = reader
+                .records()
+                .map(|record| {
+                    let record = record.map_err(body_err)?;
+                    let row = headers
+                        .iter()
+                        .zip(record.iter())
+                        .map(|(k, v)| (k.into(), str_to_json(v)))
+                        .collect();
+                    Ok(json::Value::Object(row))
+                })
+                .collect::<Result<_, RecoverableError>>()?;
+            json::Value::Array(rows)
+        }
+    };
+    Ok(value)
+}
+
+// An element becomes an object of its attributes, as `@name`, and its child elements, with a child
+// element repeated under the same name becoming an array. An element with nothing but text
+// becomes that text, otherwise its text is kept as `#text`
+fn xml_to_json(element: roxmltree::Node<'_, '_>) -> json::Value {
+    let mut object = json::Map::new();
+    for attribute in element.attributes() {
+        let name = format!("@{}", attribute.name());
+        object.insert(name, str_to_json(attribute.value()));
+    }
+    let mut text = String::new();
+    for child in element.children() {
+        if child.is_element() {
+            let name = child.tag_name().name();
+            let value = xml_to_json(child);
+            match object.get_mut(name) {
+                Some(json::Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = json::Value::Array(vec![existing.take(), value]),
+                None => {
+                    object.insert(name.into(), value);
+                }
+            }
+        } else if let Some(t) = child.text().filter(|_| child.is_text()) {
+            text.push_str(t);
+        }
+    }
+    let text = text.trim();
+    if object.is_empty() {
+        str_to_json(text)
+    } else {
+        if !text.is_empty() {
+            object.insert("#text".into(), str_to_json(text));
+        }
+        json::Value::Object(object)
+    }
+}
+
 fn handle_response_requirements(
     bitwise: u16,
     response_fields_added: &mut u16,
@@ -157,30 +298,15 @@ fn handle_response_requirements(
     // check if we need response.headers and it hasn't already been set
     if ((bitwise & RESPONSE_HEADERS) ^ (*response_fields_added & RESPONSE_HEADERS)) != 0 {
         *response_fields_added |= RESPONSE_HEADERS;
-        let mut headers_json = json::Map::new();
-        for (k, v) in response.headers() {
-            headers_json.insert(
-                k.as_str().to_string(),
-                json::Value::String(String::from_utf8_lossy(v.as_bytes()).into_owned()),
-            );
-        }
-        rp.insert("headers".into(), json::Value::Object(headers_json));
+        rp.insert("headers".into(), headers_to_json(response.headers()));
     }
     // check if we need response.headers_all and it hasn't already been set
     if ((bitwise & RESPONSE_HEADERS_ALL) ^ (*response_fields_added & RESPONSE_HEADERS_ALL)) != 0 {
         *response_fields_added |= RESPONSE_HEADERS_ALL;
-        let mut headers_json = json::Map::new();
-        for (k, v) in response.headers() {
-            headers_json
-                .entry(k.as_str())
-                .or_insert_with(|| json::Value::Array(Vec::new()))
-                .as_array_mut()
-                .expect("should be a json array")
-                .push(json::Value::String(
-                    String::from_utf8_lossy(v.as_bytes()).into_owned(),
-                ))
-        }
-        rp.insert("headers_all".into(), json::Value::Object(headers_json));
+        rp.insert(
+            "headers_all".into(),
+            headers_all_to_json(response.headers()),
+        );
     }
     // check if we need the response body and it hasn't already been set
     if ((bitwise & RESPONSE_BODY) ^ (*response_fields_added & RESPONSE_BODY)) != 0 {
@@ -212,6 +338,12 @@ mod tests {
             now,
             stats_tx,
             tags,
+            retry_after: None,
+            response_format: None,
+            is_success: None,
+            assertions: None,
+            decompress: true,
+            cookies: None,
         };
 
         let auto_returns: Option<futures::future::Pending<_>> = None;
@@ -219,4 +351,224 @@ mod tests {
         let r = block_on(rh.handle(Default::default(), auto_returns));
         assert!(r.is_ok());
     }
+
+    // a large gzip response whose body isn't valid gzip, so it fails if it is ever decoded
+    fn large_response() -> Response<HyperBody> {
+        let chunk = bytes::Bytes::from(vec![b'x'; 1024 * 1024]);
+        let chunks =
+            futures::stream::iter((0..16).map(move |_| Ok::<_, std::io::Error>(chunk.clone())));
+        Response::builder()
+            .header("content-encoding", "gzip")
+            .body(HyperBody::wrap_stream(chunks))
+            .unwrap()
+    }
+
+    fn response_handler(rr_providers: u16, stats_tx: StatsTx) -> ResponseHandler {
+        ResponseHandler {
+            provider_delays: ProviderDelays::new(),
+            template_values: TemplateValues::new(),
+            precheck_rr_providers: 0,
+            rr_providers,
+            outgoing: Vec::new().into(),
+            now: Instant::now(),
+            stats_tx,
+            tags: Arc::new(BTreeMap::new()),
+            retry_after: None,
+            response_format: None,
+            is_success: None,
+            assertions: None,
+            decompress: true,
+            cookies: None,
+        }
+    }
+
+    #[test]
+    fn unreferenced_body_is_not_buffered() {
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let rh = response_handler(0, stats_tx);
+        let r = block_on(rh.handle(large_response(), auto_returns));
+        assert!(
+            r.is_ok(),
+            "the body should have been drained without decoding it"
+        );
+        match stats_rx.try_next() {
+            Ok(Some(stats::StatsMessage::ResponseStat(stat))) => {
+                assert!(matches!(stat.kind, stats::StatKind::Response(200)));
+                assert!(stat.rtt.is_some());
+            }
+            _ => panic!("expected a response stat"),
+        }
+
+        // once something references `response.body` the same response is decoded, and fails
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let mut rh = response_handler(RESPONSE_BODY, stats_tx);
+        rh.precheck_rr_providers = RESPONSE_BODY;
+        let _ = block_on(rh.handle(large_response(), auto_returns));
+        match stats_rx.try_next() {
+            Ok(Some(stats::StatsMessage::ResponseStat(stat))) => assert!(matches!(
+                stat.kind,
+                stats::StatKind::RecoverableError(RecoverableError::BodyErr(_))
+            )),
+            _ => panic!("expected a response stat"),
+        }
+    }
+
+    // `{"greeting": "hello hello ..."}` (316 bytes) gzipped down to 45 bytes
+    const GZIPPED: &[u8] = &[
+        31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 171, 86, 74, 47, 74, 77, 45, 201, 204, 75, 87, 178, 82,
+        80, 202, 72, 205, 201, 201, 87, 24, 37, 137, 33, 149, 106, 1, 58, 219, 159, 235, 60, 1, 0,
+        0,
+    ];
+
+    // runs a response through a handler which provides `select` and returns what it provided
+    fn select_from(
+        response: Response<HyperBody>,
+        select: json::Value,
+        rr_providers: u16,
+        decompress: bool,
+    ) -> json::Value {
+        let (tx, mut rx) = channel::channel(channel::Limit::Static(1), false, "selected");
+        let select = Select::simple(select, EndpointProvidesSendOptions::Force, None, None, None);
+        let (stats_tx, _) = futures_channel::unbounded();
+        let mut rh = response_handler(rr_providers, stats_tx);
+        rh.decompress = decompress;
+        rh.outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))].into();
+
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let r = block_on(rh.handle(response, auto_returns));
+        assert!(r.is_ok());
+        rx.next().now_or_never().flatten().unwrap()
+    }
+
+    fn encoded_response(content_encoding: &str, body: &'static [u8]) -> Response<HyperBody> {
+        Response::builder()
+            .header("content-encoding", content_encoding)
+            .body(HyperBody::from(body))
+            .unwrap()
+    }
+
+    #[test]
+    fn decodes_gzip_body_and_counts_wire_bytes() {
+        let value = select_from(
+            encoded_response("gzip", GZIPPED),
+            json::json!([
+                "response.body.greeting",
+                "stats.bytes_in",
+                "stats.body_size"
+            ]),
+            RESPONSE_BODY | STATS,
+            true,
+        );
+        assert_eq!(value, json::json!(["hello ".repeat(50), 45, 316]));
+
+        // the body is still decoded for its size when only `stats` are referenced
+        let value = select_from(
+            encoded_response("gzip", GZIPPED),
+            json::json!(["stats.bytes_in", "stats.body_size"]),
+            STATS,
+            true,
+        );
+        assert_eq!(value, json::json!([45, 316]));
+    }
+
+    #[test]
+    fn passes_through_bodies_it_does_not_decode() {
+        // an encoding which isn't supported is left as it is
+        let value = select_from(
+            encoded_response("zstd", br#"{"greeting": "hello"}"#),
+            json::json!(["response.body.greeting", "stats.body_size"]),
+            RESPONSE_BODY | STATS,
+            true,
+        );
+        assert_eq!(value, json::json!(["hello", 21]));
+
+        // as is every body when `client.decompress` is false
+        let value = select_from(
+            encoded_response("gzip", GZIPPED),
+            json::json!(["response.body", "stats.bytes_in", "stats.body_size"]),
+            RESPONSE_BODY | STATS,
+            false,
+        );
+        assert_eq!(value, json::json!(["<<binary data>>", 45, 45]));
+    }
+
+    #[test]
+    fn provides_headers_whatever_their_case() {
+        let response = || {
+            Response::builder()
+                .header("X-Request-Id", "abc123")
+                .header("set-cookie", "a=1")
+                .header("Set-Cookie", "b=2; Path=/")
+                .body(HyperBody::empty())
+                .unwrap()
+        };
+        let value = select_from(
+            response(),
+            json::json!([
+                r#"response.headers["X-Request-Id"]"#,
+                r#"response.headers["x-request-id"]"#,
+                r#"response.headers["Set-Cookie"]"#
+            ]),
+            RESPONSE_HEADERS,
+            true,
+        );
+        // the values of a header which came more than once are kept, not just the last one
+        assert_eq!(value, json::json!(["abc123", "abc123", "a=1, b=2; Path=/"]));
+
+        let value = select_from(
+            response(),
+            json::json!(r#"response.headers_all["Set-Cookie"]"#),
+            RESPONSE_HEADERS_ALL,
+            true,
+        );
+        assert_eq!(value, json::json!(["a=1", "b=2; Path=/"]));
+    }
+
+    #[test]
+    fn selects_from_xml_body() {
+        let xml = r#"<order id="7"><item sku="a1">3</item><item sku="b2">5</item></order>"#;
+        let response = Response::builder().body(HyperBody::from(xml)).unwrap();
+
+        let (tx, mut rx) = channel::channel(channel::Limit::Static(1), false, "sku");
+        let select = Select::simple(
+            json::json!([
+                "response.body.order[\"@id\"]",
+                "response.body.order.item[1][\"@sku\"]"
+            ]),
+            EndpointProvidesSendOptions::Force,
+            None,
+            None,
+            None,
+        );
+        let (stats_tx, _) = futures_channel::unbounded();
+        let mut rh = response_handler(RESPONSE_BODY, stats_tx);
+        rh.response_format = Some(ResponseFormat::Xml);
+        rh.outgoing = vec![Outgoing::new(select, ProviderOrLogger::Provider(tx))].into();
+
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let r = block_on(rh.handle(response, auto_returns));
+        assert!(r.is_ok());
+        let value = rx.next().now_or_never().flatten().unwrap();
+        assert_eq!(value, json::json!([7, "b2"]));
+
+        // a body which isn't xml is a recoverable error
+        let response = Response::builder()
+            .body(HyperBody::from("<order>"))
+            .unwrap();
+        let (stats_tx, mut stats_rx) = futures_channel::unbounded();
+        let mut rh = response_handler(RESPONSE_BODY, stats_tx);
+        rh.precheck_rr_providers = RESPONSE_BODY;
+        rh.response_format = Some(ResponseFormat::Xml);
+        let auto_returns: Option<futures::future::Pending<_>> = None;
+        let _ = block_on(rh.handle(response, auto_returns));
+        match stats_rx.try_next() {
+            Ok(Some(stats::StatsMessage::ResponseStat(stat))) => assert!(matches!(
+                stat.kind,
+                stats::StatKind::RecoverableError(RecoverableError::BodyErr(_))
+            )),
+            _ => panic!("expected a response stat"),
+        }
+    }
 }
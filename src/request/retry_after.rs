@@ -0,0 +1,121 @@
+use futures::{stream, Stream, StreamExt};
+use futures_timer::Delay;
+use hyper::{header::RETRY_AFTER, HeaderMap, StatusCode};
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Holds back an endpoint's requests after the server responds with a `429` or `503` and a
+/// `Retry-After` header. The deadline is shared between the endpoint's responses, which set it,
+/// and the endpoint's start stream, which waits on it.
+#[derive(Clone, Default)]
+pub(super) struct RetryAfterGate(Arc<Mutex<Option<Instant>>>);
+
+impl RetryAfterGate {
+    /// Pauses the endpoint if the response asks the client to back off
+    pub(super) fn check_response(&self, status: StatusCode, headers: &HeaderMap) {
+        if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+            return;
+        }
+        let delay = headers
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_retry_after);
+        if let Some(delay) = delay {
+            log::info!(
+                "pausing endpoint for {:?} after a {} response",
+                delay,
+                status
+            );
+            self.pause_for(delay);
+        }
+    }
+
+    fn pause_for(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut paused_until = self
+            .0
+            .lock()
+            .expect("retry after lock should not be poisoned");
+        *paused_until = Some(paused_until.map_or(until, |current| current.max(until)));
+    }
+
+    fn paused_until(&self) -> Option<Instant> {
+        let paused_until = self
+            .0
+            .lock()
+            .expect("retry after lock should not be poisoned");
+        paused_until.filter(|until| *until > Instant::now())
+    }
+
+    /// Wraps an endpoint's start stream so no ticks are let through while the endpoint is
+    /// paused. Ticks which were scheduled during the pause are dropped rather than sent in a
+    /// burst once the pause ends.
+    pub(super) fn gate<S>(self, stream: S) -> impl Stream<Item = (Instant, Option<Instant>)>
+    where
+        S: Stream<Item = (Instant, Option<Instant>)> + Unpin,
+    {
+        stream::unfold((self, stream), |(gate, mut stream)| async move {
+            loop {
+                let paused_until = gate.paused_until();
+                if let Some(until) = paused_until {
+                    Delay::new(until.saturating_duration_since(Instant::now())).await;
+                }
+                let tick = stream.next().await?;
+                match paused_until {
+                    Some(until) if tick.0 < until => continue,
+                    _ => return Some((tick, (gate, stream))),
+                }
+            }
+        })
+    }
+}
+
+// `Retry-After` is either a number of seconds or an http date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date: SystemTime = chrono::DateTime::parse_from_rfc2822(value).ok()?.into();
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_retry_after_works() {
+        assert_eq!(parse_retry_after("1"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let future = chrono::Utc::now() + chrono::Duration::seconds(30);
+        let delay = parse_retry_after(&future.to_rfc2822()).unwrap();
+        assert!(delay > Duration::from_secs(28), "delay: {:?}", delay);
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn only_backs_off_for_429_and_503() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "5".parse().unwrap());
+
+        let gate = RetryAfterGate::default();
+        gate.check_response(StatusCode::OK, &headers);
+        gate.check_response(StatusCode::INTERNAL_SERVER_ERROR, &headers);
+        assert!(gate.paused_until().is_none());
+
+        gate.check_response(StatusCode::SERVICE_UNAVAILABLE, &HeaderMap::new());
+        assert!(gate.paused_until().is_none());
+
+        gate.check_response(StatusCode::SERVICE_UNAVAILABLE, &headers);
+        assert!(gate.paused_until().is_some());
+    }
+}
@@ -1,14 +1,23 @@
 use crate::error::{RecoverableError, TestError};
-use crate::line_writer::{blocking_writer, MsgType};
+use crate::html_report::{HtmlReport, ReportPoint};
+use crate::line_writer::{blocking_writer, MsgType, Writers};
+use crate::listing::EndpointListing;
+use crate::metrics::{InFlight, MetricsServer};
+use crate::plan::LoadPlan;
 use crate::providers;
+use crate::stats_socket::StatsSocket;
+use crate::statsd::StatsdSink;
 use crate::TestEndReason;
-use crate::{RunConfig, RunOutputFormat};
+use crate::{RunConfig, RunOutputFormat, StatsFileFormat};
 
 use channel::ChannelStatsReader;
 use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDateTime, Utc};
 use ether::Either;
 use futures::{
-    channel::mpsc::{self as futures_channel, Sender as FCSender},
+    channel::{
+        mpsc::{self as futures_channel, Sender as FCSender},
+        oneshot,
+    },
     future::join_all,
     sink::SinkExt,
     stream, FutureExt, StreamExt,
@@ -26,12 +35,12 @@ use yansi::Paint;
 
 use std::{
     borrow::Cow,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Write,
     fs::File,
     future::Future,
     io, mem,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
     task::Poll,
     time::{SystemTime, UNIX_EPOCH},
@@ -140,6 +149,7 @@ impl TimeBucket {
         &self,
         tags: &BTreeMap<Tags, usize>,
         format: RunOutputFormat,
+        percentiles: &[f64],
         bucket_size: u64,
         remaining_seconds: Option<u64>,
     ) -> String {
@@ -166,6 +176,7 @@ impl TimeBucket {
                 let piece = bucket.create_print_summary(
                     tags,
                     format,
+                    percentiles,
                     self.time,
                     test_complete,
                     bucket_size,
@@ -186,29 +197,145 @@ impl TimeBucket {
         }
         print_string
     }
+
+    // The stats of each bucket group for the html report
+    fn report_points<'a>(
+        &self,
+        tags: &'a BTreeMap<Tags, usize>,
+        percentiles: &[f64],
+    ) -> Vec<(usize, &'a Tags, ReportPoint)> {
+        tags.iter()
+            .filter_map(|(tags, index)| {
+                let stats = self.entries.get(index)?;
+                Some((*index, tags, stats.report_point(percentiles)))
+            })
+            .collect()
+    }
+}
+
+/// Every endpoint's requests and response times for a whole run, added together. With `--repeat`
+/// the totals of each run are combined for the aggregate summary at the end.
+#[derive(Clone)]
+pub struct RunTotals(BucketGroupStats);
+
+impl RunTotals {
+    fn new(bucket: &TimeBucket) -> Self {
+        let mut all = BucketGroupStats::default();
+        for stats in bucket.entries.values() {
+            all.combine(stats);
+        }
+        RunTotals(all)
+    }
+
+    pub fn combine(&mut self, rhs: &Self) {
+        self.0.combine(&rhs.0);
+    }
+
+    /// The line summing up `runs` runs of the test, printed once the last of them is done
+    pub fn create_aggregate_summary(&self, runs: usize, format: RunOutputFormat) -> String {
+        self.create_summary(Some(runs), format)
+    }
+
+    // Create the single line summing up every endpoint, printed after the test summary so the
+    // totals for the whole run are in one place. The aggregate summary adds how many runs it covers
+    fn create_summary(&self, runs: Option<usize>, format: RunOutputFormat) -> String {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        let (total, ok) = self.0.request_counts();
+        let error_rate = if total == 0 {
+            0.0
+        } else {
+            (total - ok) as f64 / total as f64
+        };
+        let p50 = self.0.rtt_histogram.value_at_quantile(0.5) as f64 / MICROS_TO_MS;
+        let p99 = self.0.rtt_histogram.value_at_quantile(0.99) as f64 / MICROS_TO_MS;
+        match format {
+            RunOutputFormat::Human => {
+                let (label, runs) = match runs {
+                    Some(runs) => ("Aggregate Summary:", format!(" {runs} runs,")),
+                    None => ("Run Summary:", String::new()),
+                };
+                format!(
+                    "\n{}{} {} requests, {:.2}% errors, p50: {}ms, p99: {}ms\n",
+                    Paint::new(label).bold(),
+                    runs,
+                    total,
+                    error_rate * 100.0,
+                    p50,
+                    p99
+                )
+            }
+            RunOutputFormat::Json => {
+                let mut output = json::json!({
+                    "type": "runSummary",
+                    "total": total,
+                    "errors": total - ok,
+                    "errorRate": error_rate,
+                    "p50": p50,
+                    "p99": p99,
+                });
+                if let Some(runs) = runs {
+                    output["type"] = "aggregateSummary".into();
+                    output["runs"] = runs.into();
+                }
+                format!("{output}\n")
+            }
+        }
+    }
 }
 
 // The aggregate statistics that are tracked for each bucket group in a given interval (bucket size)
 #[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct BucketGroupStats {
+    // every timeout and test error, counted by `RecoverableError::category`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    error_categories: BTreeMap<String, u64>,
+    // responses to requests which opened a new connection, and to ones which reused a connection
+    #[serde(default, skip_serializing_if = "is_zero")]
+    new_connections: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    reused_connections: u64,
     #[serde(skip_serializing_if = "is_zero")]
     request_timeouts: u64,
+    // attempts which were sent again by the endpoint's `retries`, counted by the status of their
+    // response or the `RecoverableError::category` of their error. They aren't counted anywhere
+    // else, so the rest of the stats are for each request's last attempt
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    retries: BTreeMap<String, u64>,
+    // responses an endpoint's `is_success` counted as failures despite a status below 400, and as
+    // successes despite a status of 400 or above
+    #[serde(default, skip_serializing_if = "is_zero")]
+    responses_failed_by_is_success: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    responses_passed_by_is_success: u64,
     #[serde(with = "histogram_serde", skip_serializing_if = "Histogram::is_empty")]
     rtt_histogram: Histogram<u64>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     status_counts: BTreeMap<u16, u64>,
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     test_errors: BTreeMap<String, u64>,
+    // the phases of every response with a `timing` added together, and how many there were
+    #[serde(default, skip_serializing_if = "is_zero")]
+    timed_responses: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timing: Option<Timing>,
 }
 
 impl Default for BucketGroupStats {
     fn default() -> Self {
         Self {
+            error_categories: Default::default(),
+            new_connections: 0,
+            reused_connections: 0,
             request_timeouts: 0,
+            retries: Default::default(),
+            responses_failed_by_is_success: 0,
+            responses_passed_by_is_success: 0,
             rtt_histogram: Histogram::new(3).expect("could not create histogram"),
             status_counts: Default::default(),
             test_errors: Default::default(),
+            timed_responses: 0,
+            timing: None,
         }
     }
 }
@@ -216,6 +343,20 @@ impl Default for BucketGroupStats {
 impl BucketGroupStats {
     // Append new stats into the aggregates
     fn append(&mut self, stat: ResponseStat) {
+        if stat.retry {
+            let reason = match &stat.kind {
+                StatKind::RecoverableError(r) => r.category().into(),
+                StatKind::Response(status) => status.to_string(),
+            };
+            *self.retries.entry(reason).or_default() += 1;
+            return;
+        }
+        if let StatKind::RecoverableError(r) = &stat.kind {
+            *self
+                .error_categories
+                .entry(r.category().into())
+                .or_default() += 1;
+        }
         match stat.kind {
             StatKind::RecoverableError(RecoverableError::Timeout(..)) => self.request_timeouts += 1,
             StatKind::RecoverableError(r) => {
@@ -230,16 +371,40 @@ impl BucketGroupStats {
                     .entry(status)
                     .and_modify(|n| *n += 1)
                     .or_insert(1);
+                match (stat.success, status < 400) {
+                    (Some(false), true) => self.responses_failed_by_is_success += 1,
+                    (Some(true), false) => self.responses_passed_by_is_success += 1,
+                    _ => (),
+                }
             }
         }
+        match stat.connection_reused {
+            Some(true) => self.reused_connections += 1,
+            Some(false) => self.new_connections += 1,
+            None => (),
+        }
         if let Some(rtt) = stat.rtt {
             self.rtt_histogram += rtt;
         }
+        if let Some(timing) = &stat.timing {
+            self.timed_responses += 1;
+            self.timing.get_or_insert_with(Default::default).add(timing);
+        }
     }
 
     // Combine two `BucketGroupStats`
     fn combine(&mut self, rhs: &Self) {
+        for (category, count) in &rhs.error_categories {
+            *self.error_categories.entry(category.clone()).or_default() += count;
+        }
+        self.new_connections += rhs.new_connections;
+        self.reused_connections += rhs.reused_connections;
         self.request_timeouts += rhs.request_timeouts;
+        for (reason, count) in &rhs.retries {
+            *self.retries.entry(reason.clone()).or_default() += count;
+        }
+        self.responses_failed_by_is_success += rhs.responses_failed_by_is_success;
+        self.responses_passed_by_is_success += rhs.responses_passed_by_is_success;
         let _ = self.rtt_histogram.add(&rhs.rtt_histogram);
         for (status, count) in &rhs.status_counts {
             self.status_counts
@@ -253,6 +418,53 @@ impl BucketGroupStats {
                 .and_modify(|n| *n += count)
                 .or_insert(*count);
         }
+        self.timed_responses += rhs.timed_responses;
+        if let Some(timing) = &rhs.timing {
+            self.timing.get_or_insert_with(Default::default).add(timing);
+        }
+    }
+
+    // the number of requests made and how many of them got a response with a status below 400,
+    // or which the endpoint's `is_success` counted as a success
+    fn request_counts(&self) -> (u64, u64) {
+        let responses: u64 = self.status_counts.values().sum();
+        let test_errors: u64 = self.test_errors.values().sum();
+        let ok: u64 = self
+            .status_counts
+            .iter()
+            .filter(|(status, _)| **status < 400)
+            .map(|(_, count)| count)
+            .sum();
+        let ok = ok - self.responses_failed_by_is_success + self.responses_passed_by_is_success;
+        (responses + self.request_timeouts + test_errors, ok)
+    }
+
+    fn report_point(&self, percentiles: &[f64]) -> ReportPoint {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        let (requests, ok) = self.request_counts();
+        let percentiles = percentiles
+            .iter()
+            .map(|p| self.rtt_histogram.value_at_quantile(p / 100.0) as f64 / MICROS_TO_MS)
+            .collect();
+        ReportPoint {
+            requests,
+            errors: requests - ok,
+            percentiles,
+        }
+    }
+
+    // the requests which failed to connect, including failed TLS handshakes
+    fn connection_errors(&self) -> u64 {
+        ["connect", "tls"]
+            .iter()
+            .filter_map(|category| self.error_categories.get(*category))
+            .sum()
+    }
+
+    // the portion of responses which came back on a connection an earlier request already used
+    fn connection_reuse_ratio(&self) -> Option<f64> {
+        let responses = self.new_connections + self.reused_connections;
+        (responses > 0).then(|| self.reused_connections as f64 / responses as f64)
     }
 
     // create a string summary for this `BucketGroupStats`
@@ -260,6 +472,7 @@ impl BucketGroupStats {
         &self,
         tags: &Tags,
         format: RunOutputFormat,
+        percentiles: &[f64],
         time: u64,
         test_complete: bool,
         bucket_size: u64,
@@ -272,15 +485,18 @@ impl BucketGroupStats {
         const MICROS_TO_MS: f64 = 1_000.0;
         let method = tags.get("method").expect("tags missing `method`");
         let url = tags.get("url").expect("tags missing `url`");
-        let p50 = self.rtt_histogram.value_at_quantile(0.5) as f64 / MICROS_TO_MS;
-        let p90 = self.rtt_histogram.value_at_quantile(0.90) as f64 / MICROS_TO_MS;
-        let p95 = self.rtt_histogram.value_at_quantile(0.95) as f64 / MICROS_TO_MS;
-        let p99 = self.rtt_histogram.value_at_quantile(0.99) as f64 / MICROS_TO_MS;
-        let p99_9 = self.rtt_histogram.value_at_quantile(0.999) as f64 / MICROS_TO_MS;
+        let percentiles: Vec<_> = percentiles
+            .iter()
+            .map(|p| {
+                let rtt = self.rtt_histogram.value_at_quantile(p / 100.0) as f64 / MICROS_TO_MS;
+                (p, rtt)
+            })
+            .collect();
         let min = self.rtt_histogram.min() as f64 / MICROS_TO_MS;
         let max = self.rtt_histogram.max() as f64 / MICROS_TO_MS;
         let mean = self.rtt_histogram.mean().round() / MICROS_TO_MS;
         let stddev = self.rtt_histogram.stdev().round() / MICROS_TO_MS;
+        let connection_reuse_ratio = self.connection_reuse_ratio();
         match format {
             RunOutputFormat::Human => {
                 // human format
@@ -299,16 +515,48 @@ impl BucketGroupStats {
                     let piece = format!("  test errors: {:?}\n", self.test_errors);
                     print_string.push_str(&piece);
                 }
-                let piece = format!(
-                    "  p50: {p50}ms, p90: {p90}ms, p95: {p95}ms, p99: {p99}ms, p99.9: {p99_9}ms\n  \
-                     min: {min}ms, max: {max}ms, avg: {mean}ms, std. dev: {stddev}ms\n"
-                );
+                if !self.error_categories.is_empty() {
+                    let piece = format!("  errors by category: {:?}\n", self.error_categories);
+                    print_string.push_str(&piece);
+                }
+                if !self.retries.is_empty() {
+                    let piece = format!("  retried: {:?}\n", self.retries);
+                    print_string.push_str(&piece);
+                }
+                if self.responses_failed_by_is_success > 0 {
+                    let piece = format!(
+                        "  failed by is_success: {}\n",
+                        self.responses_failed_by_is_success
+                    );
+                    print_string.push_str(&piece);
+                }
+                if self.responses_passed_by_is_success > 0 {
+                    let piece = format!(
+                        "  passed by is_success: {}\n",
+                        self.responses_passed_by_is_success
+                    );
+                    print_string.push_str(&piece);
+                }
+                if let Some(ratio) = connection_reuse_ratio {
+                    let piece = format!("  connections reused: {:.2}%\n", ratio * 100.0);
+                    print_string.push_str(&piece);
+                }
+                if !percentiles.is_empty() {
+                    let piece = percentiles
+                        .iter()
+                        .map(|(p, rtt)| format!("p{p}: {rtt}ms"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    print_string.push_str(&format!("  {piece}\n"));
+                }
+                let piece =
+                    format!("  min: {min}ms, max: {max}ms, avg: {mean}ms, std. dev: {stddev}ms\n");
                 print_string.push_str(&piece);
             }
             RunOutputFormat::Json => {
                 // json format
                 let summary_type = if test_complete { "test" } else { "bucket" };
-                let output = json::json!({
+                let mut output = json::json!({
                     "type": "summary",
                     "startTime": time,
                     "timestamp": time + bucket_size,
@@ -328,19 +576,27 @@ impl BucketGroupStats {
                     "testErrorCount":
                         self.test_errors.iter()
                             .fold(0, |sum, (_, c)| sum + c),
-                    "p50": p50,
-                    "p90": p90,
-                    "p95": p95,
-                    "p99": p99,
-                    "p99_9": p99_9,
+                    "errorCategories": self.error_categories,
+                    "retries": self.retries,
+                    "failedByIsSuccess": self.responses_failed_by_is_success,
+                    "passedByIsSuccess": self.responses_passed_by_is_success,
                     "min": min,
                     "max": max,
                     "mean": mean,
                     "stddev": stddev,
+                    "connectionReuseRatio": connection_reuse_ratio,
                     "tags": tags.iter()
                         .filter(|(k, _)| k.as_str() != "method" && k.as_str() != "url")
                         .collect::<BTreeMap<_, _>>(),
                 });
+                // a percentile's key can't have a `.`, so `p99.9` is `p99_9`
+                for (p, rtt) in percentiles {
+                    output[format!("p{p}").replace('.', "_")] = rtt.into();
+                }
+                // the mean of each phase up to the response headers
+                if let Some(timing) = self.timing {
+                    output["timing"] = timing.to_json_ms(self.timed_responses);
+                }
                 let piece = format!("{output}\n");
                 print_string.push_str(&piece);
             }
@@ -349,6 +605,318 @@ impl BucketGroupStats {
     }
 }
 
+// The summary of a whole test written to the result file, along with whether it stayed within
+// the configured gates
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TestResult {
+    passed: bool,
+    end_reason: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // the providers which ran out, when that ended the test
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    providers_ended: Vec<String>,
+    total: u64,
+    ok: u64,
+    errors: u64,
+    error_rate: f64,
+    connection_errors: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    error_categories: BTreeMap<String, u64>,
+    endpoints: Vec<EndpointResult>,
+    gates: Vec<GateResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EndpointResult {
+    tags: Tags,
+    total: u64,
+    ok: u64,
+    errors: u64,
+    connection_errors: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    error_categories: BTreeMap<String, u64>,
+    p50: f64,
+    p90: f64,
+    p95: f64,
+    p99: f64,
+    p99_9: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GateResult {
+    gate: &'static str,
+    limit: f64,
+    actual: f64,
+    passed: bool,
+    // the tags of the endpoint the gate was checked against, for gates checked per endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoint: Option<Tags>,
+}
+
+impl GateResult {
+    // `id: METHOD url` for the endpoint, then how it broke its SLA
+    fn sla_breach(&self) -> String {
+        let tags = self.endpoint.as_ref();
+        let tag = |name| tags.and_then(|t| t.get(name)).map_or("", String::as_str);
+        let broke = match self.gate {
+            "sla_error_rate" => format!(
+                "error rate of {:.2}% is over {}%",
+                self.actual * 100.0,
+                self.limit * 100.0
+            ),
+            "sla_connection_error_rate" => format!(
+                "connection error rate of {:.2}% is over {}%",
+                self.actual * 100.0,
+                self.limit * 100.0
+            ),
+            _ => format!("p99 of {:.2}ms is over {}ms", self.actual, self.limit),
+        };
+        format!("{}: {} {} {broke}", tag("_id"), tag("method"), tag("url"))
+    }
+}
+
+// Check each endpoint with an `sla` against all of its responses. An endpoint's stats are spread
+// over a group for each different set of tags it had, so the groups are combined first
+fn sla_results(
+    totals: &TimeBucket,
+    tags: &BTreeMap<Tags, usize>,
+    slas: &BTreeMap<usize, config::Sla>,
+) -> Vec<GateResult> {
+    const MICROS_TO_MS: f64 = 1_000.0;
+    let mut endpoints: BTreeMap<usize, (&Tags, BucketGroupStats)> = BTreeMap::new();
+    for (tags, index) in tags {
+        let id = match tags.get("_id").and_then(|id| id.parse().ok()) {
+            Some(id) if slas.contains_key(&id) => id,
+            _ => continue,
+        };
+        if let Some(bucket) = totals.entries.get(index) {
+            endpoints
+                .entry(id)
+                .or_insert_with(|| (tags, BucketGroupStats::default()))
+                .1
+                .combine(bucket);
+        }
+    }
+    let mut results = Vec::new();
+    for (id, (tags, bucket)) in endpoints {
+        let sla = &slas[&id];
+        let (total, ok) = bucket.request_counts();
+        if let (Some(p99), false) = (sla.p99, bucket.rtt_histogram.is_empty()) {
+            let limit = p99.as_secs_f64() * 1_000.0;
+            let actual = bucket.rtt_histogram.value_at_quantile(0.99) as f64 / MICROS_TO_MS;
+            results.push(GateResult {
+                gate: "sla_p99",
+                limit,
+                actual,
+                passed: actual <= limit,
+                endpoint: Some(tags.clone()),
+            });
+        }
+        if let (Some(limit), true) = (sla.error_rate, total > 0) {
+            let actual = (total - ok) as f64 / total as f64;
+            results.push(GateResult {
+                gate: "sla_error_rate",
+                limit,
+                actual,
+                passed: actual <= limit,
+                endpoint: Some(tags.clone()),
+            });
+        }
+        if let (Some(limit), true) = (sla.connection_error_rate, total > 0) {
+            let actual = bucket.connection_errors() as f64 / total as f64;
+            results.push(GateResult {
+                gate: "sla_connection_error_rate",
+                limit,
+                actual,
+                passed: actual <= limit,
+                endpoint: Some(tags.clone()),
+            });
+        }
+    }
+    results
+}
+
+impl TestResult {
+    fn new(
+        totals: &TimeBucket,
+        tags: &BTreeMap<Tags, usize>,
+        gates: &config::Gates,
+        slas: &BTreeMap<usize, config::Sla>,
+        end_reason: &Result<TestEndReason, TestError>,
+    ) -> Self {
+        const MICROS_TO_MS: f64 = 1_000.0;
+        let mut endpoints = Vec::new();
+        let mut gate_results = Vec::new();
+        for (tags, index) in tags {
+            let bucket = match totals.entries.get(index) {
+                Some(b) => b,
+                None => continue,
+            };
+            let (total, ok) = bucket.request_counts();
+            let quantile = |q| bucket.rtt_histogram.value_at_quantile(q) as f64 / MICROS_TO_MS;
+            let p99 = quantile(0.99);
+            if let (Some(max_p99), false) = (gates.max_p99, bucket.rtt_histogram.is_empty()) {
+                let limit = max_p99.as_secs_f64() * 1_000.0;
+                gate_results.push(GateResult {
+                    gate: "max_p99",
+                    limit,
+                    actual: p99,
+                    passed: p99 <= limit,
+                    endpoint: Some(tags.clone()),
+                });
+            }
+            endpoints.push(EndpointResult {
+                tags: tags.clone(),
+                total,
+                ok,
+                errors: total - ok,
+                connection_errors: bucket.connection_errors(),
+                error_categories: bucket.error_categories.clone(),
+                p50: quantile(0.5),
+                p90: quantile(0.90),
+                p95: quantile(0.95),
+                p99,
+                p99_9: quantile(0.999),
+            });
+        }
+        let total: u64 = endpoints.iter().map(|e| e.total).sum();
+        let ok: u64 = endpoints.iter().map(|e| e.ok).sum();
+        let connection_errors: u64 = endpoints.iter().map(|e| e.connection_errors).sum();
+        let mut error_categories: BTreeMap<String, u64> = BTreeMap::new();
+        for endpoint in &endpoints {
+            for (category, count) in &endpoint.error_categories {
+                *error_categories.entry(category.clone()).or_default() += count;
+            }
+        }
+        let rate = |n: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                n as f64 / total as f64
+            }
+        };
+        let error_rate = rate(total - ok);
+        if let Some(limit) = gates.max_error_rate {
+            gate_results.push(GateResult {
+                gate: "max_error_rate",
+                limit,
+                actual: error_rate,
+                passed: error_rate <= limit,
+                endpoint: None,
+            });
+        }
+        if let Some(limit) = gates.max_connection_error_rate {
+            let actual = rate(connection_errors);
+            gate_results.push(GateResult {
+                gate: "max_connection_error_rate",
+                limit,
+                actual,
+                passed: actual <= limit,
+                endpoint: None,
+            });
+        }
+        gate_results.extend(sla_results(totals, tags, slas));
+        let providers_ended = match end_reason {
+            Ok(TestEndReason::ProviderEnded(providers)) => providers.clone(),
+            _ => Vec::new(),
+        };
+        let (end_reason, error) = match end_reason {
+            Ok(TestEndReason::Completed) => ("completed", None),
+            Ok(TestEndReason::CtrlC) => ("ctrlC", None),
+            Ok(TestEndReason::KilledByLogger) => ("killedByLogger", None),
+            Ok(TestEndReason::KilledByAssertion(_)) => ("killedByAssertion", None),
+            Ok(TestEndReason::MaxRuntime) => ("maxRuntime", None),
+            Ok(TestEndReason::ProviderEnded(_)) => ("providerEnded", None),
+            Ok(TestEndReason::ConfigUpdate(_)) => ("configUpdate", None),
+            Err(e) => ("error", Some(e.to_string())),
+        };
+        TestResult {
+            passed: error.is_none() && gate_results.iter().all(|g| g.passed),
+            end_reason,
+            error,
+            providers_ended,
+            total,
+            ok,
+            errors: total - ok,
+            error_rate,
+            connection_errors,
+            error_categories,
+            endpoints,
+            gates: gate_results,
+        }
+    }
+}
+
+// an endpoint making requests at less than this portion of its target rate is flagged
+const UNDER_TARGET_RATIO: f64 = 0.9;
+
+// The rate an endpoint was scheduled to make requests at over a bucket, from its load pattern,
+// and the rate it actually made them at
+struct EndpointRate<'a> {
+    id: usize,
+    method: &'a str,
+    url: &'a str,
+    target: f64,
+    achieved: f64,
+}
+
+impl<'a> EndpointRate<'a> {
+    // the rates for every endpoint driven by a load pattern. `started` is when the test started,
+    // in seconds since the unix epoch
+    fn for_bucket(
+        bucket: &TimeBucket,
+        tags: &BTreeMap<Tags, usize>,
+        plan: &'a LoadPlan,
+        started: f64,
+        bucket_size: u64,
+    ) -> Vec<Self> {
+        let mut requests: BTreeMap<usize, u64> = BTreeMap::new();
+        for (tags, index) in tags {
+            let id = match tags.get("_id").and_then(|id| id.parse().ok()) {
+                Some(id) => id,
+                None => continue,
+            };
+            if let Some(stats) = bucket.entries.get(index) {
+                *requests.entry(id).or_default() += stats.request_counts().0;
+            }
+        }
+        // the load pattern is sampled every tenth of a second to get the hits it scheduled during
+        // the bucket, leaving out any part of the bucket from before the test started
+        const SAMPLES_PER_SECOND: u64 = 10;
+        plan.endpoints()
+            .map(|(id, method, url)| {
+                let scheduled: f64 = (0..bucket_size * SAMPLES_PER_SECOND)
+                    .filter_map(|i| {
+                        let at = bucket.time as f64 + (i as f64 + 0.5) / SAMPLES_PER_SECOND as f64
+                            - started;
+                        if at < 0.0 {
+                            return None;
+                        }
+                        plan.endpoint_rate_at(id, Duration::from_secs_f64(at))
+                    })
+                    .sum::<f64>()
+                    / SAMPLES_PER_SECOND as f64;
+                let made = requests.get(&id).copied().unwrap_or_default();
+                EndpointRate {
+                    id,
+                    method,
+                    url,
+                    target: scheduled / bucket_size as f64,
+                    achieved: made as f64 / bucket_size as f64,
+                }
+            })
+            .collect()
+    }
+
+    fn under_target(&self) -> bool {
+        self.achieved < self.target * UNDER_TARGET_RATIO
+    }
+}
+
 // helper function used by serde
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn is_zero(n: &u64) -> bool {
@@ -361,14 +929,47 @@ struct Stats {
     current: TimeBucket,
     console: FCSender<MsgType>,
     duration: u64,
+    // every endpoint in the config, only with `--require-all-endpoints`
+    endpoints: Option<EndpointListing>,
     file: FCSender<MsgType>,
     format: RunOutputFormat,
+    gates: config::Gates,
+    // print a heartbeat line with every bucket, only used with json output
+    heartbeat: bool,
+    // where the stats are served for Prometheus, only with `--metrics-port`
+    metrics: Option<MetricsServer>,
+    // by endpoint id, the paced providers the endpoint takes values from
+    pacers: BTreeMap<String, Vec<providers::Pacer>>,
+    // `general.stats_percentiles`, the response time percentiles in each endpoint's summary
+    percentiles: Vec<f64>,
+    plan: Option<LoadPlan>,
     previous: Option<TimeBucket>,
+    // where the progress line goes with every bucket, only with `--progress` and human output
+    progress: Option<Progress>,
     providers: Vec<ChannelStatsReader<json::Value>>,
+    // built up with every bucket and written in place of the json stats, only with
+    // `--stats-file-format html`
+    report: Option<HtmlReport>,
+    result_file: Option<PathBuf>,
+    // by endpoint id, the SLAs of the endpoints which have one
+    slas: BTreeMap<usize, config::Sla>,
+    // where each bucket is sent as json, only with `--stats-socket`
+    socket: Option<StatsSocket>,
+    // when the test started, in seconds since the unix epoch
+    started: f64,
+    // where each response's stats are sent over UDP, only with `general.statsd`
+    statsd: Option<StatsdSink>,
     tags: BTreeMap<Tags, usize>,
     totals: TimeBucket,
 }
 
+// The line written to stderr with `--progress`, apart from the stats on stdout
+struct Progress {
+    stderr: FCSender<MsgType>,
+    // how many providers the config has, even when their stats aren't logged
+    providers: usize,
+}
+
 // round the current time to the nearest bucket
 fn rounded_epoch(bucket_size: u64) -> u64 {
     round_time(get_epoch(), bucket_size)
@@ -387,21 +988,38 @@ impl Stats {
         console: FCSender<MsgType>,
         providers: Vec<ChannelStatsReader<json::Value>>,
         test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+        writers: &Writers,
     ) -> Result<Self, io::Error> {
-        let (file, _) = blocking_writer(
+        let (file, done) = blocking_writer(
             File::create(file_name)?,
             test_killer,
             file_name.to_string_lossy().to_string(),
         );
+        // the file is closed once the final bucket is written and `Stats` is dropped
+        writers.wait_on_shutdown(done);
         Ok(Self {
             bucket_size,
             current: TimeBucket::new(rounded_epoch(bucket_size)),
             console,
             duration: 0,
+            endpoints: None,
             file,
             format,
+            gates: Default::default(),
+            heartbeat: false,
+            metrics: None,
+            pacers: BTreeMap::new(),
+            percentiles: Vec::new(),
+            plan: None,
             previous: None,
+            progress: None,
             providers,
+            report: None,
+            result_file: None,
+            slas: BTreeMap::new(),
+            socket: None,
+            started: 0.0,
+            statsd: None,
             tags: BTreeMap::new(),
             totals: TimeBucket::new(get_epoch()),
         })
@@ -440,6 +1058,14 @@ impl Stats {
 
     // append stats to the current bucket
     async fn append(&mut self, stat: ResponseStat) {
+        if let (Some(rtt), Some(pacers)) = (
+            stat.rtt,
+            stat.tags.get("_id").and_then(|id| self.pacers.get(id)),
+        ) {
+            for pacer in pacers {
+                pacer.record(rtt);
+            }
+        }
         let mut new_tag = None;
         // check that the tags from the incoming stat exist in our tags map, if not create a new
         // entry
@@ -455,6 +1081,12 @@ impl Stats {
                 i
             }
         };
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&stat);
+        }
+        if let Some(statsd) = &mut self.statsd {
+            statsd.send(&stat);
+        }
         self.current.append(stat, index);
         if let Some(new_tag) = new_tag {
             self.write_file_message(FileMessage::Tags(new_tag)).await;
@@ -464,9 +1096,14 @@ impl Stats {
     // Write to the stats file the given message
     // this fn returns an impl future instead of being async, so as not to capture a reference to `self`
     fn write_file_message(&self, msg: FileMessage) -> impl Future<Output = ()> {
-        let mut file = self.file.clone();
+        // the html report takes the place of the json stats
+        let file = self.report.is_none().then(|| self.file.clone());
 
         async move {
+            let mut file = match file {
+                Some(f) => f,
+                None => return,
+            };
             let msg = match serde_json::to_string(&msg) {
                 Ok(m) => m,
                 Err(_) => return,
@@ -476,6 +1113,149 @@ impl Stats {
         }
     }
 
+    // the endpoints which haven't had a single response or error
+    fn idle_endpoints(&self) -> Vec<String> {
+        let endpoints = match &self.endpoints {
+            Some(e) => e,
+            None => return Vec::new(),
+        };
+        let fired: BTreeSet<usize> = self
+            .tags
+            .keys()
+            .filter_map(|tags| tags.get("_id")?.parse().ok())
+            .collect();
+        endpoints.idle(|id| fired.contains(&id))
+    }
+
+    // the endpoints which broke their SLA over the whole test
+    fn sla_breaches(&self) -> Vec<String> {
+        if self.slas.is_empty() {
+            return Vec::new();
+        }
+        let mut totals = self.totals.clone();
+        totals.combine(&self.current);
+        sla_results(&totals, &self.tags, &self.slas)
+            .iter()
+            .filter(|r| !r.passed)
+            .map(GateResult::sla_breach)
+            .collect()
+    }
+
+    // Write the result file, if there is one. This needs to happen before the final bucket is
+    // closed out as that moves the totals into the test summary
+    async fn write_result(&self, end_reason: &Result<TestEndReason, TestError>) {
+        let file = match &self.result_file {
+            Some(f) => f,
+            None => return,
+        };
+        let mut totals = self.totals.clone();
+        totals.combine(&self.current);
+        let result = TestResult::new(&totals, &self.tags, &self.gates, &self.slas, end_reason);
+        let written = match json::to_vec_pretty(&result) {
+            Ok(bytes) => tokio::fs::write(file, bytes).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = written {
+            log::error!("could not write result file `{}`: {}", file.display(), e);
+        }
+    }
+
+    // Create the summary of each endpoint's target and achieved rates for a bucket
+    fn create_rate_summary(&self, bucket: &TimeBucket) -> String {
+        let plan = match &self.plan {
+            Some(p) => p,
+            None => return String::new(),
+        };
+        let rates =
+            EndpointRate::for_bucket(bucket, &self.tags, plan, self.started, self.bucket_size);
+        let is_human_format = self.format.is_human();
+        let mut string_to_print = if is_human_format && !rates.is_empty() {
+            format!("{}", Paint::new("\nEndpoint Rates\n").bold())
+        } else {
+            String::new()
+        };
+        for rate in rates {
+            let piece = if is_human_format {
+                let under_target = if rate.under_target() {
+                    format!("  {}\n", Paint::red("under target"))
+                } else {
+                    String::new()
+                };
+                format!(
+                    "\n- {}:\n  target rate: {:.2}hps\n  achieved rate: {:.2}hps\n{}",
+                    Paint::yellow(format!("{} {}", rate.method, rate.url)).dimmed(),
+                    rate.target,
+                    rate.achieved,
+                    under_target,
+                )
+            } else {
+                let output = json::json!({
+                    "type": "rate",
+                    "timestamp": bucket.time + self.bucket_size,
+                    "id": rate.id,
+                    "method": rate.method,
+                    "url": rate.url,
+                    "targetRate": rate.target,
+                    "achievedRate": rate.achieved,
+                    "underTarget": rate.under_target(),
+                });
+                format!("{output}\n")
+            };
+            string_to_print.push_str(&piece);
+        }
+        string_to_print
+    }
+
+    // Create the line printed with every bucket, whether or not anything happened during it, so
+    // whatever is reading json output can tell the test is still running
+    fn create_heartbeat(&self, time: u64) -> String {
+        if !self.heartbeat || self.format.is_human() {
+            return String::new();
+        }
+        format!("{}\n", json::json!({"type": "heartbeat", "ts": time}))
+    }
+
+    // Create the progress line: how far through the test's duration it is, the requests per second
+    // over the bucket, and how many endpoints have made requests so far
+    fn create_progress(
+        &self,
+        bucket: &TimeBucket,
+        remaining_seconds: u64,
+        providers: usize,
+    ) -> String {
+        const WIDTH: usize = 30;
+        let elapsed = self.duration.saturating_sub(remaining_seconds);
+        let portion = if self.duration == 0 {
+            1.0
+        } else {
+            elapsed as f64 / self.duration as f64
+        };
+        let filled = (portion * WIDTH as f64).round() as usize;
+        let requests: u64 = bucket
+            .entries
+            .values()
+            .map(|stats| stats.request_counts().0)
+            .sum();
+        let rps = requests as f64 / self.bucket_size as f64;
+        let endpoints: BTreeSet<_> = self
+            .tags
+            .keys()
+            .filter_map(|tags| tags.get("_id"))
+            .collect();
+        format!(
+            "{} [{}{}] {:.0}% {} of {}, {:.2} rps, {} endpoints active, {} providers\n",
+            Paint::new("Progress").bold(),
+            Paint::green("#".repeat(filled)),
+            "-".repeat(WIDTH - filled),
+            portion * 100.0,
+            short_duration(elapsed),
+            short_duration(self.duration),
+            rps,
+            endpoints.len(),
+            providers,
+        )
+    }
+
     // Create the provider stats summary
     fn create_provider_stats_summary(&self, time: u64) -> String {
         let is_human_format = self.format.is_human();
@@ -507,9 +1287,23 @@ impl Stats {
         string_to_print
     }
 
+    // Print a summary of the bucket still being filled, without closing it out. The bucket keeps
+    // collecting stats and is written to the stats file, in full, when it is closed out as usual
+    async fn flush(&mut self, remaining_seconds: u64) {
+        let print_string = self.current.create_print_summary(
+            &self.tags,
+            self.format,
+            &self.percentiles,
+            self.bucket_size,
+            Some(remaining_seconds),
+        );
+        let _ = self.console.send(MsgType::Other(print_string)).await;
+    }
+
     // Close out the bucket. This happens when the test has completed or when it's time for a new bucket
     // When a bucket is closed out stats are written to the console and to the stats file
-    async fn close_out_bucket(&mut self, remaining_seconds: Option<u64>) {
+    // Once the test has completed the totals for the whole run are returned
+    async fn close_out_bucket(&mut self, remaining_seconds: Option<u64>) -> Option<RunTotals> {
         let test_complete = remaining_seconds.is_none();
         let mut is_new_bucket = false;
         let time = rounded_epoch(self.bucket_size) - self.bucket_size;
@@ -520,31 +1314,78 @@ impl Stats {
         let mut print_string = if test_complete {
             String::new()
         } else {
-            self.create_provider_stats_summary(time)
+            let mut s = self.create_heartbeat(time + self.bucket_size);
+            s.push_str(&self.create_provider_stats_summary(time));
+            s.push_str(&self.create_rate_summary(&bucket));
+            s
         };
         let piece = bucket.create_print_summary(
             &self.tags,
             self.format,
+            &self.percentiles,
             self.bucket_size,
             remaining_seconds.or(Some(0)),
         );
         print_string.push_str(&piece);
+        if let Some(socket) = &self.socket {
+            // subscribers get json whatever the output format, with a heartbeat so they hear from
+            // every bucket
+            let mut lines = format!(
+                "{}\n",
+                json::json!({"type": "heartbeat", "ts": time + self.bucket_size})
+            );
+            lines.push_str(&bucket.create_print_summary(
+                &self.tags,
+                RunOutputFormat::Json,
+                &self.percentiles,
+                self.bucket_size,
+                remaining_seconds.or(Some(0)),
+            ));
+            socket.send(lines);
+        }
+        // the progress line stops once the test is complete, the summary takes its place
+        let progress = self
+            .progress
+            .as_ref()
+            .zip(remaining_seconds)
+            .map(|(p, remaining)| {
+                let line = self.create_progress(&bucket, remaining, p.providers);
+                (p.stderr.clone(), line)
+            });
 
+        if let Some(report) = &mut self.report {
+            let points = bucket.report_points(&self.tags, &self.percentiles);
+            report.add_bucket(bucket.time, points);
+        }
         let mut futures = Vec::new();
         if !is_new_bucket {
             let file_message = FileMessage::Buckets(bucket);
             futures.push(Either::B(self.write_file_message(file_message)))
         }
+        let mut run_totals = None;
         let msg = if test_complete {
             let blank = TimeBucket::new(0);
             let bucket = std::mem::replace(&mut self.totals, blank);
             let print_string2 = bucket.create_print_summary(
                 &self.tags,
                 self.format,
+                &self.percentiles,
                 self.duration,
                 remaining_seconds,
             );
             print_string.push_str(&print_string2);
+            if let Some(report) = &self.report {
+                let totals = bucket
+                    .entries
+                    .iter()
+                    .map(|(index, stats)| (*index, stats.report_point(&self.percentiles)))
+                    .collect();
+                let html = report.render(self.duration, &totals);
+                let _ = self.file.clone().send(MsgType::Other(html)).await;
+            }
+            let totals = RunTotals::new(&bucket);
+            print_string.push_str(&totals.create_summary(None, self.format));
+            run_totals = Some(totals);
             MsgType::Final(print_string)
         } else {
             MsgType::Other(print_string)
@@ -552,10 +1393,14 @@ impl Stats {
         let console_output = self.console.send(msg).map(|_| ());
         futures.push(Either::A(console_output));
         join_all(futures).await;
+        if let Some((mut stderr, line)) = progress {
+            let _ = stderr.send(MsgType::Other(line)).await;
+        }
+        run_totals
     }
 }
 
-type Tags = BTreeMap<String, String>;
+pub(crate) type Tags = BTreeMap<String, String>;
 
 // get the current time as a unix epoch
 fn get_epoch() -> u64 {
@@ -593,14 +1438,73 @@ pub enum StatsMessage {
     ResponseStat(ResponseStat),
     // sent at the beginning of the test
     Start(Duration),
+    // sent before `Start`, with the rates the endpoints are scheduled to make requests at and how
+    // far into the load pattern the test starts
+    Plan(LoadPlan, Duration),
+    // print a summary of the stats received so far in the current bucket, rather than waiting for
+    // the bucket to be closed out. As it comes through the same channel as `ResponseStat`s, every
+    // stat sent before it is included
+    Flush,
+    // sent at the beginning of the test with `--require-all-endpoints`, the endpoints which are
+    // checked for having sent a request when the test ends
+    Endpoints(EndpointListing),
+    // sent at the beginning of the test, the providers with `pace_by_latency` which each
+    // endpoint's response times go to
+    Pacers(BTreeMap<String, Vec<providers::Pacer>>),
+    // sent at the beginning of the test, by endpoint id the SLAs checked when the test ends
+    Slas(BTreeMap<usize, config::Sla>),
+    // sent at the beginning of the test, by endpoint id the counts of each endpoint's open
+    // requests
+    InFlight(BTreeMap<String, InFlight>),
 }
 
 #[derive(Debug)]
 pub struct ResponseStat {
+    // whether the request was sent on a connection an earlier request already used, when a
+    // response was received
+    pub connection_reused: Option<bool>,
     pub kind: StatKind,
     pub rtt: Option<u64>,
+    // whether this attempt failed in a way the endpoint's `retries` sent the request again for
+    pub retry: bool,
+    // what the endpoint's `is_success` made of a response, `None` leaving it to the status
+    pub success: Option<bool>,
     pub time: SystemTime,
     pub tags: Arc<Tags>,
+    // where the time up to the response's headers went, when a response was received
+    pub timing: Option<Timing>,
+}
+
+/// The phases up to a response's headers arriving, in microseconds. `dns`, `connect` and `tls`
+/// are 0 when the request was sent on a connection an earlier request opened, and `dns` is 0 when
+/// the url's host is an ip address
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Timing {
+    pub dns: u64,
+    pub connect: u64,
+    pub tls: u64,
+    // from the connection being ready to the response's headers arriving
+    pub ttfb: u64,
+}
+
+impl Timing {
+    fn add(&mut self, rhs: &Self) {
+        self.dns += rhs.dns;
+        self.connect += rhs.connect;
+        self.tls += rhs.tls;
+        self.ttfb += rhs.ttfb;
+    }
+
+    /// Each phase in milliseconds, after dividing it by `n`
+    pub fn to_json_ms(self, n: u64) -> json::Value {
+        let ms = |micros: u64| micros as f64 / n.max(1) as f64 / 1000.0;
+        json::json!({
+            "dns": ms(self.dns),
+            "connect": ms(self.connect),
+            "tls": ms(self.tls),
+            "ttfb": ms(self.ttfb),
+        })
+    }
 }
 
 // A `ResponseStat` is sent when a `RecoverableError` happens, or when an HTTP response is
@@ -677,6 +1581,22 @@ fn duration_to_pretty_long_form(duration: Duration) -> String {
     format!("in approximately {long_time}")
 }
 
+// a duration as short as it can be written, like `1h5m` or `30s`
+fn short_duration(secs: u64) -> String {
+    let (hours, minutes, secs) = (secs / 3_600, secs / 60 % 60, secs % 60);
+    let mut s = String::new();
+    if hours > 0 {
+        let _ = write!(s, "{hours}h");
+    }
+    if minutes > 0 {
+        let _ = write!(s, "{minutes}m");
+    }
+    if secs > 0 || s.is_empty() {
+        let _ = write!(s, "{secs}s");
+    }
+    s
+}
+
 // create the stats channel for a try run
 pub fn create_try_run_stats_channel(
     mut test_complete: BroadcastStream<Result<TestEndReason, TestError>>,
@@ -727,13 +1647,26 @@ pub fn create_try_run_stats_channel(
     tx
 }
 
+/// What the stats channel knows about a test once it has ended.
+pub struct StatsDone {
+    /// The endpoints which never had a response or error
+    pub idle_endpoints: Vec<String>,
+    /// How each endpoint which broke its SLA broke it
+    pub sla_breaches: Vec<String>,
+    pub totals: RunTotals,
+}
+
 // create the stats channel for a full test
+#[allow(clippy::too_many_arguments)]
 pub fn create_stats_channel(
     test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
     config: &config::GeneralConfig,
     providers: &BTreeMap<String, providers::Provider>,
     mut console: FCSender<MsgType>,
+    stderr: FCSender<MsgType>,
     run_config: &RunConfig,
+    writers: &Writers,
+    stats_done_tx: oneshot::Sender<StatsDone>,
 ) -> Result<futures_channel::UnboundedSender<StatsMessage>, TestError> {
     let (tx, mut rx) = futures_channel::unbounded::<StatsMessage>();
     let now = Instant::now();
@@ -751,6 +1684,7 @@ pub fn create_stats_channel(
     let file_path = run_config.stats_file.clone();
     let output_format = run_config.output_format;
 
+    let provider_count = providers.len();
     let log_provider_stats = config.log_provider_stats;
     let providers: Vec<_> = if log_provider_stats {
         providers
@@ -770,10 +1704,45 @@ pub fn create_stats_channel(
         console.clone(),
         providers,
         test_killer,
+        writers,
     )
     .map_err(|e| {
         TestError::CannotCreateStatsFile(file_path.to_string_lossy().into_owned(), e.into())
     })?;
+    stats.gates = config.gates.clone();
+    stats.percentiles = config.stats_percentiles.clone();
+    stats.result_file = run_config.result_file.clone();
+    if let StatsFileFormat::Html = run_config.stats_file_format {
+        let percentiles = config.stats_percentiles.clone();
+        stats.report = Some(HtmlReport::new(
+            test_name.clone(),
+            bucket_size_secs,
+            percentiles,
+        ));
+    }
+    stats.heartbeat = run_config.heartbeat;
+    if let Some(addr) = &run_config.stats_socket {
+        let socket = StatsSocket::bind(addr)
+            .map_err(|e| TestError::CannotCreateStatsSocket(addr.to_string(), e.into()))?;
+        stats.socket = Some(socket);
+    }
+    if let Some(port) = run_config.metrics_port {
+        let metrics = MetricsServer::bind(port)
+            .map_err(|e| TestError::CannotCreateMetricsServer(port, e.into()))?;
+        stats.metrics = Some(metrics);
+    }
+    if let Some(statsd) = &config.statsd {
+        let sink = StatsdSink::connect(statsd).map_err(|e| {
+            TestError::CannotConnectToStatsd(format!("{}:{}", statsd.host, statsd.port), e.into())
+        })?;
+        stats.statsd = Some(sink);
+    }
+    if run_config.progress && output_format.is_human() {
+        stats.progress = Some(Progress {
+            stderr,
+            providers: provider_count,
+        });
+    }
 
     let mut test_start_time: Option<Instant> = None;
 
@@ -784,7 +1753,7 @@ pub fn create_stats_channel(
         // create a stream which combines getting incoming messages, printing stats on an interval
         // and checking if the test has ended
         enum StreamItem {
-            TestComplete,
+            TestComplete(Result<TestEndReason, TestError>),
             NewBucket,
             StatsMessage(StatsMessage),
             UpdateProviders(Vec<ChannelStatsReader<json::Value>>),
@@ -821,14 +1790,33 @@ pub fn create_stats_channel(
                     }
                 }
                 // test is complete
-                Poll::Ready(_) => Poll::Ready(Some(StreamItem::TestComplete)),
+                Poll::Ready(Some(Ok(end_reason))) => {
+                    Poll::Ready(Some(StreamItem::TestComplete(end_reason)))
+                }
+                Poll::Ready(_) => {
+                    Poll::Ready(Some(StreamItem::TestComplete(Ok(TestEndReason::Completed))))
+                }
             }
         });
 
         while let Some(datum) = stream.next().await {
             match datum {
-                StreamItem::TestComplete => {
-                    stats.close_out_bucket(None).await;
+                StreamItem::TestComplete(end_reason) => {
+                    stats.write_result(&end_reason).await;
+                    let idle_endpoints = stats.idle_endpoints();
+                    let sla_breaches = stats.sla_breaches();
+                    let totals = stats.close_out_bucket(None).await;
+                    // the port is free by the time the test is done, for the next run to use
+                    if let Some(metrics) = stats.metrics.take() {
+                        metrics.shutdown().await;
+                    }
+                    if let Some(totals) = totals {
+                        let _ = stats_done_tx.send(StatsDone {
+                            idle_endpoints,
+                            sla_breaches,
+                            totals,
+                        });
+                    }
                     break;
                 }
                 StreamItem::NewBucket => {
@@ -836,9 +1824,28 @@ pub fn create_stats_channel(
                         test_start_time.map(|start| stats.duration - start.elapsed().as_secs());
                     stats.close_out_bucket(test_end_time).await;
                 }
+                StreamItem::StatsMessage(StatsMessage::Flush) => {
+                    let test_end_time = test_start_time
+                        .map(|start| stats.duration.saturating_sub(start.elapsed().as_secs()));
+                    stats.flush(test_end_time.unwrap_or_default()).await;
+                }
                 StreamItem::UpdateProviders(providers) => {
                     stats.providers = providers;
                 }
+                StreamItem::StatsMessage(StatsMessage::Endpoints(endpoints)) => {
+                    stats.endpoints = Some(endpoints);
+                }
+                StreamItem::StatsMessage(StatsMessage::Pacers(pacers)) => {
+                    stats.pacers = pacers;
+                }
+                StreamItem::StatsMessage(StatsMessage::Slas(slas)) => {
+                    stats.slas = slas;
+                }
+                StreamItem::StatsMessage(StatsMessage::InFlight(in_flight)) => {
+                    if let Some(metrics) = &stats.metrics {
+                        metrics.set_in_flight(in_flight);
+                    }
+                }
                 StreamItem::StatsMessage(StatsMessage::Start(d)) => {
                     let mut futures = Vec::new();
                     let (start_time, msg) = if let Some(start_time) = test_start_time {
@@ -888,6 +1895,14 @@ pub fn create_stats_channel(
                     join_all(futures).await;
                 }
                 StreamItem::StatsMessage(StatsMessage::ResponseStat(rs)) => stats.append(rs).await,
+                StreamItem::StatsMessage(StatsMessage::Plan(plan, start_at)) => {
+                    // a reloaded config keeps the original start of the test
+                    if stats.plan.is_none() {
+                        let now = UNIX_EPOCH.elapsed().unwrap_or_default();
+                        stats.started = now.saturating_sub(start_at).as_secs_f64();
+                    }
+                    stats.plan = Some(plan);
+                }
             }
         }
     };
@@ -897,3 +1912,461 @@ pub fn create_stats_channel(
 
     Ok(tx)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn errors_are_counted_by_category() {
+        let io_error = |kind| -> Arc<dyn std::error::Error + Send + Sync> {
+            Arc::new(io::Error::new(kind, "injected"))
+        };
+        let errors = vec![
+            RecoverableError::Timeout(SystemTime::now()),
+            RecoverableError::Timeout(SystemTime::now()),
+            RecoverableError::Timeout(SystemTime::now()),
+            RecoverableError::ConnectionErr(
+                SystemTime::now(),
+                io_error(io::ErrorKind::ConnectionRefused),
+            ),
+            RecoverableError::ConnectionErr(
+                SystemTime::now(),
+                io_error(io::ErrorKind::ConnectionReset),
+            ),
+            RecoverableError::BodyErr(io_error(io::ErrorKind::InvalidData)),
+        ];
+        let mut bucket = TimeBucket::new(0);
+        for kind in errors
+            .into_iter()
+            .map(StatKind::RecoverableError)
+            .chain((0..14).map(|_| StatKind::Response(200)))
+        {
+            let stat = ResponseStat {
+                connection_reused: None,
+                kind,
+                rtt: Some(1),
+                retry: false,
+                success: None,
+                time: SystemTime::now(),
+                tags: Default::default(),
+                timing: None,
+            };
+            bucket.append(stat, 0);
+        }
+        let stats = &bucket.entries[&0];
+        let expected: BTreeMap<_, _> = [("body", 1), ("connect", 2), ("timeout", 3)]
+            .iter()
+            .map(|(category, count)| (category.to_string(), *count))
+            .collect();
+        assert_eq!(stats.error_categories, expected);
+        assert_eq!(stats.request_timeouts, 3);
+        assert_eq!(stats.connection_errors(), 2);
+
+        let mut t = Tags::new();
+        t.insert("_id".into(), "0".into());
+        let tags = BTreeMap::from([(t, 0)]);
+        let gates = config::Gates {
+            max_connection_error_rate: Some(0.05),
+            max_error_rate: Some(0.5),
+            ..Default::default()
+        };
+        let slas = BTreeMap::from([(
+            0,
+            config::Sla {
+                connection_error_rate: Some(0.15),
+                ..Default::default()
+            },
+        )]);
+        let result = TestResult::new(&bucket, &tags, &gates, &slas, &Ok(TestEndReason::Completed));
+        assert_eq!(result.total, 20);
+        assert_eq!(result.connection_errors, 2);
+        let gates: Vec<_> = result
+            .gates
+            .iter()
+            .map(|g| (g.gate, g.actual, g.passed))
+            .collect();
+        assert_eq!(
+            gates,
+            [
+                ("max_error_rate", 0.3, true),
+                ("max_connection_error_rate", 0.1, false),
+                ("sla_connection_error_rate", 0.1, true),
+            ]
+        );
+        assert!(!result.passed);
+    }
+
+    #[test]
+    fn is_success_overrides_the_status_in_request_counts() {
+        let mut bucket = TimeBucket::new(0);
+        let responses = [
+            (200, None),
+            (200, Some(true)),
+            (200, Some(false)),
+            (200, Some(false)),
+            (500, Some(true)),
+            (500, None),
+        ];
+        for (status, success) in responses {
+            let stat = ResponseStat {
+                connection_reused: None,
+                kind: StatKind::Response(status),
+                rtt: Some(1),
+                retry: false,
+                success,
+                time: SystemTime::now(),
+                tags: Default::default(),
+                timing: None,
+            };
+            bucket.append(stat, 0);
+        }
+        let stats = &bucket.entries[&0];
+        assert_eq!(stats.responses_failed_by_is_success, 2);
+        assert_eq!(stats.responses_passed_by_is_success, 1);
+        assert_eq!(stats.request_counts(), (6, 3));
+
+        let gates = config::Gates {
+            max_error_rate: Some(0.4),
+            ..Default::default()
+        };
+        let tags = BTreeMap::from([(Tags::new(), 0)]);
+        let result = TestResult::new(
+            &bucket,
+            &tags,
+            &gates,
+            &BTreeMap::new(),
+            &Ok(TestEndReason::Completed),
+        );
+        assert_eq!(result.gates[0].actual, 0.5);
+        assert!(!result.passed);
+    }
+
+    #[test]
+    fn summaries_have_the_configured_percentiles() {
+        let mut bucket = TimeBucket::new(0);
+        // 10us to 1000us, which the histogram records exactly
+        for rtt in (1..=100).map(|n| n * 10) {
+            let stat = ResponseStat {
+                connection_reused: None,
+                kind: StatKind::Response(200),
+                rtt: Some(rtt),
+                retry: false,
+                success: None,
+                time: SystemTime::now(),
+                tags: Default::default(),
+                timing: None,
+            };
+            bucket.append(stat, 0);
+        }
+        let stats = &bucket.entries[&0];
+        let mut tags = Tags::new();
+        tags.insert("method".into(), "GET".into());
+        tags.insert("url".into(), "http://localhost/".into());
+        let percentiles = [50.0, 90.0, 99.9];
+
+        let human =
+            stats.create_print_summary(&tags, RunOutputFormat::Human, &percentiles, 0, true, 60);
+        assert!(
+            human.contains("\n  p50: 0.5ms, p90: 0.9ms, p99.9: 1ms\n"),
+            "{}",
+            human
+        );
+
+        let summary =
+            stats.create_print_summary(&tags, RunOutputFormat::Json, &percentiles, 0, true, 60);
+        let summary: json::Value = json::from_str(&summary).unwrap();
+        assert_eq!(summary["p50"], 0.5);
+        assert_eq!(summary["p90"], 0.9);
+        assert_eq!(summary["p99_9"], 1.0);
+        assert!(summary.get("p99").is_none());
+
+        // without any percentiles there's no line for them
+        let human = stats.create_print_summary(&tags, RunOutputFormat::Human, &[], 0, true, 60);
+        assert!(!human.contains("p50"), "{}", human);
+        assert!(human.contains("\n  min: 0.01ms, max: 1ms"), "{}", human);
+    }
+
+    #[test]
+    fn throttled_endpoint_is_under_target() {
+        let config = config::LoadTest::from_config(
+            br#"
+            load_pattern:
+              - linear:
+                  from: 100%
+                  to: 100%
+                  over: 1m
+            endpoints:
+              - url: http://localhost/foo
+                peak_load: 10hps
+              - url: http://localhost/bar
+                peak_load: 10hps
+            "#,
+            Path::new("./"),
+            &Default::default(),
+        )
+        .unwrap();
+        let plan = LoadPlan::new(&config);
+
+        let mut tags = BTreeMap::new();
+        let mut bucket = TimeBucket::new(1_010);
+        for (id, responses) in [(0, 100), (1, 30)] {
+            let mut t = Tags::new();
+            t.insert("_id".into(), id.to_string());
+            tags.insert(t, id);
+            for _ in 0..responses {
+                let stat = ResponseStat {
+                    connection_reused: None,
+                    kind: StatKind::Response(200),
+                    rtt: Some(1),
+                    retry: false,
+                    success: None,
+                    time: SystemTime::now(),
+                    tags: Default::default(),
+                    timing: None,
+                };
+                bucket.append(stat, id);
+            }
+        }
+
+        let rates = EndpointRate::for_bucket(&bucket, &tags, &plan, 1_000.0, 10);
+        assert_eq!(rates.len(), 2);
+        assert!((rates[0].target - 10.0).abs() < 1e-9, "{}", rates[0].target);
+        assert!((rates[0].achieved - 10.0).abs() < 1e-9);
+        assert!(!rates[0].under_target());
+        assert!((rates[1].target - 10.0).abs() < 1e-9, "{}", rates[1].target);
+        assert!((rates[1].achieved - 3.0).abs() < 1e-9);
+        assert!(rates[1].under_target());
+    }
+
+    #[tokio::test]
+    async fn heartbeat_in_an_idle_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let (test_killer, _) = broadcast::channel(1);
+        let (console, mut console_rx) = futures_channel::channel(5);
+        let mut stats = Stats::new(
+            &dir.path().join("stats.json"),
+            60,
+            RunOutputFormat::Json,
+            console,
+            Vec::new(),
+            test_killer,
+            &Writers::default(),
+        )
+        .unwrap();
+
+        stats.close_out_bucket(Some(60)).await;
+        match console_rx.next().await {
+            Some(MsgType::Other(s)) => assert_eq!(s, ""),
+            _ => panic!("expected an empty bucket"),
+        }
+
+        stats.heartbeat = true;
+        stats.close_out_bucket(Some(0)).await;
+        let output = match console_rx.next().await {
+            Some(MsgType::Other(s)) => s,
+            _ => panic!("expected a heartbeat"),
+        };
+        let lines: Vec<json::Value> = output.lines().map(|l| json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 1, "{}", output);
+        assert_eq!(lines[0]["type"], "heartbeat");
+        let ts = lines[0]["ts"].as_u64().unwrap();
+        assert_eq!(ts % 60, 0);
+        assert!(ts.abs_diff(get_epoch()) <= 60, "{}", ts);
+    }
+
+    #[tokio::test]
+    async fn flush_summarizes_the_current_bucket() {
+        let dir = tempfile::tempdir().unwrap();
+        let (test_killer, _) = broadcast::channel(1);
+        let (console, mut console_rx) = futures_channel::channel(5);
+        let mut stats = Stats::new(
+            &dir.path().join("stats.json"),
+            3_600,
+            RunOutputFormat::Json,
+            console,
+            Vec::new(),
+            test_killer,
+            &Writers::default(),
+        )
+        .unwrap();
+
+        let mut tags = Tags::new();
+        tags.insert("method".into(), "GET".into());
+        tags.insert("url".into(), "http://localhost/".into());
+        let tags = Arc::new(tags);
+        for status in [200, 200, 500] {
+            let stat = ResponseStat {
+                connection_reused: None,
+                kind: StatKind::Response(status),
+                rtt: Some(1_000),
+                retry: false,
+                success: None,
+                time: SystemTime::now(),
+                tags: tags.clone(),
+                timing: None,
+            };
+            stats.append(stat).await;
+        }
+        stats.flush(60).await;
+
+        let summary = match console_rx.next().await {
+            Some(MsgType::Other(s)) => s,
+            _ => panic!("expected a bucket summary"),
+        };
+        let summary: json::Value = json::from_str(summary.trim()).unwrap();
+        assert_eq!(summary["callCount"], 3);
+        assert_eq!(summary["statusCounts"][0]["status"], 200);
+        assert_eq!(summary["statusCounts"][0]["count"], 2);
+
+        // the bucket is still open, so later stats land in the same bucket
+        assert_eq!(stats.current.entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn progress_with_every_bucket_until_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let (test_killer, _) = broadcast::channel(1);
+        let (console, _console_rx) = futures_channel::channel(5);
+        let (stderr, mut stderr_rx) = futures_channel::channel(5);
+        let mut stats = Stats::new(
+            &dir.path().join("stats.json"),
+            60,
+            RunOutputFormat::Human,
+            console,
+            Vec::new(),
+            test_killer,
+            &Writers::default(),
+        )
+        .unwrap();
+        stats.duration = 180;
+        stats.progress = Some(Progress {
+            stderr,
+            providers: 2,
+        });
+
+        let mut tags = Tags::new();
+        tags.insert("_id".into(), "0".into());
+        tags.insert("method".into(), "GET".into());
+        tags.insert("url".into(), "http://localhost/".into());
+        let tags = Arc::new(tags);
+        for _ in 0..30 {
+            let stat = ResponseStat {
+                connection_reused: None,
+                kind: StatKind::Response(200),
+                rtt: Some(1_000),
+                retry: false,
+                success: None,
+                time: SystemTime::now(),
+                tags: tags.clone(),
+                timing: None,
+            };
+            stats.append(stat).await;
+        }
+
+        for (remaining, done) in [(120, "33%"), (60, "67%")] {
+            stats.close_out_bucket(Some(remaining)).await;
+            let line = match stderr_rx.try_next() {
+                Ok(Some(MsgType::Other(s))) => s,
+                _ => panic!("expected a progress line"),
+            };
+            assert!(line.contains(done), "{}", line);
+            assert!(line.contains("1 endpoints active, 2 providers"), "{}", line);
+            assert!(
+                stderr_rx.try_next().is_err(),
+                "expected one line per bucket"
+            );
+        }
+
+        stats.close_out_bucket(None).await;
+        assert!(
+            stderr_rx.try_next().is_err(),
+            "expected no progress once complete"
+        );
+    }
+
+    #[tokio::test]
+    async fn html_report_in_place_of_the_stats_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("stats.html");
+        let writers = Writers::default();
+        let (test_killer, _) = broadcast::channel(1);
+        let (console, _console_rx) = futures_channel::channel(5);
+        let mut stats = Stats::new(
+            &file,
+            60,
+            RunOutputFormat::Human,
+            console,
+            Vec::new(),
+            test_killer,
+            &writers,
+        )
+        .unwrap();
+        stats.duration = 120;
+        stats.percentiles = vec![50.0, 99.0];
+        stats.report = Some(HtmlReport::new("<load>".into(), 60, vec![50.0, 99.0]));
+
+        for (id, url) in [("0", "http://localhost/a"), ("1", "http://localhost/b?c&d")] {
+            let mut tags = Tags::new();
+            tags.insert("_id".into(), id.into());
+            tags.insert("method".into(), "GET".into());
+            tags.insert("url".into(), url.into());
+            let tags = Arc::new(tags);
+            for status in [200, 200, 500] {
+                let stat = ResponseStat {
+                    connection_reused: None,
+                    kind: StatKind::Response(status),
+                    rtt: Some(2_000),
+                    retry: false,
+                    success: None,
+                    time: SystemTime::now(),
+                    tags: tags.clone(),
+                    timing: None,
+                };
+                stats.append(stat).await;
+            }
+        }
+        stats.close_out_bucket(Some(60)).await;
+        stats.close_out_bucket(None).await;
+        drop(stats);
+        writers.shutdown().await;
+
+        let html = std::fs::read_to_string(&file).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"), "{}", html);
+        assert!(html.trim_end().ends_with("</html>"), "{}", html);
+        // the json stats aren't written along with it
+        assert!(!html.contains("\"bucketSize\""), "{}", html);
+        assert!(html.contains("<title>pewpew results for &lt;load&gt;</title>"));
+        assert!(html.contains("GET http://localhost/a"), "{}", html);
+        assert!(html.contains("GET http://localhost/b?c&amp;d"), "{}", html);
+        assert!(
+            html.contains("<th>p50 (ms)</th><th>p99 (ms)</th>"),
+            "{}",
+            html
+        );
+        assert_eq!(html.matches("<svg ").count(), 3, "{}", html);
+        // nothing is fetched when the report is opened
+        assert!(!html.contains("src="), "{}", html);
+        assert!(!html.contains("href="), "{}", html);
+        // every tag which is opened is closed
+        for tag in ["html", "head", "body", "table", "svg", "ul", "h2", "h3"] {
+            let opened = html.matches(&format!("<{tag}>")).count()
+                + html.matches(&format!("<{tag} ")).count();
+            assert_eq!(
+                opened,
+                html.matches(&format!("</{tag}>")).count(),
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn short_durations() {
+        assert_eq!(short_duration(0), "0s");
+        assert_eq!(short_duration(59), "59s");
+        assert_eq!(short_duration(60), "1m");
+        assert_eq!(short_duration(3_725), "1h2m5s");
+    }
+}
@@ -0,0 +1,151 @@
+use futures::{channel::oneshot, future, FutureExt};
+use log::debug;
+use serde::Serialize;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::broadcast,
+};
+
+use std::{io, net::SocketAddr, path::PathBuf, str::FromStr, sync::Arc};
+
+// how many buckets of stats a slow subscriber can fall behind by before it misses some
+const BACKLOG: usize = 16;
+
+/// Where `--stats-socket` serves the stats, a `host:port` for TCP or otherwise the path of a Unix
+/// socket
+#[derive(Clone, Debug, Serialize)]
+pub enum StatsSocketAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for StatsSocketAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse() {
+            return Ok(StatsSocketAddr::Tcp(addr));
+        }
+        #[cfg(unix)]
+        {
+            Ok(StatsSocketAddr::Unix(s.into()))
+        }
+        #[cfg(not(unix))]
+        {
+            Err(format!("`{s}` should be in the format host:port"))
+        }
+    }
+}
+
+impl std::fmt::Display for StatsSocketAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsSocketAddr::Tcp(addr) => addr.fmt(f),
+            #[cfg(unix)]
+            StatsSocketAddr::Unix(path) => path.display().fmt(f),
+        }
+    }
+}
+
+/// The listening socket for `--stats-socket`. Every connected subscriber gets each line sent
+/// through it. Sending never waits on a subscriber, one which falls too far behind skips the lines
+/// it missed. The socket stops listening once this is dropped.
+pub struct StatsSocket {
+    tx: broadcast::Sender<Arc<str>>,
+    // dropped along with the socket, which ends the task accepting connections
+    _stop: oneshot::Sender<()>,
+    #[cfg(unix)]
+    unix_path: Option<PathBuf>,
+}
+
+impl StatsSocket {
+    /// Start listening on the address. Must be called from within the runtime.
+    pub fn bind(addr: &StatsSocketAddr) -> Result<Self, io::Error> {
+        let (tx, _) = broadcast::channel(BACKLOG);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        // the accept task's sender is dropped with it, so subscribers see the end of the stats
+        // once this is dropped
+        let tx2 = tx.clone();
+        match addr {
+            StatsSocketAddr::Tcp(addr) => {
+                let listener = std::net::TcpListener::bind(addr)?;
+                listener.set_nonblocking(true)?;
+                let listener = TcpListener::from_std(listener)?;
+                let accept = async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => subscribe(&tx2, stream),
+                            Err(e) => debug!("stats socket accept error: {e}"),
+                        }
+                    }
+                };
+                tokio::spawn(future::select(accept.boxed(), stop_rx));
+            }
+            #[cfg(unix)]
+            StatsSocketAddr::Unix(path) => {
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                listener.set_nonblocking(true)?;
+                let listener = tokio::net::UnixListener::from_std(listener)?;
+                let accept = async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => subscribe(&tx2, stream),
+                            Err(e) => debug!("stats socket accept error: {e}"),
+                        }
+                    }
+                };
+                tokio::spawn(future::select(accept.boxed(), stop_rx));
+            }
+        }
+        Ok(StatsSocket {
+            tx,
+            _stop: stop_tx,
+            #[cfg(unix)]
+            unix_path: match addr {
+                StatsSocketAddr::Unix(path) => Some(path.clone()),
+                _ => None,
+            },
+        })
+    }
+
+    /// Send the lines to every connected subscriber
+    pub fn send(&self, lines: String) {
+        // an error only means there's no one connected
+        let _ = self.tx.send(lines.into());
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StatsSocket {
+    fn drop(&mut self) {
+        // the socket file is left behind when the listener closes, which would stop the next run
+        // from binding to the same path
+        if let Some(path) = &self.unix_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// write everything sent through the socket to a newly connected subscriber, until either it
+// disconnects or the socket is dropped
+fn subscribe<W>(tx: &broadcast::Sender<Arc<str>>, mut stream: W)
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let mut rx = tx.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let lines = match rx.recv().await {
+                Ok(lines) => lines,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if stream.write_all(lines.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+        let _ = stream.shutdown().await;
+    });
+}
@@ -0,0 +1,207 @@
+use crate::stats::{ResponseStat, StatKind, Tags};
+
+use config::StatsdConfig;
+use log::warn;
+
+use std::{
+    fmt::Write,
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+};
+
+/// Sends the stats of each response to `general.statsd` over UDP in the DogStatsD format, along
+/// with the stats file. Sending never waits, and a packet which can't be sent is dropped.
+pub(crate) struct StatsdSink {
+    socket: UdpSocket,
+    prefix: String,
+    // `general.statsd.tags`, already formatted
+    tags: Vec<String>,
+    // so only the first failed send is logged
+    failed: bool,
+}
+
+impl StatsdSink {
+    pub(crate) fn connect(config: &StatsdConfig) -> Result<Self, io::Error> {
+        let addr = (config.host.as_str(), config.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no addresses"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        let tags = config
+            .tags
+            .iter()
+            .map(|(name, value)| tag(name, value))
+            .collect();
+        Ok(StatsdSink {
+            socket,
+            prefix: config.prefix.clone(),
+            tags,
+            failed: false,
+        })
+    }
+
+    /// Send the response time and counts of a response or error, in a single packet
+    pub(crate) fn send(&mut self, stat: &ResponseStat) {
+        let packet = self.packet(stat);
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            if !self.failed {
+                self.failed = true;
+                warn!("error sending stats to statsd, later errors won't be logged: {e}");
+            }
+        }
+    }
+
+    // one metric per line
+    fn packet(&self, stat: &ResponseStat) -> String {
+        let tags = self.tags_for(&stat.tags);
+        let prefix = &self.prefix;
+        let mut packet = String::new();
+        // an attempt which was retried is part of the request it was retried for
+        if stat.retry {
+            let _ = write!(packet, "{prefix}.retries:1|c|#{tags}");
+            return packet;
+        }
+        if let Some(rtt) = stat.rtt {
+            let ms = rtt as f64 / 1000.0;
+            let _ = writeln!(packet, "{prefix}.response_time:{ms}|ms|#{tags}");
+        }
+        match &stat.kind {
+            StatKind::Response(status) => {
+                let _ = write!(packet, "{prefix}.requests:1|c|#{tags},status:{status}");
+            }
+            StatKind::RecoverableError(e) => {
+                let _ = writeln!(packet, "{prefix}.requests:1|c|#{tags},status:error");
+                let kind = e.category();
+                let _ = write!(packet, "{prefix}.errors:1|c|#{tags},kind:{kind}");
+            }
+        }
+        packet
+    }
+
+    // the configured tags, then the endpoint's with its `_id` as `endpoint`
+    fn tags_for(&self, endpoint_tags: &Tags) -> String {
+        let endpoint_tags = endpoint_tags
+            .iter()
+            .map(|(name, value)| match name.as_str() {
+                "_id" => tag("endpoint", value),
+                _ => tag(name, value),
+            });
+        self.tags
+            .iter()
+            .cloned()
+            .chain(endpoint_tags)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+// the characters which separate tags, metrics and the parts of a metric can't be in a tag
+fn tag(name: &str, value: &str) -> String {
+    let clean = |s: &str| s.replace([',', '|', '\n'], "_");
+    format!("{}:{}", clean(name).replace(':', "_"), clean(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RecoverableError;
+    use std::{
+        collections::BTreeMap,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    fn stat(tags: &Arc<Tags>, kind: StatKind, retry: bool) -> ResponseStat {
+        ResponseStat {
+            connection_reused: None,
+            kind,
+            rtt: Some(12_500),
+            retry,
+            success: None,
+            time: SystemTime::now(),
+            tags: tags.clone(),
+            timing: None,
+        }
+    }
+
+    #[test]
+    fn sends_dogstatsd_packets() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let config = StatsdConfig {
+            host: "127.0.0.1".into(),
+            port: server.local_addr().unwrap().port(),
+            prefix: "pewpew".into(),
+            tags: BTreeMap::from([("team".into(), "perf".into())]),
+        };
+        let mut sink = StatsdSink::connect(&config).unwrap();
+        let tags: Arc<Tags> = Arc::new(BTreeMap::from([
+            ("_id".into(), "0".into()),
+            ("method".into(), "GET".into()),
+            ("url".into(), "http://localhost/a,b".into()),
+        ]));
+        let recv = || {
+            let mut buf = [0; 1024];
+            let n = server.recv(&mut buf).unwrap();
+            String::from_utf8(buf[..n].to_vec()).unwrap()
+        };
+        let tags_str = "team:perf,endpoint:0,method:GET,url:http://localhost/a_b";
+
+        sink.send(&stat(&tags, StatKind::Response(200), false));
+        assert_eq!(
+            recv(),
+            format!(
+                "pewpew.response_time:12.5|ms|#{tags_str}\n\
+                 pewpew.requests:1|c|#{tags_str},status:200"
+            )
+        );
+
+        let timeout = RecoverableError::Timeout(SystemTime::now());
+        sink.send(&stat(&tags, StatKind::RecoverableError(timeout), false));
+        assert_eq!(
+            recv(),
+            format!(
+                "pewpew.response_time:12.5|ms|#{tags_str}\n\
+                 pewpew.requests:1|c|#{tags_str},status:error\n\
+                 pewpew.errors:1|c|#{tags_str},kind:timeout"
+            )
+        );
+
+        sink.send(&stat(&tags, StatKind::Response(503), true));
+        assert_eq!(recv(), format!("pewpew.retries:1|c|#{tags_str}"));
+    }
+
+    // other platforms may not report the refused packets
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_sends_are_not_fatal() {
+        // nothing listens on the port once the socket is dropped
+        let port = {
+            let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+            server.local_addr().unwrap().port()
+        };
+        let config = StatsdConfig {
+            host: "127.0.0.1".into(),
+            port,
+            prefix: "pewpew".into(),
+            tags: BTreeMap::new(),
+        };
+        let mut sink = StatsdSink::connect(&config).unwrap();
+        let tags = Arc::new(BTreeMap::new());
+        for _ in 0..10 {
+            sink.send(&stat(&tags, StatKind::Response(200), false));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // the refused packets come back as errors on the later sends
+        assert!(sink.failed);
+    }
+}
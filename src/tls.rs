@@ -0,0 +1,220 @@
+use crate::error::TestError;
+use crate::util::tweak_path;
+use native_tls::{Certificate, Identity, TlsConnectorBuilder};
+
+use std::path::Path;
+
+/// The certificates from `client.tls`, read when the test starts so a missing or malformed one
+/// stops it before any requests are sent
+pub(crate) struct ClientTls {
+    identity: Option<Identity>,
+    ca_cert: Option<Certificate>,
+}
+
+impl ClientTls {
+    /// Reads the files `client.tls` names, which are relative to the config file
+    pub(crate) fn load(config: &config::TlsConfig, config_path: &Path) -> Result<Self, TestError> {
+        let read = |field: &str, path: &str| {
+            let mut path = path.to_string();
+            tweak_path(&mut path, config_path);
+            std::fs::read(&path).map_err(|e| {
+                TestError::Other(format!(
+                    "error reading client.tls `{field}` file `{path}`: {e}"
+                ))
+            })
+        };
+        let identity = match (&config.client_cert, &config.client_key) {
+            (Some(cert), Some(key)) => {
+                let cert = read("client_cert", cert)?;
+                let key = read("client_key", key)?;
+                let identity = Identity::from_pkcs8(&cert, &key).map_err(|e| {
+                    TestError::Other(format!(
+                        "invalid client.tls `client_cert` or `client_key`, expected a PEM \
+                         certificate and a PEM PKCS #8 private key: {e}"
+                    ))
+                })?;
+                Some(identity)
+            }
+            _ => None,
+        };
+        let ca_cert = match &config.ca_cert {
+            Some(ca_cert) => {
+                let pem = read("ca_cert", ca_cert)?;
+                let ca_cert = Certificate::from_pem(&pem).map_err(|e| {
+                    TestError::Other(format!(
+                        "invalid client.tls `ca_cert`, expected a PEM certificate: {e}"
+                    ))
+                })?;
+                Some(ca_cert)
+            }
+            None => None,
+        };
+        Ok(ClientTls { identity, ca_cert })
+    }
+
+    pub(crate) fn configure(&self, builder: &mut TlsConnectorBuilder) {
+        if let Some(identity) = &self.identity {
+            builder.identity(identity.clone());
+        }
+        if let Some(ca_cert) = &self.ca_cert {
+            builder.add_root_certificate(ca_cert.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_http_client, ClientSettings};
+
+    // the fixtures are next to where the config would be
+    const CONFIG_PATH: &str = "tests/pewpew.yaml";
+
+    fn tls_config(ca_cert: Option<&str>, cert_and_key: Option<(&str, &str)>) -> config::TlsConfig {
+        config::TlsConfig {
+            ca_cert: ca_cert.map(Into::into),
+            client_cert: cert_and_key.map(|(cert, _)| cert.into()),
+            client_key: cert_and_key.map(|(_, key)| key.into()),
+        }
+    }
+
+    fn load_err(config: config::TlsConfig) -> String {
+        match ClientTls::load(&config, Path::new(CONFIG_PATH)) {
+            Err(TestError::Other(e)) => e,
+            Err(e) => panic!("expected an error loading the certificates, got {}", e),
+            Ok(_) => panic!("expected an error loading the certificates"),
+        }
+    }
+
+    #[test]
+    fn loads_certificates_relative_to_the_config() {
+        let config = tls_config(
+            Some("tls_client_cert.pem"),
+            Some(("tls_client_cert.pem", "tls_client_key.pem")),
+        );
+        let tls = ClientTls::load(&config, Path::new(CONFIG_PATH)).unwrap();
+        assert!(tls.identity.is_some());
+        assert!(tls.ca_cert.is_some());
+        create_http_client(ClientSettings {
+            tls: Some(&tls),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let tls = ClientTls::load(&tls_config(None, None), Path::new(CONFIG_PATH)).unwrap();
+        assert!(tls.identity.is_none());
+        assert!(tls.ca_cert.is_none());
+    }
+
+    // a server which presents the fixture certificate and only completes the handshake with a
+    // client which presents one signed by it, answering each such connection with a `200`
+    fn start_client_cert_server() -> u16 {
+        use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+        use std::io::{Read, Write};
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor
+            .set_certificate_chain_file("tests/tls_client_cert.pem")
+            .unwrap();
+        acceptor
+            .set_private_key_file("tests/tls_client_key.pem", SslFiletype::PEM)
+            .unwrap();
+        acceptor.set_ca_file("tests/tls_client_cert.pem").unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        let acceptor = acceptor.build();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream.map(|s| acceptor.accept(s)) {
+                    Ok(Ok(stream)) => stream,
+                    _ => continue,
+                };
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                );
+                let _ = stream.shutdown();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn presents_the_client_certificate() {
+        let port = start_client_cert_server();
+        let connect_to = format!("127.0.0.1:{}", port).parse().unwrap();
+        // the fixture's common name, so the server's certificate matches the url's host
+        let url = "https://pewpew-test-client/";
+
+        let config = tls_config(
+            Some("tls_client_cert.pem"),
+            Some(("tls_client_cert.pem", "tls_client_key.pem")),
+        );
+        let tls = ClientTls::load(&config, Path::new(CONFIG_PATH)).unwrap();
+        let client = create_http_client(ClientSettings {
+            connect_to: Some(connect_to),
+            tls: Some(&tls),
+            ..Default::default()
+        })
+        .unwrap();
+        let response = client.get(url.parse().unwrap()).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        // trusting the server but without an identity the handshake is refused
+        let config = tls_config(Some("tls_client_cert.pem"), None);
+        let tls = ClientTls::load(&config, Path::new(CONFIG_PATH)).unwrap();
+        let client = create_http_client(ClientSettings {
+            connect_to: Some(connect_to),
+            tls: Some(&tls),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(client.get(url.parse().unwrap()).await.is_err());
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let e = load_err(tls_config(
+            None,
+            Some(("missing.pem", "tls_client_key.pem")),
+        ));
+        assert!(
+            e.starts_with("error reading client.tls `client_cert` file `tests/missing.pem`"),
+            "{}",
+            e
+        );
+
+        let e = load_err(tls_config(Some("missing.pem"), None));
+        assert!(
+            e.starts_with("error reading client.tls `ca_cert` file `tests/missing.pem`"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn malformed_certificates_are_errors() {
+        // a private key isn't a certificate
+        let e = load_err(tls_config(
+            None,
+            Some(("tls_client_key.pem", "tls_client_key.pem")),
+        ));
+        assert!(
+            e.starts_with("invalid client.tls `client_cert` or `client_key`"),
+            "{}",
+            e
+        );
+
+        let e = load_err(tls_config(Some("tls_client_key.pem"), None));
+        assert!(e.starts_with("invalid client.tls `ca_cert`"), "{}", e);
+    }
+}
@@ -0,0 +1,182 @@
+use crate::connection_reuse;
+use crate::line_writer::{blocking_writer, MsgType, Writers};
+use crate::{TestEndReason, TestError};
+
+use futures::channel::mpsc::Sender;
+use hyper::Response;
+use serde_json as json;
+use tokio::sync::broadcast;
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Sends straight to the trace file's writer. Every request has its own clone, which always has
+/// room for the one message a request sends.
+pub(crate) type EventsTx = Sender<MsgType>;
+
+/// The trace events for a request which is still in flight. Finishing it queues the events to be
+/// written to the trace file.
+pub(crate) struct PendingTrace {
+    tx: EventsTx,
+    name: String,
+    tid: u64,
+    https: bool,
+    start: SystemTime,
+    args: json::Map<String, json::Value>,
+    phases: Vec<(&'static str, SystemTime, SystemTime)>,
+}
+
+impl PendingTrace {
+    pub(crate) fn new(
+        tx: EventsTx,
+        method: &str,
+        tags: &BTreeMap<String, String>,
+        https: bool,
+    ) -> Self {
+        let mut args = json::Map::new();
+        if let Some(id) = tags.get("_id") {
+            args.insert("endpoint".into(), id.as_str().into());
+        }
+        args.insert("tags".into(), json::json!(tags));
+        PendingTrace {
+            tx,
+            name: format!("{method} {}", tags.get("url").map_or("", String::as_str)),
+            // each endpoint gets its own row in the trace viewer
+            tid: tags.get("_id").and_then(|id| id.parse().ok()).unwrap_or(0),
+            https,
+            start: SystemTime::now(),
+            args,
+            phases: Vec::new(),
+        }
+    }
+
+    /// Records the phases up to the response's headers arriving. Has to be called before the
+    /// response handler checks whether the connection was reused.
+    pub(crate) fn response_received<B>(&mut self, response: &Response<B>) {
+        let now = SystemTime::now();
+        self.args
+            .insert("status".into(), response.status().as_u16().into());
+        let mut sent = self.start;
+        // a connection opened for an earlier request which then went back in the pool doesn't
+        // count against this one
+        if let Some((tcp, ready)) =
+            connection_reuse::opened(response).filter(|(tcp, _)| tcp.start >= self.start)
+        {
+            self.phases.push(("connect", tcp.start, tcp.end));
+            if self.https {
+                self.phases.push(("tls", tcp.end, ready.end));
+            }
+            sent = ready.end;
+        }
+        self.phases.push(("ttfb", sent, now));
+    }
+
+    pub(crate) fn finish(mut self) {
+        let end = SystemTime::now();
+        let mut events = event(
+            &self.name,
+            "request",
+            self.tid,
+            self.start,
+            end,
+            self.args.into(),
+        )
+        .to_string();
+        events.push_str(",\n");
+        for (name, start, end) in self.phases {
+            let phase = event(name, "phase", self.tid, start, end, json::json!({}));
+            events.push_str(&phase.to_string());
+            events.push_str(",\n");
+        }
+        let _ = self.tx.try_send(MsgType::Other(events));
+    }
+}
+
+fn micros(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+// a complete ("X") event from the Chrome trace event format
+fn event(
+    name: &str,
+    cat: &str,
+    tid: u64,
+    start: SystemTime,
+    end: SystemTime,
+    args: json::Value,
+) -> json::Value {
+    let ts = micros(start);
+    json::json!({
+        "name": name,
+        "cat": cat,
+        "ph": "X",
+        "ts": ts,
+        "dur": micros(end).saturating_sub(ts),
+        "pid": 1,
+        "tid": tid,
+        "args": args,
+    })
+}
+
+/// Creates the trace file at `path` and the channel trace events are sent on to be written to
+/// it. The file is a json array of events, which is closed off when the run shuts down.
+pub(crate) fn writer(
+    path: &Path,
+    test_killer: broadcast::Sender<Result<TestEndReason, TestError>>,
+    writers: &Writers,
+) -> Result<EventsTx, TestError> {
+    let file_name = path.to_string_lossy().into_owned();
+    let file = File::create(path)
+        .map_err(|e| TestError::CannotCreateTraceFile(file_name.clone(), e.into()))?;
+    let (mut tx, done) = blocking_writer(file, test_killer, file_name);
+    writers.close_on_shutdown(&tx, done);
+    // every event is followed by a comma, so the array ends with one which isn't
+    let process_name = json::json!({
+        "name": "process_name",
+        "ph": "M",
+        "pid": 1,
+        "args": { "name": "pewpew" },
+    });
+    let _ = tx.try_send(MsgType::Other("[\n".into()));
+    let _ = tx.try_send(MsgType::Final(format!("{process_name}\n]\n")));
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn event_is_in_microseconds() {
+        let start = UNIX_EPOCH + Duration::from_millis(1500);
+        let end = start + Duration::from_millis(25);
+        let event = event(
+            "GET /",
+            "request",
+            3,
+            start,
+            end,
+            json::json!({"status": 200}),
+        );
+        assert_eq!(
+            event,
+            json::json!({
+                "name": "GET /",
+                "cat": "request",
+                "ph": "X",
+                "ts": 1_500_000,
+                "dur": 25_000,
+                "pid": 1,
+                "tid": 3,
+                "args": { "status": 200 },
+            })
+        );
+    }
+}
@@ -18,6 +18,16 @@ pub fn tweak_path(rest: &mut String, base: &Path) {
     *rest = base.with_file_name(&rest).to_string_lossy().into();
 }
 
+// a seed of its own for each named part of a test, derived from the test's `--seed` with FNV-1a,
+// which unlike the std hasher is the same in every build
+pub fn named_seed(seed: Option<u64>, name: &str) -> Option<u64> {
+    seed.map(|seed| {
+        name.bytes().fold(seed ^ 0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+    })
+}
+
 pub fn config_limit_to_channel_limit(limit: config::Limit) -> channel::Limit {
     match limit {
         config::Limit::Dynamic(n) => channel::Limit::dynamic(n),
@@ -0,0 +1,175 @@
+use crate::connection_reuse;
+use crate::HttpClient;
+
+use futures::future::{self, join_all};
+use futures_timer::Delay;
+use hyper::{header::HOST, Body, Method, Request};
+use log::debug;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+// a host which doesn't answer in time is left for the endpoints' own requests to connect to
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(10);
+const WARMUP_ROUNDS: usize = 5;
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// The root of each host endpoints send requests to, along with the client used to reach it.
+/// Endpoints whose host comes from a provider are left out, as it isn't known until the test runs.
+pub(crate) fn targets(
+    endpoints: &[config::Endpoint],
+    client: &Arc<HttpClient>,
+    connect_to_clients: &BTreeMap<SocketAddr, Arc<HttpClient>>,
+) -> Vec<(Arc<HttpClient>, url::Url)> {
+    let mut seen = BTreeSet::new();
+    endpoints
+        .iter()
+        .filter_map(|endpoint| {
+            let mut url = url::Url::parse(&endpoint.url.evaluate_with_star()).ok()?;
+            if url.host_str()?.contains('*') {
+                return None;
+            }
+            url.set_path("/");
+            url.set_query(None);
+            url.set_fragment(None);
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            if !seen.insert((endpoint.connect_to, url.clone())) {
+                return None;
+            }
+            let client = endpoint
+                .connect_to
+                .and_then(|addr| connect_to_clients.get(&addr))
+                .unwrap_or(client);
+            Some((client.clone(), url))
+        })
+        .collect()
+}
+
+/// Opens `count` connections to each target before the test starts, so a test ramping up from
+/// nothing doesn't have its first requests wait on a burst of connections and TLS handshakes. A
+/// connection is opened with a `HEAD` request to the root of the host, sent all at once so the
+/// client has to open a connection for each. A response which comes back quickly frees its
+/// connection for another of the requests, so the requests are sent again, a few times at most,
+/// until enough connections have been opened. The connections are left in the client's pool for
+/// the endpoints to use.
+pub(crate) async fn warm_up(targets: Vec<(Arc<HttpClient>, url::Url)>, count: usize) {
+    join_all(targets.iter().map(|(client, url)| async move {
+        let mut opened = 0;
+        for _ in 0..WARMUP_ROUNDS {
+            let requests = (0..count).map(|_| head(client, url));
+            opened += join_all(requests).await.into_iter().sum::<usize>();
+            if opened >= count {
+                break;
+            }
+        }
+        debug!("warmed up {} connections to `{}`", opened, url);
+    }))
+    .await;
+    // a connection goes back to the pool from a task of its own once its response is done
+    Delay::new(SETTLE_TIME).await;
+}
+
+// Send a `HEAD` request to `url`, returning 1 when it opened a new connection
+async fn head(client: &HttpClient, url: &url::Url) -> usize {
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri(url.as_str())
+        .header(HOST, url.host_str().unwrap_or_default())
+        .body(Body::empty())
+        .expect("should be a valid request");
+    match future::select(client.request(request), Delay::new(WARMUP_TIMEOUT)).await {
+        future::Either::Left((Ok(response), _)) => {
+            let opened = connection_reuse::opened(&response).is_some();
+            // the endpoints' first requests on the connection count as reusing it
+            connection_reuse::was_reused(&response);
+            let _ = hyper::body::to_bytes(response.into_body()).await;
+            opened as usize
+        }
+        future::Either::Left((Err(e), _)) => {
+            debug!("could not warm up a connection to `{}`: {}", url, e);
+            0
+        }
+        future::Either::Right(_) => {
+            debug!("timed out warming up a connection to `{}`", url);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create_http_client, ClientSettings};
+    use tokio::runtime::Runtime;
+
+    // the time spent opening connections for `n` requests sent at once
+    async fn connect_time(client: &HttpClient, port: u16, n: usize) -> Duration {
+        let requests = (0..n).map(|_| {
+            let request = Request::get(format!("http://127.0.0.1:{}/", port))
+                .header(HOST, "127.0.0.1")
+                .body(Body::empty())
+                .unwrap();
+            client.request(request)
+        });
+        join_all(requests)
+            .await
+            .into_iter()
+            .map(|response| {
+                let response = response.unwrap();
+                let connect_time = connection_reuse::opened(&response)
+                    .map(|(_, opened)| opened.end.duration_since(opened.start).unwrap())
+                    .unwrap_or_default();
+                connection_reuse::was_reused(&response);
+                connect_time
+            })
+            .sum()
+    }
+
+    #[test]
+    fn warmed_up_connections_are_used_first() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async move {
+            let (port, _kill, _) = test_common::start_test_server(None);
+            let config = config::LoadTest::from_config(
+                format!(
+                    r#"
+                    endpoints:
+                      - url: http://127.0.0.1:{port}/a?b=c
+                      - url: http://127.0.0.1:{port}/d
+                      - url: http://${{host}}/
+                    providers:
+                      host:
+                        list: [localhost]
+                    "#,
+                    port = port
+                )
+                .as_bytes(),
+                std::path::Path::new("./"),
+                &Default::default(),
+            )
+            .unwrap();
+
+            let cold: Arc<_> = create_http_client(ClientSettings::default())
+                .unwrap()
+                .into();
+            let without_warmup = connect_time(&cold, port, 3).await;
+
+            let warm: Arc<_> = create_http_client(ClientSettings::default())
+                .unwrap()
+                .into();
+            let targets = targets(&config.endpoints, &warm, &BTreeMap::new());
+            let urls: Vec<_> = targets.iter().map(|(_, url)| url.to_string()).collect();
+            assert_eq!(urls, [format!("http://127.0.0.1:{}/", port)]);
+            warm_up(targets, 4).await;
+            let with_warmup = connect_time(&warm, port, 3).await;
+
+            assert!(without_warmup > Duration::ZERO);
+            assert_eq!(with_warmup, Duration::ZERO);
+        });
+    }
+}
@@ -1,10 +1,73 @@
-use std::env;
+use std::{env, io::BufRead};
 
 use futures::FutureExt;
 use test_common::{start_test_server, TestWriter};
 use tokio::runtime::Runtime;
 
+// the config every test runs with, which each overrides as it needs
+fn run_config(config_file: &str) -> pewpew::RunConfig {
+    pewpew::RunConfig {
+        config_file: config_file.into(),
+        merge_config_files: Vec::new(),
+        output_format: pewpew::RunOutputFormat::Human,
+        json_style: pewpew::JsonStyle::Ndjson,
+        results_dir: Some("./".into()),
+        stats_file: "integration.json".into(),
+        result_file: None,
+        stats_file_format: pewpew::StatsFileFormat::Json,
+        start_at: None,
+        watch_config_file: false,
+        otlp: None,
+        trace: None,
+        plan: false,
+        print_load_curve: false,
+        list_endpoints: false,
+        config_check_only: false,
+        seed: None,
+        heartbeat: false,
+        require_all_endpoints: false,
+        max_runtime: None,
+        environment: None,
+        vars_file: None,
+        vars: Vec::new(),
+        threads: None,
+        repeat: None,
+        raise_fd_limit: false,
+        progress: false,
+        stats_socket: None,
+        metrics_port: None,
+        results_s3: None,
+        yes: false,
+        interactive: false,
+    }
+}
+
 fn run_test(path: &str) -> (bool, String, String) {
+    run_test_with_output(
+        path,
+        pewpew::RunOutputFormat::Human,
+        pewpew::JsonStyle::Ndjson,
+        None,
+    )
+}
+
+fn run_test_with_output(
+    path: &str,
+    output_format: pewpew::RunOutputFormat,
+    json_style: pewpew::JsonStyle,
+    result_file: Option<&str>,
+) -> (bool, String, String) {
+    let run_config = pewpew::RunConfig {
+        output_format,
+        json_style,
+        result_file: result_file.map(Into::into),
+        watch_config_file: true,
+        ..run_config(path)
+    };
+    run_test_with_config(run_config)
+}
+
+fn run_test_with_config(run_config: pewpew::RunConfig) -> (bool, String, String) {
     let rt = Runtime::new().unwrap();
     rt.block_on(async move {
         let (port, kill_server, _) = start_test_server(None);
@@ -12,15 +75,6 @@ fn run_test(path: &str) -> (bool, String, String) {
 
         let (_, ctrlc_channel) = futures::channel::mpsc::unbounded();
 
-        let run_config = pewpew::RunConfig {
-            config_file: path.into(),
-            output_format: pewpew::RunOutputFormat::Human,
-            results_dir: Some("./".into()),
-            stats_file: "integration.json".into(),
-            stats_file_format: pewpew::StatsFileFormat::Json,
-            start_at: None,
-            watch_config_file: true,
-        };
         let exec_config = pewpew::ExecConfig::Run(run_config);
 
         let stdout = TestWriter::new();
@@ -70,3 +124,779 @@ fn int_on_demand() {
         );
     }
 }
+
+#[test]
+fn int_json_array() {
+    let (success, stdout, stderr) = run_test_with_output(
+        "tests/int_on_demand.yaml",
+        pewpew::RunOutputFormat::Json,
+        pewpew::JsonStyle::Array,
+        None,
+    );
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let events: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|e| panic!("stdout should be a single json value. {}: {}", e, stdout));
+    let events = events.as_array().expect("stdout should be a json array");
+    assert_eq!(
+        events.first().and_then(|e| e.get("type")),
+        Some(&"start".into()),
+        "first event should be the start. {}",
+        stdout
+    );
+    assert_eq!(
+        events.last().and_then(|e| e.get("type")),
+        Some(&"end".into()),
+        "last event should be the end. {}",
+        stdout
+    );
+}
+
+#[test]
+fn int_file_logger_is_flushed() {
+    let (success, _stdout, stderr) = run_test("tests/int_file_logger.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // the file is read as soon as the run resolves, so every line must already be written
+    let logged = std::fs::read_to_string("int_file_logger.out").unwrap();
+    let _ = std::fs::remove_file("int_file_logger.out");
+    let expected: String = (1..=20).map(|n| format!("{}\n", n)).collect();
+    assert_eq!(logged, expected);
+}
+
+#[test]
+fn int_depends_on() {
+    let (success, _stdout, stderr) = run_test("tests/int_depends_on.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(
+        lines.first(),
+        Some(&"setup"),
+        "the setup endpoint should log before the endpoint depending on it. {}",
+        stderr
+    );
+    assert!(
+        lines.contains(&"main"),
+        "the dependent endpoint should run once setup has. {}",
+        stderr
+    );
+}
+
+#[test]
+fn int_cookies() {
+    let (success, _stdout, stderr) = run_test("tests/int_cookies.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // the server turns away requests without the cookie the first endpoint was given
+    let statuses: Vec<_> = stderr.lines().collect();
+    assert!(!statuses.is_empty(), "{}", stderr);
+    assert!(
+        statuses.iter().all(|s| *s == "204"),
+        "every request should have sent the cookie. {}",
+        stderr
+    );
+}
+
+#[test]
+fn int_result_file() {
+    let (success, _stdout, stderr) = run_test_with_output(
+        "tests/int_result_file.yaml",
+        pewpew::RunOutputFormat::Human,
+        pewpew::JsonStyle::Ndjson,
+        Some("int_result_file.json"),
+    );
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let result = std::fs::read_to_string("int_result_file.json").unwrap();
+    let _ = std::fs::remove_file("int_result_file.json");
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["passed"], false, "{}", result);
+    assert_eq!(result["endReason"], "completed", "{}", result);
+    assert_eq!(result["errors"], 0, "{}", result);
+    assert_eq!(result["endpoints"].as_array().map(Vec::len), Some(2));
+
+    let gates = result["gates"].as_array().unwrap();
+    let failed: Vec<_> = gates.iter().filter(|g| g["passed"] == false).collect();
+    assert_eq!(failed.len(), 1, "{}", result);
+    assert_eq!(failed[0]["gate"], "max_p99");
+    assert_eq!(failed[0]["endpoint"]["name"], "slow");
+    assert!(gates
+        .iter()
+        .any(|g| g["gate"] == "max_error_rate" && g["passed"] == true));
+}
+
+#[test]
+fn int_sla() {
+    let (success, _stdout, stderr) = run_test_with_output(
+        "tests/int_sla.yaml",
+        pewpew::RunOutputFormat::Human,
+        pewpew::JsonStyle::Ndjson,
+        Some("int_sla.json"),
+    );
+
+    assert!(!success, "test run should have failed. {}", stderr);
+    assert!(
+        stderr.contains("endpoints broke their SLA: `1: GET http://localhost:"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("is over 50ms`"), "{}", stderr);
+    assert!(!stderr.contains("`0: GET"), "{}", stderr);
+
+    let result = std::fs::read_to_string("int_sla.json").unwrap();
+    let _ = std::fs::remove_file("int_sla.json");
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["passed"], false, "{}", result);
+    let gates = result["gates"].as_array().unwrap();
+    let gate = |gate, id: &str| {
+        gates
+            .iter()
+            .find(|g| g["gate"] == gate && g["endpoint"]["_id"] == id)
+            .unwrap_or_else(|| panic!("no {} gate for endpoint {}. {}", gate, id, result))
+    };
+    assert_eq!(gate("sla_p99", "0")["passed"], true, "{}", result);
+    assert_eq!(gate("sla_error_rate", "0")["passed"], true, "{}", result);
+    assert_eq!(gate("sla_p99", "1")["passed"], false, "{}", result);
+}
+
+#[test]
+fn int_assertions() {
+    let (success, _stdout, stderr) = run_test_with_output(
+        "tests/int_assertions.yaml",
+        pewpew::RunOutputFormat::Human,
+        pewpew::JsonStyle::Ndjson,
+        Some("int_assertions.json"),
+    );
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let result = std::fs::read_to_string("int_assertions.json").unwrap();
+    let _ = std::fs::remove_file("int_assertions.json");
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    let endpoint = |id: &str| {
+        result["endpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["tags"]["_id"] == id)
+            .unwrap_or_else(|| panic!("no endpoint {}. {}", id, result))
+    };
+    // every 500 fails the assertion, which is counted as an error along with the response
+    let failing = endpoint("0");
+    let failed = failing["errorCategories"]["assertion"]
+        .as_u64()
+        .unwrap_or_default();
+    assert!(failed > 0, "{}", result);
+    assert_eq!(failing["total"], failed * 2, "{}", result);
+    assert_eq!(failing["ok"], 0, "{}", result);
+    assert_eq!(endpoint("1")["errorCategories"], serde_json::Value::Null);
+}
+
+#[test]
+fn int_assertion_kill() {
+    let start = std::time::Instant::now();
+    let (_, _stdout, stderr) = run_test("tests/int_assertion_kill.yaml");
+
+    assert!(
+        stderr.contains("Test killed early by a failed assertion: the server errored"),
+        "{}",
+        stderr
+    );
+    assert!(start.elapsed().as_secs() < 5, "{}", stderr);
+}
+
+#[test]
+fn int_fatal_error_ends_run() {
+    let start = std::time::Instant::now();
+    let (success, _stdout, stderr) = run_test("tests/int_fatal_error.yaml");
+
+    assert!(!success, "test run should have failed. {}", stderr);
+    // the error surfaces on the first request, long before the minute long load pattern ends
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(10),
+        "took {:?}",
+        start.elapsed()
+    );
+}
+
+#[test]
+fn int_confirm_before_run() {
+    let run_config = |yes| pewpew::RunConfig {
+        max_runtime: Some(std::time::Duration::from_secs(1)),
+        yes,
+        ..run_config("tests/int_confirm.yaml")
+    };
+
+    // the combined peak_load of 50hps is over `confirm_above`, and there's no one to ask
+    let (success, _stdout, stderr) = run_test_with_config(run_config(false));
+    assert!(!success, "test run should not have started. {}", stderr);
+    assert!(
+        stderr.contains("combined peak_load of 50.00 hps"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--yes"), "{}", stderr);
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config(true));
+    assert!(success, "test run failed. {}", stderr);
+}
+
+fn try_test(path: &str, format: pewpew::TryRunFormat) -> (bool, String) {
+    try_test_with_filters(path, format, None, None)
+}
+
+fn try_test_with_filters(
+    path: &str,
+    format: pewpew::TryRunFormat,
+    filters: Option<Vec<pewpew::TryFilter>>,
+    exclude: Option<Vec<pewpew::TryFilter>>,
+) -> (bool, String) {
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async move {
+        let (port, kill_server, _) = start_test_server(None);
+        env::set_var("PORT", port.to_string());
+
+        let (_, ctrlc_channel) = futures::channel::mpsc::unbounded();
+
+        let try_config = pewpew::TryConfig {
+            config_file: path.into(),
+            merge_config_files: Vec::new(),
+            file: None,
+            filters,
+            exclude,
+            format,
+            loggers_on: false,
+            results_dir: None,
+            skip_response_body_on: true,
+            skip_request_body_on: true,
+            list_endpoints: false,
+            seed: None,
+            environment: None,
+            vars_file: None,
+            vars: Vec::new(),
+        };
+        let exec_config = pewpew::ExecConfig::Try(try_config);
+
+        let stdout = TestWriter::new();
+        let stdout2 = stdout.clone();
+
+        let success = pewpew::create_run(exec_config, ctrlc_channel, stdout, TestWriter::new())
+            .map(|r| r.is_ok())
+            .await;
+
+        let _ = kill_server.send(());
+
+        (success, stdout2.get_string())
+    })
+}
+
+#[test]
+fn int_try_shows_resolved_url() {
+    // other tests set `PORT` too, so only the parts of the url from the config are checked
+    let (success, stdout) = try_test("tests/int_try_url.yaml", pewpew::TryRunFormat::Json);
+    assert!(success, "try run failed. {}", stdout);
+    let output: serde_json::Value = serde_json::from_str(&stdout)
+        .unwrap_or_else(|e| panic!("stdout should be json. {}: {}", e, stdout));
+    let url = output["request"]["url"].as_str().unwrap_or_default();
+    assert!(
+        url.starts_with("http://127.0.0.1:") && url.ends_with("/search?name=pewpew&page=2"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        output["request"]["start-line"], "GET /search?name=pewpew&page=2 HTTP/1.1",
+        "{}",
+        stdout
+    );
+
+    let (success, stdout) = try_test("tests/int_try_url.yaml", pewpew::TryRunFormat::Human);
+    assert!(success, "try run failed. {}", stdout);
+    // the heading follows the line naming the endpoint
+    let heading = stdout.trim_start().lines().nth(1).unwrap_or_default();
+    assert!(
+        heading.starts_with("Request (http://127.0.0.1:")
+            && heading.ends_with("/search?name=pewpew&page=2)"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn int_require_all_endpoints() {
+    let run_config = |require_all_endpoints| pewpew::RunConfig {
+        require_all_endpoints,
+        ..run_config("tests/int_idle_endpoint.yaml")
+    };
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config(false));
+    assert!(success, "test run failed. {}", stderr);
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config(true));
+    assert!(!success, "test run should have failed. {}", stderr);
+    assert!(
+        stderr.contains("endpoints never sent a request: `1: GET http://localhost:"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("`0: GET"), "{}", stderr);
+}
+
+#[test]
+fn int_config_check_only() {
+    let run_config = |config_file: &str| pewpew::RunConfig {
+        output_format: pewpew::RunOutputFormat::Json,
+        config_check_only: true,
+        ..run_config(config_file)
+    };
+
+    let (success, stdout, stderr) = run_test_with_config(run_config("tests/int_config_check.yaml"));
+    assert!(success, "config check failed. {}", stderr);
+    let check: serde_json::Value = serde_json::from_str(stdout.lines().next().unwrap()).unwrap();
+    assert_eq!(
+        check,
+        serde_json::json!({"type": "configCheck", "providers": 2, "endpoints": 1})
+    );
+
+    // parsing alone doesn't look for the provider's file
+    let missing = "tests/int_config_check_missing.yaml";
+    let bytes = std::fs::read(missing).unwrap();
+    let env_vars = [("PORT".to_string(), "80".to_string())].into();
+    config::LoadTest::from_config(&bytes, std::path::Path::new(missing), &env_vars).unwrap();
+
+    let (success, stdout, stderr) = run_test_with_config(run_config(missing));
+    assert!(!success, "config check should have failed. {}", stdout);
+    assert!(
+        stderr.contains("error opening file") && stderr.contains("int_config_check_missing.csv"),
+        "{}",
+        stderr
+    );
+    assert!(!stdout.contains("configCheck"), "{}", stdout);
+}
+
+#[test]
+fn int_max_runtime() {
+    let run_config = pewpew::RunConfig {
+        result_file: Some("int_max_runtime.json".into()),
+        max_runtime: Some(std::time::Duration::from_secs(2)),
+        ..run_config("tests/int_max_runtime.yaml")
+    };
+
+    let start = std::time::Instant::now();
+    let (success, _stdout, stderr) = run_test_with_config(run_config);
+    let elapsed = start.elapsed();
+
+    assert!(success, "test run failed. {}", stderr);
+    // the load pattern is an hour long and the provider never runs out
+    assert!(
+        elapsed < std::time::Duration::from_secs(10),
+        "took {:?}",
+        elapsed
+    );
+    assert!(
+        stderr.contains("Test ended early because it reached the max runtime"),
+        "{}",
+        stderr
+    );
+
+    let result = std::fs::read_to_string("int_max_runtime.json").unwrap();
+    let _ = std::fs::remove_file("int_max_runtime.json");
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["endReason"], "maxRuntime", "{}", result);
+}
+
+#[test]
+fn int_merge_config_files() {
+    let run_config = pewpew::RunConfig {
+        merge_config_files: vec!["tests/int_merge_endpoints.yaml".into()],
+        ..run_config("tests/int_merge_base.yaml")
+    };
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config);
+
+    assert!(success, "test run failed. {}", stderr);
+    // the endpoints and `name` come from the second file, the load pattern, logger and `port`
+    // from the first
+    let lines: Vec<_> = stderr.lines().filter(|line| !line.is_empty()).collect();
+    assert!(!lines.is_empty(), "{}", stderr);
+    assert!(lines.iter().all(|line| *line == "merged"), "{}", stderr);
+}
+
+#[test]
+fn int_try_shows_endpoint_tags() {
+    let (success, stdout) = try_test("tests/int_try_tags.yaml", pewpew::TryRunFormat::Json);
+    assert!(success, "try run failed. {}", stdout);
+    let outputs: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| {
+            serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("each line should be json. {}: {}", e, stdout))
+        })
+        .collect();
+    assert_eq!(outputs.len(), 2, "{}", stdout);
+    for output in &outputs {
+        let url = output["request"]["url"].as_str().unwrap_or_default();
+        let (id, name) = if url.ends_with("/one") {
+            (0, "first")
+        } else {
+            (1, "second")
+        };
+        assert_eq!(output["endpoint"], id, "{}", stdout);
+        assert_eq!(output["tags"]["_id"], id.to_string(), "{}", stdout);
+        assert_eq!(output["tags"]["name"], name, "{}", stdout);
+    }
+
+    let (success, stdout) = try_test("tests/int_try_tags.yaml", pewpew::TryRunFormat::Human);
+    assert!(success, "try run failed. {}", stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    for (id, name, path) in [(0, "first", "/one"), (1, "second", "/two")] {
+        let i = lines
+            .iter()
+            .position(|l| l.starts_with(&format!("Endpoint {} (", id)))
+            .unwrap_or_else(|| panic!("no output for endpoint {}. {}", id, stdout));
+        assert!(lines[i].contains(&format!("name: {}", name)), "{}", stdout);
+        assert!(
+            lines[i + 1].starts_with("Request (") && lines[i + 1].ends_with(&format!("{})", path)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn int_try_exclude() {
+    let filter = |s: &str| s.parse::<pewpew::TryFilter>().unwrap();
+    let (success, stdout) = try_test_with_filters(
+        "tests/int_try_exclude.yaml",
+        pewpew::TryRunFormat::Json,
+        Some(vec![filter("method=POST")]),
+        Some(vec![filter("tag=slow")]),
+    );
+    assert!(success, "try run failed. {}", stdout);
+    let mut names: Vec<_> = stdout
+        .lines()
+        .map(|line| {
+            let output: serde_json::Value = serde_json::from_str(line)
+                .unwrap_or_else(|e| panic!("each line should be json. {}: {}", e, stdout));
+            output["tags"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned()
+        })
+        .collect();
+    names.sort();
+    assert_eq!(names, ["create", "update"], "{}", stdout);
+}
+
+#[test]
+fn int_endpoint_where() {
+    let (success, _stdout, stderr) = run_test("tests/int_endpoint_where.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // only the rows passing the endpoint's `where` should have been requested
+    let lines: Vec<_> = stderr.lines().collect();
+    assert_eq!(lines, ["1", "3", "5"], "{}", stderr);
+}
+
+#[test]
+fn int_run_summary() {
+    let (success, stdout, stderr) = run_test_with_output(
+        "tests/int_run_summary.yaml",
+        pewpew::RunOutputFormat::Json,
+        pewpew::JsonStyle::Ndjson,
+        None,
+    );
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let summaries: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter(|event: &serde_json::Value| event["type"] == "runSummary")
+        .collect();
+    assert_eq!(summaries.len(), 1, "{}", stdout);
+    assert_eq!(summaries[0]["total"], 6, "{}", stdout);
+    assert_eq!(summaries[0]["errors"], 0, "{}", stdout);
+}
+
+#[test]
+fn int_warnings() {
+    let (success, stdout, stderr) = run_test_with_output(
+        "tests/int_warnings.yaml",
+        pewpew::RunOutputFormat::Json,
+        pewpew::JsonStyle::Ndjson,
+        None,
+    );
+
+    // the warnings are only printed, the test still runs
+    assert!(success, "test run failed. {}", stderr);
+    let warnings: Vec<String> = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|event| event["type"] == "warning")
+        .map(|event| event["msg"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(warnings.len(), 2, "{}", stderr);
+    assert!(warnings[0].contains("log_provider_stats"), "{}", stderr);
+    assert!(warnings[1].contains("`GET` request"), "{}", stderr);
+    assert!(stdout.contains("\"type\":\"runSummary\""), "{}", stdout);
+}
+
+#[test]
+fn int_repeat() {
+    let run_config = pewpew::RunConfig {
+        output_format: pewpew::RunOutputFormat::Json,
+        stats_file: "int_repeat.json".into(),
+        repeat: std::num::NonZeroUsize::new(2),
+        ..run_config("tests/int_run_summary.yaml")
+    };
+
+    let (success, stdout, stderr) = run_test_with_config(run_config);
+    // each run writes its own stats file
+    for run in 1..=2 {
+        let file = format!("int_repeat-{run}.json");
+        assert!(std::path::Path::new(&file).exists(), "{}", file);
+        let _ = std::fs::remove_file(file);
+    }
+    assert!(success, "test run failed. {}", stderr);
+
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    let summaries: Vec<_> = events
+        .iter()
+        .filter(|event| event["type"] == "runSummary")
+        .collect();
+    assert_eq!(summaries.len(), 2, "{}", stdout);
+    // the providers start over for the second run rather than having been used up by the first
+    for summary in &summaries {
+        assert!(summary["total"].as_u64().unwrap() > 0, "{}", stdout);
+    }
+
+    let aggregate: Vec<_> = events
+        .iter()
+        .filter(|event| event["type"] == "aggregateSummary")
+        .collect();
+    assert_eq!(aggregate.len(), 1, "{}", stdout);
+    assert_eq!(aggregate[0]["runs"], 2, "{}", stdout);
+    let total: u64 = summaries.iter().map(|s| s["total"].as_u64().unwrap()).sum();
+    assert_eq!(aggregate[0]["total"], total, "{}", stdout);
+    assert_eq!(aggregate[0]["errors"], 0, "{}", stdout);
+}
+
+#[test]
+fn int_trace() {
+    let run_config = pewpew::RunConfig {
+        trace: Some("int_trace.json".into()),
+        ..run_config("tests/int_trace.yaml")
+    };
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config);
+    assert!(success, "test run failed. {}", stderr);
+
+    let trace = std::fs::read_to_string("int_trace.json").unwrap();
+    let _ = std::fs::remove_file("int_trace.json");
+    let events: Vec<serde_json::Value> = serde_json::from_str(&trace).unwrap();
+    assert_eq!(events.last().unwrap()["ph"], "M", "{}", trace);
+    assert!(
+        events[..events.len() - 1]
+            .iter()
+            .all(|event| event["ph"] == "X" && event["ts"].is_u64() && event["dur"].is_u64()),
+        "{}",
+        trace
+    );
+
+    let requests: Vec<_> = events
+        .iter()
+        .filter(|event| event["cat"] == "request")
+        .collect();
+    assert_eq!(requests.len(), 5, "{}", trace);
+    let mut ids: Vec<_> = requests
+        .iter()
+        .map(|request| {
+            assert_eq!(request["args"]["endpoint"], "0", "{}", trace);
+            assert_eq!(request["args"]["status"], 204, "{}", trace);
+            request["args"]["tags"]["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    ids.sort();
+    assert_eq!(ids, ["1", "2", "3", "4", "5"]);
+
+    // every request waits on its first byte, and the first one also has to connect
+    let phases = |name| events.iter().filter(|event| event["name"] == name).count();
+    assert_eq!(phases("ttfb"), 5, "{}", trace);
+    assert!(phases("connect") >= 1, "{}", trace);
+    assert_eq!(phases("tls"), 0, "{}", trace);
+}
+
+#[test]
+fn int_sticky_provider() {
+    let (success, _stdout, stderr) = run_test("tests/int_sticky.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // every create is read back exactly once, by a read using the id it created
+    let mut created: Vec<u64> = stderr
+        .lines()
+        .map(|line| {
+            let line: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(line["created"], line["read"], "{}", stderr);
+            line["created"].as_u64().unwrap()
+        })
+        .collect();
+    created.sort_unstable();
+    assert_eq!(created, (1..=30).collect::<Vec<_>>(), "{}", stderr);
+}
+
+#[test]
+fn int_response_provider_initial() {
+    let (success, _stdout, stderr) = run_test("tests/int_response_initial.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // the first request can only have used the seeded value, and every later one the echo of it
+    let lines: Vec<_> = stderr.lines().collect();
+    assert!(!lines.is_empty(), "{}", stderr);
+    assert!(lines.iter().all(|line| *line == "seed"), "{}", stderr);
+}
+
+#[test]
+fn int_setup_teardown() {
+    let (_success, _stdout, stderr) = run_test("tests/int_setup_teardown.yaml");
+
+    // the main endpoint's logger ends the test, which the teardown still runs after
+    let lines: Vec<_> = stderr
+        .lines()
+        .filter(|line| matches!(*line, "setup" | "main" | "teardown abc"))
+        .collect();
+    assert_eq!(
+        lines,
+        vec!["setup", "main", "main", "main", "teardown abc"],
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn int_no_auto_returns() {
+    let (success, _stdout, stderr) = run_test("tests/int_no_auto_returns.yaml");
+
+    assert!(success, "test run failed. {}", stderr);
+
+    let count = |name| stderr.lines().filter(|line| *line == name).count();
+    assert_eq!(count("a"), 1, "{}", stderr);
+    assert!(count("b") > 1, "{}", stderr);
+}
+
+#[test]
+fn int_provider_ended() {
+    let start = std::time::Instant::now();
+    let run_config = pewpew::RunConfig {
+        output_format: pewpew::RunOutputFormat::Json,
+        result_file: Some("int_provider_ended.json".into()),
+        max_runtime: Some(std::time::Duration::from_secs(60)),
+        ..run_config("tests/int_provider_ended.yaml")
+    };
+    let (success, _stdout, stderr) = run_test_with_config(run_config);
+    let elapsed = start.elapsed();
+
+    assert!(success, "test run failed. {}", stderr);
+    // the load pattern is an hour long but the file only has three users
+    assert!(
+        elapsed < std::time::Duration::from_secs(30),
+        "took {:?}",
+        elapsed
+    );
+    let end: serde_json::Value = stderr
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|v| v["type"] == "end")
+        .unwrap_or_else(|| panic!("should have an end message. {}", stderr));
+    assert_eq!(end["providers"], serde_json::json!(["user"]), "{}", end);
+    assert_eq!(
+        end["msg"],
+        "Test ended early because one or more providers ended: user"
+    );
+
+    let result = std::fs::read_to_string("int_provider_ended.json").unwrap();
+    let _ = std::fs::remove_file("int_provider_ended.json");
+    let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+    assert_eq!(result["endReason"], "providerEnded", "{}", result);
+    assert_eq!(
+        result["providersEnded"],
+        serde_json::json!(["user"]),
+        "{}",
+        result
+    );
+}
+
+#[test]
+fn int_stats_socket() {
+    // find a free port for the socket
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let run_config = pewpew::RunConfig {
+        stats_socket: Some(pewpew::StatsSocketAddr::Tcp(addr)),
+        ..run_config("tests/int_stats_socket.yaml")
+    };
+
+    let client = std::thread::spawn(move || {
+        let start = std::time::Instant::now();
+        let stream = loop {
+            match std::net::TcpStream::connect(addr) {
+                Ok(stream) => break stream,
+                Err(_) if start.elapsed().as_secs() < 10 => {
+                    std::thread::sleep(std::time::Duration::from_millis(10))
+                }
+                Err(e) => panic!("could not connect to the stats socket. {}", e),
+            }
+        };
+        // the socket is closed once the test ends
+        std::io::BufReader::new(stream)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect::<Vec<serde_json::Value>>()
+    });
+
+    let (success, _stdout, stderr) = run_test_with_config(run_config);
+    assert!(success, "test run failed. {}", stderr);
+
+    let lines = client.join().unwrap();
+    let heartbeats = lines.iter().filter(|l| l["type"] == "heartbeat").count();
+    assert!(heartbeats >= 2, "{:?}", lines);
+    assert!(
+        lines
+            .iter()
+            .any(|l| l["type"] == "summary" && l["callCount"].as_u64().unwrap_or_default() > 0),
+        "{:?}",
+        lines
+    );
+}
+
+#[test]
+fn int_json_ndjson_has_no_end_event() {
+    let (success, stdout, stderr) = run_test_with_output(
+        "tests/int_on_demand.yaml",
+        pewpew::RunOutputFormat::Json,
+        pewpew::JsonStyle::Ndjson,
+        None,
+    );
+
+    assert!(success, "test run failed. {}", stderr);
+
+    // a test which completes normally doesn't print an end event when each event is its own line
+    for line in stdout.lines().chain(stderr.lines()) {
+        let event: serde_json::Value = serde_json::from_str(line)
+            .unwrap_or_else(|e| panic!("each line should be json. {}: {}", e, line));
+        assert_ne!(event.get("type"), Some(&"end".into()), "{}", line);
+    }
+}
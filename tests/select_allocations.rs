@@ -0,0 +1,80 @@
+// Its own test binary, as the allocator which counts what handling a response allocates is
+// global and would otherwise be installed under every other test
+use config::{EndpointProvidesSendOptions::*, Select};
+use serde_json as json;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::Arc,
+};
+
+// counts the allocations made on each thread, so other tests running alongside don't get in the
+// way
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+#[test]
+fn selects_share_a_large_body() {
+    let items: Vec<_> = (0..10_000)
+        .map(|i| json::json!({"id": i, "name": format!("item {}", i)}))
+        .collect();
+    let body = json::json!({"count": items.len(), "items": items});
+    // the template values a response's provides and logs all read from
+    let template_values = Arc::new(json::json!({ "response": { "body": body } }));
+
+    let selects: Vec<_> = vec![
+        Select::simple("response.body.count", Force, None, None, None),
+        Select::simple("response.body.items[9999].name", Block, None, None, None),
+        Select::simple("response.body.items[0].id", IfNotFull, None, None, None),
+    ]
+    .into_iter()
+    .map(Arc::new)
+    .collect();
+
+    let before = allocations();
+    let received: Vec<Vec<_>> = selects
+        .into_iter()
+        .map(|select| {
+            select
+                .iter(template_values.clone())
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        })
+        .collect();
+    let allocated = allocations() - before;
+
+    assert_eq!(
+        received,
+        vec![
+            vec![json::json!(10_000)],
+            vec![json::json!("item 9999")],
+            vec![json::json!(0)],
+        ]
+    );
+    // the selects share the one body, where a copy of it would take several allocations for each
+    // of its items
+    assert!(allocated < 100, "{} allocations", allocated);
+}